@@ -0,0 +1,108 @@
+//! Parsing speed and allocations, for comparing argument storage: run with and without
+//! `--features smallvec`. The `decode` group gives messages per second for a stream of lines, as
+//! a bouncer or log processor would see them.
+
+#[macro_use]
+extern crate criterion;
+extern crate irc;
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use criterion::Criterion;
+use criterion::Throughput;
+
+use irc::Message;
+use irc::MessageDecoder;
+use irc::MessageRef;
+
+// A mix of what a busy channel's log looks like.
+const LINES: &[&str] = &[
+    ":nick!user@host.example.com PRIVMSG #channel :Hello there, how is everyone doing today?\r\n",
+    ":other!~ident@192.0.2.1 JOIN #channel\r\n",
+    "@time=2024-01-01T12:00:00.000Z :nick!user@host PRIVMSG #channel :tagged message\r\n",
+    ":server.example.com 353 me = #channel :@op +voiced regular another someone\r\n",
+    ":nick!user@host MODE #channel +ov op voiced\r\n",
+    "PING :server.example.com\r\n",
+    ":nick!user@host QUIT :Ping timeout: 240 seconds\r\n",
+    ":server.example.com 005 me CHANTYPES=# PREFIX=(ov)@+ NETWORK=Example :are supported\r\n",
+];
+
+// Counts allocations, which matter as much as time to a busy log processor.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn parse(c: &mut Criterion) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for line in LINES {
+        criterion::black_box(Message::parse(line.as_bytes()).unwrap());
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!("Message::parse: {} allocations for {} lines", allocations, LINES.len());
+
+    let bytes: usize = LINES.iter().map(|line| line.len()).sum();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("Message", |b| {
+        b.iter(|| {
+            for line in LINES {
+                criterion::black_box(Message::parse(line.as_bytes()).unwrap());
+            }
+        })
+    });
+    group.bench_function("MessageRef", |b| {
+        b.iter(|| {
+            for line in LINES {
+                criterion::black_box(MessageRef::parse(line.as_bytes()).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let stream: Vec<u8> = LINES.iter().cycle().take(1000).flat_map(|line| line.bytes()).collect();
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(1000));
+    group.bench_function("MessageDecoder", |b| {
+        b.iter(|| {
+            let mut decoder = MessageDecoder::new();
+            decoder.feed(&stream);
+            for message in decoder {
+                criterion::black_box(message.unwrap());
+            }
+        })
+    });
+    group.bench_function("MessageRef", |b| {
+        b.iter(|| {
+            let mut remaining = &stream[..];
+            while !remaining.is_empty() {
+                let (message, rest) = MessageRef::parse(remaining).unwrap();
+                criterion::black_box(message);
+                remaining = rest;
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse, decode);
+criterion_main!(benches);
@@ -10,9 +10,9 @@ use std::io::Read;
 use std::io::Write;
 use std::env;
 use std::str::FromStr;
+use irc::Client;
 use irc::IrcStream;
-use irc::Message;
-use irc::responses;
+use irc::Registration;
 
 fn main() {
     TermLogger::init(LogLevelFilter::Trace).unwrap();
@@ -41,31 +41,22 @@ fn main() {
     }
 }
 
-fn echobot<S: Read + Write>(mut irc: IrcStream<S>, nick_str: &str, channel: &str) -> io::Result<()> {
-    let mut nick = nick_str.to_string();
+fn echobot<S: Read + Write>(irc: IrcStream<S>, nick: &str, channel: &str) -> io::Result<()> {
+    info!("Connecting with nick {} and joining channel {}", nick, channel);
 
-    info!("Connecting with nick {} and joining channel {}",
-          nick.as_str(),
-          channel);
+    let registration = Registration::new(nick, "echobot", "Echo Bot")
+        .with_alternative_nickname(&format!("{}_", nick))
+        .with_alternative_nickname(&format!("{}__", nick));
+    let mut client = Client::register(irc, &registration)?;
+    client.join(channel)?;
 
-    irc.send(&Message::nick(nick.as_str()))?;
-    irc.send(&Message::user("echobot", "Echo Bot"))?;
-    irc.send(&Message::join(channel))?;
-
-    loop {
-        let message = irc.next_message()?;
-        if let Some(ping) = message.as_ping() {
-            info!("Responding to a PING message");
-            irc.send(&ping.pong()).unwrap();
-        } else if let Some(privmsg) = message.as_privmsg() {
+    client.run(|client, message| {
+        if let Some(privmsg) = message.as_privmsg() {
             if privmsg.text.starts_with("!echo ") {
                 info!("Responding to an !echo request");
-                irc.send(&Message::privmsg(privmsg.to, &privmsg.text[6..]))?
+                client.privmsg(privmsg.to, &privmsg.text[6..])?;
             }
-        } else if message.command == responses::ERR_NICKNAMEINUSE() {
-            info!("Nick {} in use, trying {}_", nick, nick);
-            nick.push('_');
-            irc.send(&Message::nick(nick.as_str()))?;
         }
-    }
+        Ok(())
+    })
 }
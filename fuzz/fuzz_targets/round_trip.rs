@@ -0,0 +1,6 @@
+#![no_main]
+
+use irc::Message;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|message: Message| irc::testing::fuzz_round_trip(&message));
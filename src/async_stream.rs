@@ -0,0 +1,228 @@
+#[cfg(feature = "tokio")]
+use std::convert::TryFrom;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::io;
+#[cfg(feature = "tokio")]
+use std::io::ErrorKind;
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use futures_core::Stream;
+use futures_sink::Sink;
+#[cfg(feature = "tokio")]
+use futures_util::TryFutureExt;
+#[cfg(feature = "tokio")]
+use futures_util::future;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+#[cfg(feature = "tokio")]
+use tokio::net::TcpStream;
+#[cfg(feature = "tokio")]
+use tokio_rustls::TlsConnector;
+#[cfg(feature = "tokio")]
+use tokio_rustls::client::TlsStream;
+#[cfg(feature = "tokio")]
+use tokio_rustls::rustls::ClientConfig;
+#[cfg(feature = "tokio")]
+use tokio_rustls::rustls::RootCertStore;
+#[cfg(feature = "tokio")]
+use tokio_rustls::rustls::crypto::ring;
+#[cfg(feature = "tokio")]
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_util::codec::Framed;
+#[cfg(feature = "futures-io")]
+use tokio_util::compat::Compat;
+#[cfg(feature = "futures-io")]
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+use codec::IrcCodec;
+use message::Message;
+#[cfg(feature = "tokio")]
+use trace;
+
+/// The async equivalent of `IrcStream`: a `Stream` of received messages and a `Sink` for
+/// messages to send. Use `StreamExt` and `SinkExt` from the `futures` crate to work with it.
+///
+/// This isn't tied to any particular runtime. It works directly with anything implementing
+/// tokio's `AsyncRead` and `AsyncWrite`, and with the `futures-io` feature `from_futures_io`
+/// accepts the `futures` versions of those traits, as used by async-std and smol. Only the
+/// `connect` functions (with the `tokio` feature) need the tokio runtime.
+///
+/// As with `Framed`, the stream ends after the first error, including a line which couldn't be
+/// parsed.
+#[derive(Debug)]
+pub struct AsyncIrcStream<S> {
+    framed: Framed<S, IrcCodec>,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncIrcStream<TcpStream> {
+    /// Connects to a server over plain TCP.
+    pub fn connect(server: &str, port: u16) -> impl Future<Output = io::Result<Self>> {
+        // Messages are traced in whatever span the stream is polled in, rather than this one.
+        trace::connecting("irc", server, port);
+        TcpStream::connect((server.to_string(), port)).map_ok(AsyncIrcStream::new)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncIrcStream<TlsStream<TcpStream>> {
+    /// Connects to a server over TLS, trusting the usual web PKI roots.
+    ///
+    /// This uses rustls rather than OpenSSL, so client certificates set up for `IrcStream` don't
+    /// apply here.
+    pub fn connect_ssl(server: &str, port: u16) -> impl Future<Output = io::Result<Self>> {
+        trace::connecting("ircs", server, port);
+        let address = (server.to_string(), port);
+        let connector = tls_connector();
+        let name = ServerName::try_from(server.to_string())
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err));
+
+        future::ready(name)
+            .and_then(move |name| {
+                TcpStream::connect(address).and_then(move |tcp| connector.connect(name, tcp))
+            })
+            .map_ok(AsyncIrcStream::new)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite> AsyncIrcStream<S> {
+    /// Wraps an already-connected stream.
+    pub fn new(stream: S) -> Self {
+        Self::with_codec(stream, IrcCodec::new())
+    }
+
+    /// Wraps an already-connected stream, with a customised codec (e.g. a different maximum line
+    /// length).
+    pub fn with_codec(stream: S, codec: IrcCodec) -> Self {
+        AsyncIrcStream { framed: Framed::new(stream, codec) }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.framed.get_ref()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.framed.into_inner()
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<S: futures_io::AsyncRead + futures_io::AsyncWrite> AsyncIrcStream<Compat<S>> {
+    /// Wraps a stream implementing the `futures` IO traits rather than tokio's, e.g. an
+    /// async-std or smol `TcpStream`.
+    pub fn from_futures_io(stream: S) -> Self {
+        Self::new(stream.compat())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Stream for AsyncIrcStream<S> {
+    type Item = io::Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.framed).poll_next(cx)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Sink<Message> for AsyncIrcStream<S> {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Sink::<Message>::poll_ready(Pin::new(&mut self.framed), cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, message: Message) -> io::Result<()> {
+        Pin::new(&mut self.framed).start_send(message)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Sink::<Message>::poll_flush(Pin::new(&mut self.framed), cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        Sink::<Message>::poll_close(Pin::new(&mut self.framed), cx)
+    }
+}
+
+#[cfg(feature = "tokio")]
+fn tls_connector() -> TlsConnector {
+    let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("the ring provider supports the default protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    TlsConnector::from(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::SinkExt;
+    use futures_util::StreamExt;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::io::duplex;
+    use tokio::runtime::Builder;
+
+    use command::commands::PING;
+    use message::Prefix;
+
+    #[test]
+    fn receive_and_send() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let (client, mut server) = duplex(1024);
+        let mut irc = AsyncIrcStream::new(client);
+
+        runtime.block_on(server.write_all(b"PING 1\r\nPING 2\r\n")).unwrap();
+        assert_eq!(runtime.block_on(irc.next()).unwrap().unwrap(),
+                   Message::from_strs(Prefix::None, PING(), vec!["1"]));
+        assert_eq!(runtime.block_on(irc.next()).unwrap().unwrap(),
+                   Message::from_strs(Prefix::None, PING(), vec!["2"]));
+
+        runtime.block_on(irc.send(Message::privmsg("#chan", "Hi there"))).unwrap();
+        let mut sent = vec![0; 25];
+        runtime.block_on(server.read_exact(&mut sent)).unwrap();
+        assert_eq!(sent, b"PRIVMSG #chan :Hi there\r\n".to_vec());
+    }
+
+    #[test]
+    fn ends_after_invalid_line() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let (client, mut server) = duplex(1024);
+        let mut irc = AsyncIrcStream::new(client);
+
+        runtime.block_on(server.write_all(b"!!!\r\nPING 1\r\n")).unwrap();
+        assert!(runtime.block_on(irc.next()).unwrap().is_err());
+        assert!(runtime.block_on(irc.next()).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "futures-io")]
+    fn futures_io() {
+        use futures_util::io::Cursor;
+
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let mut irc = AsyncIrcStream::from_futures_io(Cursor::new(b"PING 1\r\n".to_vec()));
+        assert_eq!(runtime.block_on(irc.next()).unwrap().unwrap(),
+                   Message::from_strs(Prefix::None, PING(), vec!["1"]));
+
+        let mut irc = AsyncIrcStream::from_futures_io(Cursor::new(Vec::new()));
+        runtime.block_on(irc.send(Message::privmsg("#chan", "Hi"))).unwrap();
+        assert_eq!(irc.into_inner().into_inner().into_inner(), b"PRIVMSG #chan Hi\r\n".to_vec());
+    }
+
+    #[test]
+    fn end_of_stream() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let (client, server) = duplex(1024);
+        let mut irc = AsyncIrcStream::new(client);
+        drop(server);
+
+        assert!(runtime.block_on(irc.next()).is_none());
+    }
+}
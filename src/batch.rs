@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use irc_stream::IrcStream;
+use message::Message;
+
+/// The type of an IRCv3 batch. Types we know about get their own variant.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BatchType {
+    Netsplit,
+    Netjoin,
+    ChatHistory,
+    LabeledResponse,
+    Other(String),
+}
+
+/// A complete batch, with everything that was sent as part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Batch {
+    pub reference: String,
+    pub batch_type: BatchType,
+    /// Parameters which followed the type in the `BATCH +` message.
+    pub parameters: Vec<String>,
+    /// The `BATCH +` message which started this batch, for access to its tags.
+    pub start: Message,
+    /// The contents of the batch in the order received. Nested batches appear here as a single
+    /// entry at the point they finished.
+    pub messages: Vec<Batched>,
+}
+
+/// Something received from the server, once batches have been grouped together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Batched {
+    Message(Message),
+    Batch(Batch),
+}
+
+/// Keeps track of open batches.
+///
+/// There are two ways to use this. To just find out which batch a message belongs to, pass every
+/// received message to `observe` and ask `batch_type_of`. To have batches collected and handed back
+/// to you whole, pass every message to `push` (or use `next_batched`) instead.
+#[derive(Debug, Clone, Default)]
+pub struct BatchTracker {
+    open: HashMap<String, Batch>,
+    // Maps each open batch to the batch it is nested within, if any.
+    parents: HashMap<String, String>,
+}
+
+impl<'a> From<&'a str> for BatchType {
+    fn from(name: &'a str) -> Self {
+        match name {
+            "netsplit" => BatchType::Netsplit,
+            "netjoin" => BatchType::Netjoin,
+            "chathistory" => BatchType::ChatHistory,
+            "labeled-response" => BatchType::LabeledResponse,
+            other => BatchType::Other(other.into()),
+        }
+    }
+}
+
+impl BatchTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notes any batches started or finished by `message`, without collecting batch contents.
+    pub fn observe(&mut self, message: &Message) {
+        if let Some(batch) = message.as_batch() {
+            if batch.start {
+                let started = self.start(message);
+                self.open.insert(started.reference.clone(), started);
+            } else {
+                self.open.remove(batch.reference);
+                self.parents.remove(batch.reference);
+            }
+        }
+    }
+
+    /// The type of the open batch which `message` is part of, if any.
+    pub fn batch_type_of(&self, message: &Message) -> Option<&BatchType> {
+        message.batch_reference()
+            .and_then(|reference| self.open.get(reference))
+            .map(|batch| &batch.batch_type)
+    }
+
+    /// Whether there are any batches which have been started but not yet finished.
+    pub fn has_open_batches(&self) -> bool {
+        !self.open.is_empty()
+    }
+
+    /// Adds a message to whichever batch it belongs to. Returns it straight back if it isn't part
+    /// of a batch, or the whole batch once a top-level batch has finished. Otherwise returns
+    /// `None` because the message is being held onto.
+    pub fn push(&mut self, message: Message) -> Option<Batched> {
+        let finished = match message.as_batch() {
+            Some(ref batch) if batch.start => {
+                let started = self.start(&message);
+                if let Some(parent) = message.batch_reference() {
+                    self.parents.insert(started.reference.clone(), parent.into());
+                }
+                self.open.insert(started.reference.clone(), started);
+                return None;
+            }
+            Some(ref batch) => {
+                match self.open.remove(batch.reference) {
+                    Some(finished) => finished,
+                    None => {
+                        warn!("Received end of unknown batch: {}", message);
+                        return Some(Batched::Message(message.clone()));
+                    }
+                }
+            }
+            None => return self.add_to_batch(Batched::Message(message)),
+        };
+
+        match self.parents.remove(&finished.reference) {
+            Some(parent) => self.add_to(&parent, Batched::Batch(finished)),
+            None => Some(Batched::Batch(finished)),
+        }
+    }
+
+    /// Reads messages from `irc` until there's a message or batch to hand back, as in `push`.
+    pub fn next_batched<S: Read + Write>(&mut self,
+                                         irc: &mut IrcStream<S>)
+                                         -> io::Result<Batched> {
+        loop {
+            let message = irc.next_message()?;
+            if let Some(batched) = self.push(message) {
+                return Ok(batched);
+            }
+        }
+    }
+
+    fn start(&self, message: &Message) -> Batch {
+        let boundary = message.as_batch().unwrap();
+        Batch {
+            reference: boundary.reference.into(),
+            batch_type: boundary.batch_type.unwrap_or_default().into(),
+            parameters: boundary.parameters.to_vec(),
+            start: message.clone(),
+            messages: Vec::new(),
+        }
+    }
+
+    fn add_to_batch(&mut self, batched: Batched) -> Option<Batched> {
+        let reference = match batched {
+            Batched::Message(ref message) => message.batch_reference().map(|r| r.to_string()),
+            Batched::Batch(_) => None,
+        };
+        match reference {
+            Some(reference) => self.add_to(&reference, batched),
+            None => Some(batched),
+        }
+    }
+
+    fn add_to(&mut self, reference: &str, batched: Batched) -> Option<Batched> {
+        match self.open.get_mut(reference) {
+            Some(batch) => {
+                batch.messages.push(batched);
+                None
+            }
+            None => {
+                warn!("Received message for unknown batch {}", reference);
+                Some(batched)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc_stream::ScriptedStream;
+    use message::Message;
+
+    #[test]
+    fn unbatched_messages_pass_through() {
+        let mut tracker = BatchTracker::new();
+        let ping = message("PING 123\r\n");
+
+        assert_eq!(tracker.push(ping.clone()), Some(Batched::Message(ping)));
+    }
+
+    #[test]
+    fn groups_batch() {
+        let mut tracker = BatchTracker::new();
+
+        assert_eq!(tracker.push(message(":irc.host BATCH +ref netsplit irc.hub other.host\r\n")),
+                   None);
+        assert_eq!(tracker.push(message("@batch=ref :aji!a@a QUIT :irc.hub other.host\r\n")),
+                   None);
+        assert_eq!(tracker.push(message("@batch=ref :nenolod!a@a QUIT :irc.hub other.host\r\n")),
+                   None);
+
+        match tracker.push(message(":irc.host BATCH -ref\r\n")) {
+            Some(Batched::Batch(batch)) => {
+                assert_eq!(batch.batch_type, BatchType::Netsplit);
+                assert_eq!(batch.parameters, vec!["irc.hub", "other.host"]);
+                assert_eq!(batch.messages.len(), 2);
+            }
+            other => panic!("Expected batch, got {:?}", other),
+        }
+        assert!(!tracker.has_open_batches());
+    }
+
+    #[test]
+    fn nested_batches() {
+        let mut tracker = BatchTracker::new();
+
+        tracker.push(message(":irc.host BATCH +outer example.com/foo\r\n"));
+        tracker.push(message("@batch=outer :irc.host BATCH +inner example.com/bar\r\n"));
+        tracker.push(message("@batch=inner :nick!user@host PRIVMSG #chan :Hi\r\n"));
+        assert_eq!(tracker.push(message("@batch=outer :irc.host BATCH -inner\r\n")), None);
+
+        match tracker.push(message(":irc.host BATCH -outer\r\n")) {
+            Some(Batched::Batch(outer)) => {
+                assert_eq!(outer.batch_type, BatchType::Other("example.com/foo".into()));
+                match outer.messages[0] {
+                    Batched::Batch(ref inner) => assert_eq!(inner.messages.len(), 1),
+                    ref other => panic!("Expected nested batch, got {:?}", other),
+                }
+            }
+            other => panic!("Expected batch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn observe() {
+        let mut tracker = BatchTracker::new();
+        let quit = message("@batch=ref :aji!a@a QUIT :irc.hub other.host\r\n");
+
+        tracker.observe(&message(":irc.host BATCH +ref netjoin irc.hub other.host\r\n"));
+        assert_eq!(tracker.batch_type_of(&quit), Some(&BatchType::Netjoin));
+
+        tracker.observe(&message(":irc.host BATCH -ref\r\n"));
+        assert_eq!(tracker.batch_type_of(&quit), None);
+    }
+
+    #[test]
+    fn next_batched() {
+        let input = b":irc.host BATCH +ref chathistory #chan\r\n\
+                      @batch=ref :nick!user@host PRIVMSG #chan :Hi\r\n\
+                      :irc.host BATCH -ref\r\n\
+                      PING 123\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        let mut tracker = BatchTracker::new();
+
+        match tracker.next_batched(&mut irc).unwrap() {
+            Batched::Batch(batch) => assert_eq!(batch.batch_type, BatchType::ChatHistory),
+            other => panic!("Expected batch, got {:?}", other),
+        }
+        assert_eq!(tracker.next_batched(&mut irc).unwrap(),
+                   Batched::Message(message("PING 123\r\n")));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,927 @@
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use casemapping::IrcCase;
+use command::commands;
+use command::responses;
+use command::Command;
+use irc_stream::IrcStream;
+use irc_stream::ParsePolicy;
+use irc_stream::is_timeout;
+use isupport::IsupportInfo;
+use membership::Membership;
+use message::MAX_LINE_LENGTH;
+use message::Message;
+use message::Prefix;
+use message::UserInfo;
+use messages::UserModes;
+use names::ChannelMember;
+use names::ChannelNames;
+use names::ChannelVisibility;
+use names::NamesCollector;
+use registration::Registered;
+
+/// What a client attaching to a bouncer needs to be told to catch up: the welcome burst from
+/// registering, our nickname and user modes, and the channels we're in with their topics and
+/// members.
+///
+/// Pass every message received from the server to `handle`, and send whatever `replay` returns to
+/// each client as it attaches. `Bouncer` does both, but this works just as well with an
+/// `AsyncIrcStream` or anything else.
+#[derive(Debug, Clone)]
+pub struct BouncerState {
+    server: Prefix,
+    nickname: String,
+    // Our full nick!user@host, once our own JOIN has shown it to us.
+    me: UserInfo,
+    // The numerics sent on registering (RPL_WELCOME, ISUPPORT, LUSERS, the MOTD), in order.
+    welcome: Vec<Message>,
+    isupport: IsupportInfo,
+    user_modes: UserModes,
+    // In the order we joined them.
+    channels: Vec<Channel>,
+    names: NamesCollector,
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    names: ChannelNames,
+    topic: Option<String>,
+    // Who set the topic, and when.
+    topic_set: Option<(String, u64)>,
+}
+
+/// Glue for a simple bouncer: one upstream connection to a server, kept registered, shared by any
+/// number of clients attached to it.
+///
+/// Clients register with the bouncer as if it were the server, and are sent the welcome burst and
+/// the channels we're in by `BouncerState::replay`. From then on everything the server sends goes
+/// to all of them, and everything they send goes to the server, apart from `QUIT`, which just
+/// detaches the client. Messages clients send to channels and users are also shown to the other
+/// clients, so they see each other's side of conversations. Replies to one client's queries go to
+/// them all.
+///
+/// Clients aren't offered any capabilities, so tags are stripped from what's relayed to them.
+/// Register upstream without capabilities which change how messages look (like `extended-join`)
+/// or echo them back (`echo-message`) unless your clients can cope.
+///
+/// `poll` reads from every connection in turn, so all of them need a short read timeout, e.g. with
+/// `TcpStream::set_read_timeout`.
+///
+/// ```no_run
+/// use std::net::TcpListener;
+/// use std::time::Duration;
+/// use irc::{Bouncer, IrcStream, Registration};
+///
+/// let mut upstream = IrcStream::connect("irc.libera.chat", 6667).unwrap().with_auto_pong();
+/// let registered = Registration::new("me", "me", "Me").register(&mut upstream).unwrap();
+/// upstream.get_ref().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+/// let mut bouncer = Bouncer::new(upstream, &registered).with_password("hunter2");
+///
+/// let listener = TcpListener::bind("127.0.0.1:6667").unwrap();
+/// listener.set_nonblocking(true).unwrap();
+/// loop {
+///     if let Ok((client, _)) = listener.accept() {
+///         client.set_nonblocking(false).unwrap();
+///         client.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+///         bouncer.attach(IrcStream::new(client));
+///     }
+///     bouncer.poll().unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Bouncer<U: Read + Write, D: Read + Write> {
+    upstream: IrcStream<U>,
+    state: BouncerState,
+    password: Option<String>,
+    clients: Vec<Downstream<D>>,
+}
+
+#[derive(Debug)]
+struct Downstream<D: Read + Write> {
+    irc: IrcStream<D>,
+    // Until the client has registered, what it's told us so far.
+    registering: Option<Pending>,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    password: Option<String>,
+    nickname: Option<String>,
+    user: bool,
+    // Whether the client has started capability negotiation, and not yet ended it.
+    negotiating: bool,
+}
+
+impl BouncerState {
+    pub fn new(registered: &Registered) -> Self {
+        let mut state = BouncerState {
+            server: Prefix::None,
+            nickname: String::new(),
+            me: UserInfo::of_nickname(""),
+            welcome: Vec::new(),
+            isupport: IsupportInfo::new(),
+            user_modes: UserModes::new(),
+            channels: Vec::new(),
+            names: NamesCollector::new(),
+        };
+        state.handle(&registered.welcome);
+        state
+    }
+
+    /// Our nickname, following changes.
+    pub fn nickname(&self) -> &str {
+        &self.nickname
+    }
+
+    pub fn isupport(&self) -> &IsupportInfo {
+        &self.isupport
+    }
+
+    pub fn user_modes(&self) -> &UserModes {
+        &self.user_modes
+    }
+
+    /// The channels we're in, in the order we joined them.
+    pub fn channels(&self) -> Vec<&str> {
+        self.channels.iter().map(|channel| channel.names.channel.as_str()).collect()
+    }
+
+    /// Who's in a channel we're in, and their ranks.
+    pub fn members(&self, channel: &str) -> Option<&ChannelNames> {
+        self.channel(channel).map(|channel| &channel.names)
+    }
+
+    /// Updates from a message received from the server. An `RPL_WELCOME` means we've registered
+    /// again, e.g. after reconnecting, so everything from before is forgotten.
+    pub fn handle(&mut self, message: &Message) {
+        if message.command == responses::RPL_WELCOME() {
+            *self = BouncerState {
+                server: message.prefix.clone(),
+                nickname: message.arguments.first().cloned().unwrap_or_default(),
+                me: UserInfo::of_nickname(message.arguments.first().map_or("", |nick| nick)),
+                welcome: vec![message.clone()],
+                isupport: IsupportInfo::new(),
+                user_modes: UserModes::new(),
+                channels: Vec::new(),
+                names: NamesCollector::new(),
+            };
+            return;
+        }
+        if is_welcome(&message.command) {
+            self.add_welcome(message);
+        }
+        self.isupport.handle(message);
+        self.track_nick(message);
+        self.track_user_modes(message);
+        self.track_channels(message);
+    }
+
+    /// The lines to send a client which has just registered with us, to bring it up to date.
+    pub fn replay(&self) -> Vec<Message> {
+        let mut replay: Vec<Message> = self.welcome
+            .iter()
+            .map(|message| {
+                // Our nickname may have changed since.
+                let mut message = message.clone();
+                if let Some(nick) = message.arguments.get_mut(0) {
+                    *nick = self.nickname.clone();
+                }
+                message
+            })
+            .collect();
+        if !self.user_modes.is_empty() {
+            let modes = self.user_modes.to_string();
+            replay.push(self.sent_by_me(Message::from_strs(Prefix::None,
+                                                           commands::MODE(),
+                                                           vec![&self.nickname, &modes])));
+        }
+        for channel in &self.channels {
+            let name = &channel.names.channel;
+            replay.push(self.sent_by_me(Message::join(name)));
+            if let Some(ref topic) = channel.topic {
+                replay.push(self.reply(responses::RPL_TOPIC(), vec![name, topic]));
+            }
+            if let Some((ref set_by, set_at)) = channel.topic_set {
+                let set_at = set_at.to_string();
+                replay.push(self.reply(responses::RPL_TOPICWHOTIME(),
+                                       vec![name, set_by, &set_at]));
+            }
+            replay.extend(self.names_replies(&channel.names));
+        }
+        replay
+    }
+
+    fn add_welcome(&mut self, message: &Message) {
+        let command = &message.command;
+        if *command == responses::RPL_MOTDSTART() || *command == responses::ERR_NOMOTD() {
+            // Someone's asked for the MOTD again, so it replaces the one we had.
+            self.welcome.retain(|line| !is_motd(&line.command));
+        }
+        // There are several lines of ISUPPORT and MOTD, but just one of everything else.
+        if *command == responses::RPL_ISUPPORT() {
+            if !self.welcome.contains(message) {
+                self.welcome.push(message.clone());
+            }
+        } else if *command == responses::RPL_MOTD() {
+            self.welcome.push(message.clone());
+        } else if let Some(line) = self.welcome.iter_mut().find(|line| line.command == *command) {
+            *line = message.clone();
+        } else {
+            self.welcome.push(message.clone());
+        }
+    }
+
+    fn track_nick(&mut self, message: &Message) {
+        let change = match message.as_nick() {
+            Some(change) => change,
+            None => return,
+        };
+        let case = self.isupport.casemapping();
+        if case.eq(change.old_nick(), &self.nickname) {
+            self.nickname = change.new_nick.into();
+            self.me = match (self.me.username(), self.me.host()) {
+                (Some(user), Some(host)) => {
+                    UserInfo::of_nickname_user_host(change.new_nick, user, host)
+                }
+                _ => UserInfo::of_nickname(change.new_nick),
+            };
+        }
+        for channel in &mut self.channels {
+            for member in &mut channel.names.members {
+                if case.eq(&member.nick, change.old_nick()) {
+                    member.nick = change.new_nick.into();
+                }
+            }
+        }
+    }
+
+    fn track_user_modes(&mut self, message: &Message) {
+        let mode = match message.as_user_mode(&self.isupport) {
+            Some(mode) => mode,
+            None => return,
+        };
+        if !self.isupport.casemapping().eq(mode.nick, &self.nickname) {
+            return;
+        }
+        if mode.complete {
+            self.user_modes = UserModes::new();
+        }
+        self.user_modes.apply(&mode.changes);
+    }
+
+    fn track_channels(&mut self, message: &Message) {
+        let case = self.isupport.casemapping();
+        if let Some(join) = message.as_join() {
+            let nick = join.user.nickname();
+            if case.eq(nick, &self.nickname) {
+                self.me = join.user.clone();
+                if self.channel(join.channel).is_none() {
+                    self.channels.push(Channel::new(join.channel));
+                }
+            } else if let Some(channel) = self.channel_mut(join.channel) {
+                channel.remove(nick, case);
+                channel.names.members.push(ChannelMember {
+                    nick: nick.into(),
+                    ranks: Vec::new(),
+                });
+            }
+        } else if let Some(part) = message.as_part() {
+            self.left(part.channel, part.user.nickname());
+        } else if let Some(kick) = message.as_kick() {
+            self.left(kick.channel, kick.nick);
+        } else if let Some(quit) = message.as_quit() {
+            for channel in &mut self.channels {
+                channel.remove(quit.user.nickname(), case);
+            }
+        } else if let Some(topic) = message.as_topic() {
+            let set_by = match *topic.by {
+                Prefix::User(ref user) => user.to_string(),
+                Prefix::Server(ref server) => server.clone(),
+                Prefix::None => String::new(),
+            };
+            let set_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            if let Some(channel) = self.channel_mut(topic.channel) {
+                channel.topic = topic.topic.map(|topic| topic.into());
+                channel.topic_set = channel.topic.as_ref().map(|_| (set_by, set_at));
+            }
+        } else if let Some(reply) = message.as_topic_reply() {
+            if let Some(channel) = self.channel_mut(reply.channel) {
+                channel.topic = reply.topic.map(|topic| topic.into());
+            }
+        } else if let Some(who_time) = message.as_topic_who_time() {
+            if let Some(channel) = self.channel_mut(who_time.channel) {
+                channel.topic_set = Some((who_time.set_by.into(), who_time.set_at));
+            }
+        } else if let Some(mode) = message.as_channel_mode(&self.isupport) {
+            let prefixes = self.isupport.prefix();
+            let channel = match self.channel_mut(mode.channel) {
+                Some(channel) => channel,
+                None => return,
+            };
+            for change in &mode.changes {
+                if prefixes.prefix_for_mode(change.mode).is_none() {
+                    continue;
+                }
+                let nick = match change.argument {
+                    Some(ref nick) => nick,
+                    None => continue,
+                };
+                let rank = Membership::from_mode(change.mode);
+                let member = channel.names.members.iter_mut().find(|m| case.eq(&m.nick, nick));
+                if let Some(member) = member {
+                    member.ranks.retain(|r| *r != rank);
+                    if change.added {
+                        member.ranks.push(rank);
+                        member.ranks.sort();
+                    }
+                }
+            }
+        } else if let Some(name) = NamesCollector::channel(message) {
+            if self.channel(name).is_none() {
+                // A reply to someone's NAMES for a channel we're not in.
+                return;
+            }
+            let prefixes = self.isupport.prefix();
+            if let Some(names) = self.names.handle(message, &prefixes) {
+                if let Some(channel) = self.channel_mut(&names.channel) {
+                    channel.names = names;
+                }
+            }
+        }
+    }
+
+    // Someone left a channel: if it was us, we're not in it any more.
+    fn left(&mut self, channel: &str, nick: &str) {
+        let case = self.isupport.casemapping();
+        if case.eq(nick, &self.nickname) {
+            self.channels.retain(|c| !case.eq(&c.names.channel, channel));
+        } else if let Some(channel) = self.channel_mut(channel) {
+            channel.remove(nick, case);
+        }
+    }
+
+    fn channel(&self, name: &str) -> Option<&Channel> {
+        let case = self.isupport.casemapping();
+        self.channels.iter().find(|channel| case.eq(&channel.names.channel, name))
+    }
+
+    fn channel_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        let case = self.isupport.casemapping();
+        self.channels.iter_mut().find(|channel| case.eq(&channel.names.channel, name))
+    }
+
+    fn sent_by_me(&self, message: Message) -> Message {
+        Message { prefix: Prefix::User(self.me.clone()), ..message }
+    }
+
+    // A numeric from the server, addressed to us.
+    fn reply(&self, command: Command, arguments: Vec<&str>) -> Message {
+        let mut all = vec![self.nickname.as_str()];
+        all.extend(arguments);
+        Message::from_strs(self.server.clone(), command, all)
+    }
+
+    // As many RPL_NAMREPLY lines as it takes to list everyone without going over the line length,
+    // then RPL_ENDOFNAMES. Only each member's highest rank is shown, since the client can't have
+    // asked for multi-prefix.
+    fn names_replies(&self, names: &ChannelNames) -> Vec<Message> {
+        let visibility = match names.visibility {
+            Some(ChannelVisibility::Secret) => "@",
+            Some(ChannelVisibility::Private) => "*",
+            _ => "=",
+        };
+        let prefixes = self.isupport.prefix();
+        let empty = self.reply(responses::RPL_NAMREPLY(), vec![visibility, &names.channel, ""]);
+        let mut replies = Vec::new();
+        let mut line = String::new();
+        for member in &names.members {
+            let mut entry = String::new();
+            let rank = member.ranks.first();
+            entry.extend(rank.and_then(|rank| prefixes.prefix_for_mode(rank.mode())));
+            entry.push_str(&member.nick);
+            let full = empty.line_len() + line.len() + 1 + entry.len() > MAX_LINE_LENGTH;
+            if !line.is_empty() && full {
+                replies.push(self.reply(responses::RPL_NAMREPLY(),
+                                        vec![visibility, &names.channel, &line]));
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&entry);
+        }
+        if !line.is_empty() {
+            replies.push(self.reply(responses::RPL_NAMREPLY(),
+                                    vec![visibility, &names.channel, &line]));
+        }
+        replies.push(self.reply(responses::RPL_ENDOFNAMES(),
+                                vec![&names.channel, "End of /NAMES list"]));
+        replies
+    }
+}
+
+impl Channel {
+    fn new(name: &str) -> Self {
+        Channel {
+            names: ChannelNames {
+                channel: name.into(),
+                visibility: None,
+                members: Vec::new(),
+            },
+            topic: None,
+            topic_set: None,
+        }
+    }
+
+    fn remove(&mut self, nick: &str, case: IrcCase) {
+        self.names.members.retain(|member| !case.eq(&member.nick, nick));
+    }
+}
+
+impl<U: Read + Write, D: Read + Write> Bouncer<U, D> {
+    /// Takes over a registered connection. PINGs from the server will be answered from here on.
+    pub fn new(upstream: IrcStream<U>, registered: &Registered) -> Self {
+        Bouncer {
+            upstream: upstream.with_auto_pong(),
+            state: BouncerState::new(registered),
+            password: None,
+            clients: Vec::new(),
+        }
+    }
+
+    /// The password clients have to send with `PASS` to attach. Without one, anyone who can
+    /// connect can use the bouncer.
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn state(&self) -> &BouncerState {
+        &self.state
+    }
+
+    /// The connection to the server, for sending messages of our own.
+    pub fn upstream(&mut self) -> &mut IrcStream<U> {
+        &mut self.upstream
+    }
+
+    /// How many clients are attached, including ones still registering.
+    pub fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Adds a newly-connected client. It's sent the state once it has registered, during `poll`.
+    ///
+    /// Lines from the client which can't be parsed are skipped. Any other error reading from it
+    /// detaches it.
+    pub fn attach(&mut self, client: IrcStream<D>) {
+        self.clients.push(Downstream {
+            irc: client.with_auto_pong().with_parse_policy(ParsePolicy::SkipWithWarning),
+            registering: Some(Pending::default()),
+        });
+    }
+
+    /// Reads everything waiting from each client and from the server, until their reads time out,
+    /// and passes it on. Returns the messages from the server.
+    ///
+    /// Clients are detached if their connection fails, but failing to read from or write to the
+    /// server is returned as an error.
+    pub fn poll(&mut self) -> io::Result<Vec<Message>> {
+        let mut i = 0;
+        while i < self.clients.len() {
+            match self.poll_client(i) {
+                Ok(true) => i += 1,
+                Ok(false) => {
+                    self.clients.remove(i);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut messages = Vec::new();
+        loop {
+            let message = match self.upstream.next_message() {
+                Ok(message) => message,
+                Err(ref e) if is_timeout(e) => return Ok(messages),
+                Err(e) => return Err(e),
+            };
+            self.state.handle(&message);
+            self.relay(&message.clone().with_tags(Vec::new()), None);
+            messages.push(message);
+        }
+    }
+
+    // Handles everything a client has sent. Returns whether it's still attached, or an error if
+    // passing a message on to the server failed.
+    fn poll_client(&mut self, i: usize) -> io::Result<bool> {
+        loop {
+            let message = match self.clients[i].irc.next_message() {
+                Ok(message) => message,
+                Err(ref e) if is_timeout(e) => return Ok(true),
+                Err(e) => {
+                    info!("Client detached: {}", e);
+                    return Ok(false);
+                }
+            };
+            if !self.handle_client(i, message)? {
+                return Ok(false);
+            }
+        }
+    }
+
+    // Handles a message from a client. Returns whether it's still attached, or an error if passing
+    // the message on to the server failed.
+    fn handle_client(&mut self, i: usize, message: Message) -> io::Result<bool> {
+        let command = &message.command;
+        if *command == commands::CAP() {
+            return Ok(self.negotiate(i, &message));
+        }
+        if self.clients[i].registering.is_some() {
+            return Ok(self.register(i, &message));
+        }
+        if *command == commands::QUIT() {
+            info!("Client detached");
+            return Ok(false);
+        }
+        if *command == commands::PASS() || *command == commands::USER() ||
+           *command == commands::PONG() {
+            return Ok(true);
+        }
+
+        match self.upstream.send(&message) {
+            Ok(()) => (),
+            Err(ref e) if e.kind() == ErrorKind::InvalidInput => {
+                warn!("Not relaying message from client: {}", e);
+                return Ok(true);
+            }
+            Err(e) => return Err(e),
+        }
+        if *command == commands::PRIVMSG() || *command == commands::NOTICE() ||
+           *command == commands::TAGMSG() {
+            let message = self.state.sent_by_me(message.with_tags(Vec::new()));
+            self.relay(&message, Some(i));
+        }
+        Ok(true)
+    }
+
+    // Answers a client's capability negotiation, turning down everything it asks for.
+    fn negotiate(&mut self, i: usize, message: &Message) -> bool {
+        let server = self.state.server.clone();
+        let client = &mut self.clients[i];
+        let nick = match client.registering {
+            Some(_) => "*".to_string(),
+            None => self.state.nickname.clone(),
+        };
+        let reply = |arguments: Vec<&str>| {
+            let mut all = vec![nick.as_str()];
+            all.extend(arguments);
+            Message::from_strs(server.clone(), commands::CAP(), all)
+        };
+        let result = match message.arguments.first().map(|s| s.as_str()) {
+            Some("LS") | Some("LIST") => {
+                client.start_negotiating();
+                client.irc.send(&reply(vec![&message.arguments[0], ""]))
+            }
+            Some("REQ") => {
+                client.start_negotiating();
+                let requested = message.arguments.get(1).map_or("", |r| r);
+                client.irc.send(&reply(vec!["NAK", requested]))
+            }
+            Some("END") => {
+                if let Some(ref mut pending) = client.registering {
+                    pending.negotiating = false;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        result.is_ok() && self.finish_registering(i)
+    }
+
+    // Follows a client's registration. Returns whether it's still attached.
+    fn register(&mut self, i: usize, message: &Message) -> bool {
+        if let Some(ref mut pending) = self.clients[i].registering {
+            let arguments = &message.arguments;
+            if message.command == commands::PASS() {
+                pending.password = arguments.first().cloned();
+            } else if message.command == commands::NICK() {
+                pending.nickname = arguments.first().cloned();
+            } else if message.command == commands::USER() {
+                pending.user = true;
+            }
+        }
+        self.finish_registering(i)
+    }
+
+    // Once a client has told us everything we need, checks its password and brings it up to date.
+    // Returns whether it's still attached.
+    fn finish_registering(&mut self, i: usize) -> bool {
+        let pending = match self.clients[i].registering {
+            Some(ref pending) if pending.nickname.is_some() && pending.user &&
+                                 !pending.negotiating => pending,
+            _ => return true,
+        };
+        if self.password.is_some() && pending.password != self.password {
+            let nick = pending.nickname.clone().unwrap_or_default();
+            let refusal = Message::from_strs(self.state.server.clone(),
+                                             responses::ERR_PASSWDMISMATCH(),
+                                             vec![&nick, "Password incorrect"]);
+            let error = Message::from_strs(Prefix::None,
+                                           commands::ERROR(),
+                                           vec!["Closing link: Password incorrect"]);
+            let _ = self.clients[i].irc.send_all(&[refusal, error]);
+            info!("Client gave the wrong password");
+            return false;
+        }
+        let client = &mut self.clients[i];
+        client.registering = None;
+        info!("Client attached");
+        client.irc.send_all(&self.state.replay()).is_ok()
+    }
+
+    // Sends a message to every registered client, other than `except`. Clients which can't be
+    // written to are detached the next time they're read from.
+    fn relay(&mut self, message: &Message, except: Option<usize>) {
+        for (i, client) in self.clients.iter_mut().enumerate() {
+            if client.registering.is_some() || Some(i) == except {
+                continue;
+            }
+            if let Err(e) = client.irc.send(message) {
+                warn!("Couldn't relay to client: {}", e);
+            }
+        }
+    }
+}
+
+impl<D: Read + Write> Downstream<D> {
+    fn start_negotiating(&mut self) {
+        if let Some(ref mut pending) = self.registering {
+            pending.negotiating = true;
+        }
+    }
+}
+
+// The numerics sent on registering, which make up the welcome burst.
+fn is_welcome(command: &Command) -> bool {
+    match *command {
+        Command::Number(number) => {
+            (1..=5).contains(&number) || (251..=255).contains(&number) || is_motd(command)
+        }
+        _ => false,
+    }
+}
+
+fn is_motd(command: &Command) -> bool {
+    *command == responses::RPL_MOTDSTART() || *command == responses::RPL_MOTD() ||
+    *command == responses::RPL_ENDOFMOTD() || *command == responses::ERR_NOMOTD()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use cap::CapNegotiator;
+
+    #[test]
+    fn state_follows_channels() {
+        let mut state = BouncerState::new(&registered());
+        for line in &[":irc.example.net 005 me PREFIX=(ov)@+ :are supported by this server\r\n",
+                      ":irc.example.net 375 me :- MOTD -\r\n",
+                      ":irc.example.net 372 me :- Hello\r\n",
+                      ":irc.example.net 376 me :End of MOTD\r\n",
+                      ":irc.example.net 221 me +i\r\n",
+                      ":me!user@host JOIN #chan\r\n",
+                      ":irc.example.net 332 me #chan :Welcome\r\n",
+                      ":irc.example.net 333 me #chan op 1700000000\r\n",
+                      ":irc.example.net 353 me = #chan :@op me +alice\r\n",
+                      ":irc.example.net 366 me #chan :End of /NAMES list\r\n",
+                      ":me!user@host JOIN #other\r\n",
+                      ":bob!bob@host JOIN #chan\r\n",
+                      ":alice!alice@host NICK carol\r\n",
+                      ":op!op@host MODE #chan +o-v carol carol\r\n",
+                      ":op!op@host KICK #chan bob\r\n",
+                      ":me!user@host PART #other\r\n",
+                      ":me!user@host NICK me_\r\n"] {
+            state.handle(&message(line));
+        }
+
+        assert_eq!(state.nickname(), "me_");
+        assert_eq!(state.channels(), vec!["#chan"]);
+        let members = state.members("#CHAN").unwrap();
+        let nicks: Vec<&str> = members.members.iter().map(|m| m.nick.as_str()).collect();
+        assert_eq!(nicks, vec!["op", "me_", "carol"]);
+        assert_eq!(members.member("carol").unwrap().ranks, vec![Membership::Op]);
+
+        let replay: Vec<String> = state.replay().iter().map(|m| m.to_string()).collect();
+        assert_eq!(replay,
+                   vec![":irc.example.net 001 me_ Welcome",
+                        ":irc.example.net 005 me_ PREFIX=(ov)@+ :are supported by this server",
+                        ":irc.example.net 375 me_ :- MOTD -",
+                        ":irc.example.net 372 me_ :- Hello",
+                        ":irc.example.net 376 me_ :End of MOTD",
+                        ":me_!user@host MODE me_ +i",
+                        ":me_!user@host JOIN #chan",
+                        ":irc.example.net 332 me_ #chan Welcome",
+                        ":irc.example.net 333 me_ #chan op 1700000000",
+                        ":irc.example.net 353 me_ = #chan :@op me_ @carol",
+                        ":irc.example.net 366 me_ #chan :End of /NAMES list"]);
+
+        // Registering again starts over.
+        state.handle(&message(":irc.example.net 001 me :Welcome back\r\n"));
+        assert!(state.channels().is_empty());
+        assert_eq!(state.replay().len(), 1);
+    }
+
+    #[test]
+    fn long_names_are_split() {
+        let mut state = BouncerState::new(&registered());
+        state.handle(&message(":me!user@host JOIN #chan\r\n"));
+        let names: Vec<String> = (0..100).map(|i| format!("someone{}", i)).collect();
+        state.handle(&message(&format!(":irc.example.net 353 me = #chan :{}\r\n",
+                                       names.join(" "))));
+        state.handle(&message(":irc.example.net 366 me #chan :End of /NAMES list\r\n"));
+
+        let replies: Vec<Message> = state.replay()
+            .into_iter()
+            .filter(|m| m.command == responses::RPL_NAMREPLY())
+            .collect();
+        assert!(replies.len() > 1);
+        assert!(replies.iter().all(|reply| reply.line_len() <= MAX_LINE_LENGTH));
+        let relisted: Vec<&str> =
+            replies.iter().flat_map(|reply| reply.arguments[3].split(' ')).collect();
+        assert_eq!(relisted, names);
+    }
+
+    #[test]
+    fn relays_between_clients_and_server() {
+        let upstream = Pipe::default();
+        let mut bouncer = Bouncer::new(IrcStream::new(upstream.clone()), &registered())
+            .with_password("hunter2");
+        upstream.push(":me!user@host JOIN #chan\r\n");
+        bouncer.poll().unwrap();
+
+        let first = Pipe::default();
+        bouncer.attach(IrcStream::new(first.clone()));
+        first.push("CAP LS 302\r\nPASS hunter2\r\nNICK me\r\nUSER me 0 * :Me\r\n");
+        first.push("CAP REQ :server-time\r\nCAP END\r\n");
+        bouncer.poll().unwrap();
+        assert_eq!(first.written(),
+                   ":irc.example.net CAP * LS :\r\n\
+                    :irc.example.net CAP * NAK server-time\r\n\
+                    :irc.example.net 001 me Welcome\r\n\
+                    :me!user@host JOIN #chan\r\n\
+                    :irc.example.net 366 me #chan :End of /NAMES list\r\n");
+
+        let second = Pipe::default();
+        bouncer.attach(IrcStream::new(second.clone()));
+        second.push("PASS hunter2\r\nNICK other\r\nUSER me 0 * :Me\r\n");
+        bouncer.poll().unwrap();
+        assert!(second.written().starts_with(":irc.example.net 001 me Welcome\r\n"));
+
+        // What one client says goes to the server, and the other client sees it too.
+        first.push("@+draft/reply=1 PRIVMSG #chan :Hi\r\nPING 123\r\n");
+        bouncer.poll().unwrap();
+        assert_eq!(upstream.written(), "@+draft/reply=1 PRIVMSG #chan Hi\r\n");
+        assert_eq!(first.written(), "PONG 123\r\n");
+        assert_eq!(second.written(), ":me!user@host PRIVMSG #chan Hi\r\n");
+
+        // What the server says goes to everyone, without tags.
+        upstream.push("@time=2023-01-01T00:00:00.000Z :alice!a@host PRIVMSG #chan :Hello\r\n");
+        let received = bouncer.poll().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].as_privmsg().unwrap().text, "Hello");
+        assert_eq!(first.written(), ":alice!a@host PRIVMSG #chan Hello\r\n");
+        assert_eq!(second.written(), ":alice!a@host PRIVMSG #chan Hello\r\n");
+
+        // Quitting detaches rather than disconnecting us.
+        second.push("QUIT :Bye\r\n");
+        bouncer.poll().unwrap();
+        assert_eq!(bouncer.clients(), 1);
+        assert_eq!(upstream.written(), "");
+    }
+
+    #[test]
+    fn relays_everything_waiting() {
+        let upstream = Pipe::default();
+        let mut bouncer = Bouncer::new(IrcStream::new(upstream.clone()), &registered());
+        let client = Pipe::default();
+        bouncer.attach(IrcStream::new(client.clone()));
+        client.push("NICK me\r\nUSER me 0 * :Me\r\n");
+        bouncer.poll().unwrap();
+        client.written();
+
+        let lines: Vec<String> =
+            (0..50).map(|i| format!(":alice!a@host PRIVMSG #chan {}\r\n", i)).collect();
+        upstream.push(&lines.concat());
+        client.push("PRIVMSG #chan :Hi\r\nPRIVMSG #chan :Again\r\n");
+
+        assert_eq!(bouncer.poll().unwrap().len(), 50);
+        assert_eq!(client.written(), lines.concat());
+        assert_eq!(upstream.written(), "PRIVMSG #chan Hi\r\nPRIVMSG #chan Again\r\n");
+    }
+
+    #[test]
+    fn broken_clients_are_detached() {
+        let mut bouncer = Bouncer::new(IrcStream::new(Pipe::default()), &registered());
+        let broken = Pipe::default();
+        bouncer.attach(IrcStream::new(broken.clone()));
+        let working = Pipe::default();
+        bouncer.attach(IrcStream::new(working.clone()));
+
+        broken.push("!!!\r\nNICK me\r\nUSER me 0 * :Me\r\n");
+        working.push("NICK me\r\nUSER me 0 * :Me\r\n");
+        bouncer.poll().unwrap();
+        assert!(broken.written().starts_with(":irc.example.net 001 me Welcome\r\n"));
+        assert_eq!(bouncer.clients(), 2);
+
+        broken.fail();
+        bouncer.poll().unwrap();
+        assert_eq!(bouncer.clients(), 1);
+        assert!(working.written().starts_with(":irc.example.net 001 me Welcome\r\n"));
+    }
+
+    #[test]
+    fn wrong_password_is_refused() {
+        let mut bouncer = Bouncer::new(IrcStream::new(Pipe::default()), &registered())
+            .with_password("hunter2");
+        let client = Pipe::default();
+        bouncer.attach(IrcStream::new(client.clone()));
+        client.push("PASS guess\r\nNICK me\r\nUSER me 0 * :Me\r\n");
+        bouncer.poll().unwrap();
+
+        assert_eq!(bouncer.clients(), 0);
+        assert_eq!(client.written(),
+                   ":irc.example.net 464 me :Password incorrect\r\n\
+                    ERROR :Closing link: Password incorrect\r\n");
+    }
+
+    // Like a socket with a read timeout: reads time out when there's nothing to read yet.
+    #[derive(Clone, Default)]
+    struct Pipe {
+        input: Rc<RefCell<VecDeque<u8>>>,
+        output: Rc<RefCell<Vec<u8>>>,
+        failed: Rc<Cell<bool>>,
+    }
+
+    impl Pipe {
+        fn push(&self, lines: &str) {
+            self.input.borrow_mut().extend(lines.bytes());
+        }
+
+        // Makes every read fail, like a TLS stream which has hit a fatal error.
+        fn fail(&self) {
+            self.failed.set(true);
+        }
+
+        fn written(&self) -> String {
+            String::from_utf8(self.output.borrow_mut().split_off(0)).unwrap()
+        }
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.failed.get() {
+                return Err(io::Error::new(ErrorKind::InvalidData, "Bad record MAC"));
+            }
+            let mut input = self.input.borrow_mut();
+            if input.is_empty() {
+                return Err(io::Error::new(ErrorKind::WouldBlock, "Timed out"));
+            }
+            input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn registered() -> Registered {
+        Registered {
+            nickname: "me".into(),
+            capabilities: CapNegotiator::new(),
+            welcome: message(":irc.example.net 001 me :Welcome\r\n"),
+        }
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use command::responses;
+use irc_stream::IrcStream;
+use message::Message;
+
+/// Tracks the state of IRCv3 capability negotiation.
+///
+/// The simplest way to use this is `negotiate`, which drives the whole exchange over an
+/// `IrcStream`. If you'd rather read messages yourself, feed every received message to `handle`
+/// and send the messages from `ls_message`, `request` and `end_message` at the appropriate time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapNegotiator {
+    available: BTreeMap<String, Option<String>>,
+    ls_complete: bool,
+    pending: BTreeSet<String>,
+    acked: BTreeSet<String>,
+    naked: BTreeSet<String>,
+}
+
+impl CapNegotiator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The message which starts negotiation. We always ask for version 302 so servers will send
+    /// capability values and multi-line replies.
+    pub fn ls_message() -> Message {
+        Message::cap_ls(Some("302"))
+    }
+
+    pub fn end_message() -> Message {
+        Message::cap_end()
+    }
+
+    /// Updates state from a received message. Returns `true` if the message was a CAP message
+    /// and has been consumed.
+    ///
+    /// An `ERR_UNKNOWNCOMMAND` for `CAP` is consumed too. The server doesn't support capabilities,
+    /// so the listing is complete and empty.
+    pub fn handle(&mut self, message: &Message) -> bool {
+        if message.command == responses::ERR_UNKNOWNCOMMAND() &&
+           message.arguments.get(1).map(|command| command.as_str()) == Some("CAP") {
+            debug!("Server doesn't support CAP: {}", message);
+            self.ls_complete = true;
+            return true;
+        }
+
+        let cap = match message.as_cap() {
+            Some(cap) => cap,
+            None => return false,
+        };
+
+        match cap.subcommand {
+            "LS" => {
+                for (name, value) in cap.capabilities() {
+                    self.available.insert(name.into(), value.map(|v| v.into()));
+                }
+                if !cap.continues {
+                    self.ls_complete = true;
+                }
+            }
+            "NEW" => {
+                for (name, value) in cap.capabilities() {
+                    self.available.insert(name.into(), value.map(|v| v.into()));
+                }
+            }
+            "DEL" => {
+                for (name, _) in cap.capabilities() {
+                    self.available.remove(name);
+                    self.acked.remove(name);
+                }
+            }
+            "ACK" => {
+                for (name, _) in cap.capabilities() {
+                    if let Some(disabled) = name.strip_prefix('-') {
+                        self.pending.remove(disabled);
+                        self.acked.remove(disabled);
+                    } else {
+                        self.pending.remove(name);
+                        self.acked.insert(name.into());
+                    }
+                }
+            }
+            "NAK" => {
+                for (name, _) in cap.capabilities() {
+                    let name = name.trim_start_matches('-');
+                    self.pending.remove(name);
+                    self.naked.insert(name.into());
+                }
+            }
+            other => debug!("Ignoring CAP {} message: {}", other, message),
+        }
+
+        true
+    }
+
+    /// Builds a `CAP REQ` for whichever of `capabilities` the server has advertised, and marks them
+    /// as pending. Returns `None` if the server supports none of them.
+    pub fn request(&mut self, capabilities: &[&str]) -> Option<Message> {
+        let supported: Vec<&str> = capabilities.iter()
+            .cloned()
+            .filter(|cap| self.available.contains_key(*cap))
+            .collect();
+
+        if supported.is_empty() {
+            return None;
+        }
+
+        for cap in &supported {
+            self.pending.insert(cap.to_string());
+        }
+        Some(Message::cap_req(&supported))
+    }
+
+    /// Whether the server has finished listing its capabilities.
+    pub fn ls_complete(&self) -> bool {
+        self.ls_complete
+    }
+
+    /// Whether every requested capability has been ACKed or NAKed.
+    pub fn requests_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// All capabilities the server has advertised, with their values.
+    pub fn available(&self) -> &BTreeMap<String, Option<String>> {
+        &self.available
+    }
+
+    /// The value the server advertised for a capability, e.g. the list of mechanisms for `sasl`.
+    pub fn value(&self, capability: &str) -> Option<&str> {
+        self.available.get(capability).and_then(|value| value.as_deref())
+    }
+
+    pub fn acked(&self) -> &BTreeSet<String> {
+        &self.acked
+    }
+
+    pub fn naked(&self) -> &BTreeSet<String> {
+        &self.naked
+    }
+
+    pub fn is_enabled(&self, capability: &str) -> bool {
+        self.acked.contains(capability)
+    }
+
+    /// Lists the server's capabilities and requests whichever of `wanted` it supports, blocking
+    /// until they have all been ACKed or NAKed.
+    ///
+    /// This does not end negotiation, so there's a chance to do things like SASL first. Call `end`
+    /// once you're done.
+    ///
+    /// PINGs received in the meantime are answered; any other non-CAP messages are logged and
+    /// dropped.
+    ///
+    /// Servers which don't support capabilities either reject `CAP` or ignore it and complete
+    /// registration. Either way this returns without error and with nothing enabled. The
+    /// `RPL_WELCOME` is dropped along with everything else.
+    pub fn negotiate<S: Read + Write>(&mut self,
+                                      irc: &mut IrcStream<S>,
+                                      wanted: &[&str])
+                                      -> io::Result<()> {
+        irc.send(&Self::ls_message())?;
+        while !self.ls_complete {
+            if !self.handle_next(irc)? {
+                return Ok(());
+            }
+        }
+
+        if let Some(request) = self.request(wanted) {
+            irc.send(&request)?;
+        }
+        while !self.requests_complete() {
+            if !self.handle_next(irc)? {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ends capability negotiation, allowing registration to complete.
+    pub fn end<S: Read + Write>(&mut self, irc: &mut IrcStream<S>) -> io::Result<()> {
+        irc.send(&Self::end_message())
+    }
+
+    // Handles a message received during `negotiate`. Returns `false` if we've been welcomed, so
+    // there's nothing left to negotiate.
+    fn handle_next<S: Read + Write>(&mut self, irc: &mut IrcStream<S>) -> io::Result<bool> {
+        let message = irc.next_message()?;
+        if self.handle(&message) {
+            return Ok(true);
+        }
+
+        if message.command == responses::RPL_WELCOME() {
+            debug!("Registered during CAP negotiation: {}", message);
+            self.ls_complete = true;
+            self.pending.clear();
+            Ok(false)
+        } else if let Some(ping) = message.as_ping() {
+            irc.send(&ping.pong())?;
+            Ok(true)
+        } else {
+            debug!("Dropping message received during CAP negotiation: {}", message);
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc_stream::ScriptedStream;
+    use message::Message;
+
+    #[test]
+    fn multi_line_ls() {
+        let mut negotiator = CapNegotiator::new();
+
+        assert!(negotiator.handle(&message(":server CAP * LS * :multi-prefix sasl=PLAIN\r\n")));
+        assert!(!negotiator.ls_complete());
+        assert!(negotiator.handle(&message(":server CAP * LS :away-notify\r\n")));
+        assert!(negotiator.ls_complete());
+
+        assert_eq!(negotiator.available().keys().collect::<Vec<_>>(),
+                   vec!["away-notify", "multi-prefix", "sasl"]);
+        assert_eq!(negotiator.value("sasl"), Some("PLAIN"));
+    }
+
+    #[test]
+    fn request_only_available() {
+        let mut negotiator = CapNegotiator::new();
+        negotiator.handle(&message(":server CAP * LS :multi-prefix\r\n"));
+
+        assert_eq!(negotiator.request(&["multi-prefix", "sasl"]),
+                   Some(Message::cap_req(&["multi-prefix"])));
+        assert!(!negotiator.requests_complete());
+        assert_eq!(negotiator.request(&["sasl"]), None);
+    }
+
+    #[test]
+    fn ack_and_nak() {
+        let mut negotiator = CapNegotiator::new();
+        negotiator.handle(&message(":server CAP * LS :multi-prefix sasl away-notify\r\n"));
+        negotiator.request(&["multi-prefix", "sasl", "away-notify"]);
+
+        negotiator.handle(&message(":server CAP * ACK :multi-prefix sasl\r\n"));
+        assert!(!negotiator.requests_complete());
+        negotiator.handle(&message(":server CAP * NAK :away-notify\r\n"));
+        assert!(negotiator.requests_complete());
+
+        assert!(negotiator.is_enabled("sasl"));
+        assert!(!negotiator.is_enabled("away-notify"));
+        assert!(negotiator.naked().contains("away-notify"));
+    }
+
+    #[test]
+    fn not_cap() {
+        let mut negotiator = CapNegotiator::new();
+        assert!(!negotiator.handle(&message("PING 123\r\n")));
+    }
+
+    #[test]
+    fn negotiate() {
+        let input = b":server NOTICE * :*** Looking up your hostname\r\n\
+                      :server CAP * LS * :multi-prefix\r\n\
+                      PING 123\r\n\
+                      :server CAP * LS :sasl\r\n\
+                      :server CAP * ACK :sasl\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        let mut negotiator = CapNegotiator::new();
+
+        negotiator.negotiate(&mut irc, &["sasl", "account-tag"]).unwrap();
+
+        assert_eq!(irc.sent(), "CAP LS 302\r\nPONG 123\r\nCAP REQ sasl\r\n");
+        assert!(negotiator.is_enabled("sasl"));
+        assert!(!negotiator.is_enabled("multi-prefix"));
+    }
+
+    #[test]
+    fn negotiate_without_cap_support() {
+        let input = b":server 421 * CAP :Unknown command\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        let mut negotiator = CapNegotiator::new();
+
+        negotiator.negotiate(&mut irc, &["sasl"]).unwrap();
+        assert_eq!(irc.sent(), "CAP LS 302\r\n");
+        assert!(negotiator.ls_complete());
+        assert!(negotiator.available().is_empty());
+
+        // Or the server ignores CAP and welcomes us.
+        let input = b":server NOTICE * :*** Looking up your hostname\r\n\
+                      :server 001 bot :Welcome to the network\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        let mut negotiator = CapNegotiator::new();
+
+        negotiator.negotiate(&mut irc, &["sasl"]).unwrap();
+        assert!(negotiator.ls_complete());
+        assert!(!negotiator.is_enabled("sasl"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
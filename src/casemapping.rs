@@ -0,0 +1,155 @@
+use std::borrow::Borrow;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+/// How a server compares nicknames and channel names, from the `CASEMAPPING` ISUPPORT token.
+///
+/// Besides ASCII letters, `rfc1459` treats `{}|^` as the lowercase forms of `[]\~`, since that's
+/// how they were laid out on Scandinavian keyboards. `strict-rfc1459` is the same without `^`/`~`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IrcCase {
+    Ascii,
+    Rfc1459,
+    StrictRfc1459,
+}
+
+/// A nickname or channel name which compares (and hashes) case-insensitively, for use as a map
+/// key. It remembers the original form too.
+#[derive(Debug, Clone)]
+pub struct IrcString {
+    value: String,
+    folded: String,
+}
+
+impl Default for IrcCase {
+    /// `rfc1459`, which servers are meant to assume if they don't say otherwise.
+    fn default() -> Self {
+        IrcCase::Rfc1459
+    }
+}
+
+impl IrcCase {
+    /// Looks up a casemapping by its name in `CASEMAPPING`. Returns `None` for ones we don't
+    /// support, like `rfc7613`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(IrcCase::Ascii),
+            "rfc1459" => Some(IrcCase::Rfc1459),
+            "strict-rfc1459" => Some(IrcCase::StrictRfc1459),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            IrcCase::Ascii => "ascii",
+            IrcCase::Rfc1459 => "rfc1459",
+            IrcCase::StrictRfc1459 => "strict-rfc1459",
+        }
+    }
+
+    pub fn to_lower_char(&self, c: char) -> char {
+        match (*self, c) {
+            (_, 'A'..='Z') => c.to_ascii_lowercase(),
+            (IrcCase::Ascii, _) => c,
+            (_, '[') => '{',
+            (_, ']') => '}',
+            (_, '\\') => '|',
+            (IrcCase::Rfc1459, '~') => '^',
+            _ => c,
+        }
+    }
+
+    pub fn to_lower(&self, s: &str) -> String {
+        s.chars().map(|c| self.to_lower_char(c)).collect()
+    }
+
+    pub fn eq(&self, a: &str, b: &str) -> bool {
+        a.len() == b.len() &&
+        a.chars().zip(b.chars()).all(|(a, b)| self.to_lower_char(a) == self.to_lower_char(b))
+    }
+
+    /// Makes a map key for `s`.
+    pub fn key(&self, s: &str) -> IrcString {
+        IrcString {
+            value: s.into(),
+            folded: self.to_lower(s),
+        }
+    }
+}
+
+impl IrcString {
+    /// The string as it was given.
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+
+    /// The string in lowercase, which is what it's compared by.
+    pub fn folded(&self) -> &str {
+        &self.folded
+    }
+}
+
+impl PartialEq for IrcString {
+    fn eq(&self, other: &Self) -> bool {
+        self.folded == other.folded
+    }
+}
+
+impl Eq for IrcString {}
+
+impl Hash for IrcString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.folded.hash(state)
+    }
+}
+
+impl Display for IrcString {
+    fn fmt(&self, fmt: &mut Formatter) -> ::std::fmt::Result {
+        self.value.fmt(fmt)
+    }
+}
+
+impl Borrow<str> for IrcString {
+    /// Borrows the folded form, so maps can be looked up with `IrcCase::to_lower`.
+    fn borrow(&self) -> &str {
+        &self.folded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn casemappings() {
+        assert!(IrcCase::Rfc1459.eq("Nick[away]^", "nick{AWAY}~"));
+        assert!(IrcCase::StrictRfc1459.eq("Nick[away]\\", "nick{AWAY}|"));
+        assert!(!IrcCase::StrictRfc1459.eq("nick^", "nick~"));
+        assert!(IrcCase::Ascii.eq("NICK", "nick"));
+        assert!(!IrcCase::Ascii.eq("nick[]", "nick{}"));
+        assert!(!IrcCase::Rfc1459.eq("nick", "nick_"));
+        assert_eq!(IrcCase::Rfc1459.to_lower("ÉNick[]"), "Énick{}");
+    }
+
+    #[test]
+    fn names() {
+        for case in &[IrcCase::Ascii, IrcCase::Rfc1459, IrcCase::StrictRfc1459] {
+            assert_eq!(IrcCase::from_name(case.name()), Some(*case));
+        }
+        assert_eq!(IrcCase::from_name("rfc7613"), None);
+    }
+
+    #[test]
+    fn keys() {
+        let mut map = HashMap::new();
+        map.insert(IrcCase::Rfc1459.key("Nick[1]"), 1);
+
+        assert_eq!(map.get(&IrcCase::Rfc1459.key("nick{1}")), Some(&1));
+        assert_eq!(map.get("nick{1}"), Some(&1));
+        assert_eq!(map.keys().next().unwrap().as_str(), "Nick[1]");
+    }
+}
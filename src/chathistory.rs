@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use batch::Batch;
+use batch::BatchTracker;
+use batch::BatchType;
+use batch::Batched;
+use casemapping::IrcCase;
+use command::commands;
+use irc_stream::IrcStream;
+use message::Message;
+use server_time::ServerTime;
+
+/// A message from a channel's history, with the time the server says it was originally sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryMessage {
+    pub time: Option<ServerTime>,
+    pub message: Message,
+}
+
+/// Fetches history using the IRCv3 `chathistory` extension. The `batch` and `server-time`
+/// capabilities need to be enabled for this to work.
+///
+/// `fetch` blocks until the history arrives. PINGs received in the meantime are answered, and
+/// anything else unrelated is kept and can be fetched with `take_other`.
+#[derive(Debug, Default)]
+pub struct HistoryFetcher {
+    case: IrcCase,
+    batches: BatchTracker,
+    other: VecDeque<Batched>,
+}
+
+impl HistoryFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how channel names and nicknames are compared, from the server's `CASEMAPPING` ISUPPORT
+    /// token. Defaults to `rfc1459`.
+    pub fn set_casemapping(&mut self, case: IrcCase) {
+        self.case = case;
+    }
+
+    /// Sends `request` (built with one of the `Message::chathistory_*` functions) and returns the
+    /// messages the server sends back, oldest first.
+    ///
+    /// Returns an error if the server replies with `FAIL CHATHISTORY`.
+    pub fn fetch<S: Read + Write>(&mut self,
+                                  irc: &mut IrcStream<S>,
+                                  request: &Message)
+                                  -> io::Result<Vec<HistoryMessage>> {
+        let target = request.arguments.get(1).cloned().unwrap_or_default();
+        irc.send(request)?;
+
+        loop {
+            let message = irc.next_message()?;
+            if let Some(ping) = message.as_ping() {
+                irc.send(&ping.pong())?;
+                continue;
+            }
+            if message.command == commands::FAIL() &&
+               message.arguments.first().map(|c| c.as_str()) == Some("CHATHISTORY") {
+                return Err(io::Error::other(format!("CHATHISTORY request failed: {}", message)));
+            }
+
+            match self.batches.push(message) {
+                Some(Batched::Batch(ref batch)) if is_history_for(batch, &target, self.case) => {
+                    return Ok(batch.history());
+                }
+                Some(other) => self.other.push_back(other),
+                None => (),
+            }
+        }
+    }
+
+    /// Takes the oldest unrelated message or batch received during a `fetch`.
+    pub fn take_other(&mut self) -> Option<Batched> {
+        self.other.pop_front()
+    }
+}
+
+impl Batch {
+    /// Pulls the messages out of a `chathistory` batch in the order the server sent them, which
+    /// the spec says is oldest first. Messages from nested batches are included in place.
+    pub fn history(&self) -> Vec<HistoryMessage> {
+        let mut messages = Vec::new();
+        collect(self, &mut messages);
+        messages
+    }
+}
+
+fn collect(batch: &Batch, into: &mut Vec<HistoryMessage>) {
+    for batched in &batch.messages {
+        match *batched {
+            Batched::Message(ref message) => {
+                into.push(HistoryMessage {
+                    time: message.server_time(),
+                    message: message.clone(),
+                })
+            }
+            Batched::Batch(ref nested) => collect(nested, into),
+        }
+    }
+}
+
+fn is_history_for(batch: &Batch, target: &str, case: IrcCase) -> bool {
+    batch.batch_type == BatchType::ChatHistory &&
+    batch.parameters.first().map(|t| case.eq(t, target)).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc_stream::ScriptedStream;
+    use message::Message;
+
+    #[test]
+    fn fetch() {
+        let input = b"PING 123\r\n\
+                      :irc.host BATCH +h chathistory #chan\r\n\
+                      @batch=h;time=2019-01-04T14:33:26.123Z :alice!a@host PRIVMSG #chan :Hi\r\n\
+                      @batch=h;time=2019-01-04T14:34:00.000Z :bob!b@host PRIVMSG #chan :Hey\r\n\
+                      :irc.host BATCH -h\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        let mut fetcher = HistoryFetcher::new();
+
+        let history = fetcher.fetch(&mut irc, &Message::chathistory_latest("#chan", None, 50))
+            .unwrap();
+
+        assert_eq!(irc.sent(), "CHATHISTORY LATEST #chan * 50\r\nPONG 123\r\n");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].time.unwrap().to_string(), "2019-01-04T14:33:26.123Z");
+        assert_eq!(history[1].message.arguments[1], "Hey");
+        assert_eq!(fetcher.take_other(), None);
+    }
+
+    #[test]
+    fn untimed_messages_stay_in_place() {
+        let input = b":irc.host BATCH +h chathistory #chan\r\n\
+                      @batch=h;time=2019-01-04T14:33:26.123Z :alice!a@host PRIVMSG #chan :One\r\n\
+                      @batch=h :bob!b@host PRIVMSG #chan :Two\r\n\
+                      @batch=h;time=2019-01-04T14:34:00.000Z :alice!a@host PRIVMSG #chan :Three\r\n\
+                      :irc.host BATCH -h\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let history = HistoryFetcher::new()
+            .fetch(&mut irc, &Message::chathistory_latest("#chan", None, 50))
+            .unwrap();
+        let texts: Vec<&str> = history.iter().map(|m| m.message.arguments[1].as_str()).collect();
+        assert_eq!(texts, vec!["One", "Two", "Three"]);
+    }
+
+    #[test]
+    fn fetch_empty() {
+        let input = b":irc.host BATCH +h chathistory #chan\r\n\
+                      :irc.host BATCH -h\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let history = HistoryFetcher::new()
+            .fetch(&mut irc, &Message::chathistory_latest("#chan", None, 50))
+            .unwrap();
+        assert!(history.is_empty());
+    }
+
+    #[test]
+    fn fetch_failure() {
+        let input = b":irc.host FAIL CHATHISTORY INVALID_TARGET LATEST #chan :No such channel\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let result = HistoryFetcher::new()
+            .fetch(&mut irc, &Message::chathistory_latest("#chan", None, 50));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,566 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use command::responses;
+use handler::EventHandler;
+use irc_stream::IrcStream;
+use isupport::IsupportInfo;
+use list::ListEntry;
+use list::ListQuery;
+use message::MAX_LINE_LENGTH;
+use message::Message;
+use messages::OperReply;
+use messages::UserModes;
+use names::ChannelNames;
+use names::NamesCollector;
+use registration::Registered;
+use registration::Registration;
+use who::WhoCollector;
+use who::WhoEntry;
+use whois::WhoisCollector;
+use whois::WhoisInfo;
+
+// Room left for the ":nick!user@host " the server adds to our messages, beyond the nickname
+// itself: a 10-character username and a 63-character hostname is as much as most networks allow.
+const PREFIX_ALLOWANCE: usize = ":!@ ".len() + 10 + 63;
+
+/// A registered connection to a server, for the common case of a bot which reacts to messages.
+///
+/// ```no_run
+/// use irc::{Client, IrcStream, Registration};
+///
+/// let irc = IrcStream::connect("irc.libera.chat", 6667).unwrap();
+/// let mut client = Client::register(irc, &Registration::new("mybot", "mybot", "My Bot")).unwrap();
+/// client.join("#mychannel").unwrap();
+/// client.run(|client, message| {
+///     if let Some(privmsg) = message.as_privmsg() {
+///         if privmsg.text == "!hello" {
+///             client.privmsg(privmsg.to, "Hello!")?;
+///         }
+///     }
+///     Ok(())
+/// }).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Client<S: Read + Write> {
+    irc: IrcStream<S>,
+    registered: Registered,
+    nickname: String,
+    isupport: IsupportInfo,
+    user_modes: UserModes,
+    // Messages received while waiting for the reply to a query, to be returned by next_message.
+    pending: VecDeque<Message>,
+}
+
+/// The channels listed in reply to `Client::list`, read as they arrive. Messages which aren't part
+/// of the listing are kept to be returned by `next_message` afterwards.
+#[derive(Debug)]
+pub struct ChannelListing<'a, S: Read + Write + 'a> {
+    client: &'a mut Client<S>,
+    done: bool,
+}
+
+// How a message relates to a query we're waiting on the reply to.
+enum Reply<T> {
+    Unrelated,
+    Partial,
+    Done(T),
+}
+
+impl<S: Read + Write> Client<S> {
+    /// Registers on a freshly-made connection. PINGs will be answered automatically from here on.
+    pub fn register(irc: IrcStream<S>, registration: &Registration) -> io::Result<Self> {
+        let mut irc = irc.with_auto_pong();
+        let registered = registration.register(&mut irc)?;
+        Ok(Client {
+            nickname: registered.nickname.clone(),
+            irc: irc,
+            registered: registered,
+            isupport: IsupportInfo::new(),
+            user_modes: UserModes::new(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    /// What we found out while registering.
+    pub fn registered(&self) -> &Registered {
+        &self.registered
+    }
+
+    /// The nickname the server knows us by. This follows changes we make and ones forced on us by
+    /// the server, as long as messages are read through the client rather than `stream`.
+    pub fn current_nick(&self) -> &str {
+        &self.nickname
+    }
+
+    /// What the server has told us about itself. This is only filled in as messages are read
+    /// through the client; servers send it just after registering.
+    pub fn isupport(&self) -> &IsupportInfo {
+        &self.isupport
+    }
+
+    /// Our user modes, e.g. whether we're an IRC operator. As with `current_nick`, this only
+    /// follows changes read through the client.
+    pub fn user_modes(&self) -> &UserModes {
+        &self.user_modes
+    }
+
+    /// The underlying stream, for anything this doesn't cover.
+    pub fn stream(&mut self) -> &mut IrcStream<S> {
+        &mut self.irc
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        self.irc.send(message)
+    }
+
+    /// Sends a message to a channel or user. Long or multi-line text is split into as many
+    /// messages as it takes, so none gets truncated. Fails with `InvalidInput` if our nickname and
+    /// `to` are so long there's no room left for any text.
+    pub fn privmsg(&mut self, to: &str, text: &str) -> io::Result<()> {
+        // "PRIVMSG <to> :<text>\r\n", with room for at least one character of text.
+        let overhead = "PRIVMSG ".len() + to.len() + " :".len() + "\r\n".len() + 4;
+        let max_len = MAX_LINE_LENGTH.checked_sub(PREFIX_ALLOWANCE + self.nickname.len())
+            .filter(|&max_len| max_len >= overhead)
+            .ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidInput,
+                               format!("No room for any text in a PRIVMSG from {} to {}",
+                                       self.nickname,
+                                       to))
+            })?;
+        for message in Message::privmsg_split(to, text, max_len) {
+            self.irc.send(&message)?;
+        }
+        Ok(())
+    }
+
+    pub fn join(&mut self, channel: &str) -> io::Result<()> {
+        self.irc.send(&Message::join(channel))
+    }
+
+    pub fn part(&mut self, channel: &str, reason: Option<&str>) -> io::Result<()> {
+        self.irc.send(&Message::part(channel, reason))
+    }
+
+    pub fn quit(&mut self, reason: Option<&str>) -> io::Result<()> {
+        self.irc.send(&Message::quit(reason))
+    }
+
+    /// Lists the members of `channel`, blocking until the server has sent them all. Messages
+    /// received in the meantime are kept to be returned by `next_message`.
+    pub fn names(&mut self, channel: &str) -> io::Result<ChannelNames> {
+        let request = Message::names(&[channel]);
+        let mut collector = NamesCollector::new();
+        let prefixes = self.isupport.prefix();
+        let case = self.isupport.casemapping();
+        self.query(&request, |message| {
+            match NamesCollector::channel(message) {
+                Some(names_channel) if case.eq(names_channel, channel) => {
+                    match collector.handle(message, &prefixes) {
+                        Some(names) => Reply::Done(names),
+                        None => Reply::Partial,
+                    }
+                }
+                _ => Reply::Unrelated,
+            }
+        })
+    }
+
+    /// Lists channels, returning an iterator which reads them as the server sends them rather than
+    /// waiting for the whole list. Fails with `InvalidInput` if the server doesn't support the
+    /// query's filters.
+    pub fn list(&mut self, query: &ListQuery) -> io::Result<ChannelListing<S>> {
+        if !query.is_supported(&self.isupport) {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      "Server doesn't support the LIST filters asked for"));
+        }
+        self.irc.send(&query.message())?;
+        Ok(ChannelListing {
+            client: self,
+            done: false,
+        })
+    }
+
+    /// Lists the users matching `mask` (often a channel) with WHO, blocking until the server has
+    /// replied. As with `names`, messages received in the meantime are kept.
+    pub fn who(&mut self, mask: &str) -> io::Result<Vec<WhoEntry>> {
+        let request = Message::who(mask);
+        let mut collector = WhoCollector::new();
+        self.query(&request, |message| {
+            if !WhoCollector::is_reply(message) {
+                return Reply::Unrelated;
+            }
+            match collector.handle(message) {
+                Some(entries) => Reply::Done(entries),
+                None => Reply::Partial,
+            }
+        })
+    }
+
+    /// Looks up `nick` with WHOIS, blocking until the server has replied. As with `names`,
+    /// messages received in the meantime are kept. Fails with `NotFound` if there's no such user.
+    pub fn whois(&mut self, nick: &str) -> io::Result<WhoisInfo> {
+        let request = Message::whois(nick);
+        let mut collector = WhoisCollector::new(nick);
+        collector.set_casemapping(self.isupport.casemapping());
+        self.query(&request, |message| {
+            if !collector.is_reply(message) {
+                return Reply::Unrelated;
+            }
+            match collector.handle(message) {
+                Some(result) => Reply::Done(result),
+                None => Reply::Partial,
+            }
+        })?
+    }
+
+    /// Becomes an IRC operator, blocking until the server has replied. As with `names`, messages
+    /// received in the meantime are kept. Fails with `PermissionDenied` if the server refuses.
+    pub fn oper(&mut self, name: &str, password: &str) -> io::Result<()> {
+        let request = Message::oper(name, password);
+        self.query(&request, |message| {
+            match message.as_oper_reply() {
+                Some(OperReply::Opered) => Reply::Done(Ok(())),
+                Some(_) => {
+                    Reply::Done(Err(io::Error::new(ErrorKind::PermissionDenied,
+                                                   format!("OPER failed: {}", message))))
+                }
+                None => Reply::Unrelated,
+            }
+        })?
+    }
+
+    /// Reads the next message from the server. An ERROR from the server, which means it's about to
+    /// close the connection, is returned as a `ConnectionAborted` error with its reason.
+    pub fn next_message(&mut self) -> io::Result<Message> {
+        match self.pending.pop_front() {
+            Some(message) => Ok(message),
+            None => self.read_message(),
+        }
+    }
+
+    /// Passes every message received to `handler` until the connection fails or `handler` returns
+    /// an error, either of which is returned. The connection being closed shows up as
+    /// `UnexpectedEof`, or `ConnectionAborted` if the server said why with an ERROR.
+    pub fn run<F>(&mut self, mut handler: F) -> io::Result<()>
+        where F: FnMut(&mut Self, &Message) -> io::Result<()>
+    {
+        loop {
+            let message = self.next_message()?;
+            handler(self, &message)?;
+        }
+    }
+
+    /// Passes everything that happens to `handler` until the connection fails or `handler`
+    /// returns an error, either of which is returned. `on_disconnect` is called first if the
+    /// connection failed.
+    pub fn run_handler<H: EventHandler<S> + ?Sized>(&mut self, handler: &mut H) -> io::Result<()> {
+        loop {
+            let message = match self.next_message() {
+                Ok(message) => message,
+                Err(e) => {
+                    handler.on_disconnect(self, &e);
+                    return Err(e);
+                }
+            };
+            handler.handle(self, &message)?;
+        }
+    }
+
+    fn read_message(&mut self) -> io::Result<Message> {
+        let message = self.irc.next_message()?;
+        if let Some(reason) = message.as_server_error() {
+            return Err(io::Error::new(ErrorKind::ConnectionAborted,
+                                      format!("Server closed the connection: {}", reason)));
+        }
+        self.track_nick(&message);
+        self.isupport.handle(&message);
+        self.track_user_modes(&message);
+        Ok(message)
+    }
+
+    // Sends `request` and passes messages to `reply` until it's done.
+    fn query<T, F>(&mut self, request: &Message, mut reply: F) -> io::Result<T>
+        where F: FnMut(&Message) -> Reply<T>
+    {
+        self.irc.send(request)?;
+        loop {
+            let message = self.read_message()?;
+            match reply(&message) {
+                Reply::Unrelated => self.pending.push_back(message),
+                Reply::Partial => (),
+                Reply::Done(result) => return Ok(result),
+            }
+        }
+    }
+
+    fn track_user_modes(&mut self, message: &Message) {
+        let mode = match message.as_user_mode(&self.isupport) {
+            Some(mode) => mode,
+            None => return,
+        };
+        if !self.isupport.casemapping().eq(mode.nick, &self.nickname) {
+            return;
+        }
+        if mode.complete {
+            self.user_modes = UserModes::new();
+        }
+        self.user_modes.apply(&mode.changes);
+    }
+
+    fn track_nick(&mut self, message: &Message) {
+        let new_nick = if let Some(change) = message.as_nick() {
+            let case = self.isupport.casemapping();
+            if case.eq(change.old_nick(), &self.nickname) {
+                Some(change.new_nick)
+            } else {
+                None
+            }
+        } else if message.command == responses::RPL_WELCOME() {
+            message.arguments.first().map(|nick| nick.as_str())
+        } else {
+            None
+        };
+        if let Some(new_nick) = new_nick {
+            if new_nick != self.nickname {
+                info!("Our nickname is now {}", new_nick);
+                self.irc.nickname_changed(new_nick);
+                self.nickname = new_nick.into();
+            }
+        }
+    }
+}
+
+impl<'a, S: Read + Write> Iterator for ChannelListing<'a, S> {
+    type Item = io::Result<ListEntry>;
+
+    fn next(&mut self) -> Option<io::Result<ListEntry>> {
+        while !self.done {
+            let message = match self.client.read_message() {
+                Ok(message) => message,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            if let Some(entry) = ListEntry::parse(&message) {
+                return Some(Ok(entry));
+            } else if message.command == responses::RPL_LISTEND() {
+                self.done = true;
+            } else if message.command != responses::RPL_LISTSTART() {
+                self.client.pending.push_back(message);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+    use irc_stream::ScriptedStream;
+
+    #[test]
+    fn register_and_run() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      PING 123\r\n\
+                      :server 005 bot NETWORK=Example :are supported by this server\r\n\
+                      :someone!user@host PRIVMSG #chan :!hello\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+        assert_eq!(client.registered().nickname, "bot");
+
+        let mut seen = Vec::new();
+        let error = client.run(|client, message| {
+                if let Some(privmsg) = message.as_privmsg() {
+                    client.privmsg(privmsg.to, "Hello!")?;
+                }
+                seen.push(message.clone());
+                Ok(())
+            })
+            .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(seen.len(), 2);
+        assert_eq!(client.isupport().network(), Some("Example"));
+        assert!(client.stream().sent().ends_with("PONG 123\r\nPRIVMSG #chan Hello!\r\n"));
+    }
+
+    #[test]
+    fn server_error_ends_connection() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      ERROR :Closing Link: bot (Ping timeout)\r\n\
+                      :someone!user@host PRIVMSG #chan :Too late\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+
+        let error = client.next_message().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ConnectionAborted);
+        assert!(error.to_string().contains("Ping timeout"));
+    }
+
+    #[test]
+    fn follows_nick_changes() {
+        let input = b":server 001 bot_ :Welcome\r\n\
+                      :bot_!user@host NICK bot\r\n\
+                      :someone!user@host NICK other\r\n\
+                      :BOT!user@host NICK :forced123\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let registration = Registration::new("bot", "bot", "A bot");
+        let mut client = Client::register(irc, &registration).unwrap();
+        assert_eq!(client.current_nick(), "bot_");
+
+        client.next_message().unwrap();
+        assert_eq!(client.current_nick(), "bot");
+        client.next_message().unwrap();
+        assert_eq!(client.current_nick(), "bot");
+        client.next_message().unwrap();
+        assert_eq!(client.current_nick(), "forced123");
+    }
+
+    #[test]
+    fn tracks_user_modes() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :server 221 bot +iw\r\n\
+                      :bot MODE bot :+o-w\r\n\
+                      :server MODE #chan +o bot\r\n\
+                      :server 221 bot +x\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+
+        client.next_message().unwrap();
+        assert_eq!(client.user_modes().to_string(), "+iw");
+        client.next_message().unwrap();
+        client.next_message().unwrap();
+        assert_eq!(client.user_modes().to_string(), "+io");
+        assert!(client.user_modes().is_oper());
+        client.next_message().unwrap();
+        assert_eq!(client.user_modes().to_string(), "+x");
+    }
+
+    #[test]
+    fn oper() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :server 381 bot :You are now an IRC operator\r\n\
+                      :server 491 bot :No O-lines for your host\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+
+        client.oper("admin", "hunter2").unwrap();
+        assert!(client.stream().sent().ends_with("OPER admin hunter2\r\n"));
+        let error = client.oper("admin", "hunter2").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn names() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :server 353 bot = #other :carol\r\n\
+                      :server 353 bot = #Chan :@alice bob\r\n\
+                      :someone!user@host PRIVMSG #chan :hi\r\n\
+                      :server 366 bot #other :End of /NAMES list\r\n\
+                      :server 366 bot #Chan :End of /NAMES list\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+
+        let names = client.names("#chan").unwrap();
+        assert_eq!(names.members.len(), 2);
+        assert!(client.stream().sent().ends_with("NAMES #chan\r\n"));
+
+        // Everything else is still there.
+        assert_eq!(client.next_message().unwrap().arguments[..], ["bot", "=", "#other", "carol"]);
+        assert!(client.next_message().unwrap().as_privmsg().is_some());
+        assert_eq!(client.next_message().unwrap().arguments[1], "#other");
+        assert!(client.next_message().is_err());
+    }
+
+    #[test]
+    fn who() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :server 352 bot #chan al host server alice H :0 Alice\r\n\
+                      :server 352 bot #chan bob host server bob G :0 Bob\r\n\
+                      :server 315 bot #chan :End of WHO list\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+
+        let entries = client.who("#chan").unwrap();
+        assert_eq!(entries.iter().map(|e| &e.nick[..]).collect::<Vec<_>>(), vec!["alice", "bob"]);
+        assert!(client.stream().sent().ends_with("WHO #chan\r\n"));
+    }
+
+    #[test]
+    fn list() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :server 005 bot ELIST=U :are supported\r\n\
+                      :server 321 bot Channel :Users  Name\r\n\
+                      :server 322 bot #rust 1234 :Rust things\r\n\
+                      PING 123\r\n\
+                      :server 322 bot #irc 200 :\r\n\
+                      :server 323 bot :End of /LIST\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+        client.next_message().unwrap();
+
+        assert_eq!(client.list(&ListQuery::new().created_within(10)).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+        let channels: Vec<String> = client.list(&ListQuery::new().with_more_users_than(100))
+            .unwrap()
+            .map(|entry| entry.unwrap().channel)
+            .collect();
+        assert_eq!(channels, vec!["#rust", "#irc"]);
+        assert!(client.stream().sent().ends_with("LIST >100\r\nPONG 123\r\n"));
+        assert!(client.next_message().is_err());
+    }
+
+    #[test]
+    fn whois() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :server 311 bot alice al host * :Alice\r\n\
+                      :server 330 bot alice acct :is logged in as\r\n\
+                      :server 318 bot alice :End of /WHOIS list\r\n\
+                      :server 401 bot nobody :No such nick\r\n\
+                      :server 318 bot nobody :End of /WHOIS list\r\n\
+                      :alice!al@host PRIVMSG bot :Hi\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+
+        assert_eq!(client.whois("alice").unwrap().account, Some("acct".into()));
+        assert_eq!(client.whois("nobody").unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(client.stream().sent().ends_with("WHOIS alice\r\nWHOIS nobody\r\n"));
+        // The end of the failed reply isn't left behind.
+        assert!(client.next_message().unwrap().as_privmsg().is_some());
+    }
+
+    #[test]
+    fn long_privmsgs_are_split() {
+        let irc = IrcStream::new(ScriptedStream::new(b":server 001 bot :Welcome\r\n"));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+
+        client.privmsg("#chan", &"word ".repeat(200)).unwrap();
+        let sent = client.stream().sent();
+        assert_eq!(sent.matches("PRIVMSG").count(), 3);
+        let max_len = MAX_LINE_LENGTH - PREFIX_ALLOWANCE - "bot".len();
+        assert!(sent.lines().all(|line| line.len() + "\r\n".len() <= max_len));
+    }
+
+    #[test]
+    fn privmsg_without_room_for_text() {
+        let long_nick = "n".repeat(MAX_LINE_LENGTH);
+        let welcome = format!(":server 001 {} :Welcome\r\n", long_nick);
+        let irc = IrcStream::new(ScriptedStream::new(welcome.as_bytes()));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+        let error = client.privmsg("#chan", "Hi").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        let irc = IrcStream::new(ScriptedStream::new(b":server 001 bot :Welcome\r\n"));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+        let targets = vec!["#channel"; 60].join(",");
+        let error = client.privmsg(&targets, "Hi").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(!client.stream().sent().contains("PRIVMSG"));
+    }
+}
@@ -0,0 +1,182 @@
+use std::io;
+use std::io::ErrorKind;
+use std::sync::Arc;
+
+use bytes::BufMut;
+use bytes::BytesMut;
+use tokio_util::codec::Decoder;
+use tokio_util::codec::Encoder;
+
+use decoder::DEFAULT_MAX_LINE_LENGTH;
+use decoder::line_end;
+use encoding::TextEncoding;
+use irc_stream::check_sendable;
+use message::Message;
+use metrics::Metrics;
+use metrics::MetricsSink;
+use trace;
+
+/// A codec for using `Message`s with tokio's `Framed`.
+///
+/// Lines are framed the same way as with `MessageDecoder`; partial lines are left in the buffer
+/// until the rest arrives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrcCodec {
+    max_line_length: usize,
+    encoding: TextEncoding,
+    // How far into the buffer we've already looked for a line ending.
+    searched: usize,
+    metrics: Metrics,
+}
+
+impl IrcCodec {
+    pub fn new() -> Self {
+        Self::with_max_line_length(DEFAULT_MAX_LINE_LENGTH)
+    }
+
+    /// Creates a codec which rejects received lines longer than `max_line_length` bytes,
+    /// including the CRLF.
+    pub fn with_max_line_length(max_line_length: usize) -> Self {
+        IrcCodec {
+            max_line_length: max_line_length,
+            encoding: TextEncoding::default(),
+            searched: 0,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Sets how text is encoded, as with `IrcStream::with_encoding`.
+    pub fn with_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Reports messages and bytes sent and received to `sink`, as with `IrcStream::with_metrics`.
+    pub fn with_metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Metrics::new(sink);
+        self
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+}
+
+impl Default for IrcCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for IrcCodec {
+    type Item = Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Message>> {
+        let end = match line_end(src, &mut self.searched, self.max_line_length)? {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+        let line = src.split_to(end);
+        self.metrics.bytes_received(line.len());
+        let line = self.encoding.decode(&line)?;
+        match Message::parse(&line) {
+            Ok((message, _)) => {
+                trace::received(&message);
+                self.metrics.message_received(&message.command);
+                Ok(Some(message))
+            }
+            Err(parse_error) => Err(io::Error::new(ErrorKind::InvalidData, parse_error)),
+        }
+    }
+}
+
+impl Encoder<Message> for IrcCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> io::Result<()> {
+        Encoder::<&Message>::encode(self, &message, dst)
+    }
+}
+
+impl<'a> Encoder<&'a Message> for IrcCodec {
+    type Error = io::Error;
+
+    /// Fails with `InvalidInput` if the message is too long to send or has a line break in an
+    /// argument, as with `IrcStream::send`.
+    fn encode(&mut self, message: &'a Message, dst: &mut BytesMut) -> io::Result<()> {
+        check_sendable(message)?;
+        trace::sent(message);
+        let line = message.to_bytes();
+        let encoded = self.encoding.encode(&line);
+        self.metrics.message_sent(&message.command);
+        self.metrics.bytes_sent(encoded.len());
+        dst.put_slice(&encoded);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::commands::PING;
+    use message::MAX_LINE_LENGTH;
+    use message::Prefix;
+
+    #[test]
+    fn decode_partial_lines() {
+        let mut codec = IrcCodec::new();
+        let mut buf = BytesMut::from(&b"PING 1\r\nPI"[..]);
+
+        assert_eq!(codec.decode(&mut buf).unwrap(),
+                   Some(Message::from_strs(Prefix::None, PING(), vec!["1"])));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"NG 2\r\n");
+        assert_eq!(codec.decode(&mut buf).unwrap(),
+                   Some(Message::from_strs(Prefix::None, PING(), vec!["2"])));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_too_long() {
+        let mut codec = IrcCodec::with_max_line_length(10);
+
+        assert!(codec.decode(&mut BytesMut::from(&b"PING 123456789"[..])).is_err());
+        assert!(codec.decode(&mut BytesMut::from(&b"PING 1234\r\n"[..])).is_err());
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let mut codec = IrcCodec::new();
+
+        assert!(codec.decode(&mut BytesMut::from(&b"!!!\r\n"[..])).is_err());
+    }
+
+    #[test]
+    fn encode() {
+        let mut codec = IrcCodec::new();
+        let mut buf = BytesMut::new();
+
+        codec.encode(Message::privmsg("#chan", "Hi there"), &mut buf).unwrap();
+        assert_eq!(&buf[..], &b"PRIVMSG #chan :Hi there\r\n"[..]);
+    }
+
+    #[test]
+    fn encode_too_long() {
+        let mut codec = IrcCodec::new();
+        let text = "a".repeat(MAX_LINE_LENGTH);
+
+        assert!(codec.encode(Message::privmsg("#chan", &text), &mut BytesMut::new()).is_err());
+    }
+
+    #[test]
+    fn encode_rejects_line_breaks() {
+        let mut codec = IrcCodec::new();
+        let mut buf = BytesMut::new();
+
+        let error = codec.encode(Message::privmsg("#chan", "hi\rQUIT :bye"), &mut buf).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+    }
+}
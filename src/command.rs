@@ -1,17 +1,44 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std;
 
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Deserializer;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::Serializer;
+#[cfg(feature = "serde")]
+use serde::de;
+
 /// An IRC command. These can either be a sequence of letters
 /// (which I'm calling "word") or a numeric value.
 /// Note that creating one of these directly will
 /// bypass validation and cause you to have a Bad Time.
+///
+/// The constants in `commands` and `responses` don't allocate, and nor do commands parsed from
+/// the wire if they're one of those constants, so comparing against them is cheap. To `match` on
+/// a command, match on `word()` or the `Number`.
 #[derive(Debug,Clone, PartialEq, Eq)]
 pub enum Command {
-    Word(String),
+    Word(Cow<'static, str>),
     Number(u16),
 }
 
+/// The reason a command couldn't be created by `Command::try_word` or `Command::try_number`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// The word was empty or had characters outside of `[a-zA-Z]`.
+    InvalidWord(String),
+    /// The number was too large to be a 3-digit numeric.
+    NumberTooLarge(u16),
+}
+
 impl Command {
     /// Creates a Command::Word validated to ensure it is a valid IRC command.
     /// Only validates that the command is made up of valid characters, not that
@@ -19,21 +46,13 @@ impl Command {
     ///
     /// # Panics
     ///
-    /// Will panic if `word` has any characters outside of `[a-zA-Z]`.
+    /// Will panic if `word` has any characters outside of `[a-zA-Z]`. Use `try_word` if `word`
+    /// comes from somewhere you don't control.
     pub fn of_word(word: &str) -> Self {
-        for (i, c) in word.chars().enumerate() {
-            // As deep as my hatred for regexes goes this is getting a bit silly
-            let codepoint = c as u32;
-            let is_lowercase_letter = codepoint >= 0x41 && codepoint <= 0x5A;
-            let is_uppercase_letter = codepoint >= 0x61 && codepoint <= 0x7A;
-            assert!(is_lowercase_letter || is_uppercase_letter,
-                    "Word IRC commands must contain only chars A-Za-z but [{}] index {} is [{}]",
-                    word,
-                    i,
-                    c);
+        match Self::try_word(word) {
+            Ok(command) => command,
+            Err(e) => panic!("{}", e),
         }
-
-        Command::Word(word.into())
     }
 
     /// Creates a Command::Number validated to ensure it is a valid IRC command.
@@ -43,77 +62,198 @@ impl Command {
     /// # Panics
     ///
     /// Will panic if `number` cannot be represented as a 3-digit number (i.e. if it is
-    /// greater than 999).
+    /// greater than 999). Use `try_number` to get an error instead.
     pub fn of_number(number: u16) -> Self {
-        assert!(number <= 999,
-                "Numeric IRC commands must be representable as a 3-digit number but got {}",
-                number);
-        Command::Number(number)
+        match Self::try_number(number) {
+            Ok(command) => command,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Like `of_word`, but returns an error rather than panicking if `word` isn't valid.
+    pub fn try_word(word: &str) -> Result<Self, CommandError> {
+        if word.is_empty() || !word.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(CommandError::InvalidWord(word.into()));
+        }
+        match commands::known(word) {
+            Some(known) => Ok(Command::Word(Cow::Borrowed(known))),
+            None => Ok(Command::Word(Cow::Owned(word.into()))),
+        }
+    }
+
+    /// Like `of_number`, but returns an error rather than panicking if `number` isn't valid.
+    pub fn try_number(number: u16) -> Result<Self, CommandError> {
+        if number > 999 {
+            return Err(CommandError::NumberTooLarge(number));
+        }
+        Ok(Command::Number(number))
+    }
+
+    /// The command if it's a word, e.g. `Some("PRIVMSG")`.
+    pub fn word(&self) -> Option<&str> {
+        match *self {
+            Command::Word(ref word) => Some(word),
+            Command::Number(_) => None,
+        }
     }
 }
 
-/// Constants for the command types documented in RFC 8212
-#[allow(non_snake_case)]
-pub mod commands {
-    use super::Command;
+impl<'a> TryFrom<&'a str> for Command {
+    type Error = CommandError;
+
+    fn try_from(word: &'a str) -> Result<Self, CommandError> {
+        Command::try_word(word)
+    }
+}
+
+impl TryFrom<u16> for Command {
+    type Error = CommandError;
+
+    fn try_from(number: u16) -> Result<Self, CommandError> {
+        Command::try_number(number)
+    }
+}
+
+impl Error for CommandError {
+    fn description(&self) -> &str {
+        "invalid IRC command"
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match *self {
+            CommandError::InvalidWord(ref word) => {
+                write!(fmt,
+                       "Word IRC commands must be non-empty and contain only chars A-Za-z but got \
+                        [{}]",
+                       word)
+            }
+            CommandError::NumberTooLarge(number) => {
+                write!(fmt,
+                       "Numeric IRC commands must be representable as a 3-digit number but got {}",
+                       number)
+            }
+        }
+    }
+}
+
+// Each command is listed once, as its `KnownCommand` variant and its word, and this generates
+// both the `commands` constants and `KnownCommand`.
+macro_rules! commands {
+    ( $( $variant:ident => $word:ident ),* ) => {
+        /// Constants for the command types documented in RFC 8212
+        #[allow(non_snake_case)]
+        pub mod commands {
+            use std::borrow::Cow;
+
+            use super::Command;
 
-    macro_rules! commands {
-        ( $( $x:ident ),* ) => {
             $(
-                pub fn $x() -> Command {
-                    Command::of_word(stringify!($x))
+                pub const fn $word() -> Command {
+                    Command::Word(Cow::Borrowed(stringify!($word)))
                 }
             )*
-        };
-    }
 
-    commands!(ADMIN,
-              AWAY,
-              CONNECT,
-              DIE,
-              ERROR,
-              INFO,
-              INVITE,
-              ISON,
-              JOIN,
-              KICK,
-              KILL,
-              LINKS,
-              LIST,
-              LUSERS,
-              MODE,
-              MOTD,
-              NAMES,
-              NICK,
-              NOTICE,
-              OPER,
-              PART,
-              PASS,
-              PING,
-              PONG,
-              PRIVMSG,
-              QUIT,
-              REHASH,
-              RESTART,
-              SERVICE,
-              SERVLIST,
-              SQUERY,
-              SQUIT,
-              STATS,
-              SUMMON,
-              TIME,
-              TOPIC,
-              TRACE,
-              USER,
-              USERHOST,
-              USERS,
-              VERSION,
-              WALLOPS,
-              WHO,
-              WHOIS,
-              WHOWAS);
+            const KNOWN: &[&str] = &[$( stringify!($word) ),*];
+
+            // The static name of a command, so parsing one doesn't allocate.
+            pub(super) fn known(word: &str) -> Option<&'static str> {
+                KNOWN.iter().find(|known| **known == word).cloned()
+            }
+        }
+
+        /// A command as an enum of the ones this crate knows about, from `Command::known`, so you
+        /// can `match` on it exhaustively. Words are matched case-sensitively, as `Command`s are
+        /// compared.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum KnownCommand {
+            $( $variant, )*
+            /// A numeric reply; see `responses`.
+            Numeric(u16),
+            /// Any other word.
+            Unknown(String),
+        }
+
+        impl KnownCommand {
+            fn of_word(word: &str) -> Self {
+                $(
+                    if word == stringify!($word) {
+                        return KnownCommand::$variant;
+                    }
+                )*
+                KnownCommand::Unknown(word.into())
+            }
+
+            /// The equivalent `Command`.
+            pub fn command(&self) -> Command {
+                match *self {
+                    $( KnownCommand::$variant => commands::$word(), )*
+                    KnownCommand::Numeric(number) => Command::Number(number),
+                    KnownCommand::Unknown(ref word) => Command::Word(Cow::Owned(word.clone())),
+                }
+            }
+        }
+    };
 }
 
+commands!(Admin => ADMIN,
+          Away => AWAY,
+          Connect => CONNECT,
+          Die => DIE,
+          Error => ERROR,
+          Info => INFO,
+          Invite => INVITE,
+          Ison => ISON,
+          Join => JOIN,
+          Kick => KICK,
+          Kill => KILL,
+          Links => LINKS,
+          List => LIST,
+          Lusers => LUSERS,
+          Mode => MODE,
+          Motd => MOTD,
+          Names => NAMES,
+          Nick => NICK,
+          Notice => NOTICE,
+          Oper => OPER,
+          Part => PART,
+          Pass => PASS,
+          Ping => PING,
+          Pong => PONG,
+          Privmsg => PRIVMSG,
+          Quit => QUIT,
+          Rehash => REHASH,
+          Restart => RESTART,
+          Service => SERVICE,
+          Servlist => SERVLIST,
+          Squery => SQUERY,
+          Squit => SQUIT,
+          Stats => STATS,
+          Summon => SUMMON,
+          Time => TIME,
+          Topic => TOPIC,
+          Trace => TRACE,
+          User => USER,
+          Userhost => USERHOST,
+          Users => USERS,
+          Version => VERSION,
+          Wallops => WALLOPS,
+          Who => WHO,
+          Whois => WHOIS,
+          Whowas => WHOWAS,
+          // Added by IRCv3 extensions rather than the RFC.
+          Account => ACCOUNT,
+          Ack => ACK,
+          Authenticate => AUTHENTICATE,
+          Batch => BATCH,
+          Cap => CAP,
+          Chathistory => CHATHISTORY,
+          Fail => FAIL,
+          Monitor => MONITOR,
+          Tagmsg => TAGMSG,
+          Webirc => WEBIRC);
+
 /// Constants for all of the response types documented in RFC 8212
 #[allow(non_snake_case)]
 pub mod responses {
@@ -121,7 +261,7 @@ pub mod responses {
 
     macro_rules! response {
         ( $number:expr , $name:ident ) => {
-            pub fn $name() -> Command {
+            pub const fn $name() -> Command {
                 Command::Number($number)
             }
         };
@@ -132,6 +272,8 @@ pub mod responses {
     response!(3, RPL_CREATED);
     response!(4, RPL_MYINFO);
     response!(5, RPL_BOUNCE);
+    // RFC 2812 says 005 is RPL_BOUNCE, but every modern server uses it for this instead.
+    response!(5, RPL_ISUPPORT);
     response!(200, RPL_TRACELINK);
     response!(201, RPL_TRACECONNECTING);
     response!(202, RPL_TRACEHANDSHAKE);
@@ -249,6 +391,7 @@ pub mod responses {
     response!(465, ERR_YOUREBANNEDCREEP);
     response!(466, ERR_YOUWILLBEBANNED);
     response!(467, ERR_KEYSET);
+    response!(470, ERR_LINKCHANNEL);
     response!(471, ERR_CHANNELISFULL);
     response!(472, ERR_UNKNOWNMODE);
     response!(473, ERR_INVITEONLYCHAN);
@@ -266,6 +409,41 @@ pub mod responses {
     response!(492, ERR_NOSERVICEHOST);
     response!(501, ERR_UMODEUNKNOWNFLAG);
     response!(502, ERR_USERSDONTMATCH);
+
+    // Numerics which aren't in the RFC but are widely implemented.
+    response!(330, RPL_WHOISACCOUNT);
+    response!(333, RPL_TOPICWHOTIME);
+    response!(338, RPL_WHOISACTUALLY);
+    response!(354, RPL_WHOSPCRPL);
+    response!(671, RPL_WHOISSECURE);
+
+    // Numerics from the IRCv3 MONITOR specification rather than the RFC.
+    response!(730, RPL_MONONLINE);
+    response!(731, RPL_MONOFFLINE);
+    response!(732, RPL_MONLIST);
+    response!(733, RPL_ENDOFMONLIST);
+    response!(734, ERR_MONLISTFULL);
+
+    // Numerics from the IRCv3 SASL specification rather than the RFC.
+    response!(900, RPL_LOGGEDIN);
+    response!(901, RPL_LOGGEDOUT);
+    response!(902, ERR_NICKLOCKED);
+    response!(903, RPL_SASLSUCCESS);
+    response!(904, ERR_SASLFAIL);
+    response!(905, ERR_SASLTOOLONG);
+    response!(906, ERR_SASLABORTED);
+    response!(907, ERR_SASLALREADY);
+    response!(908, RPL_SASLMECHS);
+}
+
+impl Command {
+    /// This command as a `KnownCommand`, for matching on.
+    pub fn known(&self) -> KnownCommand {
+        match *self {
+            Command::Word(ref word) => KnownCommand::of_word(word),
+            Command::Number(number) => KnownCommand::Numeric(number),
+        }
+    }
 }
 
 impl Display for Command {
@@ -277,6 +455,29 @@ impl Display for Command {
     }
 }
 
+/// Commands are serialized as they appear on the wire, e.g. `"PRIVMSG"` or `"001"`.
+#[cfg(feature = "serde")]
+impl Serialize for Command {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Goes through the same validation as `try_word` and `try_number`.
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let command = if !text.is_empty() && text.bytes().all(|c| c.is_ascii_digit()) {
+            let number = text.parse().map_err(de::Error::custom)?;
+            Command::try_number(number)
+        } else {
+            Command::try_word(&text)
+        };
+        command.map_err(de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -303,6 +504,30 @@ mod tests {
         Command::of_word("PR1VMSG");
     }
 
+    #[test]
+    fn try_word() {
+        assert_eq!(Command::try_word("PRIVMSG"), Ok(Command::Word("PRIVMSG".into())));
+        assert_eq!(Command::try_word("PR1VMSG"),
+                   Err(CommandError::InvalidWord("PR1VMSG".into())));
+        assert_eq!(Command::try_word(""), Err(CommandError::InvalidWord("".into())));
+    }
+
+    #[test]
+    fn try_number() {
+        assert_eq!(Command::try_from(999), Ok(Command::Number(999)));
+        assert_eq!(Command::try_from(1000), Err(CommandError::NumberTooLarge(1000)));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        assert_eq!(::serde_json::to_string(&Command::of_number(1)).unwrap(), "\"001\"");
+        assert_eq!(::serde_json::from_str::<Command>("\"PRIVMSG\"").unwrap(),
+                   commands::PRIVMSG());
+        assert!(::serde_json::from_str::<Command>("\"1000\"").is_err());
+        assert!(::serde_json::from_str::<Command>("\"PR1VMSG\"").is_err());
+    }
+
     #[test]
     fn fmt_number_unpadded() {
         assert_eq!(format!("{}", Command::of_number(123)), "123");
@@ -318,6 +543,34 @@ mod tests {
         assert_eq!(commands::PRIVMSG(), Command::of_word("PRIVMSG"));
     }
 
+    #[test]
+    fn known_words_are_borrowed() {
+        assert_eq!(Command::of_word("PRIVMSG"), Command::Word(Cow::Borrowed("PRIVMSG")));
+        match Command::of_word("PRIVMSG") {
+            Command::Word(Cow::Borrowed(_)) => (),
+            other => panic!("Expected a borrowed word, got {:?}", other),
+        }
+        match Command::of_word("FOO") {
+            Command::Word(Cow::Owned(ref word)) if word == "FOO" => (),
+            other => panic!("Expected an owned word, got {:?}", other),
+        }
+        assert_eq!(commands::PING().word(), Some("PING"));
+        assert_eq!(responses::RPL_WELCOME().word(), None);
+    }
+
+    #[test]
+    fn known_commands() {
+        assert_eq!(commands::PRIVMSG().known(), KnownCommand::Privmsg);
+        assert_eq!(Command::of_word("privmsg").known(), KnownCommand::Unknown("privmsg".into()));
+        assert_eq!(commands::WEBIRC().known(), KnownCommand::Webirc);
+        assert_eq!(responses::RPL_WELCOME().known(), KnownCommand::Numeric(1));
+        assert_eq!(Command::of_word("Foo").known(), KnownCommand::Unknown("Foo".into()));
+
+        assert_eq!(KnownCommand::Join.command(), commands::JOIN());
+        assert_eq!(KnownCommand::Numeric(433).command(), responses::ERR_NICKNAMEINUSE());
+        assert_eq!(KnownCommand::Unknown("FOO".into()).command(), Command::of_word("FOO"));
+    }
+
     #[test]
     fn replies() {
         assert_eq!(responses::RPL_BOUNCE(), Command::of_number(5));
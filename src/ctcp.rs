@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use client::Client;
+use handler::EventHandler;
+use message::Message;
+use messages::Privmsg;
+use server_time::ServerTime;
+
+/// Answers the CTCP queries people expect every client to: VERSION, PING, TIME, CLIENTINFO and
+/// (if configured) SOURCE. Anything else is left alone.
+///
+/// Replies go to whoever asked, and at most one is sent to each host per `min_interval`, so
+/// flooding us with queries doesn't get us disconnected for flooding in turn.
+///
+/// Pass received messages to `handle` and send what it returns, or use it as an `EventHandler`.
+#[derive(Debug, Clone)]
+pub struct CtcpResponder {
+    version: String,
+    source: Option<String>,
+    min_interval: Duration,
+    // When we last replied to each host (or nickname, for users whose host we don't know).
+    last_reply: HashMap<String, Instant>,
+}
+
+impl CtcpResponder {
+    /// Answers VERSION queries with `version`, e.g. `mybot 1.0`.
+    pub fn new(version: &str) -> Self {
+        CtcpResponder {
+            version: version.into(),
+            source: None,
+            min_interval: Duration::from_secs(2),
+            last_reply: HashMap::new(),
+        }
+    }
+
+    /// Answers SOURCE queries with `source`, usually a URL for the bot's code.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// How long to wait after replying to a host before replying to it again. Defaults to 2
+    /// seconds.
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// The reply to send to `message`, if it's a CTCP query we answer.
+    pub fn handle(&mut self, message: &Message) -> Option<Message> {
+        self.handle_at(message, Instant::now())
+    }
+
+    /// As `handle`, with `now` as when the reply would be sent, which is checked against the last
+    /// reply to the same host.
+    pub fn handle_at(&mut self, message: &Message, now: Instant) -> Option<Message> {
+        let privmsg = message.as_privmsg()?;
+        let (command, argument) = parse_query(privmsg.text)?;
+        let reply = self.reply(&command, argument)?;
+
+        let min_interval = self.min_interval;
+        self.last_reply.retain(|_, at| now.duration_since(*at) < min_interval);
+        let key = privmsg.from.host().unwrap_or(privmsg.from.nickname());
+        if self.last_reply.contains_key(key) {
+            debug!("Not answering CTCP {} because we replied recently: {}", command, message);
+            return None;
+        }
+        self.last_reply.insert(key.into(), now);
+
+        let text = format!("\u{1}{} {}\u{1}", command, reply);
+        Some(Message::notice(privmsg.from.nickname(), &text))
+    }
+
+    fn reply(&self, command: &str, argument: &str) -> Option<String> {
+        match command {
+            "VERSION" => Some(self.version.clone()),
+            "PING" => Some(argument.into()),
+            "TIME" => ServerTime::from_system_time(SystemTime::now()).map(|t| t.to_string()),
+            "SOURCE" => self.source.clone(),
+            "CLIENTINFO" => {
+                let mut supported = vec!["CLIENTINFO", "PING"];
+                if self.source.is_some() {
+                    supported.push("SOURCE");
+                }
+                supported.extend(&["TIME", "VERSION"]);
+                Some(supported.join(" "))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<S: Read + Write> EventHandler<S> for CtcpResponder {
+    fn on_privmsg(&mut self,
+                  client: &mut Client<S>,
+                  _privmsg: &Privmsg,
+                  message: &Message)
+                  -> io::Result<()> {
+        match self.handle(message) {
+            Some(reply) => client.send(&reply),
+            None => Ok(()),
+        }
+    }
+}
+
+// Splits "\x01COMMAND argument\x01" into its (uppercased) command and argument. The closing \x01
+// is optional, since some clients leave it off.
+pub(crate) fn parse_query(text: &str) -> Option<(String, &str)> {
+    let body = text.strip_prefix('\u{1}')?;
+    let body = body.strip_suffix('\u{1}').unwrap_or(body);
+    let (command, argument) = body.split_once(' ').unwrap_or((body, ""));
+    if command.is_empty() {
+        return None;
+    }
+    Some((command.to_ascii_uppercase(), argument))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use std::time::Instant;
+    use message::Message;
+
+    #[test]
+    fn answers_queries() {
+        let mut responder = CtcpResponder::new("mybot 1.0")
+            .with_source("https://example.com/mybot")
+            .with_min_interval(Duration::from_secs(0));
+
+        assert_eq!(responder.handle(&query("VERSION")),
+                   Some(Message::notice("nick", "\u{1}VERSION mybot 1.0\u{1}")));
+        assert_eq!(responder.handle(&query("PING 1234567890")),
+                   Some(Message::notice("nick", "\u{1}PING 1234567890\u{1}")));
+        assert_eq!(responder.handle(&query("SOURCE")),
+                   Some(Message::notice("nick", "\u{1}SOURCE https://example.com/mybot\u{1}")));
+        let supported = "\u{1}CLIENTINFO CLIENTINFO PING SOURCE TIME VERSION\u{1}";
+        assert_eq!(responder.handle(&query("CLIENTINFO")),
+                   Some(Message::notice("nick", supported)));
+
+        let time = responder.handle(&query("TIME")).unwrap();
+        assert!(time.as_notice().unwrap().text.starts_with("\u{1}TIME 20"));
+    }
+
+    #[test]
+    fn ignores_others() {
+        let mut responder = CtcpResponder::new("mybot 1.0");
+
+        assert_eq!(responder.handle(&query("ACTION waves")), None);
+        assert_eq!(responder.handle(&query("SOURCE")), None);
+        let plain = message(":nick!user@host PRIVMSG me :VERSION\r\n");
+        assert_eq!(responder.handle(&plain), None);
+    }
+
+    #[test]
+    fn rate_limited_per_host() {
+        let mut responder = CtcpResponder::new("mybot 1.0");
+        let start = Instant::now();
+
+        assert!(responder.handle_at(&query("VERSION"), start).is_some());
+        let same_host = message(":other!user@host PRIVMSG me :\u{1}PING 1\u{1}\r\n");
+        assert!(responder.handle_at(&same_host, start + Duration::from_secs(1)).is_none());
+        let other_host = message(":other!user@elsewhere PRIVMSG me :\u{1}PING 1\u{1}\r\n");
+        assert!(responder.handle_at(&other_host, start + Duration::from_secs(1)).is_some());
+
+        assert!(responder.handle_at(&query("VERSION"), start + Duration::from_secs(2)).is_some());
+    }
+
+    fn query(query: &str) -> Message {
+        message(&format!(":nick!user@host PRIVMSG me :\u{1}{}\u{1}\r\n", query))
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+
+use ctcp;
+use message::Message;
+use message::UserInfo;
+
+/// A DCC request, sent over CTCP to set up a direct connection between two users.
+///
+/// This only deals with the messages; making the connection and transferring the file is up to
+/// you.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dcc {
+    /// Someone offering us a file.
+    Send(DccSend),
+    /// The receiver asking to continue an interrupted transfer part way through.
+    Resume(DccResume),
+    /// The sender agreeing to a `Resume`.
+    Accept(DccResume),
+}
+
+/// A DCC SEND offer.
+///
+/// Normally the sender listens for the receiver to connect. For passive (or reverse) DCC, used
+/// when the sender can't accept connections (e.g. behind NAT), the offer has port 0 and a token;
+/// the receiver listens instead, and replies with a SEND giving its own address and port along
+/// with the same token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DccSend {
+    pub filename: String,
+    pub address: IpAddr,
+    pub port: u16,
+    /// The file's size in bytes, if the sender said.
+    pub size: Option<u64>,
+    /// Identifies a passive offer, so the reply can be matched up with it.
+    pub token: Option<String>,
+}
+
+/// The details of a DCC RESUME or ACCEPT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DccResume {
+    pub filename: String,
+    /// The port from the SEND being resumed, which identifies it (0 for passive offers).
+    pub port: u16,
+    /// How many bytes we already have, so where the transfer should continue from.
+    pub position: u64,
+    /// The token from the SEND being resumed, for passive offers.
+    pub token: Option<String>,
+}
+
+impl Message {
+    /// Parses a DCC request sent to us in a PRIVMSG, along with who sent it.
+    pub fn as_dcc(&self) -> Option<(&UserInfo, Dcc)> {
+        let privmsg = self.as_privmsg()?;
+        let (command, arguments) = ctcp::parse_query(privmsg.text)?;
+        if command != "DCC" {
+            return None;
+        }
+        let dcc = Dcc::parse(arguments);
+        if dcc.is_none() {
+            warn!("Not parsing message as Dcc because it isn't a request we understand: {}",
+                  self);
+        }
+        Some((privmsg.from, dcc?))
+    }
+}
+
+impl Dcc {
+    /// Parses the arguments of a CTCP DCC query, e.g. `SEND file.txt 3232235777 5000 1024`.
+    pub fn parse(arguments: &str) -> Option<Self> {
+        let (kind, rest) = arguments.split_once(' ')?;
+        let (filename, rest) = split_filename(rest)?;
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        match &kind.to_ascii_uppercase()[..] {
+            "SEND" if (2..=4).contains(&fields.len()) => {
+                Some(Dcc::Send(DccSend {
+                    filename: filename,
+                    address: parse_address(fields[0])?,
+                    port: fields[1].parse().ok()?,
+                    size: match fields.get(2) {
+                        Some(size) => Some(size.parse().ok()?),
+                        None => None,
+                    },
+                    token: fields.get(3).map(|token| token.to_string()),
+                }))
+            }
+            "RESUME" | "ACCEPT" if (2..=3).contains(&fields.len()) => {
+                let resume = DccResume {
+                    filename: filename,
+                    port: fields[0].parse().ok()?,
+                    position: fields[1].parse().ok()?,
+                    token: fields.get(2).map(|token| token.to_string()),
+                };
+                if kind.eq_ignore_ascii_case("RESUME") {
+                    Some(Dcc::Resume(resume))
+                } else {
+                    Some(Dcc::Accept(resume))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The PRIVMSG sending this to `to`.
+    pub fn message(&self, to: &str) -> Message {
+        let arguments = match *self {
+            Dcc::Send(ref send) => {
+                let mut arguments = format!("SEND {} {} {}",
+                                            quote_filename(&send.filename),
+                                            format_address(send.address),
+                                            send.port);
+                // The token goes after the size, so the size has to be there if the token is.
+                match (send.size, &send.token) {
+                    (size, Some(token)) => {
+                        arguments += &format!(" {} {}", size.unwrap_or(0), token)
+                    }
+                    (Some(size), None) => arguments += &format!(" {}", size),
+                    (None, None) => (),
+                }
+                arguments
+            }
+            Dcc::Resume(ref resume) => format!("RESUME {}", resume.arguments()),
+            Dcc::Accept(ref resume) => format!("ACCEPT {}", resume.arguments()),
+        };
+        Message::privmsg(to, &format!("\u{1}DCC {}\u{1}", arguments))
+    }
+}
+
+impl DccSend {
+    /// Whether this is a passive offer, which we have to listen for the connection for.
+    pub fn is_passive(&self) -> bool {
+        self.port == 0 && self.token.is_some()
+    }
+
+    /// The reply to a passive offer, saying we're listening on `address` and `port`.
+    pub fn passive_reply(&self, address: IpAddr, port: u16) -> Dcc {
+        Dcc::Send(DccSend {
+            address: address,
+            port: port,
+            ..self.clone()
+        })
+    }
+
+    /// Asks to continue this transfer from `position` bytes in. Wait for the sender's `Accept`
+    /// before connecting.
+    pub fn resume(&self, position: u64) -> Dcc {
+        Dcc::Resume(DccResume {
+            filename: self.filename.clone(),
+            port: self.port,
+            position: position,
+            token: self.token.clone(),
+        })
+    }
+}
+
+impl DccResume {
+    /// The sender's reply agreeing to this `Resume`.
+    pub fn accept(&self) -> Dcc {
+        Dcc::Accept(self.clone())
+    }
+
+    fn arguments(&self) -> String {
+        let mut arguments = format!("{} {} {}",
+                                    quote_filename(&self.filename),
+                                    self.port,
+                                    self.position);
+        if let Some(ref token) = self.token {
+            arguments += &format!(" {}", token);
+        }
+        arguments
+    }
+}
+
+// Filenames with spaces are quoted.
+fn split_filename(arguments: &str) -> Option<(String, &str)> {
+    match arguments.strip_prefix('"') {
+        Some(quoted) => {
+            let (filename, rest) = quoted.split_once('"')?;
+            Some((filename.into(), rest))
+        }
+        None => {
+            let (filename, rest) = arguments.split_once(' ')?;
+            Some((filename.into(), rest))
+        }
+    }
+}
+
+fn quote_filename(filename: &str) -> String {
+    if filename.contains(' ') {
+        format!("\"{}\"", filename)
+    } else {
+        filename.into()
+    }
+}
+
+// IPv4 addresses are sent as a single number, IPv6 ones as usual.
+fn parse_address(address: &str) -> Option<IpAddr> {
+    match address.parse::<u32>() {
+        Ok(number) => Some(IpAddr::V4(Ipv4Addr::from(number))),
+        Err(_) => address.parse().ok(),
+    }
+}
+
+fn format_address(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4) => u32::from(v4).to_string(),
+        IpAddr::V6(v6) => v6.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use std::net::Ipv4Addr;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn send() {
+        let message = message(":nick!user@host PRIVMSG me :\u{1}DCC SEND \"my file.txt\" \
+                               3232235777 5000 1024\u{1}\r\n");
+        let (from, dcc) = message.as_dcc().unwrap();
+
+        assert_eq!(from, &UserInfo::of_nickname_user_host("nick", "user", "host"));
+        assert_eq!(dcc,
+                   Dcc::Send(DccSend {
+                       filename: "my file.txt".into(),
+                       address: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
+                       port: 5000,
+                       size: Some(1024),
+                       token: None,
+                   }));
+        assert_eq!(dcc.message("nick"),
+                   Message::privmsg("nick",
+                                    "\u{1}DCC SEND \"my file.txt\" 3232235777 5000 1024\u{1}"));
+    }
+
+    #[test]
+    fn passive() {
+        let offer = Dcc::parse("SEND file.txt 3232235777 0 1024 42").unwrap();
+        let send = match offer {
+            Dcc::Send(send) => send,
+            other => panic!("Expected a SEND, got {:?}", other),
+        };
+        assert!(send.is_passive());
+
+        let reply = send.passive_reply(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 6000);
+        assert_eq!(reply.message("nick"),
+                   Message::privmsg("nick", "\u{1}DCC SEND file.txt 167772161 6000 1024 42\u{1}"));
+    }
+
+    #[test]
+    fn resume_and_accept() {
+        let send = DccSend {
+            filename: "file.txt".into(),
+            address: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
+            port: 5000,
+            size: Some(1024),
+            token: None,
+        };
+
+        let resume = send.resume(512);
+        assert_eq!(resume.message("nick"),
+                   Message::privmsg("nick", "\u{1}DCC RESUME file.txt 5000 512\u{1}"));
+
+        let message = message(":nick!user@host PRIVMSG me :\u{1}DCC RESUME file.txt 5000 \
+                               512\u{1}\r\n");
+        let resume = match message.as_dcc().unwrap().1 {
+            Dcc::Resume(resume) => resume,
+            other => panic!("Expected a RESUME, got {:?}", other),
+        };
+        assert_eq!(resume.position, 512);
+        assert_eq!(resume.accept().message("nick"),
+                   Message::privmsg("nick", "\u{1}DCC ACCEPT file.txt 5000 512\u{1}"));
+    }
+
+    #[test]
+    fn not_dcc() {
+        assert_eq!(Dcc::parse("CHAT chat 3232235777"), None);
+        assert_eq!(message(":nick!user@host PRIVMSG me :\u{1}VERSION\u{1}\r\n").as_dcc(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
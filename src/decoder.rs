@@ -0,0 +1,221 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::io;
+use std::io::ErrorKind;
+
+use encoding::TextEncoding;
+use message::MAX_LINE_LENGTH;
+use message::Message;
+use trace;
+
+/// The longest line `MessageDecoder`, `IrcCodec` and `IrcStream` accept by default: the 512 bytes
+/// allowed by the RFC plus the 8191 bytes the IRCv3 spec allows for message tags.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = MAX_LINE_LENGTH + 8191;
+
+/// Received a line longer than we're willing to buffer. This is returned inside an `InvalidData`
+/// `io::Error`; use `OversizedLine::of` to pick it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OversizedLine {
+    max_line_length: usize,
+}
+
+impl OversizedLine {
+    /// The `OversizedLine` inside `error`, if that's what it is.
+    pub fn of(error: &io::Error) -> Option<&OversizedLine> {
+        error.get_ref().and_then(|inner| inner.downcast_ref())
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+}
+
+impl Error for OversizedLine {
+    fn description(&self) -> &str {
+        "received line too long"
+    }
+}
+
+impl Display for OversizedLine {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "Received a line longer than {} bytes", self.max_line_length)
+    }
+}
+
+/// Turns bytes into `Message`s however they arrive, for I/O that isn't a blocking `Read` (mio, or
+/// a custom transport) and so can't use `IrcStream`.
+///
+/// `feed` it whatever was received, then call `decode` until it returns `None`. Partial lines are
+/// kept until the rest arrives. A line that grows past the maximum length without ending is an
+/// error, so a misbehaving server can't make us buffer forever.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageDecoder {
+    max_line_length: usize,
+    encoding: TextEncoding,
+    buffer: Vec<u8>,
+    // Where the lines we haven't decoded yet start. Decoded lines are only removed from the buffer
+    // when more is fed, so that decoding a big read doesn't keep shifting the rest of it down.
+    start: usize,
+    // How far past the start we've already looked for a line ending.
+    searched: usize,
+}
+
+impl MessageDecoder {
+    pub fn new() -> Self {
+        Self::with_max_line_length(DEFAULT_MAX_LINE_LENGTH)
+    }
+
+    /// Creates a decoder which rejects received lines longer than `max_line_length` bytes,
+    /// including the CRLF.
+    pub fn with_max_line_length(max_line_length: usize) -> Self {
+        MessageDecoder {
+            max_line_length: max_line_length,
+            encoding: TextEncoding::default(),
+            buffer: Vec::new(),
+            start: 0,
+            searched: 0,
+        }
+    }
+
+    /// Sets how text is encoded, as with `IrcStream::with_encoding`.
+    pub fn with_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    /// Adds received bytes, which can end (or start) partway through a line.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The next complete message, or `None` if we need more input first.
+    ///
+    /// A line that's too long or can't be parsed is an `InvalidData` error. It's dropped from the
+    /// buffer, so it's fine to carry on calling this. (The end of a line that was too long is
+    /// likely to fail to parse once it arrives.)
+    pub fn decode(&mut self) -> io::Result<Option<Message>> {
+        let pending = &self.buffer[self.start..];
+        let end = match line_end(pending, &mut self.searched, self.max_line_length) {
+            Ok(Some(end)) => end,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                let newline = pending.iter().position(|&c| c == b'\n');
+                self.start += newline.map_or(pending.len(), |newline| newline + 1);
+                return Err(e);
+            }
+        };
+        let line = self.start..self.start + end;
+        self.start += end;
+        let line = self.encoding.decode(&self.buffer[line])?;
+        match Message::parse(&line) {
+            Ok((message, _)) => {
+                trace::received(&message);
+                Ok(Some(message))
+            }
+            Err(parse_error) => Err(io::Error::new(ErrorKind::InvalidData, parse_error)),
+        }
+    }
+
+    /// How many bytes are waiting for the rest of their line.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+}
+
+impl Default for MessageDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Iterator for MessageDecoder {
+    type Item = io::Result<Message>;
+
+    /// Decodes everything fed so far, as `decode` does.
+    fn next(&mut self) -> Option<io::Result<Message>> {
+        self.decode().transpose()
+    }
+}
+
+// Finds where the first line in `buffer` ends, carrying on from where the last search left off.
+pub(crate) fn line_end(buffer: &[u8],
+                       searched: &mut usize,
+                       max_line_length: usize)
+                       -> io::Result<Option<usize>> {
+    let start = (*searched).min(buffer.len());
+    let newline = buffer[start..].iter().position(|&c| c == b'\n');
+    let end = match newline {
+        Some(offset) => start + offset + 1,
+        None if buffer.len() > max_line_length => {
+            *searched = 0;
+            return Err(too_long(max_line_length));
+        }
+        None => {
+            *searched = buffer.len();
+            return Ok(None);
+        }
+    };
+    *searched = 0;
+    if end > max_line_length {
+        return Err(too_long(max_line_length));
+    }
+    Ok(Some(end))
+}
+
+pub(crate) fn too_long(max_line_length: usize) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData,
+                   OversizedLine { max_line_length: max_line_length })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn partial_lines() {
+        let mut decoder = MessageDecoder::new();
+        decoder.feed(b"PING 1\r\nPI");
+        assert_eq!(decoder.decode().unwrap(), Some(message("PING 1\r\n")));
+        assert_eq!(decoder.decode().unwrap(), None);
+        assert_eq!(decoder.buffered(), 2);
+
+        decoder.feed(b"NG 2\r");
+        assert_eq!(decoder.decode().unwrap(), None);
+        decoder.feed(b"\nPING 3\r\nPING 4\r\n");
+        let messages: Vec<Message> = decoder.by_ref().map(|m| m.unwrap()).collect();
+        assert_eq!(messages,
+                   vec![message("PING 2\r\n"), message("PING 3\r\n"), message("PING 4\r\n")]);
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn too_long_and_invalid() {
+        let mut decoder = MessageDecoder::with_max_line_length(10);
+        decoder.feed(b"PING 123456789");
+        let error = decoder.decode().unwrap_err();
+        assert_eq!(OversizedLine::of(&error).map(|e| e.max_line_length()), Some(10));
+        assert_eq!(decoder.buffered(), 0);
+
+        decoder.feed(b"PING 123456789\r\n!!!\r\nPING 1\r\n");
+        assert!(decoder.decode().is_err());
+        assert!(decoder.decode().is_err());
+        assert_eq!(decoder.decode().unwrap(), Some(message("PING 1\r\n")));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
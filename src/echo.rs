@@ -0,0 +1,107 @@
+use casemapping::IrcCase;
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+/// Recognises our own messages being sent back to us when the `echo-message` capability is
+/// enabled, by comparing the sender of PRIVMSG, NOTICE and TAGMSG messages with our nickname.
+///
+/// Pass every received message to `handle` so nickname changes are followed. If you need to match
+/// an echo up with the exact message that was sent, send it with a `LabelTracker` instead: the
+/// echo comes back labeled, as the response to that message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EchoDetector {
+    nick: String,
+    case: IrcCase,
+}
+
+impl EchoDetector {
+    pub fn new(nick: &str) -> Self {
+        EchoDetector {
+            nick: nick.into(),
+            case: IrcCase::default(),
+        }
+    }
+
+    pub fn nick(&self) -> &str {
+        &self.nick
+    }
+
+    /// Sets our nickname, e.g. if the one we asked for at registration was taken.
+    pub fn set_nick(&mut self, nick: &str) {
+        self.nick = nick.into();
+    }
+
+    /// Sets the `CASEMAPPING` the server advertised, so an echo is recognised whatever case the
+    /// server sends our nickname back in. Defaults to `rfc1459`.
+    pub fn set_casemapping(&mut self, case: IrcCase) {
+        self.case = case;
+    }
+
+    /// Follows changes to our nickname.
+    pub fn handle(&mut self, message: &Message) {
+        if message.command != commands::NICK() || message.arguments.is_empty() {
+            return;
+        }
+        if self.is_from_us(message) {
+            self.nick = message.arguments[0].clone();
+        }
+    }
+
+    /// Whether `message` is a chat message that we sent ourselves.
+    pub fn is_echo(&self, message: &Message) -> bool {
+        let is_chat = message.command == commands::PRIVMSG() ||
+                      message.command == commands::NOTICE() ||
+                      message.command == commands::TAGMSG();
+        is_chat && self.is_from_us(message)
+    }
+
+    fn is_from_us(&self, message: &Message) -> bool {
+        match message.prefix {
+            Prefix::User(ref user) => self.case.eq(user.nickname(), &self.nick),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn own_privmsg_is_echo() {
+        let detector = EchoDetector::new("me");
+
+        assert!(detector.is_echo(&message(":me!user@host PRIVMSG #chan :Hello\r\n")));
+        assert!(detector.is_echo(&message(":ME!user@host NOTICE #chan :Hello\r\n")));
+    }
+
+    #[test]
+    fn other_traffic_is_not_echo() {
+        let detector = EchoDetector::new("me");
+
+        assert!(!detector.is_echo(&message(":you!user@host PRIVMSG #chan :Hello\r\n")));
+        assert!(!detector.is_echo(&message(":me!user@host JOIN #chan\r\n")));
+        assert!(!detector.is_echo(&message(":irc.host NOTICE me :Hello\r\n")));
+    }
+
+    #[test]
+    fn follows_nick_changes() {
+        let mut detector = EchoDetector::new("me");
+
+        detector.handle(&message(":you!user@host NICK :someone\r\n"));
+        assert_eq!(detector.nick(), "me");
+        detector.handle(&message(":me!user@host NICK :newme\r\n"));
+        assert_eq!(detector.nick(), "newme");
+        assert!(detector.is_echo(&message(":newme!user@host PRIVMSG #chan :Hello\r\n")));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+use std::io;
+use std::io::ErrorKind;
+use std::str;
+
+#[cfg(feature = "encoding")]
+use encoding_rs::Encoding;
+
+/// How text on a connection is encoded.
+///
+/// IRC itself doesn't say, and while almost everything now uses UTF-8, some older networks and
+/// clients still send Latin-1 or Windows-1252. Messages are always UTF-8 once received; this
+/// decides how bytes from the server get there, and how ours are sent.
+///
+/// `Charset` only exists with the `encoding` feature, so matching on this needs a wildcard arm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TextEncoding {
+    /// UTF-8, with anything invalid replaced by U+FFFD. This is the default.
+    #[default]
+    Utf8Lossy,
+    /// UTF-8, with lines that aren't valid UTF-8 being an `InvalidData` error.
+    Utf8Strict,
+    /// UTF-8, except that words which aren't valid UTF-8 are taken to be Windows-1252 (which
+    /// Latin-1 is almost all of). We send UTF-8.
+    Utf8OrCp1252,
+    /// A fixed character set for both receiving and sending, e.g. `encoding_rs::WINDOWS_1251` for
+    /// Cyrillic networks. Characters it can't represent are sent as HTML numeric references.
+    #[cfg(feature = "encoding")]
+    Charset(&'static Encoding),
+}
+
+impl TextEncoding {
+    /// Converts a received line to UTF-8.
+    pub fn decode<'a>(&self, line: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+        match *self {
+            TextEncoding::Utf8Lossy => Ok(Cow::Borrowed(line)),
+            TextEncoding::Utf8Strict => {
+                match str::from_utf8(line) {
+                    Ok(_) => Ok(Cow::Borrowed(line)),
+                    Err(e) => Err(io::Error::new(ErrorKind::InvalidData, e)),
+                }
+            }
+            TextEncoding::Utf8OrCp1252 => {
+                if str::from_utf8(line).is_ok() {
+                    return Ok(Cow::Borrowed(line));
+                }
+                // Spaces are the same in both, so the message's structure survives this.
+                let words = line.split(|&b| b == b' ').map(|word| match str::from_utf8(word) {
+                    Ok(word) => word.to_string(),
+                    Err(_) => word.iter().map(|&b| cp1252_char(b)).collect(),
+                });
+                Ok(Cow::Owned(words.collect::<Vec<_>>().join(" ").into_bytes()))
+            }
+            #[cfg(feature = "encoding")]
+            TextEncoding::Charset(encoding) => {
+                let (text, _) = encoding.decode_without_bom_handling(line);
+                Ok(match text {
+                    Cow::Borrowed(text) => Cow::Borrowed(text.as_bytes()),
+                    Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+                })
+            }
+        }
+    }
+
+    /// Converts a line we're sending from UTF-8.
+    pub fn encode<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        match *self {
+            #[cfg(feature = "encoding")]
+            TextEncoding::Charset(encoding) => {
+                let text = String::from_utf8_lossy(line);
+                let (bytes, _, _) = encoding.encode(&text);
+                Cow::Owned(bytes.into_owned())
+            }
+            _ => Cow::Borrowed(line),
+        }
+    }
+}
+
+// Windows-1252 is Latin-1 except for 0x80 to 0x9F, where it has printable characters instead of
+// control codes. The five it leaves undefined are kept as the control codes.
+fn cp1252_char(b: u8) -> char {
+    const HIGH: [char; 32] = ['€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ',
+                              '\u{8d}', 'Ž', '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—',
+                              '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ'];
+    match b {
+        0x80..=0x9f => HIGH[(b - 0x80) as usize],
+        _ => b as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lossy_and_strict() {
+        let line = b"PRIVMSG #chan :caf\xe9\r\n";
+        assert_eq!(TextEncoding::Utf8Lossy.decode(line).unwrap(), &line[..]);
+        assert_eq!(TextEncoding::Utf8Strict.decode(line).unwrap_err().kind(),
+                   ErrorKind::InvalidData);
+        assert_eq!(TextEncoding::Utf8Strict.decode("café".as_bytes()).unwrap(),
+                   "café".as_bytes());
+    }
+
+    #[test]
+    fn cp1252_fallback_per_word() {
+        let line = b"PRIVMSG #chan :caf\xe9 \xe2\x82\xac5 \x805\r\n";
+        assert_eq!(TextEncoding::Utf8OrCp1252.decode(line).unwrap(),
+                   "PRIVMSG #chan :café €5 €5\r\n".as_bytes());
+        assert_eq!(TextEncoding::Utf8OrCp1252.encode("café".as_bytes()), "café".as_bytes());
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn charset() {
+        let cyrillic = TextEncoding::Charset(::encoding_rs::WINDOWS_1251);
+        assert_eq!(cyrillic.decode(b"PRIVMSG #chan :\xcf\xf0\xe8\xe2\xe5\xf2\r\n").unwrap(),
+                   "PRIVMSG #chan :Привет\r\n".as_bytes());
+        assert_eq!(cyrillic.encode("Привет".as_bytes()), &b"\xcf\xf0\xe8\xe2\xe5\xf2"[..]);
+    }
+}
@@ -0,0 +1,235 @@
+// A hand-rolled parser for the same grammar as the nom one in `parser`, for the hot path of
+// parsing every received line. It makes a single pass over the line, where the nom parser
+// backtracks through the prefix alternatives and makes separate passes to validate and convert.
+//
+// It must accept exactly the lines the nom grammar does and produce the same messages from them;
+// the tests in `parser` check that. Errors are left to `ParseError::invalid`, which uses the nom
+// grammar to work out what went wrong, so this only needs to say whether a line parsed.
+
+use std::str;
+
+use command::Command;
+use message::Arguments;
+use message::Message;
+use message::Tag;
+use message::unescape_tag_value;
+use message_ref::MessageRef;
+use message_ref::PrefixRef;
+use parser::is_host_char;
+use parser::is_nickname_char;
+use parser::is_tag_key_char;
+use parser::is_tag_value_char;
+use parser::is_username_char;
+use parser::make_number;
+use parser::not_space;
+use parser::trailing_char;
+
+/// Parses a message from the start of `input`, returning it along with whatever comes after its
+/// line ending (which can be CRLF, LF or the end of the input).
+pub fn parse_message(input: &[u8]) -> Option<(Message, &[u8])> {
+    let mut tags = Vec::new();
+    let mut arguments = Arguments::new();
+    let line = split(input,
+                     |key, value| tags.push(make_tag(key, value)),
+                     |argument| arguments.push(String::from_utf8_lossy(argument).into_owned()))?;
+    let command = if line.command[0].is_ascii_digit() {
+        make_number(line.command).ok()?
+    } else {
+        Command::try_word(str::from_utf8(line.command).ok()?).ok()?
+    };
+    let message =
+        Message::with_arguments(line.prefix.to_owned(), command, arguments).with_tags(tags);
+    Some((message, line.rest))
+}
+
+/// As `parse_message`, but borrowing from `input`.
+pub fn parse_message_ref(input: &[u8]) -> Option<(MessageRef, &[u8])> {
+    let line = split(input, |_, _| (), |_| ())?;
+    if line.command[0].is_ascii_digit() {
+        make_number(line.command).ok()?;
+    }
+    let message = MessageRef::new(line.tags,
+                                  line.prefix,
+                                  str::from_utf8(line.command).ok()?,
+                                  line.params);
+    Some((message, line.rest))
+}
+
+// The parts of a line, with the tags and parameters as they appear in it.
+struct Line<'a> {
+    tags: &'a [u8],
+    prefix: PrefixRef<'a>,
+    command: &'a [u8],
+    params: &'a [u8],
+    rest: &'a [u8],
+}
+
+// Splits up and validates a line, passing each tag and parameter to the callbacks as it goes.
+fn split<'a, T, P>(input: &'a [u8], on_tag: T, on_param: P) -> Option<Line<'a>>
+    where T: FnMut(&'a [u8], Option<&'a [u8]>),
+          P: FnMut(&'a [u8])
+{
+    let (tags, rest) = match input.first() {
+        Some(&b'@') => split_tags(&input[1..], on_tag)?,
+        _ => (&input[..0], input),
+    };
+    let (prefix, rest) = match rest.first() {
+        Some(&b':') => split_prefix(&rest[1..])?,
+        _ => (PrefixRef::None, rest),
+    };
+    let command_end = match rest.first() {
+        Some(c) if c.is_ascii_alphabetic() => span(rest, 0, |c| c.is_ascii_alphabetic()),
+        Some(c) if c.is_ascii_digit() => span(rest, 0, |c| c.is_ascii_digit()),
+        _ => return None,
+    };
+    let (command, rest) = rest.split_at(command_end);
+    let (params, rest) = split_params(rest, on_param);
+    let rest = match rest.first() {
+        None => rest,
+        Some(&b'\n') => &rest[1..],
+        Some(&b'\r') if rest.get(1) == Some(&b'\n') => &rest[2..],
+        _ => return None,
+    };
+    Some(Line {
+        tags: tags,
+        prefix: prefix,
+        command: command,
+        params: params,
+        rest: rest,
+    })
+}
+
+// `input` starts just after the `@`. Returns the tags up to the space ending them, and what's
+// after the space.
+fn split_tags<'a, T>(input: &'a [u8], mut on_tag: T) -> Option<(&'a [u8], &'a [u8])>
+    where T: FnMut(&'a [u8], Option<&'a [u8]>)
+{
+    let mut position = 0;
+    loop {
+        let key_end = span(input, position, is_tag_key_char);
+        if key_end == position {
+            return None;
+        }
+        let key = &input[position..key_end];
+        position = key_end;
+        let value = if input.get(position) == Some(&b'=') {
+            let value_end = span(input, position + 1, is_tag_value_char);
+            let value = &input[position + 1..value_end];
+            position = value_end;
+            Some(value)
+        } else {
+            None
+        };
+        on_tag(key, value);
+        match input.get(position) {
+            Some(&b';') => position += 1,
+            Some(&b' ') => return Some((&input[..position], &input[position + 1..])),
+            _ => return None,
+        }
+    }
+}
+
+// `input` starts just after the `:`. The forms are tried in the same order as the grammar does,
+// which matters where a prefix could be read as either a nickname or a server.
+fn split_prefix(input: &[u8]) -> Option<(PrefixRef, &[u8])> {
+    let nickname_end = span(input, 0, is_nickname_char);
+    if nickname_end > 0 {
+        let nickname = ascii(&input[..nickname_end]);
+        match input.get(nickname_end) {
+            Some(&b'!') => {
+                let user_end = span(input, nickname_end + 1, is_username_char);
+                let username = str::from_utf8(&input[nickname_end + 1..user_end]).ok();
+                if user_end > nickname_end + 1 && input.get(user_end) == Some(&b'@') {
+                    if let (Some(username), Some((host, rest))) = (username,
+                                                                   host(input, user_end + 1)) {
+                        let prefix = PrefixRef::User {
+                            nickname: nickname,
+                            username: Some(username),
+                            host: Some(host),
+                        };
+                        return Some((prefix, rest));
+                    }
+                }
+            }
+            Some(&b'@') => {
+                if let Some((host, rest)) = host(input, nickname_end + 1) {
+                    let prefix = PrefixRef::User {
+                        nickname: nickname,
+                        username: None,
+                        host: Some(host),
+                    };
+                    return Some((prefix, rest));
+                }
+            }
+            Some(&b' ') => {
+                let prefix = PrefixRef::User {
+                    nickname: nickname,
+                    username: None,
+                    host: None,
+                };
+                return Some((prefix, &input[nickname_end + 1..]));
+            }
+            _ => {}
+        }
+    }
+    host(input, 0).map(|(server, rest)| (PrefixRef::Server(server), rest))
+}
+
+// A host starting at `start` and ending in a space, and what's after the space.
+fn host(input: &[u8], start: usize) -> Option<(&str, &[u8])> {
+    let end = span(input, start, is_host_char);
+    if end > start && input.get(end) == Some(&b' ') {
+        Some((ascii(&input[start..end]), &input[end + 1..]))
+    } else {
+        None
+    }
+}
+
+// Returns the parameters, starting with the space before the first, and what's after them.
+fn split_params<'a, P>(input: &'a [u8], mut on_param: P) -> (&'a [u8], &'a [u8])
+    where P: FnMut(&'a [u8])
+{
+    let mut position = 0;
+    while input.get(position) == Some(&b' ') {
+        let start = position + 1;
+        let end = if input.get(start) == Some(&b':') {
+            let end = span(input, start + 1, trailing_char);
+            on_param(&input[start + 1..end]);
+            end
+        } else {
+            let end = span(input, start, not_space);
+            if end == start {
+                // Two spaces in a row, or a space at the end: not a parameter.
+                break;
+            }
+            on_param(&input[start..end]);
+            end
+        };
+        position = end;
+    }
+    input.split_at(position)
+}
+
+fn make_tag(key: &[u8], value: Option<&[u8]>) -> Tag {
+    Tag {
+        key: ascii(key).into(),
+        value: value.and_then(|value| if value.is_empty() {
+            None
+        } else {
+            Some(unescape_tag_value(&String::from_utf8_lossy(value)))
+        }),
+    }
+}
+
+// Where the run of characters matching `allowed` starting at `start` ends.
+fn span(input: &[u8], start: usize, allowed: fn(u8) -> bool) -> usize {
+    match input[start..].iter().position(|&c| !allowed(c)) {
+        Some(length) => start + length,
+        None => input.len(),
+    }
+}
+
+// Tag keys, nicknames, hosts and commands are only ever made up of ASCII.
+fn ascii(input: &[u8]) -> &str {
+    str::from_utf8(input).unwrap_or_default()
+}
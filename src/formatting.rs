@@ -0,0 +1,591 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+const BOLD: char = '\u{2}';
+const COLOR: char = '\u{3}';
+const HEX_COLOR: char = '\u{4}';
+const MONOSPACE: char = '\u{11}';
+const RESET: char = '\u{f}';
+const REVERSE: char = '\u{16}';
+const ITALIC: char = '\u{1d}';
+const STRIKETHROUGH: char = '\u{1e}';
+const UNDERLINE: char = '\u{1f}';
+
+/// One of the colors IRC clients agree on. Codes 16 to 98 are also widely supported, but look
+/// different from client to client; they're `Extended`, and made with `Color::from_code`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    White,
+    Black,
+    Blue,
+    Green,
+    Red,
+    Brown,
+    Magenta,
+    Orange,
+    Yellow,
+    LightGreen,
+    Cyan,
+    LightCyan,
+    LightBlue,
+    Pink,
+    Grey,
+    LightGrey,
+    Extended(ExtendedColor),
+}
+
+/// One of the extended color codes, 16 to 98. Only `Color::from_code` makes these, so they're
+/// always in range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExtendedColor(u8);
+
+/// The formatting in effect for a piece of text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub monospace: bool,
+    pub reverse: bool,
+    /// `None` for the client's default color.
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+}
+
+/// A piece of formatted text, all with the same style.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span<'a> {
+    pub text: &'a str,
+    pub style: Style,
+}
+
+/// Builds text with IRC formatting, so bots don't have to embed control codes by hand.
+///
+/// Each piece of text is formatted on its own, with formatting reset after it.
+///
+/// ```
+/// use irc::{Color, Fmt, Message};
+///
+/// let text = Fmt::new().bold("alert").color(Color::Red, " failure").plain(" in job 42");
+/// let message = Message::privmsg("#ops", &text.to_string());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fmt {
+    text: String,
+}
+
+impl Color {
+    /// The number used for this color in formatting codes.
+    pub fn code(&self) -> u8 {
+        match *self {
+            Color::White => 0,
+            Color::Black => 1,
+            Color::Blue => 2,
+            Color::Green => 3,
+            Color::Red => 4,
+            Color::Brown => 5,
+            Color::Magenta => 6,
+            Color::Orange => 7,
+            Color::Yellow => 8,
+            Color::LightGreen => 9,
+            Color::Cyan => 10,
+            Color::LightCyan => 11,
+            Color::LightBlue => 12,
+            Color::Pink => 13,
+            Color::Grey => 14,
+            Color::LightGrey => 15,
+            Color::Extended(ExtendedColor(code)) => code,
+        }
+    }
+
+    /// The color with the given code, if it's one IRC has (0 to 98).
+    pub fn from_code(code: u8) -> Option<Self> {
+        let color = match code {
+            0 => Color::White,
+            1 => Color::Black,
+            2 => Color::Blue,
+            3 => Color::Green,
+            4 => Color::Red,
+            5 => Color::Brown,
+            6 => Color::Magenta,
+            7 => Color::Orange,
+            8 => Color::Yellow,
+            9 => Color::LightGreen,
+            10 => Color::Cyan,
+            11 => Color::LightCyan,
+            12 => Color::LightBlue,
+            13 => Color::Pink,
+            14 => Color::Grey,
+            15 => Color::LightGrey,
+            16..=98 => Color::Extended(ExtendedColor(code)),
+            _ => return None,
+        };
+        Some(color)
+    }
+
+    /// The closest of the 256 xterm colors, for showing this in a terminal. The first 16 map to
+    /// the standard terminal colors, so follow the terminal's theme.
+    pub fn ansi_256(&self) -> u8 {
+        match self.code() {
+            code @ 0..=15 => ANSI_16[code as usize],
+            code => ANSI_EXTENDED[code as usize - 16],
+        }
+    }
+
+    /// The color as `(red, green, blue)`, for showing it on a web page. The first 16 are mIRC's
+    /// colors, which most clients copy.
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        if self.code() < 16 {
+            return RGB_16[self.code() as usize];
+        }
+        // The extended colors are all from xterm's 6x6x6 color cube or its grays.
+        match self.ansi_256() {
+            gray @ 232..=255 => {
+                let level = 8 + (gray - 232) * 10;
+                (level, level, level)
+            }
+            cube => {
+                let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+                let cube = cube - 16;
+                (level(cube / 36), level(cube / 6 % 6), level(cube % 6))
+            }
+        }
+    }
+}
+
+const RGB_16: [(u8, u8, u8); 16] = [(255, 255, 255),
+                                    (0, 0, 0),
+                                    (0, 0, 127),
+                                    (0, 147, 0),
+                                    (255, 0, 0),
+                                    (127, 0, 0),
+                                    (156, 0, 156),
+                                    (252, 127, 0),
+                                    (255, 255, 0),
+                                    (0, 252, 0),
+                                    (0, 147, 147),
+                                    (0, 255, 255),
+                                    (0, 0, 252),
+                                    (255, 0, 255),
+                                    (127, 127, 127),
+                                    (210, 210, 210)];
+
+// The terminal colors closest to IRC's first 16.
+const ANSI_16: [u8; 16] = [15, 0, 4, 2, 9, 1, 5, 3, 11, 10, 6, 14, 12, 13, 8, 7];
+
+// IRC colors 16 to 98, which clients that support them agree are these xterm colors.
+const ANSI_EXTENDED: [u8; 83] = [
+    52, 94, 100, 58, 22, 29, 23, 24, 17, 54, 53, 89,
+    88, 130, 142, 64, 28, 35, 30, 25, 18, 91, 90, 125,
+    124, 166, 184, 106, 34, 49, 37, 33, 19, 129, 127, 161,
+    196, 208, 226, 154, 46, 86, 51, 75, 21, 171, 201, 198,
+    203, 215, 227, 191, 83, 122, 87, 111, 63, 177, 207, 205,
+    217, 223, 229, 193, 157, 158, 159, 153, 147, 183, 219, 212,
+    16, 233, 235, 237, 239, 241, 244, 247, 250, 254, 231,
+];
+
+impl ExtendedColor {
+    pub fn code(&self) -> u8 {
+        self.0
+    }
+}
+
+impl<'a> Span<'a> {
+    /// Splits formatted text into spans, removing the formatting codes. Empty spans are left out.
+    pub fn parse(text: &'a str) -> Vec<Span<'a>> {
+        let mut spans = Vec::new();
+        let mut style = Style::default();
+        let mut start = 0;
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let rest = &text[i + c.len_utf8()..];
+            let mut next_style = style;
+            match c {
+                BOLD => next_style.bold = !style.bold,
+                ITALIC => next_style.italic = !style.italic,
+                UNDERLINE => next_style.underline = !style.underline,
+                STRIKETHROUGH => next_style.strikethrough = !style.strikethrough,
+                MONOSPACE => next_style.monospace = !style.monospace,
+                REVERSE => next_style.reverse = !style.reverse,
+                RESET => next_style = Style::default(),
+                COLOR => {
+                    match color_code(rest) {
+                        Some(code) => {
+                            next_style.foreground = Color::from_code(code);
+                            let background = rest[digits(rest)..].strip_prefix(',');
+                            if let Some(code) = background.and_then(color_code) {
+                                next_style.background = Color::from_code(code);
+                            }
+                        }
+                        None => {
+                            next_style.foreground = None;
+                            next_style.background = None;
+                        }
+                    }
+                }
+                // Hex colors aren't widely supported, so skip over them.
+                HEX_COLOR => (),
+                _ => continue,
+            }
+
+            if i > start {
+                spans.push(Span {
+                    text: &text[start..i],
+                    style: style,
+                });
+            }
+            style = next_style;
+            start = text.len() - rest.len() + code_length(c, rest);
+            while chars.peek().is_some_and(|&(j, _)| j < start) {
+                chars.next();
+            }
+        }
+        if start < text.len() {
+            spans.push(Span {
+                text: &text[start..],
+                style: style,
+            });
+        }
+        spans
+    }
+}
+
+// How long the color code after a formatting character is.
+fn code_length(c: char, rest: &str) -> usize {
+    match c {
+        COLOR => {
+            let foreground = digits(rest);
+            if foreground == 0 {
+                return 0;
+            }
+            match rest[foreground..].strip_prefix(',') {
+                Some(background) if digits(background) > 0 => foreground + 1 + digits(background),
+                _ => foreground,
+            }
+        }
+        HEX_COLOR => {
+            let hex = |s: &str| s.bytes().take(6).take_while(|b| b.is_ascii_hexdigit()).count();
+            let foreground = hex(rest);
+            if foreground != 6 {
+                return 0;
+            }
+            match rest[6..].strip_prefix(',') {
+                Some(background) if hex(background) == 6 => 13,
+                _ => 6,
+            }
+        }
+        _ => 0,
+    }
+}
+
+// How many digits (up to 2) a color code has.
+fn digits(text: &str) -> usize {
+    text.bytes().take(2).take_while(|b| b.is_ascii_digit()).count()
+}
+
+fn color_code(text: &str) -> Option<u8> {
+    let digits = digits(text);
+    if digits == 0 {
+        return None;
+    }
+    text[..digits].parse().ok()
+}
+
+/// Converts formatted IRC text to text with ANSI escape codes, for showing in a terminal.
+/// Monospace has no equivalent, so is left out.
+pub fn to_ansi(text: &str) -> String {
+    let mut ansi = String::new();
+    for span in Span::parse(text) {
+        let style = span.style;
+        let mut codes = Vec::new();
+        if style.bold {
+            codes.push("1".to_string());
+        }
+        if style.italic {
+            codes.push("3".to_string());
+        }
+        if style.underline {
+            codes.push("4".to_string());
+        }
+        if style.reverse {
+            codes.push("7".to_string());
+        }
+        if style.strikethrough {
+            codes.push("9".to_string());
+        }
+        if let Some(foreground) = style.foreground {
+            codes.push(format!("38;5;{}", foreground.ansi_256()));
+        }
+        if let Some(background) = style.background {
+            codes.push(format!("48;5;{}", background.ansi_256()));
+        }
+
+        if codes.is_empty() {
+            ansi.push_str(span.text);
+        } else {
+            ansi.push_str(&format!("\u{1b}[{}m{}\u{1b}[0m", codes.join(";"), span.text));
+        }
+    }
+    ansi
+}
+
+impl Fmt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds text without any formatting.
+    pub fn plain(mut self, text: &str) -> Self {
+        self.text.push_str(text);
+        self
+    }
+
+    pub fn bold(self, text: &str) -> Self {
+        self.toggled(BOLD, text)
+    }
+
+    pub fn italic(self, text: &str) -> Self {
+        self.toggled(ITALIC, text)
+    }
+
+    pub fn underline(self, text: &str) -> Self {
+        self.toggled(UNDERLINE, text)
+    }
+
+    pub fn strikethrough(self, text: &str) -> Self {
+        self.toggled(STRIKETHROUGH, text)
+    }
+
+    pub fn monospace(self, text: &str) -> Self {
+        self.toggled(MONOSPACE, text)
+    }
+
+    /// Adds text with the foreground and background colors swapped.
+    pub fn reverse(self, text: &str) -> Self {
+        self.toggled(REVERSE, text)
+    }
+
+    pub fn color(mut self, foreground: Color, text: &str) -> Self {
+        self.text.push_str(&format!("{}{:02}", COLOR, foreground.code()));
+        // A comma straight after the color would be taken as the start of a background color, so
+        // separate them with a pair of bold codes, which cancel out.
+        if text.starts_with(',') {
+            self.text.push(BOLD);
+            self.text.push(BOLD);
+        }
+        self.formatted(text)
+    }
+
+    pub fn color_on(mut self, foreground: Color, background: Color, text: &str) -> Self {
+        self.text.push_str(&format!("{}{:02},{:02}", COLOR, foreground.code(), background.code()));
+        self.formatted(text)
+    }
+
+    /// The text, with formatting codes.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    fn toggled(mut self, code: char, text: &str) -> Self {
+        self.text.push(code);
+        self.formatted(text)
+    }
+
+    fn formatted(mut self, text: &str) -> Self {
+        self.text.push_str(text);
+        self.text.push(RESET);
+        self
+    }
+}
+
+impl Display for Fmt {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.text)
+    }
+}
+
+/// Converts formatted IRC text to HTML, with each differently formatted piece in a `<span>` with
+/// inline styles. The text is escaped, so this is safe to put straight into a page.
+pub fn to_html(text: &str) -> String {
+    let mut html = String::new();
+    for span in Span::parse(text) {
+        let style = span.style;
+        let (foreground, background) = if style.reverse {
+            (Some(style.background.unwrap_or(Color::White)),
+             Some(style.foreground.unwrap_or(Color::Black)))
+        } else {
+            (style.foreground, style.background)
+        };
+
+        let mut css = Vec::new();
+        if style.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if style.italic {
+            css.push("font-style:italic".to_string());
+        }
+        match (style.underline, style.strikethrough) {
+            (true, true) => css.push("text-decoration:underline line-through".to_string()),
+            (true, false) => css.push("text-decoration:underline".to_string()),
+            (false, true) => css.push("text-decoration:line-through".to_string()),
+            (false, false) => (),
+        }
+        if style.monospace {
+            css.push("font-family:monospace".to_string());
+        }
+        if let Some(foreground) = foreground {
+            css.push(format!("color:{}", html_color(foreground)));
+        }
+        if let Some(background) = background {
+            css.push(format!("background-color:{}", html_color(background)));
+        }
+
+        let text = escape_html(span.text);
+        if css.is_empty() {
+            html.push_str(&text);
+        } else {
+            html.push_str(&format!("<span style=\"{}\">{}</span>", css.join(";"), text));
+        }
+    }
+    html
+}
+
+fn html_color(color: Color) -> String {
+    let (red, green, blue) = color.rgb();
+    format!("#{:02x}{:02x}{:02x}", red, green, blue)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_codes() {
+        let text = Fmt::new().bold("alert").color(Color::Red, " failure").plain(" in job 42");
+        assert_eq!(text.as_str(), "\u{2}alert\u{f}\u{3}04 failure\u{f} in job 42");
+
+        let text = Fmt::new().italic("a").underline("b").color_on(Color::White, Color::Blue, "c");
+        assert_eq!(text.to_string(), "\u{1d}a\u{f}\u{1f}b\u{f}\u{3}00,02c\u{f}");
+    }
+
+    #[test]
+    fn digits_and_commas_after_colors() {
+        assert_eq!(Fmt::new().color(Color::from_code(52).unwrap(), "5 items").as_str(),
+                   "\u{3}525 items\u{f}");
+        assert_eq!(Fmt::new().color(Color::Red, ",5").as_str(), "\u{3}04\u{2}\u{2},5\u{f}");
+    }
+
+    #[test]
+    fn parses_spans() {
+        let text = "\u{2}alert\u{f}\u{3}04 failure\u{3} in \u{3}4,2job\u{1d}42";
+        let red = Style {
+            foreground: Some(Color::Red),
+            ..Style::default()
+        };
+        let red_on_blue = Style {
+            background: Some(Color::Blue),
+            ..red
+        };
+        assert_eq!(Span::parse(text),
+                   vec![span("alert",
+                             Style {
+                                 bold: true,
+                                 ..Style::default()
+                             }),
+                        span(" failure", red),
+                        span(" in ", Style::default()),
+                        span("job", red_on_blue),
+                        span("42",
+                             Style {
+                                 italic: true,
+                                 ..red_on_blue
+                             })]);
+    }
+
+    #[test]
+    fn parses_odd_colors() {
+        // Only two digits belong to the color, a comma without digits after isn't a background,
+        // and 99 is the default color.
+        assert_eq!(Span::parse("\u{3}045,\u{3}99,99x\u{4}ff0000hex"),
+                   vec![span("5,",
+                             Style {
+                                 foreground: Some(Color::Red),
+                                 ..Style::default()
+                             }),
+                        span("x", Style::default()),
+                        span("hex", Style::default())]);
+    }
+
+    #[test]
+    fn round_trips_fmt() {
+        let text = Fmt::new().underline("a").color_on(Color::from_code(52).unwrap(), Color::White, "b");
+        let spans = Span::parse(text.as_str());
+        assert!(spans[0].style.underline);
+        assert_eq!(spans[1].style.foreground, Some(Color::from_code(52).unwrap()));
+        assert_eq!(spans[1].style.background, Some(Color::White));
+    }
+
+    #[test]
+    fn ansi() {
+        assert_eq!(to_ansi("plain \u{2}bold\u{2} \u{3}4,52red\u{f}"),
+                   "plain \u{1b}[1mbold\u{1b}[0m \u{1b}[38;5;9;48;5;196mred\u{1b}[0m");
+    }
+
+    #[test]
+    fn html() {
+        assert_eq!(to_html("<b> & \u{2}\u{1f}bold\u{f} \u{3}4,52red"),
+                   "&lt;b&gt; &amp; <span style=\"font-weight:bold;text-decoration:underline\">\
+                    bold</span> <span style=\"color:#ff0000;background-color:#ff0000\">red</span>");
+        assert_eq!(to_html("\u{16}\u{3}2reversed"),
+                   "<span style=\"color:#ffffff;background-color:#00007f\">reversed</span>");
+    }
+
+    #[test]
+    fn color_codes() {
+        for code in 0..99 {
+            assert_eq!(Color::from_code(code).unwrap().code(), code);
+        }
+        assert_eq!(Color::from_code(99), None);
+        assert_eq!(Color::Red.ansi_256(), 9);
+        assert_eq!(Color::from_code(98).unwrap().ansi_256(), 231);
+        assert_eq!(Color::Blue.rgb(), (0, 0, 127));
+        assert_eq!(Color::from_code(52).unwrap().rgb(), (255, 0, 0));
+        assert_eq!(Color::from_code(89).unwrap().rgb(), (18, 18, 18));
+    }
+
+    #[test]
+    fn out_of_range_codes() {
+        assert_eq!(Color::from_code(99), None);
+        assert_eq!(Color::from_code(255), None);
+        match Color::from_code(98) {
+            Some(Color::Extended(extended)) => assert_eq!(extended.code(), 98),
+            other => panic!("Expected an extended color, got {:?}", other),
+        }
+        // 99 means the default color to clients which support it.
+        assert_eq!(Span::parse("\u{3}99text")[0].style.foreground, None);
+    }
+
+    fn span(text: &str, style: Style) -> Span {
+        Span {
+            text: text,
+            style: style,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// `Arbitrary` implementations, for fuzzing and property tests. They only produce values which can
+// be sent and parsed back again: arbitrary strings would mostly make messages that can't be.
+
+use arbitrary::Arbitrary;
+use arbitrary::Result;
+use arbitrary::Unstructured;
+
+use command::Command;
+use message::Arguments;
+use message::Message;
+use message::Prefix;
+use message::Tag;
+use message::UserInfo;
+
+impl<'a> Arbitrary<'a> for Command {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        if u.arbitrary()? {
+            Ok(Command::Number(u.int_in_range(0..=999)?))
+        } else {
+            let word = text(u, 1, |c| c.is_ascii_alphabetic())?;
+            Ok(Command::of_word(&word.to_uppercase()))
+        }
+    }
+}
+
+impl<'a> Arbitrary<'a> for Prefix {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Prefix::None,
+            // A dot stops the server name being mistaken for a nickname.
+            1 => {
+                let (server, domain) = (text(u, 1, is_host_char)?, text(u, 1, is_host_char)?);
+                Prefix::Server(format!("{}.{}", server, domain))
+            }
+            2 => Prefix::User(UserInfo::of_nickname(&nickname(u)?)),
+            3 => {
+                let host = text(u, 1, is_host_char)?;
+                Prefix::User(UserInfo::of_nickname_host(&nickname(u)?, &host))
+            }
+            _ => {
+                let user = text(u, 1, |c| c.is_ascii_alphanumeric() || c == '~')?;
+                let host = text(u, 1, is_host_char)?;
+                Prefix::User(UserInfo::of_nickname_user_host(&nickname(u)?, &user, &host))
+            }
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Tag {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let key = text(u, 1, |c| c.is_ascii_alphanumeric() || "-./+".contains(c))?;
+        // Values are escaped when they're sent, so can contain anything but NUL.
+        let value: Option<String> = u.arbitrary()?;
+        let value = value.map(|value| value.replace('\0', ""));
+        Ok(Tag::new(&key, value.as_deref()))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Message {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let tags: Vec<Tag> = u.arbitrary()?;
+        let mut arguments = Arguments::new();
+        for _ in 0..u.int_in_range(0..=4)? {
+            let middle = text(u, 1, |c| c != ' ' && is_allowed(c))?;
+            // Only the last argument can start with a colon.
+            let middle = middle.trim_start_matches(':');
+            if !middle.is_empty() {
+                arguments.push(middle.into());
+            }
+        }
+        if u.arbitrary()? {
+            arguments.push(text(u, 0, is_allowed)?);
+        }
+        Ok(Message::with_arguments(u.arbitrary()?, u.arbitrary()?, arguments).with_tags(tags))
+    }
+}
+
+// An arbitrary string made up of the characters `allowed`, at least `min` long.
+fn text(u: &mut Unstructured, min: usize, allowed: fn(char) -> bool) -> Result<String> {
+    let text: String = u.arbitrary()?;
+    let mut text: String = text.chars().filter(|&c| allowed(c)).collect();
+    while text.len() < min {
+        text.push('x');
+    }
+    Ok(text)
+}
+
+fn nickname(u: &mut Unstructured) -> Result<String> {
+    text(u, 1, |c| c.is_ascii_alphanumeric() || "[]\\`_^{|}-".contains(c))
+}
+
+fn is_host_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-'
+}
+
+fn is_allowed(c: char) -> bool {
+    c != '\0' && c != '\r' && c != '\n'
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::Arbitrary;
+    use arbitrary::Unstructured;
+    use message::Arguments;
+    use message::Message;
+    use testing::fuzz_round_trip;
+
+    #[test]
+    fn arbitrary_messages_round_trip() {
+        // Deterministic, but varied enough to produce every kind of prefix, tag and argument.
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut bytes = vec![0; 4096];
+        for _ in 0..500 {
+            for byte in bytes.iter_mut() {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                *byte = seed as u8;
+            }
+            let mut u = Unstructured::new(&bytes);
+            while let Ok(message) = Message::arbitrary(&mut u) {
+                fuzz_round_trip(&message);
+                if u.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn canonicalized_messages_round_trip() {
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut bytes = vec![0; 4096];
+        for _ in 0..500 {
+            for byte in bytes.iter_mut() {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                *byte = seed as u8;
+            }
+            let mut u = Unstructured::new(&bytes);
+            while let Ok(message) = Message::arbitrary(&mut u) {
+                // Arguments that can't be sent as they are: empty, with spaces, colons or CRLF.
+                let arguments: Vec<String> = match u.arbitrary() {
+                    Ok(arguments) => arguments,
+                    Err(_) => break,
+                };
+                let message = Message { arguments: Arguments::from(arguments), ..message };
+                let canonical = message.canonicalize().unwrap();
+                fuzz_round_trip(&canonical);
+                if u.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+}
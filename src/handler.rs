@@ -0,0 +1,287 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use client::Client;
+use command::Command;
+use message::Message;
+use messages::Join;
+use messages::Privmsg;
+
+/// Reacts to what happens on a `Client`, for use with `Client::run_handler`.
+///
+/// Every method does nothing by default, so implement just the ones you need. For each message
+/// `on_message` is called first, then whichever more specific method fits. Returning an error
+/// from any of them stops the client.
+///
+/// Several handlers can be used together by putting them in a `Vec<Box<dyn EventHandler<S>>>`,
+/// which passes everything to each in turn.
+pub trait EventHandler<S: Read + Write> {
+    /// Called for every message received.
+    fn on_message(&mut self, _client: &mut Client<S>, _message: &Message) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_privmsg(&mut self,
+                  _client: &mut Client<S>,
+                  _privmsg: &Privmsg,
+                  _message: &Message)
+                  -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_join(&mut self,
+               _client: &mut Client<S>,
+               _join: &Join,
+               _message: &Message)
+               -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called for numeric replies, e.g. `RPL_TOPIC` (332).
+    fn on_numeric(&mut self,
+                  _client: &mut Client<S>,
+                  _numeric: u16,
+                  _message: &Message)
+                  -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called when the connection fails or is closed, with the error `run_handler` is about to
+    /// return.
+    fn on_disconnect(&mut self, _client: &mut Client<S>, _error: &io::Error) {}
+
+    /// Calls `on_message` and then whichever other method fits `message`. Override this to do
+    /// your own dispatching.
+    fn handle(&mut self, client: &mut Client<S>, message: &Message) -> io::Result<()> {
+        self.on_message(client, message)?;
+        if let Some(privmsg) = message.as_privmsg() {
+            self.on_privmsg(client, &privmsg, message)
+        } else if let Some(join) = message.as_join() {
+            self.on_join(client, &join, message)
+        } else if let Command::Number(numeric) = message.command {
+            self.on_numeric(client, numeric, message)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<S: Read + Write, H: EventHandler<S> + ?Sized> EventHandler<S> for Box<H> {
+    fn on_message(&mut self, client: &mut Client<S>, message: &Message) -> io::Result<()> {
+        (**self).on_message(client, message)
+    }
+
+    fn on_privmsg(&mut self,
+                  client: &mut Client<S>,
+                  privmsg: &Privmsg,
+                  message: &Message)
+                  -> io::Result<()> {
+        (**self).on_privmsg(client, privmsg, message)
+    }
+
+    fn on_join(&mut self,
+               client: &mut Client<S>,
+               join: &Join,
+               message: &Message)
+               -> io::Result<()> {
+        (**self).on_join(client, join, message)
+    }
+
+    fn on_numeric(&mut self,
+                  client: &mut Client<S>,
+                  numeric: u16,
+                  message: &Message)
+                  -> io::Result<()> {
+        (**self).on_numeric(client, numeric, message)
+    }
+
+    fn handle(&mut self, client: &mut Client<S>, message: &Message) -> io::Result<()> {
+        (**self).handle(client, message)
+    }
+
+    fn on_disconnect(&mut self, client: &mut Client<S>, error: &io::Error) {
+        (**self).on_disconnect(client, error)
+    }
+}
+
+impl<S: Read + Write, H: EventHandler<S>> EventHandler<S> for Vec<H> {
+    fn handle(&mut self, client: &mut Client<S>, message: &Message) -> io::Result<()> {
+        for handler in self.iter_mut() {
+            handler.handle(client, message)?;
+        }
+        Ok(())
+    }
+
+    fn on_disconnect(&mut self, client: &mut Client<S>, error: &io::Error) {
+        for handler in self.iter_mut() {
+            handler.on_disconnect(client, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+    use irc_stream::IrcStream;
+    use irc_stream::ScriptedStream;
+    use registration::Registration;
+
+    #[derive(Default)]
+    struct Counter {
+        messages: usize,
+        privmsgs: usize,
+        joins: usize,
+        numerics: Vec<u16>,
+        disconnected: bool,
+    }
+
+    impl EventHandler<ScriptedStream> for Counter {
+        fn on_message(&mut self, _: &mut Client<ScriptedStream>, _: &Message) -> io::Result<()> {
+            self.messages += 1;
+            Ok(())
+        }
+
+        fn on_privmsg(&mut self,
+                      _: &mut Client<ScriptedStream>,
+                      _: &Privmsg,
+                      _: &Message)
+                      -> io::Result<()> {
+            self.privmsgs += 1;
+            Ok(())
+        }
+
+        fn on_join(&mut self,
+                   _: &mut Client<ScriptedStream>,
+                   _: &Join,
+                   _: &Message)
+                   -> io::Result<()> {
+            self.joins += 1;
+            Ok(())
+        }
+
+        fn on_numeric(&mut self,
+                      _: &mut Client<ScriptedStream>,
+                      numeric: u16,
+                      _: &Message)
+                      -> io::Result<()> {
+            self.numerics.push(numeric);
+            Ok(())
+        }
+
+        fn on_disconnect(&mut self, _: &mut Client<ScriptedStream>, _: &io::Error) {
+            self.disconnected = true;
+        }
+    }
+
+    struct Greeter;
+
+    impl EventHandler<ScriptedStream> for Greeter {
+        fn on_join(&mut self,
+                   client: &mut Client<ScriptedStream>,
+                   join: &Join,
+                   _: &Message)
+                   -> io::Result<()> {
+            client.privmsg(join.channel, &format!("Hi {}", join.user.nickname()))
+        }
+    }
+
+    // Says which method was called, so calls through a `Box<dyn EventHandler>` can be seen.
+    struct Logger;
+
+    impl EventHandler<ScriptedStream> for Logger {
+        fn on_message(&mut self,
+                      client: &mut Client<ScriptedStream>,
+                      _: &Message)
+                      -> io::Result<()> {
+            client.privmsg("log", "message")
+        }
+
+        fn on_privmsg(&mut self,
+                      client: &mut Client<ScriptedStream>,
+                      _: &Privmsg,
+                      _: &Message)
+                      -> io::Result<()> {
+            client.privmsg("log", "privmsg")
+        }
+
+        fn on_join(&mut self,
+                   client: &mut Client<ScriptedStream>,
+                   _: &Join,
+                   _: &Message)
+                   -> io::Result<()> {
+            client.privmsg("log", "join")
+        }
+
+        fn on_numeric(&mut self,
+                      client: &mut Client<ScriptedStream>,
+                      numeric: u16,
+                      _: &Message)
+                      -> io::Result<()> {
+            client.privmsg("log", &format!("numeric {}", numeric))
+        }
+    }
+
+    #[test]
+    fn dispatch() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :someone!user@host JOIN #chan\r\n\
+                      :someone!user@host PRIVMSG #chan :Hi\r\n\
+                      :server 332 bot #chan :Topic\r\n\
+                      :server NOTICE bot :Hello\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+        let mut counter = Counter::default();
+
+        let error = client.run_handler(&mut counter).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(counter.messages, 4);
+        assert_eq!(counter.privmsgs, 1);
+        assert_eq!(counter.joins, 1);
+        assert_eq!(counter.numerics, vec![332]);
+        assert!(counter.disconnected);
+    }
+
+    #[test]
+    fn several_handlers() {
+        let input = b":server 001 bot :Welcome\r\n\
+                      :someone!user@host JOIN #chan\r\n";
+        let irc = IrcStream::new(ScriptedStream::new(input));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+        let mut handlers: Vec<Box<dyn EventHandler<ScriptedStream>>> =
+            vec![Box::new(Counter::default()), Box::new(Greeter)];
+
+        client.run_handler(&mut handlers).unwrap_err();
+        assert!(client.stream().sent().ends_with("PRIVMSG #chan :Hi someone\r\n"));
+    }
+
+    #[test]
+    fn boxed_handlers_forward_everything() {
+        let irc = IrcStream::new(ScriptedStream::new(b":server 001 bot :Welcome\r\n"));
+        let mut client = Client::register(irc, &Registration::new("bot", "bot", "A bot")).unwrap();
+        let mut handlers: Vec<Box<dyn EventHandler<ScriptedStream>>> = vec![Box::new(Logger)];
+        let privmsg = message(":someone!user@host PRIVMSG #chan :Hi\r\n");
+        let join = message(":someone!user@host JOIN #chan\r\n");
+        let numeric = message(":server 332 bot #chan :Topic\r\n");
+
+        for handler in handlers.iter_mut() {
+            handler.on_message(&mut client, &privmsg).unwrap();
+            handler.on_privmsg(&mut client, &privmsg.as_privmsg().unwrap(), &privmsg).unwrap();
+            handler.on_join(&mut client, &join.as_join().unwrap(), &join).unwrap();
+            handler.on_numeric(&mut client, 332, &numeric).unwrap();
+        }
+        assert!(client.stream().sent().ends_with("PRIVMSG log message\r\n\
+                                                   PRIVMSG log privmsg\r\n\
+                                                   PRIVMSG log join\r\n\
+                                                   PRIVMSG log :numeric 332\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::net::Ipv4Addr;
+
+use casemapping::IrcCase;
+use message::UserInfo;
+
+/// A `nick!user@host` mask, where `*` matches any number of characters and `?` matches exactly
+/// one, as used in bans and ignore lists.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hostmask {
+    mask: String,
+}
+
+impl Hostmask {
+    /// Creates a mask, filling in missing parts the way servers do: `nick` becomes `nick!*@*`,
+    /// `user@host` becomes `*!user@host` and `nick!user` becomes `nick!user@*`.
+    pub fn new(mask: &str) -> Self {
+        let mask = match (mask.find('!'), mask.find('@')) {
+            (Some(_), Some(_)) => mask.to_string(),
+            (Some(_), None) => format!("{}@*", mask),
+            (None, Some(_)) => format!("*!{}", mask),
+            (None, None) => format!("{}!*@*", mask),
+        };
+        Hostmask { mask: mask }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.mask
+    }
+
+    /// Whether the mask matches a `nick!user@host` string, comparing them with the `rfc1459`
+    /// casemapping.
+    pub fn matches(&self, target: &str) -> bool {
+        self.matches_with_case(target, IrcCase::default())
+    }
+
+    /// As `matches`, but with the casemapping to use, from the server's `CASEMAPPING` ISUPPORT
+    /// token.
+    pub fn matches_with_case(&self, target: &str, case: IrcCase) -> bool {
+        let mask: Vec<char> = case.to_lower(&self.mask).chars().collect();
+        let target: Vec<char> = case.to_lower(target).chars().collect();
+        wildcard_match(&mask, &target)
+    }
+
+    /// Whether the mask matches a user. Parts of their address we don't know only match `*`.
+    pub fn matches_user(&self, user: &UserInfo, case: IrcCase) -> bool {
+        let target = format!("{}!{}@{}",
+                             user.nickname(),
+                             user.username().unwrap_or(""),
+                             user.host().unwrap_or(""));
+        self.matches_with_case(&target, case)
+    }
+}
+
+impl Display for Hostmask {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.mask)
+    }
+}
+
+/// Which parts of a user's address a ban mask from `UserInfo::ban_mask` covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BanMaskStyle {
+    /// `*!*@host`: anyone from their host.
+    Host,
+    /// `*!*user@host`: their username on their host. A leading `~` (meaning the server couldn't
+    /// verify the username) is replaced with the `*`.
+    UserHost,
+    /// `nick!*@*`: anyone using their nickname.
+    Nick,
+    /// `*!*@*.domain`: anyone from their host's domain, e.g. `*!*@*.example.com` for
+    /// `host.example.com`, or `*!*@192.0.2.*` for an IPv4 address.
+    Domain,
+}
+
+impl UserInfo {
+    /// A mask for banning this user, e.g. as the argument to `+b`.
+    ///
+    /// If we don't know the parts of their address the style needs, this falls back to the closest
+    /// one we can make: `Host` if we don't know their username, and `Nick` if we don't know their
+    /// host either. Hosts which don't look like domains (such as cloaks) get `Host` for `Domain`.
+    pub fn ban_mask(&self, style: BanMaskStyle) -> Hostmask {
+        let host = match self.host() {
+            Some(host) => host,
+            None => return Hostmask::new(&format!("{}!*@*", self.nickname())),
+        };
+        match (style, self.username()) {
+            (BanMaskStyle::Nick, _) => Hostmask::new(&format!("{}!*@*", self.nickname())),
+            (BanMaskStyle::UserHost, Some(user)) => {
+                let user = user.trim_start_matches('~');
+                Hostmask::new(&format!("*!*{}@{}", user, host))
+            }
+            (BanMaskStyle::Domain, _) => Hostmask::new(&format!("*!*@{}", domain_mask(host))),
+            _ => Hostmask::new(&format!("*!*@{}", host)),
+        }
+    }
+}
+
+// Replaces the most specific part of a host with a wildcard, if it looks like there is one.
+fn domain_mask(host: &str) -> String {
+    if let Ok(address) = host.parse::<Ipv4Addr>() {
+        let octets = address.octets();
+        return format!("{}.{}.{}.*", octets[0], octets[1], octets[2]);
+    }
+    match host.split_once('.') {
+        // Keep at least two labels, so we don't end up banning all of .com.
+        Some((_, domain)) if domain.contains('.') && !host.contains('/') => {
+            format!("*.{}", domain)
+        }
+        _ => host.into(),
+    }
+}
+
+// Matches without recursion: when a `*` turns out to have matched too little, go back and have it
+// match one more character.
+fn wildcard_match(mask: &[char], target: &[char]) -> bool {
+    let (mut m, mut t) = (0, 0);
+    // Where the last `*` was in the mask, and where in the target it started matching.
+    let mut star: Option<(usize, usize)> = None;
+    while t < target.len() {
+        match mask.get(m) {
+            Some('*') => {
+                star = Some((m, t));
+                m += 1;
+            }
+            Some(&c) if c == '?' || c == target[t] => {
+                m += 1;
+                t += 1;
+            }
+            _ => {
+                match star {
+                    Some((star_m, star_t)) => {
+                        m = star_m + 1;
+                        t = star_t + 1;
+                        star = Some((star_m, star_t + 1));
+                    }
+                    None => return false,
+                }
+            }
+        }
+    }
+    mask[m..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use casemapping::IrcCase;
+    use message::UserInfo;
+
+    #[test]
+    fn wildcards() {
+        let mask = Hostmask::new("*!*@*.example.com");
+        assert!(mask.matches("nick!user@host.example.com"));
+        assert!(!mask.matches("nick!user@example.com"));
+        assert!(!mask.matches("nick!user@host.example.org"));
+
+        let mask = Hostmask::new("n?ck!*user@*");
+        assert!(mask.matches("nick!~user@host"));
+        assert!(mask.matches("neck!user@host"));
+        assert!(!mask.matches("nck!user@host"));
+
+        assert!(Hostmask::new("*a*b*c").matches("xaxxbxxc!@"));
+        assert!(!Hostmask::new("*!*@*ab").matches("n!u@hab!ax"));
+    }
+
+    #[test]
+    fn fills_in_missing_parts() {
+        assert_eq!(Hostmask::new("nick").as_str(), "nick!*@*");
+        assert_eq!(Hostmask::new("*@host").as_str(), "*!*@host");
+        assert_eq!(Hostmask::new("nick!user").as_str(), "nick!user@*");
+        assert_eq!(Hostmask::new("a!b@c").as_str(), "a!b@c");
+    }
+
+    #[test]
+    fn casemapping() {
+        let mask = Hostmask::new("Nick[1]!*@HOST");
+        assert!(mask.matches("nick{1}!user@host"));
+        assert!(!mask.matches_with_case("nick{1}!user@host", IrcCase::Ascii));
+        assert!(mask.matches_with_case("nick[1]!user@host", IrcCase::Ascii));
+    }
+
+    #[test]
+    fn users() {
+        let case = IrcCase::default();
+        let user = UserInfo::of_nickname_user_host("nick", "user", "host.example.com");
+        assert!(Hostmask::new("*!*@*.example.com").matches_user(&user, case));
+
+        let nick_only = UserInfo::of_nickname("nick");
+        assert!(Hostmask::new("nick").matches_user(&nick_only, case));
+        assert!(!Hostmask::new("*!*@*.example.com").matches_user(&nick_only, case));
+    }
+
+    #[test]
+    fn ban_masks() {
+        let user = UserInfo::of_nickname_user_host("nick", "~user", "host.example.com");
+        assert_eq!(user.ban_mask(BanMaskStyle::Host).as_str(), "*!*@host.example.com");
+        assert_eq!(user.ban_mask(BanMaskStyle::UserHost).as_str(), "*!*user@host.example.com");
+        assert_eq!(user.ban_mask(BanMaskStyle::Nick).as_str(), "nick!*@*");
+        assert_eq!(user.ban_mask(BanMaskStyle::Domain).as_str(), "*!*@*.example.com");
+        assert!(user.ban_mask(BanMaskStyle::Domain).matches_user(&user, IrcCase::default()));
+
+        let ip = UserInfo::of_nickname_user_host("nick", "user", "192.0.2.10");
+        assert_eq!(ip.ban_mask(BanMaskStyle::Domain).as_str(), "*!*@192.0.2.*");
+        let cloak = UserInfo::of_nickname_user_host("nick", "user", "user/nick.name");
+        assert_eq!(cloak.ban_mask(BanMaskStyle::Domain).as_str(), "*!*@user/nick.name");
+        let short = UserInfo::of_nickname_user_host("nick", "user", "example.com");
+        assert_eq!(short.ban_mask(BanMaskStyle::Domain).as_str(), "*!*@example.com");
+    }
+
+    #[test]
+    fn ban_mask_fallbacks() {
+        let no_user = UserInfo::of_nickname_host("nick", "host");
+        assert_eq!(no_user.ban_mask(BanMaskStyle::UserHost).as_str(), "*!*@host");
+        let nick_only = UserInfo::of_nickname("nick");
+        assert_eq!(nick_only.ban_mask(BanMaskStyle::Host).as_str(), "nick!*@*");
+    }
+}
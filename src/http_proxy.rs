@@ -0,0 +1,184 @@
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+
+use base64;
+use irc_stream::IrcStream;
+use tls::TlsConnector;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::DefaultTlsConnector;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsOptions;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsStream;
+
+// Proxies don't send much back, so anything longer than this is something going wrong.
+const MAX_RESPONSE_LENGTH: usize = 8192;
+
+/// An HTTP proxy to connect through, using the `CONNECT` method to open a tunnel to the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpProxy {
+    host: String,
+    port: u16,
+    auth: Option<(String, String)>,
+}
+
+impl HttpProxy {
+    pub fn new(host: &str, port: u16) -> Self {
+        HttpProxy {
+            host: host.into(),
+            port: port,
+            auth: None,
+        }
+    }
+
+    /// Authenticates with the proxy using HTTP Basic auth. Note the password isn't protected at
+    /// all unless the connection to the proxy itself is trusted.
+    pub fn with_auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Connects to the proxy and asks it to open a tunnel to `server`.
+    pub fn connect(&self, server: &str, port: u16) -> io::Result<TcpStream> {
+        debug!("Connecting to {}:{} via HTTP proxy {}:{}",
+               server,
+               port,
+               self.host,
+               self.port);
+        let mut stream = TcpStream::connect((&self.host[..], self.port))?;
+        self.handshake(&mut stream, server, port)?;
+        Ok(stream)
+    }
+
+    fn handshake<S: Read + Write>(&self, stream: &mut S, server: &str, port: u16) -> io::Result<()> {
+        let authority = if server.contains(':') {
+            format!("[{}]:{}", server, port)
+        } else {
+            format!("{}:{}", server, port)
+        };
+        let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+        if let Some((ref username, ref password)) = self.auth {
+            let credentials = format!("{}:{}", username, password);
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n",
+                                      base64::encode(credentials.as_bytes())));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        let response = read_response(stream)?;
+        let status_line = response.lines().next().unwrap_or("");
+        let mut parts = status_line.splitn(3, ' ');
+        if !parts.next().unwrap_or("").starts_with("HTTP/") {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Proxy isn't speaking HTTP"));
+        }
+        match parts.next() {
+            Some(status) if status.starts_with('2') => Ok(()),
+            Some("407") => {
+                Err(io::Error::new(ErrorKind::PermissionDenied,
+                                   format!("Proxy wants authentication: {}", status_line)))
+            }
+            _ => Err(io::Error::other(format!("Proxy couldn't connect: {}", status_line))),
+        }
+    }
+}
+
+/// Reads the response headers, one byte at a time so nothing after them (i.e. the start of the
+/// tunnelled connection) is consumed.
+fn read_response<S: Read>(stream: &mut S) -> io::Result<String> {
+    let mut response = Vec::new();
+    let mut byte = [0; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_LENGTH {
+            return Err(io::Error::new(ErrorKind::InvalidData, "Proxy response is too long"));
+        }
+        stream.read_exact(&mut byte)?;
+        response.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&response).into_owned())
+}
+
+impl IrcStream<TcpStream> {
+    /// Connect to a server through an HTTP proxy and wrap in an `IrcStream`.
+    pub fn connect_via_http_proxy(proxy: &HttpProxy, server: &str, port: u16) -> io::Result<Self> {
+        Ok(IrcStream::new(proxy.connect(server, port)?))
+    }
+}
+
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+impl IrcStream<TlsStream> {
+    /// Connect to a server over SSL configured with `options` through an HTTP proxy, and wrap in
+    /// an `IrcStream`.
+    pub fn connect_ssl_via_http_proxy(proxy: &HttpProxy,
+                                      server: &str,
+                                      port: u16,
+                                      options: &TlsOptions)
+                                      -> io::Result<Self> {
+        Self::connect_tls_via_http_proxy(proxy, server, port, &DefaultTlsConnector::new(options)?)
+    }
+}
+
+impl<S: Read + Write> IrcStream<S> {
+    /// Connect to a server over TLS using `connector`, through an HTTP proxy.
+    pub fn connect_tls_via_http_proxy<C: TlsConnector<Stream = S>>(proxy: &HttpProxy,
+                                                                   server: &str,
+                                                                   port: u16,
+                                                                   connector: &C)
+                                                                   -> io::Result<Self> {
+        let raw_connection = proxy.connect(server, port)?;
+        Ok(IrcStream::new(connector.connect(server, raw_connection)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::commands::PING;
+    use irc_stream::ScriptedStream;
+    use message::Message;
+    use message::Prefix;
+
+    #[test]
+    fn connect() {
+        let proxy = HttpProxy::new("localhost", 8080);
+        let mut stream = ScriptedStream::new(b"HTTP/1.1 200 Connection established\r\n\r\nPING 1\r\n");
+
+        proxy.handshake(&mut stream, "irc.example.com", 6697).unwrap();
+        assert_eq!(String::from_utf8(stream.output.clone()).unwrap(),
+                   "CONNECT irc.example.com:6697 HTTP/1.1\r\nHost: irc.example.com:6697\r\n\r\n");
+        // The tunnelled connection is left untouched.
+        assert_eq!(IrcStream::new(stream).next_message().unwrap(),
+                   Message::from_strs(Prefix::None, PING(), vec!["1"]));
+    }
+
+    #[test]
+    fn connect_with_auth() {
+        let proxy = HttpProxy::new("localhost", 8080).with_auth("user", "pass");
+        let mut stream = ScriptedStream::new(b"HTTP/1.0 200 OK\r\n\r\n");
+
+        proxy.handshake(&mut stream, "2001:db8::1", 6667).unwrap();
+        assert_eq!(String::from_utf8(stream.output).unwrap(),
+                   "CONNECT [2001:db8::1]:6667 HTTP/1.1\r\nHost: [2001:db8::1]:6667\r\n\
+                    Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n");
+    }
+
+    #[test]
+    fn errors() {
+        let proxy = HttpProxy::new("localhost", 8080);
+        let needs_auth = proxy.handshake(&mut ScriptedStream::new(b"HTTP/1.1 407 Proxy \
+                                                                      Authentication Required\r\n\r\n"),
+                                         "irc",
+                                         6667);
+        assert_eq!(needs_auth.unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+        let forbidden = proxy.handshake(&mut ScriptedStream::new(b"HTTP/1.1 403 Forbidden\r\n\r\n"),
+                                        "irc",
+                                        6667);
+        assert_eq!(forbidden.unwrap_err().kind(), ErrorKind::Other);
+
+        let not_http = proxy.handshake(&mut ScriptedStream::new(b"\x05\x00\r\n\r\n"), "irc", 6667);
+        assert_eq!(not_http.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::io;
 use std::io::Read;
 use std::io::BufRead;
@@ -5,20 +6,124 @@ use std::io::BufReader;
 use std::io::ErrorKind;
 use std::io::Write;
 use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::mem;
+use std::time::Duration;
+use std::time::Instant;
 
+use command::Command;
+use decoder::DEFAULT_MAX_LINE_LENGTH;
+use decoder::OversizedLine;
+use decoder::too_long;
+use encoding::TextEncoding;
+use message::MAX_LINE_LENGTH;
 use message::Message;
-
-use openssl::ssl::SslConnectorBuilder;
-use openssl::ssl::SslMethod;
-use openssl::ssl::SslStream;
+use message_ref::MessageRef;
+use metrics::Metrics;
+use metrics::MetricsSink;
+use parser::LineEndings;
+use rate_limit::RateLimiter;
+use send_queue::SendQueue;
+use tls::TlsConnector;
+use trace;
+use trace::Span;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::DefaultTlsConnector;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsOptions;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsStream;
 
 /// A type representing an IRC connection, equivalent to `TcpStream` for TCP connections.
 #[derive(Debug)]
 pub struct IrcStream<S: Read + Write> {
     reader: BufReader<S>,
+    rate_limiter: Option<RateLimiter>,
+    auto_pong: bool,
+    encoding: TextEncoding,
+    parse_policy: ParsePolicy,
+    line_endings: LineEndings,
+    keepalive: Option<Keepalive>,
+    queue: SendQueue,
+    buffer_writes: bool,
+    // Lines sent but not yet written to the stream.
+    unflushed: Vec<u8>,
+    // The command and length of each line in `unflushed`, which are reported to `metrics` once
+    // the whole line has been written, and how much of the first one has been written so far.
+    unflushed_lines: VecDeque<(Command, usize)>,
+    partly_flushed: usize,
+    // Whatever we've read of a line which hasn't finished arriving yet.
+    partial: Vec<u8>,
+    max_line_length: usize,
+    // Whether we're throwing away the rest of a line that was too long.
+    discarding: bool,
+    // Where what happens on the connection is traced.
+    span: Span,
+    metrics: Metrics,
+}
+
+/// What `IrcStream::next_message` does with a line from the server which can't be parsed or is
+/// too long. Real servers do occasionally send garbage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParsePolicy {
+    /// Fail with an `InvalidData` error. The line has been used up, so reading can carry on
+    /// afterwards, but loops which give up on errors won't. This is the default.
+    #[default]
+    Strict,
+    /// Log a warning and skip to the next line.
+    SkipWithWarning,
+}
+
+/// A line received by `IrcStream::next_received`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "smallvec", allow(clippy::large_enum_variant))]
+pub enum Received {
+    Message(Message),
+    /// A line which couldn't be decoded or parsed, as it was received.
+    Malformed(Vec<u8>),
+}
+
+// A received message, or the line it came from and why it couldn't be parsed.
+type Parsed = Result<Message, (Vec<u8>, io::Error)>;
+
+#[derive(Debug)]
+struct Keepalive {
+    idle: Duration,
+    timeout: Duration,
+    last_received: Instant,
+    pinged: bool,
 }
 
-impl IrcStream<SslStream<TcpStream>> {
+/// Paths to a PEM-encoded client certificate and its private key, presented to the server during
+/// the TLS handshake. Networks use this to identify you (CERTFP), most usefully with SASL EXTERNAL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCert {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// The hash used to compute a certificate fingerprint. Which one a network expects varies, though
+/// SHA-256 is most common.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertFpHash {
+    Sha256,
+    Sha512,
+}
+
+impl ClientCert {
+    pub fn new<C: AsRef<Path>, K: AsRef<Path>>(cert_path: C, key_path: K) -> Self {
+        ClientCert {
+            cert_path: cert_path.as_ref().to_path_buf(),
+            key_path: key_path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+impl IrcStream<TlsStream> {
     /// Connect to a server over SSL and wrap in an `IrcStream`.
     ///
     /// Note that the connection here uses default configuration for everything. If you need to
@@ -26,12 +131,28 @@ impl IrcStream<SslStream<TcpStream>> {
     /// TCP connection will have an unlimited read timeout, which won't be appropriate for all
     /// cases.
     pub fn connect_ssl(server: &str, port: u16) -> io::Result<Self> {
-        debug!("Connecting to ircs://{}:{}", server, port);
-        let ssl_connector = SslConnectorBuilder::new(SslMethod::tls())?.build();
-        let raw_connection = TcpStream::connect((server, port))?;
-        let connection = ssl_connector.connect(server, raw_connection)
-            .map_err(|ssl_err| io::Error::new(ErrorKind::Other, ssl_err))?;
-        Ok(IrcStream::new(connection))
+        Self::connect_ssl_with_options(server, port, &TlsOptions::default())
+    }
+
+    /// Connect to a server over SSL, presenting a client certificate, and wrap in an `IrcStream`.
+    ///
+    /// Otherwise the same as `connect_ssl`.
+    pub fn connect_ssl_with_cert(server: &str,
+                                 port: u16,
+                                 client_cert: &ClientCert)
+                                 -> io::Result<Self> {
+        Self::connect_ssl_with_options(server,
+                                       port,
+                                       &TlsOptions::new().with_client_cert(client_cert.clone()))
+    }
+
+    /// Connect to a server over SSL configured with `options`, and wrap in an `IrcStream`. This
+    /// uses `DefaultTlsConnector`, i.e. whichever TLS library is enabled.
+    pub fn connect_ssl_with_options(server: &str,
+                                    port: u16,
+                                    options: &TlsOptions)
+                                    -> io::Result<Self> {
+        Self::connect_tls(server, port, &DefaultTlsConnector::new(options)?)
     }
 }
 
@@ -42,41 +163,473 @@ impl IrcStream<TcpStream> {
     /// configure your connection at all, consider using `IrcStream::new`. In particular the TCP
     /// connection will have an unlimited read timeout, which won't be appropriate for all cases.
     pub fn connect(server: &str, port: u16) -> io::Result<Self> {
-        debug!("Connecting to irc://{}:{}", server, port);
+        let span = trace::connecting("irc", server, port);
         let connection = TcpStream::connect((server, port))?;
-        Ok(IrcStream::new(connection))
+        Ok(IrcStream::new(connection).in_span(span))
     }
 }
 
 impl<S: Read + Write> IrcStream<S> {
     /// Create a new `IrcStream` wrapping a provided stream.
     pub fn new(stream: S) -> Self {
-        IrcStream { reader: BufReader::new(stream) }
+        IrcStream {
+            reader: BufReader::new(stream),
+            rate_limiter: None,
+            auto_pong: false,
+            encoding: TextEncoding::default(),
+            parse_policy: ParsePolicy::default(),
+            line_endings: LineEndings::default(),
+            keepalive: None,
+            queue: SendQueue::new(),
+            buffer_writes: false,
+            unflushed: Vec::new(),
+            unflushed_lines: VecDeque::new(),
+            partly_flushed: 0,
+            partial: Vec::new(),
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            discarding: false,
+            span: Span::current(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Connect to a server over TLS using `connector`, and wrap in an `IrcStream`. This is how to
+    /// use a TLS library other than the default, or one configured in ways `TlsOptions` can't.
+    pub fn connect_tls<C: TlsConnector<Stream = S>>(server: &str,
+                                                    port: u16,
+                                                    connector: &C)
+                                                    -> io::Result<Self> {
+        let span = trace::connecting("ircs", server, port);
+        let raw_connection = TcpStream::connect((server, port))?;
+        let connection = connector.connect(server, raw_connection)?;
+        Ok(IrcStream::new(connection).in_span(span))
+    }
+
+    /// The underlying stream.
+    pub fn get_ref(&self) -> &S {
+        self.reader.get_ref()
+    }
+
+    /// Traces what happens on this connection in `span`, e.g. one with fields saying which
+    /// network it's for. A stream made with `new` otherwise uses the span it was made in, and one
+    /// made by connecting gets an `irc` span with `server`, `port` and (once registered) `nick`.
+    #[cfg(feature = "tracing")]
+    pub fn with_span(self, span: Span) -> Self {
+        self.in_span(span)
+    }
+
+    /// The span what happens on this connection is traced in.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Reports messages and bytes sent and received, and the depth of the send queue, to `sink`.
+    pub fn with_metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Metrics::new(sink);
+        self
+    }
+
+    pub(crate) fn in_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    // Notes our new nickname on the span.
+    pub(crate) fn nickname_changed(&self, nickname: &str) {
+        trace::nickname(&self.span, nickname);
+    }
+
+    /// Answers PINGs from the server automatically. They're dealt with inside `next_message` and
+    /// never returned from it.
+    pub fn with_auto_pong(mut self) -> Self {
+        self.auto_pong = true;
+        self
+    }
+
+    /// Limits how quickly `send` sends lines. Once the limit is reached `send` blocks until the
+    /// line can go out, so messages are effectively queued up in order.
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Sets how text is encoded on the connection. Defaults to UTF-8, with anything invalid
+    /// replaced.
+    pub fn with_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets what `next_message` does with lines which can't be parsed. Defaults to `Strict`.
+    pub fn with_parse_policy(mut self, policy: ParsePolicy) -> Self {
+        self.parse_policy = policy;
+        self
+    }
+
+    /// Sets the longest line we'll receive, including the line ending. Reading a longer line fails
+    /// with an `OversizedLine` error rather than buffering it, and the rest of it is skipped.
+    /// Defaults to `DEFAULT_MAX_LINE_LENGTH`, which leaves room for message tags.
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Sets which line endings received lines can have. Defaults to `Lenient`, CRLF or LF; use
+    /// `AllowEof` to also accept a last line cut off by the connection closing.
+    pub fn with_line_endings(mut self, line_endings: LineEndings) -> Self {
+        self.line_endings = line_endings;
+        self
+    }
+
+    /// Checks the connection is still alive when the server goes quiet: after `idle` without
+    /// receiving anything we send a PING, and if nothing arrives within `timeout` after that,
+    /// `next_message` fails with `TimedOut` (which `ReconnectingIrcStream` reconnects after).
+    /// Otherwise a connection which died without being closed could leave us waiting forever.
+    ///
+    /// This relies on the underlying stream's reads timing out, so it has to have a read timeout
+    /// shorter than `idle`, e.g. with `TcpStream::set_read_timeout`. The server's PONG is returned
+    /// from `next_message` like any other message.
+    pub fn with_keepalive(mut self, idle: Duration, timeout: Duration) -> Self {
+        self.keepalive = Some(Keepalive {
+            idle: idle,
+            timeout: timeout,
+            last_received: Instant::now(),
+            pinged: false,
+        });
+        self
+    }
+
+    /// Buffers what `send` sends until `flush` is called, rather than writing each message out
+    /// as it's sent. This saves a write (and with TLS, a record) per line when sending a lot at
+    /// once. Anything buffered is flushed before reading, so replies aren't waited for forever,
+    /// and before waiting for the rate limit.
+    pub fn with_buffered_writes(mut self) -> Self {
+        self.buffer_writes = true;
+        self
     }
 
     /// Sends a message to the target of the stream.
+    ///
+    /// Returns an `InvalidInput` error without sending anything if the message is longer than
+    /// `MAX_LINE_LENGTH`, since servers would truncate or reject it, or if an argument contains
+    /// CR, LF or NUL, which would end the line early. Long or multi-line PRIVMSGs can be broken
+    /// up with `Message::privmsg_split`.
     pub fn send(&mut self, message: &Message) -> io::Result<()> {
-        debug!("SEND> {}", message);
-        write!(self.stream(), "{}\r\n", message)?;
+        self.send_at(message, Instant::now())
+    }
+
+    fn send_at(&mut self, message: &Message, now: Instant) -> io::Result<()> {
+        check_sendable(message)?;
+        self.buffer(message, now)?;
+        if self.buffer_writes {
+            return Ok(());
+        }
+        self.flush()
+    }
+
+    /// Sends several messages, writing as many at once as the rate limit allows. As with `send`,
+    /// nothing is sent if any message is too long.
+    pub fn send_all(&mut self, messages: &[Message]) -> io::Result<()> {
+        for message in messages {
+            check_sendable(message)?;
+        }
+        for message in messages {
+            self.buffer(message, Instant::now())?;
+        }
+        self.flush()
+    }
+
+    /// Writes out anything buffered by `with_buffered_writes` or `send_all`, in one go. If writing
+    /// fails, whatever wasn't written is kept for the next `flush`.
+    pub fn flush(&mut self) -> io::Result<()> {
+        // One contiguous write rather than a vectored one, since most TLS streams don't implement
+        // vectored writes and would write each line separately anyway.
+        while !self.unflushed.is_empty() {
+            let written = match self.reader.get_mut().write(&self.unflushed) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "Failed to write lines")),
+                Ok(written) => written,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.unflushed.drain(..written);
+            self.written(written);
+        }
         self.stream().flush()
     }
 
-    /// Read the next message from this reader.
+    // Reports the lines which `written` more bytes have finished writing.
+    fn written(&mut self, written: usize) {
+        self.partly_flushed += written;
+        while let Some(&(_, len)) = self.unflushed_lines.front() {
+            if self.partly_flushed < len {
+                break;
+            }
+            self.partly_flushed -= len;
+            if let Some((command, len)) = self.unflushed_lines.pop_front() {
+                self.metrics.message_sent(&command);
+                self.metrics.bytes_sent(len);
+            }
+        }
+    }
+
+    // Sends a message of our own making while reading, which can't wait to be flushed: we're
+    // about to go back to reading, maybe for a long time.
+    fn send_now(&mut self, message: &Message) -> io::Result<()> {
+        check_sendable(message)?;
+        self.buffer(message, Instant::now())?;
+        self.flush()
+    }
+
+    // Waits for the rate limit as of `now`, then adds a message to what's waiting to be written.
+    fn buffer(&mut self, message: &Message, now: Instant) -> io::Result<()> {
+        let span = self.span.clone();
+        let _entered = span.enter();
+        let delay = match self.rate_limiter {
+            Some(ref mut limiter) => limiter.delay_at(now),
+            None => Duration::from_secs(0),
+        };
+        if delay > Duration::from_secs(0) {
+            // Holding on to lines while we wait would only send them in a burst afterwards.
+            self.flush()?;
+            debug!("Rate limited, waiting {:?} before sending", delay);
+            thread::sleep(delay);
+        }
+        trace::sent(message);
+        let line = message.to_bytes();
+        let encoded = self.encoding.encode(&line);
+        self.unflushed_lines.push_back((message.command.clone(), encoded.len()));
+        self.unflushed.extend_from_slice(&encoded);
+        Ok(())
+    }
+
+    /// Queues a message to be sent once the rate limit allows, ahead of any queued messages with a
+    /// lower `Priority`, so a bot with a lot of output queued still kicks spammers promptly.
+    /// Messages sent with `send` (including automatic PONGs) go ahead of everything queued,
+    /// waiting only for the rate limit.
+    ///
+    /// Queued messages are only sent by `send_queued`, which `next_message` calls before reading
+    /// and again whenever a read times out. On a blocking stream with no read timeout they'd wait
+    /// for the server to send something, so either give the stream a read timeout or call
+    /// `send_queued` regularly yourself. As with `send`, messages longer than `MAX_LINE_LENGTH`
+    /// are an `InvalidInput` error.
+    pub fn queue(&mut self, message: &Message) -> io::Result<()> {
+        check_sendable(message)?;
+        self.queue.push(message.clone());
+        self.metrics.queue_depth(self.queue.len());
+        Ok(())
+    }
+
+    /// Sends queued messages, highest priority first, until the queue is empty or sending more
+    /// would mean waiting for the rate limit. Returns how many are still queued.
+    pub fn send_queued(&mut self) -> io::Result<usize> {
+        self.send_queued_at(Instant::now())
+    }
+
+    /// As `send_queued`, with `now` as the time the rate limit is checked at.
+    pub fn send_queued_at(&mut self, now: Instant) -> io::Result<usize> {
+        while !self.queue.is_empty() {
+            if let Some(ref limiter) = self.rate_limiter {
+                if limiter.wait_at(now) > Duration::from_secs(0) {
+                    break;
+                }
+            }
+            if let Some(message) = self.queue.pop() {
+                self.metrics.queue_depth(self.queue.len());
+                self.send_at(&message, now)?;
+            }
+        }
+        Ok(self.queue.len())
+    }
+
+    /// Read the next message from this reader. What happens to lines which can't be parsed
+    /// depends on the `ParsePolicy`.
     pub fn next_message(&mut self) -> io::Result<Message> {
-        // TODO: Is the buffer being in here really good? Moving it out leads to all manner of
-        // annoying borrow errors.
-        let mut buf = Vec::new();
-        self.reader.read_until(b'\n', &mut buf)?;
-        match Message::parse(&buf[..]) {
+        loop {
+            let error = match self.next_parsed() {
+                Ok(Ok(message)) => return Ok(message),
+                Ok(Err((_, error))) => error,
+                Err(error) if OversizedLine::of(&error).is_some() => error,
+                Err(error) => return Err(error),
+            };
+            match self.parse_policy {
+                ParsePolicy::Strict => return Err(error),
+                ParsePolicy::SkipWithWarning => warn!("Skipping line: {}", error),
+            }
+        }
+    }
+
+    /// Like `next_message`, but returns lines which can't be parsed rather than following the
+    /// `ParsePolicy`.
+    pub fn next_received(&mut self) -> io::Result<Received> {
+        match self.next_parsed()? {
+            Ok(message) => Ok(Received::Message(message)),
+            Err((line, _)) => Ok(Received::Malformed(line)),
+        }
+    }
+
+    /// The next line exactly as it was received, line ending and all, without decoding it or
+    /// parsing it into a `Message`. Parse it with `MessageRef` to get at arguments which aren't
+    /// text with `arguments_raw`. Queued messages are sent and PINGs answered as with
+    /// `next_message`.
+    pub fn next_line(&mut self) -> io::Result<Vec<u8>> {
+        self.send_queued()?;
+        self.flush()?;
+        loop {
+            let line = self.read_line()?;
+            if self.auto_pong {
+                let ping = match MessageRef::parse_with(&line, self.line_endings) {
+                    Ok((message, _)) if message.command() == "PING" => Some(message.to_owned()),
+                    _ => None,
+                };
+                if let Some(ping) = ping.as_ref().and_then(|ping| ping.as_ping()) {
+                    self.send_now(&ping.pong())?;
+                    continue;
+                }
+            }
+            return Ok(line);
+        }
+    }
+
+    fn next_parsed(&mut self) -> io::Result<Parsed> {
+        self.send_queued()?;
+        self.flush()?;
+        loop {
+            let parsed = self.read_message()?;
+            if self.auto_pong {
+                if let Ok(Some(ping)) = parsed.as_ref().map(|message| message.as_ping()) {
+                    self.send_now(&ping.pong())?;
+                    continue;
+                }
+            }
+            return Ok(parsed);
+        }
+    }
+
+    fn read_message(&mut self) -> io::Result<Parsed> {
+        let line = self.read_line()?;
+        let _entered = self.span.enter();
+        let decoded = match self.encoding.decode(&line) {
+            Ok(decoded) => decoded,
+            Err(e) => return Ok(Err((line, e))),
+        };
+        match Message::parse_with(&decoded, self.line_endings) {
             Ok((msg, remaining)) => {
                 assert!(remaining.len() == 0);
-                debug!("RECV> {}", msg);
-                Ok(msg)
+                trace::received(&msg);
+                self.metrics.message_received(&msg.command);
+                Ok(Ok(msg))
+            }
+            Err(parse_error) => {
+                let error = io::Error::new(ErrorKind::InvalidData, parse_error);
+                Ok(Err((line, error)))
             }
-            Err(parse_error) => Err(io::Error::new(ErrorKind::InvalidData, parse_error)),
         }
     }
 
+    fn read_line(&mut self) -> io::Result<Vec<u8>> {
+        // Anything read before an error is left in the buffer, so it's kept for the next try.
+        let mut buf = mem::take(&mut self.partial);
+        loop {
+            let read_before = buf.len();
+            let result = self.read_until_newline(&mut buf);
+            if buf.len() > read_before {
+                if let Some(ref mut keepalive) = self.keepalive {
+                    keepalive.last_received = Instant::now();
+                    keepalive.pinged = false;
+                }
+            }
+            match result {
+                Ok(0) => {
+                    return Err(io::Error::new(ErrorKind::UnexpectedEof,
+                                              "Connection closed by server"))
+                }
+                Ok(_) => {
+                    self.metrics.bytes_received(buf.len());
+                    return Ok(buf);
+                }
+                Err(ref e) if is_timeout(e) && self.keepalive.is_some() => {
+                    let result = self.check_keepalive()
+                        .and_then(|_| self.send_queued())
+                        .and_then(|_| self.flush());
+                    if let Err(e) = result {
+                        self.partial = buf;
+                        return Err(e);
+                    }
+                }
+                Err(e) => {
+                    self.partial = buf;
+                    if is_timeout(&e) {
+                        // Nothing to read, so it's a good time to catch up with the queue.
+                        self.send_queued()?;
+                        self.flush()?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    // Like `read_until`, but with the line length capped. Returns how many bytes were added to
+    // `buf`, so skipping the end of a long line followed by the connection closing looks like EOF.
+    fn read_until_newline(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let mut read = 0;
+        loop {
+            let (newline, used) = {
+                let available = match self.reader.fill_buf() {
+                    Ok(available) => available,
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                if available.is_empty() {
+                    return Ok(read);
+                }
+                let newline = available.iter().position(|&c| c == b'\n');
+                let used = newline.map_or(available.len(), |newline| newline + 1);
+                if !self.discarding {
+                    buf.extend_from_slice(&available[..used]);
+                }
+                (newline.is_some(), used)
+            };
+            self.reader.consume(used);
+
+            if self.discarding {
+                self.discarding = !newline;
+                continue;
+            }
+            read += used;
+            if buf.len() > self.max_line_length {
+                buf.clear();
+                self.discarding = !newline;
+                return Err(too_long(self.max_line_length));
+            }
+            if newline {
+                return Ok(read);
+            }
+        }
+    }
+
+    // Called when a read times out, to PING the server or give up on it.
+    fn check_keepalive(&mut self) -> io::Result<()> {
+        let (silence, pinged, idle, timeout) = match self.keepalive {
+            Some(ref k) => (k.last_received.elapsed(), k.pinged, k.idle, k.timeout),
+            None => return Ok(()),
+        };
+        if pinged && silence >= idle + timeout {
+            return Err(io::Error::new(ErrorKind::TimedOut,
+                                      format!("Nothing received from server for {:?}",
+                                              silence)));
+        }
+        if !pinged && silence >= idle {
+            debug!("Nothing received for {:?}, checking the connection", silence);
+            self.send_now(&Message::ping("keepalive"))?;
+            if let Some(ref mut keepalive) = self.keepalive {
+                keepalive.pinged = true;
+            }
+        }
+        Ok(())
+    }
+
     fn stream(&mut self) -> &mut S {
         self.reader.get_mut()
     }
@@ -90,12 +643,127 @@ impl<S: Read + Write> Iterator for IrcStream<S> {
     }
 }
 
+// Whether a message can be sent as it is: not too long, and without anything in its arguments
+// which would end the line early and let the rest be read as another command.
+pub(crate) fn check_sendable(message: &Message) -> io::Result<()> {
+    if message.line_len() > MAX_LINE_LENGTH {
+        return Err(io::Error::new(ErrorKind::InvalidInput,
+                                  format!("Message is longer than {} bytes: {}",
+                                          MAX_LINE_LENGTH,
+                                          message)));
+    }
+    let line_break = |c: char| c == '\r' || c == '\n' || c == '\0';
+    if message.arguments.iter().any(|argument| argument.contains(line_break)) {
+        return Err(io::Error::new(ErrorKind::InvalidInput,
+                                  format!("Message contains CR, LF or NUL: {:?}",
+                                          message.to_string())));
+    }
+    Ok(())
+}
+
+pub(crate) fn is_timeout(error: &io::Error) -> bool {
+    error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut
+}
+
+/// A stream for tests which reads from a fixed script of input, and collects whatever is written
+/// separately (unlike a `Cursor`, where writes would clobber the input).
+#[cfg(test)]
+#[derive(Debug)]
+pub struct ScriptedStream {
+    input: io::Cursor<Vec<u8>>,
+    pub output: Vec<u8>,
+    pub writes: usize,
+}
+
+#[cfg(test)]
+impl ScriptedStream {
+    pub fn new(input: &[u8]) -> Self {
+        ScriptedStream {
+            input: io::Cursor::new(input.to_vec()),
+            output: Vec::new(),
+            writes: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+impl Read for ScriptedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for ScriptedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writes += 1;
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A stream for tests which writes at most the given number of bytes for each write in turn, with
+/// `None` being a write that timed out. Once they've run out, writes everything.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct StallingWrites {
+    writes: Vec<Option<usize>>,
+    pub output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl StallingWrites {
+    pub fn new(mut writes: Vec<Option<usize>>) -> Self {
+        writes.reverse();
+        StallingWrites {
+            writes: writes,
+            output: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Read for StallingWrites {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+impl Write for StallingWrites {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let limit = match self.writes.pop() {
+            Some(Some(limit)) => limit,
+            Some(None) => return Err(io::Error::new(ErrorKind::TimedOut, "Timed out")),
+            None => buf.len(),
+        };
+        self.output.write(&buf[..limit.min(buf.len())])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl IrcStream<ScriptedStream> {
+    /// Everything which has been sent on this stream so far.
+    pub fn sent(&mut self) -> String {
+        String::from_utf8_lossy(&self.stream().output).into_owned()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Cursor;
+    use decoder::OversizedLine;
     use message::Message;
     use message::Prefix;
+    use command::commands;
     use command::commands::PING;
 
     #[test]
@@ -111,7 +779,316 @@ mod tests {
         assert_eq!(reader.next_message().unwrap(),
                    Message::from_strs(Prefix::None, PING(), vec!["789"]));
 
-        assert!(reader.next_message().is_err());
+        assert_eq!(reader.next_message().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn send_rejects_long_lines() {
+        let mut irc = IrcStream::new(ScriptedStream::new(b""));
+        let text = "a".repeat(MAX_LINE_LENGTH);
+
+        assert!(irc.send(&Message::privmsg("#chan", &text)).is_err());
+        assert_eq!(irc.sent(), "");
+    }
+
+    #[test]
+    fn send_rejects_line_breaks() {
+        let mut irc = IrcStream::new(ScriptedStream::new(b""));
+
+        for text in &["hi\rQUIT :bye", "hi\nQUIT", "hi\0"] {
+            let error = irc.send(&Message::privmsg("#chan", text)).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+        assert_eq!(irc.sent(), "");
+    }
+
+    #[test]
+    fn auto_pong() {
+        let input = b"PING :irc.host\r\nPRIVMSG me :Hi\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input)).with_auto_pong();
+
+        assert_eq!(irc.next_message().unwrap(), Message::privmsg("me", "Hi"));
+        assert_eq!(irc.sent(), "PONG irc.host\r\n");
+    }
+
+    #[test]
+    fn raw_lines() {
+        let input = b"PING :irc.host\r\nPRIVMSG me :\x01DCC \xff\x01\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input)).with_auto_pong();
+        let line = irc.next_line().unwrap();
+
+        assert_eq!(line, b"PRIVMSG me :\x01DCC \xff\x01\r\n".to_vec());
+        let (message, _) = MessageRef::parse(&line).unwrap();
+        assert_eq!(message.arguments_raw().nth(1), Some(&b"\x01DCC \xff\x01"[..]));
+        assert_eq!(irc.sent(), "PONG irc.host\r\n");
+    }
+
+    #[test]
+    fn latin1_fallback() {
+        let input = b"PRIVMSG me :caf\xe9\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input))
+            .with_encoding(TextEncoding::Utf8OrCp1252);
+
+        assert_eq!(irc.next_message().unwrap(), Message::privmsg("me", "café"));
+    }
+
+    #[test]
+    fn send_with_rate_limit() {
+        let limiter = RateLimiter::new(Duration::from_millis(50), 1);
+        let mut irc = IrcStream::new(ScriptedStream::new(b"")).with_rate_limit(limiter);
+        let start = ::std::time::Instant::now();
+
+        irc.send(&Message::privmsg("#chan", "one")).unwrap();
+        irc.send(&Message::privmsg("#chan", "two")).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(irc.sent(), "PRIVMSG #chan one\r\nPRIVMSG #chan two\r\n");
+    }
+
+    #[test]
+    fn keepalive() {
+        let reads = vec![Some(&b"PRIVMSG me :Hi\r\n"[..]),
+                         None,
+                         Some(b":server PONG server :keepalive\r\n"),
+                         None,
+                         None];
+        let mut irc = IrcStream::new(StallingStream::new(reads))
+            .with_keepalive(Duration::from_secs(0), Duration::from_secs(0));
+
+        assert_eq!(irc.next_message().unwrap(), Message::privmsg("me", "Hi"));
+        // The server went quiet, so we checked on it.
+        assert_eq!(irc.next_message().unwrap().command, commands::PONG());
+        assert_eq!(irc.get_ref().output, b"PING keepalive\r\n");
+        // Then it went quiet again, and didn't answer.
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn keeps_partial_lines_after_timeouts() {
+        let reads = vec![Some(&b"PRIVMSG me"[..]), None, Some(b" :Hi\r\n")];
+        let mut irc = IrcStream::new(StallingStream::new(reads));
+
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(irc.next_message().unwrap(), Message::privmsg("me", "Hi"));
+    }
+
+    // Reads each chunk in turn, with `None` being a read that timed out.
+    struct StallingStream {
+        reads: Vec<Option<&'static [u8]>>,
+        output: Vec<u8>,
+    }
+
+    impl StallingStream {
+        fn new(mut reads: Vec<Option<&'static [u8]>>) -> Self {
+            reads.reverse();
+            StallingStream {
+                reads: reads,
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for StallingStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.reads.pop() {
+                Some(Some(chunk)) => {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    Ok(chunk.len())
+                }
+                Some(None) => Err(io::Error::new(ErrorKind::WouldBlock, "Timed out")),
+                None => Ok(0),
+            }
+        }
+    }
+
+    impl Write for StallingStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn queue_priorities() {
+        let limiter = RateLimiter::new(Duration::from_millis(50), 2);
+        let mut irc = IrcStream::new(ScriptedStream::new(b"")).with_rate_limit(limiter);
+        let start = Instant::now();
+
+        for text in &["one", "two", "three"] {
+            irc.queue(&Message::privmsg("#chan", text)).unwrap();
+        }
+        irc.queue(&Message::kick("#chan", "spammer", None)).unwrap();
+        assert_eq!(irc.send_queued_at(start).unwrap(), 2);
+        assert_eq!(irc.sent(), "KICK #chan spammer\r\nPRIVMSG #chan one\r\n");
+
+        // A message sent directly, like a PONG, doesn't wait behind the queue.
+        irc.send(&Message::from_strs(Prefix::None, commands::PONG(), vec!["irc.host"])).unwrap();
+        assert!(irc.sent().ends_with("PRIVMSG #chan one\r\nPONG irc.host\r\n"));
+        assert!(irc.queue(&Message::privmsg("#chan", &"a".repeat(MAX_LINE_LENGTH))).is_err());
+    }
+
+    #[test]
+    fn send_all_writes_once() {
+        let mut irc = IrcStream::new(ScriptedStream::new(b""));
+        let joins = vec![Message::join("#one"), Message::join("#two"), Message::join("#three")];
+
+        irc.send_all(&joins).unwrap();
+        assert_eq!(irc.sent(), "JOIN #one\r\nJOIN #two\r\nJOIN #three\r\n");
+        assert_eq!(irc.get_ref().writes, 1);
+    }
+
+    #[test]
+    fn buffered_writes() {
+        let mut irc = IrcStream::new(ScriptedStream::new(b"PING 123\r\n")).with_buffered_writes();
+
+        irc.send(&Message::privmsg("#chan", "one")).unwrap();
+        irc.send(&Message::privmsg("#chan", "two")).unwrap();
+        assert_eq!(irc.sent(), "");
+        irc.flush().unwrap();
+        assert_eq!(irc.sent(), "PRIVMSG #chan one\r\nPRIVMSG #chan two\r\n");
+        assert_eq!(irc.get_ref().writes, 1);
+
+        // Reading flushes first.
+        irc.send(&Message::privmsg("#chan", "three")).unwrap();
+        irc.next_message().unwrap();
+        assert!(irc.sent().ends_with("PRIVMSG #chan three\r\n"));
+    }
+
+    #[test]
+    fn failed_flush_keeps_lines() {
+        let mut irc = IrcStream::new(StallingWrites::new(vec![Some(5), None]))
+            .with_buffered_writes();
+        irc.send(&Message::privmsg("#chan", "one")).unwrap();
+        irc.send(&Message::privmsg("#chan", "two")).unwrap();
+
+        assert_eq!(irc.flush().unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(irc.get_ref().output, b"PRIVM");
+        irc.flush().unwrap();
+        assert_eq!(irc.get_ref().output, b"PRIVMSG #chan one\r\nPRIVMSG #chan two\r\n");
+    }
+
+    #[test]
+    fn queue_drains_on_read_timeout() {
+        let limiter = RateLimiter::new(Duration::from_millis(20), 1);
+        let stream = SlowTimeouts(StallingStream::new(vec![None]));
+        let mut irc = IrcStream::new(stream).with_rate_limit(limiter);
+        irc.queue(&Message::privmsg("#chan", "one")).unwrap();
+        irc.queue(&Message::privmsg("#chan", "two")).unwrap();
+
+        // The first goes before reading, and the second when the read times out, by which time
+        // the rate limit allows it.
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(irc.get_ref().0.output, b"PRIVMSG #chan one\r\nPRIVMSG #chan two\r\n");
+    }
+
+    // Reads take a while to time out, like a socket with a read timeout.
+    struct SlowTimeouts(StallingStream);
+
+    impl Read for SlowTimeouts {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_millis(30));
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for SlowTimeouts {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    #[test]
+    fn buffered_writes_still_pong() {
+        let reads = vec![Some(&b"PING :irc.host\r\n"[..]), None];
+        let mut irc = IrcStream::new(StallingStream::new(reads))
+            .with_auto_pong()
+            .with_buffered_writes();
+
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::WouldBlock);
+        assert_eq!(irc.get_ref().output, b"PONG irc.host\r\n");
+    }
+
+    #[test]
+    fn buffered_writes_still_keepalive() {
+        let reads = vec![None, Some(&b":server PONG server :keepalive\r\n"[..])];
+        let mut irc = IrcStream::new(StallingStream::new(reads))
+            .with_keepalive(Duration::from_secs(0), Duration::from_secs(60))
+            .with_buffered_writes();
+
+        assert_eq!(irc.next_message().unwrap().command, commands::PONG());
+        assert_eq!(irc.get_ref().output, b"PING keepalive\r\n");
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let input = b"!!!\r\nPING 123\r\n";
+
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(irc.next_message().unwrap().command, PING());
+
+        let mut irc = IrcStream::new(ScriptedStream::new(input))
+            .with_parse_policy(ParsePolicy::SkipWithWarning);
+        assert_eq!(irc.next_message().unwrap().command, PING());
+
+        let long = b"PRIVMSG #chan :Far too long\r\nPING 1\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(long))
+            .with_max_line_length(20)
+            .with_parse_policy(ParsePolicy::SkipWithWarning);
+        assert_eq!(irc.next_message().unwrap().command, PING());
+
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        assert_eq!(irc.next_received().unwrap(), Received::Malformed(b"!!!\r\n".to_vec()));
+        match irc.next_received().unwrap() {
+            Received::Message(message) => assert_eq!(message.command, PING()),
+            other => panic!("Expected a message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn line_endings() {
+        let input = b"PING 1\nPING 2\r\nPING 3";
+
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        assert_eq!(irc.next_message().unwrap().arguments[..], ["1"]);
+        assert_eq!(irc.next_message().unwrap().arguments[..], ["2"]);
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let mut irc = IrcStream::new(ScriptedStream::new(input))
+            .with_line_endings(LineEndings::Strict);
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(irc.next_message().unwrap().arguments[..], ["2"]);
+
+        let mut irc = IrcStream::new(ScriptedStream::new(input))
+            .with_line_endings(LineEndings::AllowEof);
+        assert_eq!(irc.by_ref().count(), 3);
+    }
+
+    #[test]
+    fn oversized_lines() {
+        let input = format!("PRIVMSG #chan :{}\r\nPING 1\r\nPING 2\r\n", "a".repeat(100));
+        let mut irc = IrcStream::new(ScriptedStream::new(input.as_bytes()))
+            .with_max_line_length(20);
+
+        let error = irc.next_message().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(OversizedLine::of(&error).map(|e| e.max_line_length()), Some(20));
+        assert_eq!(irc.next_message().unwrap().arguments[..], ["1"]);
+        assert_eq!(irc.next_message().unwrap().arguments[..], ["2"]);
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        // Longer than the read buffer, so the rest of it is skipped over several reads.
+        let input = format!("PRIVMSG #chan :{}\r\nPING 1\r\n", "a".repeat(50000));
+        let mut irc = IrcStream::new(ScriptedStream::new(input.as_bytes()));
+        assert!(OversizedLine::of(&irc.next_message().unwrap_err()).is_some());
+        assert_eq!(irc.next_message().unwrap().arguments[..], ["1"]);
     }
 
     #[test]
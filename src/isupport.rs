@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use casemapping::IrcCase;
+use command::responses;
+use membership::MembershipPrefixes;
+use message::Message;
+
+/// What the server told us about itself in `RPL_ISUPPORT` (005) messages, e.g. which characters
+/// start channel names and how many targets a PRIVMSG can have.
+///
+/// Servers send several 005 lines after registering, and may send more later to change or remove
+/// tokens, so pass every received message to `handle`. Accessors for the common tokens fall back
+/// to the traditional values if the server hasn't said otherwise; use `get` for anything else.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IsupportInfo {
+    // Keyed by uppercased token name. Values are unescaped, and empty for tokens without one.
+    tokens: HashMap<String, String>,
+}
+
+/// The kinds of channel mode, from the `CHANMODES` token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelModes {
+    /// Modes which add or remove an address from a list, like `b` (type A).
+    pub list: String,
+    /// Modes which always take a parameter, like `k` (type B).
+    pub parameter: String,
+    /// Modes which only take a parameter when being set, like `l` (type C).
+    pub set_parameter: String,
+    /// Modes which never take a parameter, like `m` (type D).
+    pub flag: String,
+}
+
+impl Default for ChannelModes {
+    /// The modes from RFC 2811, for servers which don't say otherwise.
+    fn default() -> Self {
+        ChannelModes::parse("beI,k,l,imnpst").unwrap()
+    }
+}
+
+impl ChannelModes {
+    /// Parses the value of a `CHANMODES` token, e.g. `beI,k,l,imnpst`. Returns `None` if there
+    /// are fewer than four types; any beyond four are ignored, as the spec asks.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut types = value.split(',');
+        Some(ChannelModes {
+            list: types.next()?.into(),
+            parameter: types.next()?.into(),
+            set_parameter: types.next()?.into(),
+            flag: types.next()?.into(),
+        })
+    }
+}
+
+impl IsupportInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates from a received message, returning whether it was an `RPL_ISUPPORT`.
+    pub fn handle(&mut self, message: &Message) -> bool {
+        if message.command != responses::RPL_ISUPPORT() {
+            return false;
+        }
+        // <client> <tokens>... :are supported by this server
+        let args = &message.arguments;
+        if args.len() < 3 {
+            warn!("Ignoring RPL_ISUPPORT with no tokens: {}", message);
+            return true;
+        }
+        for token in &args[1..args.len() - 1] {
+            if let Some(name) = token.strip_prefix('-') {
+                self.tokens.remove(&name.to_ascii_uppercase());
+            } else {
+                let (name, value) = token.split_once('=').unwrap_or((token, ""));
+                self.tokens.insert(name.to_ascii_uppercase(), unescape(value));
+            }
+        }
+        true
+    }
+
+    /// Whether the server has sent `token`, with or without a value.
+    pub fn is_supported(&self, token: &str) -> bool {
+        self.tokens.contains_key(&token.to_ascii_uppercase())
+    }
+
+    /// The value of `token`, if the server has sent it. Tokens without a value give `""`.
+    pub fn get(&self, token: &str) -> Option<&str> {
+        self.tokens.get(&token.to_ascii_uppercase()).map(|value| &value[..])
+    }
+
+    /// The characters channel names can start with. Defaults to `#&`.
+    pub fn chantypes(&self) -> &str {
+        self.get("CHANTYPES").unwrap_or("#&")
+    }
+
+    /// Whether `target` is a channel name rather than a nickname.
+    pub fn is_channel(&self, target: &str) -> bool {
+        target.chars().next().is_some_and(|c| self.chantypes().contains(c))
+    }
+
+    pub fn chanmodes(&self) -> ChannelModes {
+        self.get("CHANMODES").and_then(ChannelModes::parse).unwrap_or_default()
+    }
+
+    pub fn prefix(&self) -> MembershipPrefixes {
+        self.get("PREFIX").and_then(MembershipPrefixes::parse).unwrap_or_default()
+    }
+
+    /// The network's name, e.g. `Libera.Chat`.
+    pub fn network(&self) -> Option<&str> {
+        self.get("NETWORK").filter(|network| !network.is_empty())
+    }
+
+    /// How the server compares nicknames and channel names. Defaults to `rfc1459`, which is also
+    /// used if the server names one we don't support.
+    pub fn casemapping(&self) -> IrcCase {
+        self.get("CASEMAPPING").and_then(IrcCase::from_name).unwrap_or_default()
+    }
+
+    pub fn nicklen(&self) -> Option<usize> {
+        self.number("NICKLEN")
+    }
+
+    pub fn channellen(&self) -> Option<usize> {
+        self.number("CHANNELLEN")
+    }
+
+    pub fn topiclen(&self) -> Option<usize> {
+        self.number("TOPICLEN")
+    }
+
+    /// How many modes with parameters can be set in one MODE message, or `None` if there's no
+    /// limit. Defaults to 3.
+    pub fn modes(&self) -> Option<usize> {
+        match self.get("MODES") {
+            None => Some(3),
+            Some(_) => self.number("MODES"),
+        }
+    }
+
+    /// How many targets `command` (e.g. `PRIVMSG`) can be sent to at once, or `None` if there's no
+    /// limit.
+    pub fn max_targets(&self, command: &str) -> Option<usize> {
+        match self.get("TARGMAX") {
+            Some(targmax) => {
+                targmax.split(',')
+                    .filter_map(|limit| limit.split_once(':'))
+                    .find(|&(name, _)| name.eq_ignore_ascii_case(command))
+                    .and_then(|(_, limit)| limit.parse().ok())
+            }
+            None => self.number("MAXTARGETS"),
+        }
+    }
+
+    fn number(&self, token: &str) -> Option<usize> {
+        self.get(token).and_then(|value| value.parse().ok())
+    }
+}
+
+// Values escape awkward characters as \xHH.
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes[i..].starts_with(b"\\x") && bytes.len() >= i + 4;
+        let byte = if escaped {
+            ::std::str::from_utf8(&bytes[i + 2..i + 4])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+        match byte {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 4;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use membership::Membership;
+    use message::Message;
+
+    #[test]
+    fn defaults() {
+        let isupport = IsupportInfo::new();
+        assert_eq!(isupport.chantypes(), "#&");
+        assert_eq!(isupport.chanmodes().parameter, "k");
+        assert_eq!(isupport.prefix(), MembershipPrefixes::default());
+        assert_eq!(isupport.casemapping(), IrcCase::Rfc1459);
+        assert_eq!(isupport.network(), None);
+        assert_eq!(isupport.modes(), Some(3));
+        assert_eq!(isupport.max_targets("PRIVMSG"), None);
+    }
+
+    #[test]
+    fn accumulates() {
+        let mut isupport = IsupportInfo::new();
+        assert!(isupport.handle(&message(":server 005 bot CHANTYPES=# EXCEPTS INVEX \
+                                           CHANMODES=eIbq,k,flj,CFLMPQScgimnprstuz \
+                                           PREFIX=(ov)@+ NETWORK=Example\\x20Net \
+                                           :are supported by this server\r\n")));
+        assert!(isupport.handle(&message(":server 005 bot CASEMAPPING=ascii NICKLEN=16 MODES \
+                                           TARGMAX=NAMES:1,PRIVMSG:4,JOIN: \
+                                           :are supported by this server\r\n")));
+        assert!(!isupport.handle(&message(":server 001 bot :Welcome\r\n")));
+
+        assert_eq!(isupport.chantypes(), "#");
+        assert!(isupport.is_channel("#chan"));
+        assert!(!isupport.is_channel("&chan"));
+        assert!(isupport.is_supported("excepts"));
+        assert_eq!(isupport.get("INVEX"), Some(""));
+        assert_eq!(isupport.chanmodes(),
+                   ChannelModes {
+                       list: "eIbq".into(),
+                       parameter: "k".into(),
+                       set_parameter: "flj".into(),
+                       flag: "CFLMPQScgimnprstuz".into(),
+                   });
+        assert_eq!(isupport.prefix().membership_for_prefix('@'), Some(Membership::Op));
+        assert_eq!(isupport.network(), Some("Example Net"));
+        assert_eq!(isupport.casemapping(), IrcCase::Ascii);
+        assert_eq!(isupport.nicklen(), Some(16));
+        assert_eq!(isupport.modes(), None);
+        assert_eq!(isupport.max_targets("privmsg"), Some(4));
+        assert_eq!(isupport.max_targets("JOIN"), None);
+    }
+
+    #[test]
+    fn removal() {
+        let mut isupport = IsupportInfo::new();
+        isupport.handle(&message(":server 005 bot NICKLEN=16 WHOX :are supported\r\n"));
+        isupport.handle(&message(":server 005 bot -WHOX -NICKLEN :are supported\r\n"));
+
+        assert!(!isupport.is_supported("WHOX"));
+        assert_eq!(isupport.nicklen(), None);
+    }
+
+    #[test]
+    fn unescaping() {
+        assert_eq!(unescape("a\\x20b\\x3Dc"), "a b=c");
+        assert_eq!(unescape("\\x2"), "\\x2");
+        assert_eq!(unescape("\\xzz"), "\\xzz");
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+use batch::Batch;
+use batch::BatchTracker;
+use batch::Batched;
+use command::commands;
+use irc_stream::IrcStream;
+use message::Message;
+use message::Tag;
+
+/// What the server sent back in response to a labeled command, when the `labeled-response`
+/// capability is enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabeledResponse {
+    /// The command produced no output; the server acknowledged it with `ACK`.
+    Ack(Message),
+    /// The command produced a single message.
+    Message(Message),
+    /// The command produced several messages, grouped in a `labeled-response` batch.
+    Batch(Batch),
+}
+
+/// A message (or batch) received while using a `LabelTracker`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelEvent {
+    Response(String, LabeledResponse),
+    /// Anything not correlated with a label we sent.
+    Other(Batched),
+}
+
+/// Labels outgoing commands and matches up the responses, as described by the IRCv3
+/// `labeled-response` specification. Batches are grouped, since that's how multi-message
+/// responses arrive.
+///
+/// For request/response style code use `request`, which blocks until the response arrives.
+/// Anything else received in the meantime is kept and can be fetched with `take_other`.
+#[derive(Debug, Default)]
+pub struct LabelTracker {
+    next_label: u64,
+    pending: HashSet<String>,
+    batches: BatchTracker,
+    other: VecDeque<LabelEvent>,
+}
+
+impl LabelTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a fresh `label` tag to `message`, returning the label along with the tagged message.
+    pub fn label(&mut self, message: &Message) -> (String, Message) {
+        self.next_label += 1;
+        let label = format!("L{}", self.next_label);
+        self.pending.insert(label.clone());
+
+        let mut labeled = message.clone();
+        labeled.tags.retain(|tag| tag.key != "label");
+        labeled.tags.push(Tag::new("label", Some(&label)));
+        (label, labeled)
+    }
+
+    /// Labels and sends `message`, returning the label used.
+    pub fn send<S: Read + Write>(&mut self,
+                                 irc: &mut IrcStream<S>,
+                                 message: &Message)
+                                 -> io::Result<String> {
+        let (label, labeled) = self.label(message);
+        irc.send(&labeled)?;
+        Ok(label)
+    }
+
+    /// Sends `message` and blocks until its response arrives. PINGs received in the meantime are
+    /// answered.
+    pub fn request<S: Read + Write>(&mut self,
+                                    irc: &mut IrcStream<S>,
+                                    message: &Message)
+                                    -> io::Result<LabeledResponse> {
+        let label = self.send(irc, message)?;
+        loop {
+            let message = irc.next_message()?;
+            if let Some(ping) = message.as_ping() {
+                irc.send(&ping.pong())?;
+                continue;
+            }
+            match self.push(message) {
+                Some(LabelEvent::Response(received, response)) if received == label => {
+                    return Ok(response);
+                }
+                Some(event) => self.other.push_back(event),
+                None => (),
+            }
+        }
+    }
+
+    /// Takes the oldest event received during a `request` which wasn't the response to that
+    /// request.
+    pub fn take_other(&mut self) -> Option<LabelEvent> {
+        self.other.pop_front()
+    }
+
+    /// Processes a received message. Returns `None` while a batch is still being collected.
+    pub fn push(&mut self, message: Message) -> Option<LabelEvent> {
+        let batched = self.batches.push(message)?;
+
+        let label = match batched {
+            Batched::Message(ref message) => message.tag_value("label"),
+            Batched::Batch(ref batch) => batch.start.tag_value("label"),
+        };
+        let label = match label {
+            Some(label) if self.pending.contains(label) => label.to_string(),
+            _ => return Some(LabelEvent::Other(batched)),
+        };
+        self.pending.remove(&label);
+
+        let response = match batched {
+            Batched::Message(message) => {
+                if message.command == commands::ACK() {
+                    LabeledResponse::Ack(message)
+                } else {
+                    LabeledResponse::Message(message)
+                }
+            }
+            Batched::Batch(batch) => LabeledResponse::Batch(batch),
+        };
+        Some(LabelEvent::Response(label, response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use batch::BatchType;
+    use irc_stream::ScriptedStream;
+    use message::Message;
+
+    #[test]
+    fn labels_are_unique() {
+        let mut tracker = LabelTracker::new();
+        let (first, _) = tracker.label(&Message::nick("somebody"));
+        let (second, labeled) = tracker.label(&Message::nick("somebody"));
+
+        assert!(first != second);
+        assert_eq!(labeled.tag_value("label"), Some(second.as_str()));
+    }
+
+    #[test]
+    fn single_message_response() {
+        let mut tracker = LabelTracker::new();
+        let (label, _) = tracker.label(&Message::nick("somebody"));
+
+        let response = message(&format!("@label={} :irc.host 433 me somebody :Nickname is \
+                                         already in use\r\n",
+                                        label));
+        assert_eq!(tracker.push(response.clone()),
+                   Some(LabelEvent::Response(label, LabeledResponse::Message(response))));
+    }
+
+    #[test]
+    fn ack_response() {
+        let mut tracker = LabelTracker::new();
+        let (label, _) = tracker.label(&Message::nick("somebody"));
+
+        match tracker.push(message(&format!("@label={} :irc.host ACK\r\n", label))) {
+            Some(LabelEvent::Response(_, LabeledResponse::Ack(_))) => (),
+            other => panic!("Expected ACK, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_label_is_other() {
+        let mut tracker = LabelTracker::new();
+        let response = message("@label=nope :irc.host ACK\r\n");
+
+        assert_eq!(tracker.push(response.clone()),
+                   Some(LabelEvent::Other(Batched::Message(response))));
+    }
+
+    #[test]
+    fn request_batch() {
+        let input = b"PING 123\r\n\
+                      @label=L1 :irc.host BATCH +b labeled-response\r\n\
+                      @batch=b :irc.host 311 me nick user host * :Real Name\r\n\
+                      @batch=b :irc.host 318 me nick :End of /WHOIS list.\r\n\
+                      :irc.host BATCH -b\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+        let mut tracker = LabelTracker::new();
+
+        match tracker.request(&mut irc, &Message::nick("nick")).unwrap() {
+            LabeledResponse::Batch(batch) => {
+                assert_eq!(batch.batch_type, BatchType::LabeledResponse);
+                assert_eq!(batch.messages.len(), 2);
+            }
+            other => panic!("Expected batch, got {:?}", other),
+        }
+        assert_eq!(irc.sent(), "@label=L1 NICK nick\r\nPONG 123\r\n");
+        assert_eq!(tracker.take_other(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
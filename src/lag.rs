@@ -0,0 +1,226 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use command::commands;
+use message::Message;
+use metrics::Metrics;
+use metrics::MetricsSink;
+
+// How many round trips `average` covers.
+const SAMPLES: usize = 8;
+
+/// Something `LagMonitor` noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagEvent {
+    /// A PING came back after this long.
+    Measured(Duration),
+    /// The lag went over the threshold: either a PING came back after this long, or it's been
+    /// this long and it hasn't come back yet. This is reported once per PING.
+    Exceeded(Duration),
+}
+
+/// Measures lag by sending our own PINGs and timing how long the PONGs take to come back.
+///
+/// Each PING carries a unique token, so PONGs for ones the server answered late (or that someone
+/// else sent) aren't mistaken for the latest. Call `poll` regularly (say every second) and send
+/// any PING it returns, and pass every received message to `handle`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LagMonitor {
+    interval: Duration,
+    threshold: Duration,
+    next_token: u64,
+    // The PING we're waiting on, when it was sent, and whether we've reported it as late.
+    outstanding: Option<(String, Instant, bool)>,
+    last_sent: Option<Instant>,
+    samples: VecDeque<Duration>,
+    metrics: Metrics,
+}
+
+impl Default for LagMonitor {
+    fn default() -> Self {
+        LagMonitor {
+            interval: Duration::from_secs(30),
+            threshold: Duration::from_secs(20),
+            next_token: 0,
+            outstanding: None,
+            last_sent: None,
+            samples: VecDeque::new(),
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+impl LagMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How often to send a PING. Defaults to 30 seconds.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How much lag counts as too much. Defaults to 20 seconds.
+    pub fn with_threshold(mut self, threshold: Duration) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Reports each lag measured to `sink`, and on each `poll` while a PING is waiting to come
+    /// back, how long it's been waiting so far.
+    pub fn with_metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Metrics::new(sink);
+        self
+    }
+
+    /// The PING to send, if it's time for one, along with an `Exceeded` event if the last one is
+    /// overdue. No new PING is sent until the last has come back.
+    pub fn poll(&mut self) -> (Option<Message>, Option<LagEvent>) {
+        self.poll_at(Instant::now())
+    }
+
+    /// As `poll`, but with the current time passed in.
+    pub fn poll_at(&mut self, now: Instant) -> (Option<Message>, Option<LagEvent>) {
+        let threshold = self.threshold;
+        if let Some((_, sent, ref mut reported)) = self.outstanding {
+            let waited = now.duration_since(sent);
+            self.metrics.lag(waited);
+            if waited > threshold && !*reported {
+                *reported = true;
+                return (None, Some(LagEvent::Exceeded(waited)));
+            }
+            return (None, None);
+        }
+        match self.last_sent {
+            Some(last_sent) if now.duration_since(last_sent) < self.interval => (None, None),
+            _ => {
+                self.next_token += 1;
+                let token = format!("lag{}", self.next_token);
+                let ping = Message::ping(&token);
+                self.outstanding = Some((token, now, false));
+                self.last_sent = Some(now);
+                (Some(ping), None)
+            }
+        }
+    }
+
+    /// Recognises the PONG for our PING.
+    pub fn handle(&mut self, message: &Message) -> Option<LagEvent> {
+        self.handle_at(message, Instant::now())
+    }
+
+    /// As `handle`, with `now` as when the message arrived, which the lag is measured up to.
+    pub fn handle_at(&mut self, message: &Message, now: Instant) -> Option<LagEvent> {
+        if message.command != commands::PONG() {
+            return None;
+        }
+        let (sent, reported) = match self.outstanding {
+            Some((ref token, sent, reported)) if message.arguments.last() == Some(token) => {
+                (sent, reported)
+            }
+            _ => return None,
+        };
+        self.outstanding = None;
+
+        let lag = now.duration_since(sent);
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(lag);
+        self.metrics.lag(lag);
+        if lag > self.threshold && !reported {
+            Some(LagEvent::Exceeded(lag))
+        } else {
+            Some(LagEvent::Measured(lag))
+        }
+    }
+
+    /// The lag measured by the last PING to come back.
+    pub fn current(&self) -> Option<Duration> {
+        self.samples.back().cloned()
+    }
+
+    /// The average lag over the last few PINGs.
+    pub fn average(&self) -> Option<Duration> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        Some(self.samples.iter().sum::<Duration>() / self.samples.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use std::time::Instant;
+    use message::Message;
+
+    #[test]
+    fn measures_round_trips() {
+        let mut lag = LagMonitor::new();
+        let start = Instant::now();
+
+        let (ping, event) = lag.poll_at(start);
+        assert_eq!(ping, Some(Message::ping("lag1")));
+        assert_eq!(event, None);
+        // Nothing more until the interval is up.
+        assert_eq!(lag.poll_at(start + Duration::from_secs(1)), (None, None));
+
+        let pong = message(":server PONG server :lag1\r\n");
+        assert_eq!(lag.handle_at(&pong, start + Duration::from_millis(200)),
+                   Some(LagEvent::Measured(Duration::from_millis(200))));
+        assert_eq!(lag.poll_at(start + Duration::from_secs(29)), (None, None));
+
+        let later = start + Duration::from_secs(30);
+        assert_eq!(lag.poll_at(later).0, Some(Message::ping("lag2")));
+        let pong = message(":server PONG server :lag2\r\n");
+        lag.handle_at(&pong, later + Duration::from_millis(400));
+
+        assert_eq!(lag.current(), Some(Duration::from_millis(400)));
+        assert_eq!(lag.average(), Some(Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn ignores_other_pongs() {
+        let mut lag = LagMonitor::new();
+        let start = Instant::now();
+        lag.poll_at(start);
+
+        let pong = message(":server PONG server :something\r\n");
+        assert_eq!(lag.handle_at(&pong, start + Duration::from_secs(1)), None);
+        assert_eq!(lag.current(), None);
+    }
+
+    #[test]
+    fn exceeded_threshold() {
+        let mut lag = LagMonitor::new().with_threshold(Duration::from_secs(5));
+        let start = Instant::now();
+        lag.poll_at(start);
+
+        assert_eq!(lag.poll_at(start + Duration::from_secs(5)), (None, None));
+        assert_eq!(lag.poll_at(start + Duration::from_secs(6)),
+                   (None, Some(LagEvent::Exceeded(Duration::from_secs(6)))));
+        // Only reported once, even when it does come back.
+        assert_eq!(lag.poll_at(start + Duration::from_secs(7)), (None, None));
+        let pong = message(":server PONG server :lag1\r\n");
+        assert_eq!(lag.handle_at(&pong, start + Duration::from_secs(8)),
+                   Some(LagEvent::Measured(Duration::from_secs(8))));
+
+        let mut lag = LagMonitor::new().with_threshold(Duration::from_secs(5));
+        lag.poll_at(start);
+        assert_eq!(lag.handle_at(&pong, start + Duration::from_secs(6)),
+                   Some(LagEvent::Exceeded(Duration::from_secs(6))));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
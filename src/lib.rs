@@ -16,29 +16,277 @@ extern crate log;
 #[macro_use]
 extern crate nom;
 
+#[cfg(feature = "local-bind")]
+extern crate socket2;
+
+#[cfg(feature = "native-tls")]
+extern crate native_tls;
+#[cfg(feature = "openssl")]
 extern crate openssl;
+#[cfg(feature = "rustls")]
+extern crate rustls;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(feature = "encoding")]
+extern crate encoding_rs;
+
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+#[cfg(feature = "async")]
+extern crate bytes;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "futures-io")]
+extern crate futures_io;
+#[cfg(feature = "async")]
+extern crate futures_sink;
+#[cfg(feature = "async")]
+extern crate futures_util;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "tokio")]
+extern crate tokio_rustls;
+#[cfg(feature = "async")]
+extern crate tokio_util;
+#[cfg(any(feature = "tokio", feature = "rustls"))]
+extern crate webpki_roots;
+
+#[cfg(feature = "async")]
+mod async_stream;
+mod base64;
+mod batch;
+mod bouncer;
+mod cap;
+mod casemapping;
+mod chathistory;
+mod client;
+#[cfg(feature = "async")]
+mod codec;
 mod command;
+mod ctcp;
+mod dcc;
+mod decoder;
+mod echo;
+mod encoding;
+mod fast_parser;
+mod formatting;
+#[cfg(feature = "arbitrary")]
+mod fuzzing;
+mod handler;
+mod hostmask;
+mod http_proxy;
 mod irc_stream;
+mod isupport;
+mod label;
+mod lag;
+mod list;
+#[cfg(feature = "local-bind")]
+mod local_bind;
+mod membership;
+mod metrics;
+mod monitor;
 mod message;
+mod message_ref;
+mod names;
+#[cfg(feature = "native-tls")]
+mod native_tls_connector;
+#[cfg(feature = "openssl")]
+mod openssl_connector;
 mod parser;
+mod rate_limit;
+mod recording;
+mod reconnect;
+mod rejoin;
+mod registration;
+#[cfg(feature = "rustls")]
+mod rustls_connector;
+mod send_queue;
+mod server_time;
+mod services;
+mod socks5;
+mod tls;
+mod trace;
+mod typing;
+mod users;
+mod who;
+mod whois;
 
 pub mod messages;
+pub mod sasl;
+pub mod testing;
+#[cfg(feature = "async")]
+pub use async_stream::AsyncIrcStream;
+pub use batch::Batch;
+pub use batch::BatchTracker;
+pub use batch::BatchType;
+pub use batch::Batched;
+pub use bouncer::Bouncer;
+pub use bouncer::BouncerState;
+pub use cap::CapNegotiator;
+pub use casemapping::IrcCase;
+pub use casemapping::IrcString;
+pub use chathistory::HistoryFetcher;
+pub use chathistory::HistoryMessage;
+pub use client::ChannelListing;
+pub use client::Client;
+#[cfg(feature = "async")]
+pub use codec::IrcCodec;
 pub use command::Command;
+pub use command::CommandError;
+pub use command::KnownCommand;
 pub use command::responses;
 pub use command::commands;
+pub use ctcp::CtcpResponder;
+pub use dcc::Dcc;
+pub use dcc::DccResume;
+pub use dcc::DccSend;
+pub use decoder::DEFAULT_MAX_LINE_LENGTH;
+pub use decoder::MessageDecoder;
+pub use decoder::OversizedLine;
+pub use echo::EchoDetector;
+pub use encoding::TextEncoding;
+pub use formatting::Color;
+pub use formatting::ExtendedColor;
+pub use formatting::Fmt;
+pub use formatting::Span;
+pub use formatting::Style;
+pub use formatting::to_ansi;
+pub use formatting::to_html;
+pub use handler::EventHandler;
+pub use hostmask::BanMaskStyle;
+pub use hostmask::Hostmask;
+pub use http_proxy::HttpProxy;
+pub use message::Arguments;
+pub use message::MAX_LINE_LENGTH;
 pub use message::Message;
 pub use message::Prefix;
+pub use message::Tag;
 pub use message::UserInfo;
+pub use message_ref::ArgumentsRef;
+pub use message_ref::MessageRef;
+pub use message_ref::PrefixRef;
+pub use message_ref::RawArgumentsRef;
+pub use message_ref::TagRef;
+pub use message_ref::TagsRef;
+pub use irc_stream::CertFpHash;
+pub use irc_stream::ClientCert;
 pub use irc_stream::IrcStream;
+pub use irc_stream::ParsePolicy;
+pub use irc_stream::Received;
+pub use isupport::ChannelModes;
+pub use isupport::IsupportInfo;
+pub use label::LabelEvent;
+pub use label::LabelTracker;
+pub use label::LabeledResponse;
+pub use lag::LagEvent;
+pub use lag::LagMonitor;
+pub use list::ListEntry;
+pub use list::ListQuery;
+#[cfg(feature = "local-bind")]
+pub use local_bind::LocalBind;
+pub use membership::Membership;
+pub use membership::MembershipPrefixes;
+pub use metrics::MetricsSink;
+pub use monitor::MonitorEvent;
+pub use names::ChannelMember;
+pub use names::ChannelNames;
+pub use names::ChannelVisibility;
+pub use names::NamesCollector;
+pub use monitor::MonitorList;
+#[cfg(feature = "native-tls")]
+pub use native_tls_connector::NativeTlsConnector;
+#[cfg(feature = "openssl")]
+pub use openssl_connector::OpensslConnector;
+pub use parser::LineEndings;
+pub use parser::ParseElement;
 pub use parser::ParseError;
+pub use rate_limit::RateLimiter;
+pub use recording::Direction;
+pub use recording::RecordedLine;
+pub use recording::RecordingStream;
+pub use recording::Replay;
+pub use recording::read_recording;
+pub use reconnect::Backoff;
+pub use reconnect::ConnectionEvent;
+pub use reconnect::ReconnectingIrcStream;
+pub use rejoin::AutoRejoin;
+pub use registration::Registered;
+pub use registration::Registration;
+#[cfg(feature = "rustls")]
+pub use rustls_connector::RustlsConnector;
+pub use send_queue::Priority;
+pub use send_queue::SendQueue;
+pub use server_time::ServerTime;
+pub use server_time::ServerTimeError;
+pub use services::NickServ;
+pub use services::NickServEvent;
+pub use socks5::Socks5Proxy;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+pub use tls::DefaultTlsConnector;
+pub use tls::TlsConnector;
+pub use tls::TlsOptions;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+pub use tls::TlsStream;
+pub use tls::TlsVersion;
+pub use typing::TypingThrottle;
+pub use users::UserState;
+pub use users::UserTracker;
+pub use who::WhoCollector;
+pub use who::WhoEntry;
+pub use whois::WhoisCollector;
+pub use whois::WhoisInfo;
+
+use std::convert::TryFrom;
+use std::str::FromStr;
 
+use parser::parse_line;
 use parser::parse_message;
+use parser::parse_message_with;
 
 impl Message {
+    /// Parses a single message from the start of `input`, returning it along with whatever input
+    /// comes after it. The line can end with CRLF or a bare LF.
     pub fn parse(input: &[u8]) -> Result<(Message, &[u8]), ParseError> {
         parse_message(input)
     }
+
+    /// As `parse`, but with which line endings to accept.
+    pub fn parse_with(input: &[u8],
+                      line_endings: LineEndings)
+                      -> Result<(Message, &[u8]), ParseError> {
+        parse_message_with(input, line_endings)
+    }
+}
+
+/// Parses a single line, e.g. `"PRIVMSG #chan :Hello".parse::<Message>()`. The line ending is
+/// optional, but it's an error for there to be anything after it.
+impl FromStr for Message {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Message, ParseError> {
+        parse_line(line.as_bytes())
+    }
+}
+
+/// As with `FromStr`, the input must be exactly one line.
+impl<'a> TryFrom<&'a [u8]> for Message {
+    type Error = ParseError;
+
+    fn try_from(line: &'a [u8]) -> Result<Message, ParseError> {
+        parse_line(line)
+    }
 }
 
 #[test]
@@ -52,3 +300,28 @@ fn simple_parse() {
         other => panic!("{:?}", other),
     }
 }
+
+#[test]
+fn from_str() {
+    let message: Message = ":irc.host PING 12345".parse().unwrap();
+    assert_eq!(message,
+               Message::from_strs(Prefix::Server("irc.host".into()),
+                                  commands::PING(),
+                                  vec!["12345"]));
+    assert!(Message::try_from(&b"PING 1\r\nPING 2\r\n"[..]).is_err());
+}
+
+#[test]
+fn line_endings() {
+    let expected = Message::from_strs(Prefix::None, commands::PING(), vec!["1"]);
+    let (message, remaining) = Message::parse(b"PING 1\nPING 2\n").unwrap();
+    assert_eq!((message, remaining), (expected.clone(), &b"PING 2\n"[..]));
+    assert!(Message::parse(b"PING 1").is_err());
+    assert!(Message::parse(b"PING 1\r").is_err());
+
+    assert!(Message::parse_with(b"PING 1\n", LineEndings::Strict).is_err());
+    assert_eq!(Message::parse_with(b"PING 1\r\n", LineEndings::Strict).unwrap().0, expected);
+    assert_eq!(Message::parse_with(b"PING 1", LineEndings::AllowEof).unwrap().0, expected);
+    assert_eq!(Message::parse_with(b"PING :", LineEndings::AllowEof).unwrap().0.arguments[..],
+               [""]);
+}
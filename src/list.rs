@@ -0,0 +1,159 @@
+use command::responses;
+use isupport::IsupportInfo;
+use message::Message;
+
+/// A LIST request, optionally filtered so big networks don't send every channel they have.
+///
+/// Filters other than plain channel names need the server to support them, which it says in the
+/// `ELIST` ISUPPORT token; check with `is_supported`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListQuery {
+    // Each filter, and the ELIST letter it needs (if any).
+    filters: Vec<(String, Option<char>)>,
+}
+
+/// A channel listed in an `RPL_LIST` (322) reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListEntry {
+    pub channel: String,
+    /// How many users are in the channel.
+    pub users: u32,
+    pub topic: String,
+}
+
+impl ListQuery {
+    /// A query for every channel.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only lists channels matching `mask`, which may be a plain channel name or contain `*` and
+    /// `?` wildcards (needing `M`).
+    pub fn with_mask(self, mask: &str) -> Self {
+        let needs = if mask.contains(['*', '?']) { Some('M') } else { None };
+        self.with_filter(mask.into(), needs)
+    }
+
+    /// Leaves out channels matching `mask` (needs `N`).
+    pub fn without_mask(self, mask: &str) -> Self {
+        self.with_filter(format!("!{}", mask), Some('N'))
+    }
+
+    /// Only lists channels with more than `users` users (needs `U`).
+    pub fn with_more_users_than(self, users: u32) -> Self {
+        self.with_filter(format!(">{}", users), Some('U'))
+    }
+
+    /// Only lists channels with fewer than `users` users (needs `U`).
+    pub fn with_fewer_users_than(self, users: u32) -> Self {
+        self.with_filter(format!("<{}", users), Some('U'))
+    }
+
+    /// Only lists channels created less than `minutes` ago (needs `C`).
+    pub fn created_within(self, minutes: u32) -> Self {
+        self.with_filter(format!("C<{}", minutes), Some('C'))
+    }
+
+    /// Only lists channels created more than `minutes` ago (needs `C`).
+    pub fn created_before(self, minutes: u32) -> Self {
+        self.with_filter(format!("C>{}", minutes), Some('C'))
+    }
+
+    /// Only lists channels whose topic changed less than `minutes` ago (needs `T`).
+    pub fn topic_changed_within(self, minutes: u32) -> Self {
+        self.with_filter(format!("T<{}", minutes), Some('T'))
+    }
+
+    /// Only lists channels whose topic changed more than `minutes` ago (needs `T`).
+    pub fn topic_changed_before(self, minutes: u32) -> Self {
+        self.with_filter(format!("T>{}", minutes), Some('T'))
+    }
+
+    /// Whether the server supports all the filters, according to its `ELIST` token.
+    pub fn is_supported(&self, isupport: &IsupportInfo) -> bool {
+        let elist = isupport.get("ELIST").unwrap_or("").to_ascii_uppercase();
+        self.filters.iter().all(|&(_, needs)| needs.is_none_or(|c| elist.contains(c)))
+    }
+
+    /// The LIST message to send.
+    pub fn message(&self) -> Message {
+        let filters: Vec<&str> = self.filters.iter().map(|(filter, _)| &filter[..]).collect();
+        if filters.is_empty() {
+            Message::list(None)
+        } else {
+            Message::list(Some(&filters.join(",")))
+        }
+    }
+
+    fn with_filter(mut self, filter: String, needs: Option<char>) -> Self {
+        self.filters.push((filter, needs));
+        self
+    }
+}
+
+impl ListEntry {
+    /// Parses an `RPL_LIST`, returning `None` for anything else.
+    pub fn parse(message: &Message) -> Option<Self> {
+        if message.command != responses::RPL_LIST() {
+            return None;
+        }
+        // <client> <channel> <client count> :<topic>
+        let args = &message.arguments;
+        if args.len() != 4 {
+            warn!("Not parsing message as ListEntry because we expect 4 arguments: {}", message);
+            return None;
+        }
+
+        Some(ListEntry {
+            channel: args[1].clone(),
+            users: args[2].parse().unwrap_or(0),
+            topic: args[3].clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use isupport::IsupportInfo;
+    use message::Message;
+
+    #[test]
+    fn query() {
+        assert_eq!(ListQuery::new().message(), message("LIST\r\n"));
+
+        let query = ListQuery::new()
+            .with_mask("#rust*")
+            .without_mask("#rust-offtopic")
+            .with_more_users_than(100)
+            .created_within(60);
+        assert_eq!(query.message(), message("LIST #rust*,!#rust-offtopic,>100,C<60\r\n"));
+
+        let mut isupport = IsupportInfo::new();
+        assert!(!query.is_supported(&isupport));
+        assert!(ListQuery::new().with_mask("#rust").is_supported(&isupport));
+
+        isupport.handle(&message(":irc.host 005 me ELIST=CMNTU :are supported\r\n"));
+        assert!(query.is_supported(&isupport));
+    }
+
+    #[test]
+    fn entry() {
+        let entry = ListEntry::parse(&message(":irc.host 322 me #rust 1234 :Rust things\r\n"));
+        assert_eq!(entry,
+                   Some(ListEntry {
+                       channel: "#rust".into(),
+                       users: 1234,
+                       topic: "Rust things".into(),
+                   }));
+        assert_eq!(ListEntry::parse(&message(":irc.host 323 me :End of /LIST\r\n")), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+
+use socket2::Domain;
+use socket2::Protocol;
+use socket2::Socket;
+use socket2::Type;
+
+use irc_stream::IrcStream;
+use tls::TlsConnector;
+use trace;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::DefaultTlsConnector;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsOptions;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsStream;
+
+/// Where outgoing connections should come from, for machines with more than one address (e.g. to
+/// pick a vhost with a particular reverse DNS).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocalBind {
+    address: Option<IpAddr>,
+    interface: Option<String>,
+}
+
+impl LocalBind {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects from this local address. Only server addresses of the same family (IPv4 or IPv6)
+    /// will be tried.
+    pub fn with_address(mut self, address: IpAddr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Connects through this network interface (e.g. `eth1`), using `SO_BINDTODEVICE`. This
+    /// usually needs root or `CAP_NET_RAW`.
+    #[cfg(target_os = "linux")]
+    pub fn with_interface(mut self, interface: &str) -> Self {
+        self.interface = Some(interface.into());
+        self
+    }
+
+    pub fn address(&self) -> Option<IpAddr> {
+        self.address
+    }
+
+    pub fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    /// Connects to `server`, trying each of its addresses in turn like `TcpStream::connect`.
+    pub fn connect(&self, server: &str, port: u16) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        for address in (server, port).to_socket_addrs()? {
+            if self.address.is_some_and(|local| local.is_ipv4() != address.is_ipv4()) {
+                continue;
+            }
+            match self.connect_to(address) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(ErrorKind::AddrNotAvailable,
+                           format!("{} has no addresses reachable from {:?}", server, self.address))
+        }))
+    }
+
+    fn connect_to(&self, address: SocketAddr) -> io::Result<TcpStream> {
+        let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+        if let Some(local) = self.address {
+            debug!("Binding to {}", local);
+            socket.bind(&SocketAddr::new(local, 0).into())?;
+        }
+        self.bind_interface(&socket)?;
+        socket.connect(&address.into())?;
+        Ok(socket.into())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn bind_interface(&self, socket: &Socket) -> io::Result<()> {
+        if let Some(ref interface) = self.interface {
+            debug!("Binding to interface {}", interface);
+            socket.bind_device(Some(interface.as_bytes()))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn bind_interface(&self, _socket: &Socket) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl IrcStream<TcpStream> {
+    /// Connect to a server from a particular local address or interface, and wrap in an
+    /// `IrcStream`.
+    pub fn connect_bound(bind: &LocalBind, server: &str, port: u16) -> io::Result<Self> {
+        let span = trace::connecting("irc", server, port);
+        Ok(IrcStream::new(bind.connect(server, port)?).in_span(span))
+    }
+}
+
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+impl IrcStream<TlsStream> {
+    /// Connect to a server over SSL configured with `options`, from a particular local address
+    /// or interface, and wrap in an `IrcStream`.
+    pub fn connect_ssl_bound(bind: &LocalBind,
+                             server: &str,
+                             port: u16,
+                             options: &TlsOptions)
+                             -> io::Result<Self> {
+        Self::connect_tls_bound(bind, server, port, &DefaultTlsConnector::new(options)?)
+    }
+}
+
+impl<S: Read + Write> IrcStream<S> {
+    /// Connect to a server over TLS using `connector`, from a particular local address or
+    /// interface.
+    pub fn connect_tls_bound<C: TlsConnector<Stream = S>>(bind: &LocalBind,
+                                                          server: &str,
+                                                          port: u16,
+                                                          connector: &C)
+                                                          -> io::Result<Self> {
+        let span = trace::connecting("ircs", server, port);
+        let raw_connection = bind.connect(server, port)?;
+        Ok(IrcStream::new(connector.connect(server, raw_connection)?).in_span(span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::net::Ipv6Addr;
+    use std::net::TcpListener;
+
+    #[test]
+    fn connects_from_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let bind = LocalBind::new().with_address(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+
+        let stream = bind.connect("127.0.0.1", port).unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
+        let (_, peer) = listener.accept().unwrap();
+        assert_eq!(peer, stream.local_addr().unwrap());
+    }
+
+    #[test]
+    fn skips_other_address_families() {
+        let bind = LocalBind::new().with_address(IpAddr::V6(Ipv6Addr::LOCALHOST));
+
+        let error = bind.connect("127.0.0.1", 6667).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AddrNotAvailable);
+    }
+}
@@ -0,0 +1,166 @@
+use std;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// A channel membership rank, like operator or voice.
+///
+/// Variants are ordered from most to least powerful, so sorting a list of these puts the highest
+/// rank first. Ranks which aren't in common use are kept as their mode letter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Membership {
+    Owner,
+    Admin,
+    Op,
+    HalfOp,
+    Voice,
+    Other(char),
+}
+
+/// The mapping between membership modes and the prefixes shown before nicknames (e.g. in NAMES),
+/// as advertised by the server in the `PREFIX` ISUPPORT token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MembershipPrefixes {
+    // (mode, prefix) pairs, most powerful first.
+    modes: Vec<(char, char)>,
+}
+
+impl Membership {
+    pub fn from_mode(mode: char) -> Self {
+        match mode {
+            'q' => Membership::Owner,
+            'a' => Membership::Admin,
+            'o' => Membership::Op,
+            'h' => Membership::HalfOp,
+            'v' => Membership::Voice,
+            other => Membership::Other(other),
+        }
+    }
+
+    pub fn mode(&self) -> char {
+        match *self {
+            Membership::Owner => 'q',
+            Membership::Admin => 'a',
+            Membership::Op => 'o',
+            Membership::HalfOp => 'h',
+            Membership::Voice => 'v',
+            Membership::Other(mode) => mode,
+        }
+    }
+}
+
+impl Display for Membership {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "+{}", self.mode())
+    }
+}
+
+impl Default for MembershipPrefixes {
+    /// The RFC's `(ov)@+`, for servers which don't say otherwise.
+    fn default() -> Self {
+        MembershipPrefixes { modes: vec![('o', '@'), ('v', '+')] }
+    }
+}
+
+impl MembershipPrefixes {
+    /// Parses the value of a `PREFIX` token, e.g. `(qaohv)~&@%+`. Returns `None` if it's malformed.
+    /// An empty value is valid and means the server has no membership prefixes.
+    pub fn parse(value: &str) -> Option<Self> {
+        if value.is_empty() {
+            return Some(MembershipPrefixes { modes: Vec::new() });
+        }
+        if !value.starts_with('(') {
+            return None;
+        }
+        let close = value.find(')')?;
+        let modes = &value[1..close];
+        let prefixes = &value[close + 1..];
+        if modes.chars().count() != prefixes.chars().count() {
+            return None;
+        }
+
+        Some(MembershipPrefixes { modes: modes.chars().zip(prefixes.chars()).collect() })
+    }
+
+    pub fn is_prefix(&self, c: char) -> bool {
+        self.modes.iter().any(|&(_, prefix)| prefix == c)
+    }
+
+    pub fn membership_for_prefix(&self, prefix: char) -> Option<Membership> {
+        self.modes
+            .iter()
+            .find(|&&(_, p)| p == prefix)
+            .map(|&(mode, _)| Membership::from_mode(mode))
+    }
+
+    pub fn prefix_for_mode(&self, mode: char) -> Option<char> {
+        self.modes.iter().find(|&&(m, _)| m == mode).map(|&(_, prefix)| prefix)
+    }
+
+    /// Splits a nickname as it appears in NAMES into its memberships and the bare nickname. With
+    /// the `multi-prefix` capability several prefixes may be present, e.g. `@+nick`. Memberships
+    /// are returned most powerful first, whatever order the prefixes were in.
+    pub fn split<'a>(&self, prefixed: &'a str) -> (Vec<Membership>, &'a str) {
+        let nick_start = prefixed.find(|c| !self.is_prefix(c)).unwrap_or(prefixed.len());
+        let mut ranks: Vec<(usize, Membership)> = prefixed[..nick_start]
+            .chars()
+            .filter_map(|c| {
+                self.modes
+                    .iter()
+                    .position(|&(_, p)| p == c)
+                    .map(|i| (i, Membership::from_mode(self.modes[i].0)))
+            })
+            .collect();
+        ranks.sort();
+        ranks.dedup();
+
+        (ranks.into_iter().map(|(_, rank)| rank).collect(), &prefixed[nick_start..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let prefixes = MembershipPrefixes::parse("(qaohv)~&@%+").unwrap();
+
+        assert_eq!(prefixes.membership_for_prefix('~'), Some(Membership::Owner));
+        assert_eq!(prefixes.membership_for_prefix('%'), Some(Membership::HalfOp));
+        assert_eq!(prefixes.prefix_for_mode('v'), Some('+'));
+        assert_eq!(prefixes.membership_for_prefix('!'), None);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(MembershipPrefixes::parse("qaohv~&@%+"), None);
+        assert_eq!(MembershipPrefixes::parse("(qaohv)~&@"), None);
+    }
+
+    #[test]
+    fn split_single() {
+        let prefixes = MembershipPrefixes::default();
+        assert_eq!(prefixes.split("@nick"), (vec![Membership::Op], "nick"));
+        assert_eq!(prefixes.split("nick"), (vec![], "nick"));
+    }
+
+    #[test]
+    fn split_multiple_in_rank_order() {
+        let prefixes = MembershipPrefixes::parse("(qaohv)~&@%+").unwrap();
+        assert_eq!(prefixes.split("+@~nick"),
+                   (vec![Membership::Owner, Membership::Op, Membership::Voice], "nick"));
+    }
+
+    #[test]
+    fn split_uses_server_mapping() {
+        let prefixes = MembershipPrefixes::parse("(oy)@!").unwrap();
+        assert_eq!(prefixes.split("@!nick"),
+                   (vec![Membership::Op, Membership::Other('y')], "nick"));
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(Membership::Owner < Membership::Op);
+        assert!(Membership::Voice < Membership::Other('y'));
+    }
+}
@@ -1,22 +1,66 @@
 
 use command::Command;
+use parser::ParseElement;
+use parser::ParseError;
+use parser::parse_message;
 use std;
 use std::convert::Into;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::io;
+use std::io::Write;
+use std::iter::FromIterator;
 use std::iter::Iterator;
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::slice;
 use std::vec::Vec;
 
+#[cfg(feature = "smallvec")]
+use smallvec::SmallVec;
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// A message's arguments. This derefs to a slice, and converts to and from `Vec<String>`.
+///
+/// They're stored in a `Vec` normally, or with the `smallvec` feature a `SmallVec` which holds up
+/// to 4 without allocating, which most messages fit in. The type is the same either way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct Arguments(Storage);
+
+#[cfg(not(feature = "smallvec"))]
+type Storage = Vec<String>;
+#[cfg(feature = "smallvec")]
+type Storage = SmallVec<[String; 4]>;
+
 /// A single IRC message, as sent to and from server and client.
 #[derive(Debug,Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Message {
+    pub tags: Vec<Tag>,
     pub prefix: Prefix,
     pub command: Command,
-    pub arguments: Vec<String>,
+    pub arguments: Arguments,
+}
+
+/// An IRCv3 message tag, as in `@key=value`. The value is held unescaped; escaping only happens
+/// on the wire.
+///
+/// The spec treats `key=` and `key` as equivalent, so an empty value is always represented as
+/// `None`.
+#[derive(Debug,Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Tag {
+    pub key: String,
+    pub value: Option<String>,
 }
 
 /// The prefix of an IRC message.
 #[derive(Debug,Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Prefix {
     /// The message has no prefix.
     None,
@@ -30,6 +74,7 @@ pub enum Prefix {
 /// Contains a nickname (`nickname`), and (optionally) information about the
 /// host and username of the user (`host`)
 #[derive(Debug,Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum UserInfo {
     /// Nickname-only, as in prefix `:nickname`
     Nick(String),
@@ -42,17 +87,186 @@ pub enum UserInfo {
 impl Message {
     /// Creates a new Message instance.
     pub fn new(prefix: Prefix, command: Command, arguments: Vec<String>) -> Self {
+        Self::with_arguments(prefix, command, arguments.into())
+    }
+
+    pub(crate) fn with_arguments(prefix: Prefix, command: Command, arguments: Arguments) -> Self {
         Message {
+            tags: Vec::new(),
             prefix: prefix,
             command: command,
             arguments: arguments,
         }
     }
 
+    /// Replaces the tags on this message.
+    pub fn with_tags(mut self, tags: Vec<Tag>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Finds the tag with the given key, if this message has one.
+    pub fn tag(&self, key: &str) -> Option<&Tag> {
+        self.tags.iter().find(|tag| tag.key == key)
+    }
+
+    /// Gets the (unescaped) value of the tag with the given key. Returns `None` both if the tag
+    /// is missing and if it is present without a value; use `tag` to tell these apart.
+    pub fn tag_value(&self, key: &str) -> Option<&str> {
+        self.tag(key).and_then(|tag| tag.value.as_ref()).map(|value| value.as_str())
+    }
+
     pub fn from_strs(prefix: Prefix, command: Command, arguments: Vec<&str>) -> Self {
-        let cows: Vec<String> = arguments.iter().map(|arg| arg.to_string()).collect();
+        let arguments: Arguments = arguments.iter().map(|arg| arg.to_string()).collect();
+
+        Self::with_arguments(prefix, command, arguments)
+    }
+}
+
+impl Arguments {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, argument: String) {
+        self.0.push(argument)
+    }
+
+    pub fn insert(&mut self, index: usize, argument: String) {
+        self.0.insert(index, argument)
+    }
+
+    pub fn remove(&mut self, index: usize) -> String {
+        self.0.remove(index)
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        self.0.pop()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    pub fn into_vec(self) -> Vec<String> {
+        self.0.into_iter().collect()
+    }
+}
+
+impl Deref for Arguments {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl DerefMut for Arguments {
+    fn deref_mut(&mut self) -> &mut [String] {
+        &mut self.0
+    }
+}
+
+impl From<Vec<String>> for Arguments {
+    fn from(arguments: Vec<String>) -> Self {
+        Arguments(arguments.into())
+    }
+}
 
-        Self::new(prefix, command, cows)
+impl From<Arguments> for Vec<String> {
+    fn from(arguments: Arguments) -> Self {
+        arguments.into_vec()
+    }
+}
+
+impl FromIterator<String> for Arguments {
+    fn from_iter<I: IntoIterator<Item = String>>(iter: I) -> Self {
+        Arguments(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Arguments {
+    type Item = String;
+    type IntoIter = <Storage as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Arguments {
+    type Item = &'a String;
+    type IntoIter = slice::Iter<'a, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl Extend<String> for Arguments {
+    fn extend<I: IntoIterator<Item = String>>(&mut self, iter: I) {
+        self.0.extend(iter)
+    }
+}
+
+impl Tag {
+    /// Creates a new tag. An empty `value` is treated the same as no value.
+    pub fn new(key: &str, value: Option<&str>) -> Self {
+        Tag {
+            key: key.into(),
+            value: value.and_then(|v| if v.is_empty() { None } else { Some(v.into()) }),
+        }
+    }
+}
+
+/// Escapes a tag value for the wire, as described in the IRCv3 message tags spec.
+pub fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// Reverses `escape_tag_value`. Following the spec, unknown escapes drop the backslash and a
+/// trailing lone backslash is dropped entirely.
+pub fn unescape_tag_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => (),
+        }
+    }
+    unescaped
+}
+
+impl Display for Tag {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match self.value {
+            Some(ref value) => write!(fmt, "{}={}", self.key, escape_tag_value(value)),
+            None => write!(fmt, "{}", self.key),
+        }
     }
 }
 
@@ -113,22 +327,154 @@ impl Display for UserInfo {
     }
 }
 
+/// The longest line the RFC allows, including the trailing CRLF. Message tags don't count towards
+/// this; they have their own separate limit.
+pub const MAX_LINE_LENGTH: usize = 512;
+
 // Is using "Display" to format these for the wire a misuse?
 // Should we be using a Write or soemthing instead?
 impl Display for Message {
     fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
-        match self.prefix {
+        for (i, tag) in self.tags.iter().enumerate() {
+            write!(fmt, "{}{}", if i == 0 { "@" } else { ";" }, tag)?;
+        }
+        if !self.tags.is_empty() {
+            write!(fmt, " ")?;
+        }
+
+        write!(fmt, "{}", WithoutTags(self))
+    }
+}
+
+impl Message {
+    /// The length in bytes of this message on the wire, including the CRLF but not counting any
+    /// tags. This is what needs to fit in `MAX_LINE_LENGTH`.
+    pub fn line_len(&self) -> usize {
+        let mut counter = Counter {
+            inner: io::sink(),
+            written: 0,
+        };
+        // Writing to a sink can't fail.
+        let _ = write!(counter, "{}\r\n", WithoutTags(self));
+        counter.written
+    }
+
+    /// Writes this message to `writer` exactly as it should appear on the wire, CRLF included.
+    /// Returns the number of bytes written.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut counter = Counter {
+            inner: writer,
+            written: 0,
+        };
+        write!(counter, "{}\r\n", self)?;
+        Ok(counter.written)
+    }
+
+    /// The wire form of this message, CRLF included.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        // Writing to a Vec can't fail.
+        let _ = self.write_to(&mut bytes);
+        bytes
+    }
+
+    /// This message as it will be after being sent and parsed again, fixing up arguments that
+    /// couldn't be sent as they are. Parsing a message and sending it always gives back the same
+    /// message; this is the same guarantee for messages that have been built instead.
+    ///
+    /// Arguments lose any NUL, CR or LF characters, which would end the line early. Only the last
+    /// argument can be empty, contain spaces or start with a colon, so an earlier one that's
+    /// empty is dropped, and one that has spaces or a colon becomes the last argument, with the
+    /// arguments after it joined on with spaces.
+    ///
+    /// Fails if the message can't be sent at all, e.g. if the prefix or a tag's key contains
+    /// characters they can't have, or would be read back as a different message.
+    pub fn canonicalize(&self) -> Result<Message, ParseError> {
+        let mut fixed = self.clone();
+        for tag in &mut fixed.tags {
+            if let Some(ref mut value) = tag.value {
+                value.retain(|c| c != '\0');
+            }
+        }
+        fixed.arguments = Arguments::new();
+        let mut arguments = self.arguments.iter().enumerate().map(|(i, argument)| {
+            let argument: String = argument.chars()
+                .filter(|&c| c != '\0' && c != '\r' && c != '\n')
+                .collect();
+            (i + 1 == self.arguments.len(), argument)
+        });
+        while let Some((is_last, argument)) = arguments.next() {
+            if argument.is_empty() && !is_last {
+                continue;
+            }
+            if argument.contains(' ') || argument.starts_with(':') {
+                let joined = arguments.by_ref().fold(argument, |joined, (_, next)| {
+                    joined + " " + &next
+                });
+                fixed.arguments.push(joined);
+            } else {
+                fixed.arguments.push(argument);
+            }
+        }
+        let bytes = fixed.to_bytes();
+        let (parsed, _) = parse_message(&bytes)?;
+        let changed = if parsed.tags != fixed.tags {
+            Some(ParseElement::Tags)
+        } else if parsed.prefix != fixed.prefix {
+            Some(ParseElement::Prefix)
+        } else if parsed.command != fixed.command {
+            Some(ParseElement::Command)
+        } else if parsed.arguments != fixed.arguments {
+            Some(ParseElement::Params)
+        } else {
+            None
+        };
+        match changed {
+            Some(element) => Err(ParseError::changed(&bytes, element)),
+            None => Ok(parsed),
+        }
+    }
+}
+
+// Passes writes through, keeping count of the bytes.
+struct Counter<W> {
+    inner: W,
+    written: usize,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Everything after the tags.
+struct WithoutTags<'a>(&'a Message);
+
+impl<'a> Display for WithoutTags<'a> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        let message = self.0;
+        match message.prefix {
             Prefix::None => Ok(()),
             Prefix::Server(ref server) => write!(fmt, ":{} ", server),
             Prefix::User(ref user_info) => write!(fmt, ":{} ", user_info),
         }?;
 
-        write!(fmt, "{}", self.command)?;
+        write!(fmt, "{}", message.command)?;
 
-        for (i, argument) in self.arguments.iter().enumerate() {
+        for (i, argument) in message.arguments.iter().enumerate() {
             write!(fmt, " ")?;
 
-            if i == self.arguments.len() - 1 && argument.contains(' ') {
+            // The last argument needs a colon if it wouldn't otherwise survive parsing.
+            let is_last = i == message.arguments.len() - 1;
+            if is_last &&
+               (argument.is_empty() || argument.contains(' ') || argument.starts_with(':')) {
                 write!(fmt, ":")?;
             }
 
@@ -142,7 +488,7 @@ impl Display for Message {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use command::commands::{PING, PRIVMSG};
+    use command::commands::{PING, PRIVMSG, TOPIC};
     use command::responses::RPL_WELCOME;
 
     #[test]
@@ -214,6 +560,129 @@ mod tests {
                    "PRIVMSG someone :Hey I love being on IRC");
     }
 
+    #[test]
+    fn command_args_with_empty_final_argument() {
+        let line = Message::from_strs(Prefix::None, TOPIC(), vec!["#chan", ""]);
+
+        assert_eq!(format!("{}", line), "TOPIC #chan :");
+    }
+
+    #[test]
+    fn command_args_with_colon_final_argument() {
+        let line = Message::from_strs(Prefix::None, PRIVMSG(), vec!["someone", ":)"]);
+
+        assert_eq!(format!("{}", line), "PRIVMSG someone ::)");
+    }
+
+    #[test]
+    fn command_args_colon_only_on_final_argument() {
+        let line = Message::from_strs(Prefix::None, PRIVMSG(), vec!["someone", "a:b", "c:"]);
+
+        assert_eq!(format!("{}", line), "PRIVMSG someone a:b c:");
+    }
+
+    #[test]
+    fn tags() {
+        let line = Message::new(Prefix::None, PING(), vec![])
+            .with_tags(vec![Tag::new("aaa", Some("bbb")), Tag::new("example.com/ddd", None)]);
+
+        assert_eq!(format!("{}", line), "@aaa=bbb;example.com/ddd PING");
+    }
+
+    #[test]
+    fn tags_escaped() {
+        let line = Message::new(Prefix::None, PING(), vec![])
+            .with_tags(vec![Tag::new("key", Some("semi;space \\back\r\n"))]);
+
+        assert_eq!(format!("{}", line), "@key=semi\\:space\\s\\\\back\\r\\n PING");
+    }
+
+    #[test]
+    fn tag_empty_value_is_none() {
+        assert_eq!(Tag::new("key", Some("")), Tag::new("key", None));
+    }
+
+    #[test]
+    fn unescape_round_trip() {
+        let value = "a;b c\\d\re\nf";
+        assert_eq!(unescape_tag_value(&escape_tag_value(value)), value);
+    }
+
+    #[test]
+    fn unescape_invalid_escapes() {
+        assert_eq!(unescape_tag_value("a\\bc\\"), "abc");
+    }
+
+    #[test]
+    fn line_len_ignores_tags() {
+        let line = Message::from_strs(Prefix::None, PRIVMSG(), vec!["#chan", "Hi there"])
+            .with_tags(vec![Tag::new("msgid", Some("abc"))]);
+
+        assert_eq!(line.line_len(), "PRIVMSG #chan :Hi there\r\n".len());
+    }
+
+    #[test]
+    fn to_bytes() {
+        let line = Message::from_strs(Prefix::None, PRIVMSG(), vec!["#chan", "Hi there"])
+            .with_tags(vec![Tag::new("+typing", Some("active"))]);
+
+        assert_eq!(line.to_bytes(), b"@+typing=active PRIVMSG #chan :Hi there\r\n".to_vec());
+    }
+
+    #[test]
+    fn write_to_counts_bytes() {
+        let line = Message::new(Prefix::None, PING(), vec![]);
+        let mut out = Vec::new();
+
+        assert_eq!(line.write_to(&mut out).unwrap(), 6);
+        assert_eq!(out, b"PING\r\n".to_vec());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        let line = Message::from_strs(UserInfo::of_nickname_user_host("n", "u", "h").into(),
+                                      PRIVMSG(),
+                                      vec!["#chan", "Hi there"])
+            .with_tags(vec![Tag::new("msgid", Some("abc"))]);
+
+        let json = ::serde_json::to_string(&line).unwrap();
+        assert_eq!(::serde_json::from_str::<Message>(&json).unwrap(), line);
+    }
+
+    #[test]
+    fn canonicalize() {
+        let line = Message::from_strs(Prefix::None,
+                                      PRIVMSG(),
+                                      vec!["", "#chan", ":not last", "Hi", "", "there\r\n"]);
+        let canonical = line.canonicalize().unwrap();
+
+        assert_eq!(canonical.arguments[..], ["#chan", ":not last Hi  there"]);
+        assert_eq!(canonical.canonicalize().unwrap(), canonical);
+        assert!(Message::new(Prefix::Server("bad host".into()), PING(), vec![])
+            .canonicalize()
+            .is_err());
+    }
+
+    #[test]
+    fn parsed_messages_round_trip() {
+        let lines = ["PRIVMSG #chan :\r\n",
+                     "PING ::colon\r\n",
+                     "PING :\r\n",
+                     "PRIVMSG #chan :  spaces  \r\n",
+                     "PRIVMSG #chan ::)\r\n",
+                     "PRIVMSG #chan a:b c::\r\n",
+                     "@a=semi\\:colon\\sspace\\\\;b :n!u@h PRIVMSG #chan :Hi\r\n",
+                     ":server 001 nick :\r\n"];
+        for line in lines.iter() {
+            let (parsed, _) = Message::parse(line.as_bytes()).unwrap();
+            let (reparsed, _) = Message::parse(&parsed.to_bytes()).unwrap();
+
+            assert_eq!(reparsed, parsed, "{:?} didn't round trip", line);
+            assert_eq!(parsed.canonicalize().unwrap(), parsed);
+        }
+    }
+
     #[test]
     fn everything() {
         let line = Message::from_strs(Prefix::Server("information".into()),
@@ -223,4 +692,16 @@ mod tests {
         assert_eq!(format!("{}", line),
                    ":information PRIVMSG someone something :Hey I love being on IRC");
     }
+
+    #[test]
+    fn arguments_convert() {
+        let mut message = Message::new(Prefix::None, PRIVMSG(), vec!["#chan".into()]);
+        message.arguments = vec!["#other".to_string(), "Hi".to_string()].into();
+        message.arguments.push("there".into());
+
+        assert_eq!(message.arguments[..], ["#other", "Hi", "there"]);
+        let arguments: Vec<String> = message.arguments.clone().into();
+        assert_eq!(arguments, vec!["#other", "Hi", "there"]);
+        assert_eq!(arguments.into_iter().collect::<Arguments>(), message.arguments);
+    }
 }
@@ -0,0 +1,299 @@
+use std::borrow::Cow;
+
+use message::Message;
+use message::Prefix;
+use message::Tag;
+use message::UserInfo;
+use message::unescape_tag_value;
+use parser::ParseError;
+use parser::command_of;
+use parser::LineEndings;
+use parser::parse_message_ref_with;
+
+/// A message borrowed from the buffer it was parsed from, for when allocating a `Message` for
+/// every line is too expensive (e.g. a busy bouncer that only looks at a few messages).
+///
+/// Parsing doesn't allocate. Tags and arguments are picked out of the line as they're iterated
+/// over, and only allocate if they need unescaping or aren't valid UTF-8. Use `to_owned` to turn
+/// one into a `Message` if you want to keep it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageRef<'a> {
+    tags: &'a [u8],
+    prefix: PrefixRef<'a>,
+    command: &'a str,
+    params: &'a [u8],
+}
+
+/// The borrowed equivalent of `Prefix`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefixRef<'a> {
+    None,
+    Server(&'a str),
+    User {
+        nickname: &'a str,
+        username: Option<&'a str>,
+        host: Option<&'a str>,
+    },
+}
+
+/// The borrowed equivalent of `Tag`. As with `Tag` an empty value is represented as `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagRef<'a> {
+    pub key: &'a str,
+    pub value: Option<Cow<'a, str>>,
+}
+
+/// Iterator over the tags of a `MessageRef`.
+#[derive(Debug, Clone)]
+pub struct TagsRef<'a> {
+    remaining: &'a [u8],
+}
+
+/// Iterator over the arguments of a `MessageRef`.
+#[derive(Debug, Clone)]
+pub struct ArgumentsRef<'a> {
+    remaining: &'a [u8],
+}
+
+/// Iterator over the arguments of a `MessageRef` as the bytes they were received as.
+#[derive(Debug, Clone)]
+pub struct RawArgumentsRef<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> MessageRef<'a> {
+    /// Parses a single message from the start of `input`, returning it along with whatever input
+    /// comes after it.
+    pub fn parse(input: &'a [u8]) -> Result<(MessageRef<'a>, &'a [u8]), ParseError> {
+        parse_message_ref_with(input, LineEndings::default())
+    }
+
+    /// As `parse`, but with which line endings to accept.
+    pub fn parse_with(input: &'a [u8],
+                      line_endings: LineEndings)
+                      -> Result<(MessageRef<'a>, &'a [u8]), ParseError> {
+        parse_message_ref_with(input, line_endings)
+    }
+
+    pub(crate) fn new(tags: &'a [u8],
+                      prefix: PrefixRef<'a>,
+                      command: &'a str,
+                      params: &'a [u8])
+                      -> Self {
+        MessageRef {
+            tags: tags,
+            prefix: prefix,
+            command: command,
+            params: params,
+        }
+    }
+
+    pub fn tags(&self) -> TagsRef<'a> {
+        TagsRef { remaining: self.tags }
+    }
+
+    /// Finds the tag with the given key, if this message has one.
+    pub fn tag(&self, key: &str) -> Option<TagRef<'a>> {
+        self.tags().find(|tag| tag.key == key)
+    }
+
+    /// Gets the (unescaped) value of the tag with the given key, as with `Message::tag_value`.
+    pub fn tag_value(&self, key: &str) -> Option<Cow<'a, str>> {
+        self.tag(key).and_then(|tag| tag.value)
+    }
+
+    pub fn prefix(&self) -> PrefixRef<'a> {
+        self.prefix
+    }
+
+    /// The command as it appeared on the wire, e.g. `PRIVMSG` or `001`.
+    pub fn command(&self) -> &'a str {
+        self.command
+    }
+
+    pub fn arguments(&self) -> ArgumentsRef<'a> {
+        ArgumentsRef { remaining: self.params }
+    }
+
+    /// The arguments without any conversion to text, for payloads which deliberately aren't UTF-8
+    /// (some DCC and gateway traffic) and would be mangled by `arguments`.
+    pub fn arguments_raw(&self) -> RawArgumentsRef<'a> {
+        RawArgumentsRef { remaining: self.params }
+    }
+
+    /// Copies everything out of the input buffer into a `Message`.
+    pub fn to_owned(&self) -> Message {
+        Message::with_arguments(self.prefix.to_owned(),
+                                command_of(self.command),
+                                self.arguments().map(|arg| arg.into_owned()).collect())
+            .with_tags(self.tags().map(|tag| tag.to_owned()).collect())
+    }
+}
+
+impl<'a> PrefixRef<'a> {
+    /// The nickname, if this is a user prefix.
+    pub fn nickname(&self) -> Option<&'a str> {
+        match *self {
+            PrefixRef::User { nickname, .. } => Some(nickname),
+            _ => None,
+        }
+    }
+
+    pub fn to_owned(&self) -> Prefix {
+        match *self {
+            PrefixRef::None => Prefix::None,
+            PrefixRef::Server(server) => Prefix::Server(server.into()),
+            PrefixRef::User { nickname, username: Some(user), host: Some(host) } => {
+                UserInfo::of_nickname_user_host(nickname, user, host).into()
+            }
+            PrefixRef::User { nickname, host: Some(host), .. } => {
+                UserInfo::of_nickname_host(nickname, host).into()
+            }
+            PrefixRef::User { nickname, .. } => UserInfo::of_nickname(nickname).into(),
+        }
+    }
+}
+
+impl<'a> TagRef<'a> {
+    pub fn to_owned(&self) -> Tag {
+        Tag::new(self.key, self.value.as_ref().map(|value| value.as_ref()))
+    }
+}
+
+impl<'a> Iterator for TagsRef<'a> {
+    type Item = TagRef<'a>;
+
+    fn next(&mut self) -> Option<TagRef<'a>> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+
+        let end = self.remaining.iter().position(|&c| c == b';').unwrap_or(self.remaining.len());
+        let tag = &self.remaining[..end];
+        self.remaining = if end < self.remaining.len() {
+            &self.remaining[end + 1..]
+        } else {
+            &[]
+        };
+
+        let (key, value) = match tag.iter().position(|&c| c == b'=') {
+            Some(equals) => (&tag[..equals], &tag[equals + 1..]),
+            None => (tag, &[][..]),
+        };
+        // The parser only accepts ASCII keys, so this can't fail.
+        let key = ::std::str::from_utf8(key).unwrap_or_default();
+
+        Some(TagRef {
+            key: key,
+            value: if value.is_empty() {
+                None
+            } else {
+                Some(unescape(value))
+            },
+        })
+    }
+}
+
+impl<'a> Iterator for ArgumentsRef<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        next_argument(&mut self.remaining).map(String::from_utf8_lossy)
+    }
+}
+
+impl<'a> Iterator for RawArgumentsRef<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        next_argument(&mut self.remaining)
+    }
+}
+
+fn next_argument<'a>(remaining: &mut &'a [u8]) -> Option<&'a [u8]> {
+    // The parser has already checked that each argument is preceded by exactly one space.
+    if remaining.is_empty() {
+        return None;
+    }
+    let rest = &remaining[1..];
+
+    if rest.first() == Some(&b':') {
+        *remaining = &[];
+        return Some(&rest[1..]);
+    }
+
+    let end = rest.iter().position(|&c| c == b' ').unwrap_or(rest.len());
+    *remaining = &rest[end..];
+    Some(&rest[..end])
+}
+
+fn unescape(value: &[u8]) -> Cow<str> {
+    let value = String::from_utf8_lossy(value);
+    if value.contains('\\') {
+        unescape_tag_value(&value).into()
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn borrows_from_input() {
+        let input = b"@id=1;+x :nick!user@host PRIVMSG #chan :Hello there\r\nPING 1\r\n";
+        let (message, remaining) = MessageRef::parse(input).unwrap();
+
+        assert_eq!(message.command(), "PRIVMSG");
+        assert_eq!(message.prefix().nickname(), Some("nick"));
+        assert_eq!(message.arguments().collect::<Vec<_>>(), vec!["#chan", "Hello there"]);
+        assert_eq!(message.tag_value("id"), Some(Cow::Borrowed("1")));
+        assert_eq!(message.tag("+x").map(|tag| tag.value), Some(None));
+        assert_eq!(remaining, b"PING 1\r\n");
+    }
+
+    #[test]
+    fn raw_arguments() {
+        let input = b"PRIVMSG #chan \xff\x01 :caf\xe9 au lait\r\n";
+        let (message, _) = MessageRef::parse(input).unwrap();
+
+        assert_eq!(message.arguments_raw().collect::<Vec<_>>(),
+                   vec![&b"#chan"[..], &b"\xff\x01"[..], &b"caf\xe9 au lait"[..]]);
+        assert_eq!(message.arguments().nth(1), Some(Cow::Owned("\u{fffd}\u{1}".into())));
+    }
+
+    #[test]
+    fn unescapes_tag_values() {
+        let (message, _) = MessageRef::parse(b"@k=a\\sb PING\r\n").unwrap();
+
+        assert_eq!(message.tag_value("k"), Some(Cow::Owned("a b".into())));
+    }
+
+    #[test]
+    fn to_owned_matches_message_parse() {
+        let lines: &[&[u8]] = &[b"PING\r\n",
+                                b"PING 12345\r\n",
+                                b":irc.host 001 me :Welcome to IRC\r\n",
+                                b":nick@host JOIN #chan\r\n",
+                                b":nick MODE #chan +o other\r\n",
+                                b"@a=b\\:c;d=;e :n!u@h PRIVMSG #chan :\r\n",
+                                b"PRIVMSG someone :Hey there \xc3\r\n"];
+
+        for line in lines {
+            let (borrowed, _) = MessageRef::parse(line).unwrap();
+            let (owned, _) = Message::parse(line).unwrap();
+            assert_eq!(borrowed.to_owned(), owned);
+        }
+    }
+
+    #[test]
+    fn rejects_what_message_rejects() {
+        assert!(MessageRef::parse(b"PING  1\r\n").is_err());
+        assert!(MessageRef::parse(b"PING 1").is_err());
+        assert!(MessageRef::parse(b"@ PING\r\n").is_err());
+        // Numerics are limited to three digits, as for Message.
+        assert!(MessageRef::parse(b"1000\r\n").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+use message::UserInfo;
+
+/// Simple accessor for a received ACCOUNT message, sent with the `account-notify` capability when
+/// a user logs in or out of their services account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Account<'a> {
+    pub user: &'a UserInfo,
+    /// The account logged into, or `None` if the user has logged out.
+    pub account: Option<&'a str>,
+}
+
+impl Message {
+    pub fn as_account(&self) -> Option<Account<'_>> {
+        if self.command != commands::ACCOUNT() {
+            return None;
+        }
+        if self.arguments.len() != 1 {
+            warn!("Not parsing message as Account because we expect 1 argument: {}",
+                  self);
+            return None;
+        }
+        let user = match self.prefix {
+            Prefix::User(ref u) => u,
+            _ => {
+                warn!("Not parsing message as Account because we expect prefix of user: {}",
+                      self);
+                return None;
+            }
+        };
+
+        let account = &self.arguments[0];
+        Some(Account {
+            user: user,
+            account: if account == "*" { None } else { Some(account) },
+        })
+    }
+
+    /// The services account of whoever sent this message, from the `account` tag added by the
+    /// `account-tag` capability.
+    pub fn sender_account(&self) -> Option<&str> {
+        self.tag_value("account")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn logged_in() {
+        let message = message(":nick!user@host ACCOUNT accountname\r\n");
+
+        assert_eq!(message.as_account(),
+                   Some(Account {
+                       user: &UserInfo::of_nickname_user_host("nick", "user", "host"),
+                       account: Some("accountname"),
+                   }));
+    }
+
+    #[test]
+    fn logged_out() {
+        let message = message(":nick!user@host ACCOUNT *\r\n");
+        assert_eq!(message.as_account().unwrap().account, None);
+    }
+
+    #[test]
+    fn bad_server_prefix() {
+        let message = message(":irc.host ACCOUNT accountname\r\n");
+        assert_eq!(message.as_account(), None);
+    }
+
+    #[test]
+    fn sender_account() {
+        let message = message("@account=bob :nick!user@host PRIVMSG #chan :Hello\r\n");
+        assert_eq!(message.sender_account(), Some("bob"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+impl Message {
+    /// Creates an `AUTHENTICATE` message. This is used both to pick a SASL mechanism and to send
+    /// (base64-encoded) payloads, with `+` standing in for an empty payload.
+    pub fn authenticate(payload: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::AUTHENTICATE(), vec![payload])
+    }
+
+    /// If this is an `AUTHENTICATE` message from the server, returns its payload.
+    pub fn as_authenticate(&self) -> Option<&str> {
+        if self.command != commands::AUTHENTICATE() || self.arguments.len() != 1 {
+            return None;
+        }
+
+        Some(&self.arguments[0])
+    }
+}
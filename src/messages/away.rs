@@ -0,0 +1,88 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+use message::UserInfo;
+
+/// Simple accessor for a received AWAY message, sent with the `away-notify` capability when a user
+/// goes away or comes back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Away<'a> {
+    pub user: &'a UserInfo,
+    /// The away message, or `None` if the user is no longer away.
+    pub message: Option<&'a str>,
+}
+
+impl Message {
+    pub fn as_away(&self) -> Option<Away<'_>> {
+        if self.command != commands::AWAY() {
+            return None;
+        }
+        if self.arguments.len() > 1 {
+            warn!("Not parsing message as Away because we expect at most 1 argument: {}",
+                  self);
+            return None;
+        }
+        let user = match self.prefix {
+            Prefix::User(ref u) => u,
+            _ => {
+                warn!("Not parsing message as Away because we expect prefix of user: {}",
+                      self);
+                return None;
+            }
+        };
+
+        Some(Away {
+            user: user,
+            message: self.arguments.first().map(|m| m.as_str()).filter(|m| !m.is_empty()),
+        })
+    }
+
+    /// Creates an `AWAY` message, marking us away with `message`, or back if it's `None`.
+    pub fn away(message: Option<&str>) -> Message {
+        Message::from_strs(Prefix::None, commands::AWAY(), message.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn gone_away() {
+        let message = message(":nick!user@host AWAY :Gone to lunch\r\n");
+
+        assert_eq!(message.as_away(),
+                   Some(Away {
+                       user: &UserInfo::of_nickname_user_host("nick", "user", "host"),
+                       message: Some("Gone to lunch"),
+                   }));
+    }
+
+    #[test]
+    fn back() {
+        let message = message(":nick!user@host AWAY\r\n");
+        assert_eq!(message.as_away().unwrap().message, None);
+    }
+
+    #[test]
+    fn bad_server_prefix() {
+        let message = message(":irc.host AWAY :Gone\r\n");
+        assert_eq!(message.as_away(), None);
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(Message::away(Some("Gone to lunch")), message("AWAY :Gone to lunch\r\n"));
+        assert_eq!(Message::away(None), message("AWAY\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use command::commands;
+use message::Message;
+
+/// Simple accessor for a received BATCH message, which either starts or ends a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchBoundary<'a> {
+    /// `true` for `BATCH +ref ...`, `false` for `BATCH -ref`.
+    pub start: bool,
+    pub reference: &'a str,
+    /// The batch type, only present when starting a batch.
+    pub batch_type: Option<&'a str>,
+    /// Any parameters after the type.
+    pub parameters: &'a [String],
+}
+
+impl Message {
+    pub fn as_batch(&self) -> Option<BatchBoundary<'_>> {
+        if self.command != commands::BATCH() || self.arguments.is_empty() {
+            return None;
+        }
+
+        let first = &self.arguments[0];
+        let start = match first.chars().next() {
+            Some('+') => true,
+            Some('-') => false,
+            _ => {
+                warn!("Not parsing message as BatchBoundary because reference has no +/-: {}",
+                      self);
+                return None;
+            }
+        };
+        if start && self.arguments.len() < 2 {
+            warn!("Not parsing message as BatchBoundary because it has no type: {}",
+                  self);
+            return None;
+        }
+
+        Some(BatchBoundary {
+            start: start,
+            reference: &first[1..],
+            batch_type: self.arguments.get(1).map(|t| t.as_str()),
+            parameters: if start { &self.arguments[2..] } else { &[] },
+        })
+    }
+
+    /// The reference of the batch this message is part of, from its `batch` tag.
+    pub fn batch_reference(&self) -> Option<&str> {
+        self.tag_value("batch")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn start() {
+        let message = message(":irc.host BATCH +yXNAbvnRHTRBv netsplit irc.hub other.host\r\n");
+
+        assert_eq!(message.as_batch(),
+                   Some(BatchBoundary {
+                       start: true,
+                       reference: "yXNAbvnRHTRBv",
+                       batch_type: Some("netsplit"),
+                       parameters: &["irc.hub".to_string(), "other.host".to_string()],
+                   }));
+    }
+
+    #[test]
+    fn end() {
+        let message = message(":irc.host BATCH -yXNAbvnRHTRBv\r\n");
+
+        assert_eq!(message.as_batch(),
+                   Some(BatchBoundary {
+                       start: false,
+                       reference: "yXNAbvnRHTRBv",
+                       batch_type: None,
+                       parameters: &[],
+                   }));
+    }
+
+    #[test]
+    fn bad_no_sign() {
+        let message = message(":irc.host BATCH yXNAbvnRHTRBv netsplit\r\n");
+        assert_eq!(message.as_batch(), None);
+    }
+
+    #[test]
+    fn reference_tag() {
+        let message = message("@batch=yXNAbvnRHTRBv :aji!a@a QUIT :irc.hub other.host\r\n");
+        assert_eq!(message.batch_reference(), Some("yXNAbvnRHTRBv"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+/// Simple accessor for a received CAP message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cap<'a> {
+    /// The nickname (or `*` before registration) the server is talking to.
+    pub target: &'a str,
+    /// The CAP subcommand, e.g. `LS`, `ACK` or `NAK`.
+    pub subcommand: &'a str,
+    /// Set if this is one line of a multi-line reply and more lines follow.
+    pub continues: bool,
+    /// The raw space-separated capability list.
+    pub capabilities: &'a str,
+}
+
+impl Message {
+    pub fn as_cap(&self) -> Option<Cap<'_>> {
+        if self.command != commands::CAP() {
+            return None;
+        }
+
+        let (continues, capabilities) = match self.arguments.len() {
+            3 => (false, &self.arguments[2]),
+            4 if self.arguments[2] == "*" => (true, &self.arguments[3]),
+            _ => {
+                warn!("Not parsing message as Cap because of unexpected arguments: {}",
+                      self);
+                return None;
+            }
+        };
+
+        Some(Cap {
+            target: &self.arguments[0],
+            subcommand: &self.arguments[1],
+            continues: continues,
+            capabilities: capabilities,
+        })
+    }
+
+    /// Creates a `CAP LS` message, optionally advertising support for a CAP protocol version
+    /// (e.g. `302`).
+    pub fn cap_ls(version: Option<&str>) -> Message {
+        let mut arguments = vec!["LS"];
+        arguments.extend(version);
+        Message::from_strs(Prefix::None, commands::CAP(), arguments)
+    }
+
+    /// Creates a `CAP REQ` message requesting all of the provided capabilities.
+    pub fn cap_req(capabilities: &[&str]) -> Message {
+        let joined = capabilities.join(" ");
+        Message::from_strs(Prefix::None, commands::CAP(), vec!["REQ", &joined])
+    }
+
+    pub fn cap_end() -> Message {
+        Message::from_strs(Prefix::None, commands::CAP(), vec!["END"])
+    }
+}
+
+impl<'a> Cap<'a> {
+    /// Iterates over the capabilities in this message as `(name, value)` pairs, e.g.
+    /// `sasl=PLAIN,EXTERNAL` becomes `("sasl", Some("PLAIN,EXTERNAL"))`.
+    pub fn capabilities(&self) -> Vec<(&'a str, Option<&'a str>)> {
+        self.capabilities
+            .split(' ')
+            .filter(|cap| !cap.is_empty())
+            .map(|cap| match cap.find('=') {
+                Some(i) => (&cap[..i], Some(&cap[i + 1..])),
+                None => (cap, None),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn single_line() {
+        let message = message(":irc.example.com CAP * LS :multi-prefix sasl=PLAIN,EXTERNAL\r\n");
+        let cap = message.as_cap().unwrap();
+
+        assert_eq!(cap,
+                   Cap {
+                       target: "*",
+                       subcommand: "LS",
+                       continues: false,
+                       capabilities: "multi-prefix sasl=PLAIN,EXTERNAL",
+                   });
+        assert_eq!(cap.capabilities(),
+                   vec![("multi-prefix", None), ("sasl", Some("PLAIN,EXTERNAL"))]);
+    }
+
+    #[test]
+    fn continuation() {
+        let message = message(":irc.example.com CAP nick LS * :multi-prefix\r\n");
+        let cap = message.as_cap().unwrap();
+
+        assert!(cap.continues);
+        assert_eq!(cap.capabilities(), vec![("multi-prefix", None)]);
+    }
+
+    #[test]
+    fn bad_too_few_arguments() {
+        let message = message(":irc.example.com CAP * LS\r\n");
+        assert_eq!(message.as_cap(), None);
+    }
+
+    #[test]
+    fn cap_req() {
+        assert_eq!(format!("{}", Message::cap_req(&["multi-prefix", "sasl"])),
+                   "CAP REQ :multi-prefix sasl");
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
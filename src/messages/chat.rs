@@ -0,0 +1,79 @@
+use message::Message;
+use message::Prefix;
+
+/// A PRIVMSG or NOTICE, for code which treats them the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChatMessage<'a> {
+    pub from: &'a Prefix,
+    pub to: &'a str,
+    pub text: &'a str,
+    /// Whether this is a NOTICE. Bots shouldn't automatically reply to notices, to avoid loops
+    /// with other bots.
+    pub is_notice: bool,
+}
+
+impl Message {
+    /// Reads either a PRIVMSG or a NOTICE.
+    pub fn as_chat(&self) -> Option<ChatMessage<'_>> {
+        if let Some(privmsg) = self.as_privmsg() {
+            Some(ChatMessage {
+                from: &self.prefix,
+                to: privmsg.to,
+                text: privmsg.text,
+                is_notice: false,
+            })
+        } else {
+            self.as_notice().map(|notice| {
+                ChatMessage {
+                    from: notice.from,
+                    to: notice.to,
+                    text: notice.text,
+                    is_notice: true,
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn privmsg_and_notice() {
+        let privmsg = message(":nick!user@host PRIVMSG #channel :Hello\r\n");
+        let notice = message(":nick!user@host NOTICE #channel :Hello\r\n");
+        let from = Prefix::User(UserInfo::of_nickname_user_host("nick", "user", "host"));
+
+        assert_eq!(privmsg.as_chat(),
+                   Some(ChatMessage {
+                       from: &from,
+                       to: "#channel",
+                       text: "Hello",
+                       is_notice: false,
+                   }));
+        assert_eq!(notice.as_chat(),
+                   Some(ChatMessage {
+                       from: &from,
+                       to: "#channel",
+                       text: "Hello",
+                       is_notice: true,
+                   }));
+    }
+
+    #[test]
+    fn other_commands() {
+        let message = message(":nick!user@host JOIN #channel\r\n");
+        assert_eq!(message.as_chat(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use command::commands;
+use message::Message;
+use message::Prefix;
+use server_time::ServerTime;
+
+/// Identifies a point in a channel's history for a CHATHISTORY request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistorySelector {
+    Timestamp(ServerTime),
+    MsgId(String),
+}
+
+impl Display for HistorySelector {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match *self {
+            HistorySelector::Timestamp(ref time) => write!(fmt, "timestamp={}", time),
+            HistorySelector::MsgId(ref id) => write!(fmt, "msgid={}", id),
+        }
+    }
+}
+
+impl Message {
+    /// Requests the most recent `limit` messages in `target`, optionally only those after `since`.
+    pub fn chathistory_latest(target: &str,
+                              since: Option<&HistorySelector>,
+                              limit: usize)
+                              -> Message {
+        let since = since.map(|s| s.to_string()).unwrap_or_else(|| "*".into());
+        Self::chathistory("LATEST", target, &[&since], limit)
+    }
+
+    /// Requests up to `limit` messages in `target` from before `before`.
+    pub fn chathistory_before(target: &str, before: &HistorySelector, limit: usize) -> Message {
+        Self::chathistory("BEFORE", target, &[&before.to_string()], limit)
+    }
+
+    /// Requests up to `limit` messages in `target` from after `after`.
+    pub fn chathistory_after(target: &str, after: &HistorySelector, limit: usize) -> Message {
+        Self::chathistory("AFTER", target, &[&after.to_string()], limit)
+    }
+
+    /// Requests up to `limit` messages in `target` between `from` and `to`.
+    pub fn chathistory_between(target: &str,
+                               from: &HistorySelector,
+                               to: &HistorySelector,
+                               limit: usize)
+                               -> Message {
+        Self::chathistory("BETWEEN",
+                          target,
+                          &[&from.to_string(), &to.to_string()],
+                          limit)
+    }
+
+    fn chathistory(subcommand: &str, target: &str, selectors: &[&str], limit: usize) -> Message {
+        let limit = limit.to_string();
+        let mut arguments = vec![subcommand, target];
+        arguments.extend(selectors);
+        arguments.push(&limit);
+        Message::from_strs(Prefix::None, commands::CHATHISTORY(), arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn latest() {
+        assert_eq!(format!("{}", Message::chathistory_latest("#chan", None, 50)),
+                   "CHATHISTORY LATEST #chan * 50");
+
+        let since = HistorySelector::MsgId("abc".into());
+        assert_eq!(format!("{}", Message::chathistory_latest("#chan", Some(&since), 50)),
+                   "CHATHISTORY LATEST #chan msgid=abc 50");
+    }
+
+    #[test]
+    fn before_and_after() {
+        let time = HistorySelector::Timestamp("2019-01-04T14:33:26.123Z".parse().unwrap());
+
+        assert_eq!(format!("{}", Message::chathistory_before("#chan", &time, 10)),
+                   "CHATHISTORY BEFORE #chan timestamp=2019-01-04T14:33:26.123Z 10");
+        assert_eq!(format!("{}", Message::chathistory_after("#chan", &time, 10)),
+                   "CHATHISTORY AFTER #chan timestamp=2019-01-04T14:33:26.123Z 10");
+    }
+
+    #[test]
+    fn between() {
+        let from = HistorySelector::MsgId("abc".into());
+        let to = HistorySelector::MsgId("def".into());
+
+        assert_eq!(format!("{}", Message::chathistory_between("#chan", &from, &to, 100)),
+                   "CHATHISTORY BETWEEN #chan msgid=abc msgid=def 100");
+    }
+}
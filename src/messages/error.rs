@@ -0,0 +1,39 @@
+use command::commands;
+use message::Message;
+
+impl Message {
+    /// The reason given in an ERROR from the server, which it sends just before closing the
+    /// connection (e.g. `Closing Link: ...`).
+    pub fn as_server_error(&self) -> Option<&str> {
+        if self.command != commands::ERROR() {
+            return None;
+        }
+        if self.arguments.len() > 1 {
+            warn!("Not parsing message as server error because we expect at most 1 argument: {}",
+                  self);
+            return None;
+        }
+        Some(self.arguments.first().map(|r| r.as_str()).unwrap_or(""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn server_error() {
+        let message = message("ERROR :Closing Link: bot[192.0.2.1] (Ping timeout)\r\n");
+        assert_eq!(message.as_server_error(), Some("Closing Link: bot[192.0.2.1] (Ping timeout)"));
+
+        assert_eq!(Message::privmsg("#chan", "ERROR").as_server_error(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
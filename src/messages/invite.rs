@@ -0,0 +1,91 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+/// Simple accessor for a received INVITE message. We get these when someone invites us, and with
+/// the `invite-notify` capability when someone invites another user to a channel we're in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Invite<'a> {
+    /// Who sent the invite. This is usually a user, but can be a server or services.
+    pub by: &'a Prefix,
+    /// The nickname of the user who was invited.
+    pub nick: &'a str,
+    pub channel: &'a str,
+}
+
+impl Message {
+    pub fn as_invite(&self) -> Option<Invite<'_>> {
+        if self.command != commands::INVITE() {
+            return None;
+        }
+        if self.arguments.len() != 2 {
+            warn!("Not parsing message as Invite because we expect 2 arguments: {}", self);
+            return None;
+        }
+
+        // Should be <nick> <channel>, but some servers have sent them the other way round.
+        let (nick, channel) = if is_channel(&self.arguments[0]) && !is_channel(&self.arguments[1]) {
+            (&self.arguments[1], &self.arguments[0])
+        } else {
+            (&self.arguments[0], &self.arguments[1])
+        };
+
+        Some(Invite {
+            by: &self.prefix,
+            nick: nick,
+            channel: channel,
+        })
+    }
+
+    /// Creates an `INVITE` message, inviting `nick` to `channel`.
+    pub fn invite(nick: &str, channel: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::INVITE(), vec![nick, channel])
+    }
+}
+
+// We don't have the server's CHANTYPES here, so go with every prefix that's in common use.
+fn is_channel(target: &str) -> bool {
+    target.starts_with(['#', '&', '+', '!'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn invite() {
+        let message = message(":nick!user@host INVITE me :#channel\r\n");
+
+        assert_eq!(message.as_invite(),
+                   Some(Invite {
+                       by: &Prefix::User(UserInfo::of_nickname_user_host("nick", "user", "host")),
+                       nick: "me",
+                       channel: "#channel",
+                   }));
+    }
+
+    #[test]
+    fn channel_first() {
+        let message = message(":nick!user@host INVITE #channel :me\r\n");
+        let invite = message.as_invite().unwrap();
+
+        assert_eq!(invite.nick, "me");
+        assert_eq!(invite.channel, "#channel");
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(Message::invite("friend", "#channel"),
+                   message("INVITE friend #channel\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -1,9 +1,123 @@
 use command::commands;
 use message::Message;
 use message::Prefix;
+use message::UserInfo;
+
+/// Simple accessor for a received JOIN message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Join<'a> {
+    pub user: &'a UserInfo,
+    /// The channel joined. Servers send a separate JOIN for each channel, but this may be a
+    /// comma-separated list in JOINs from elsewhere (e.g. ones relayed by a bouncer); see
+    /// `channels`.
+    pub channel: &'a str,
+    /// The services account of the joining user. Only sent with the `extended-join` capability,
+    /// and `None` if the user isn't logged in.
+    pub account: Option<&'a str>,
+    /// The realname of the joining user. Only sent with the `extended-join` capability.
+    pub realname: Option<&'a str>,
+}
 
 impl Message {
+    pub fn as_join(&self) -> Option<Join<'_>> {
+        if self.command != commands::JOIN() {
+            return None;
+        }
+        if self.arguments.len() != 1 && self.arguments.len() != 3 {
+            warn!("Not parsing message as Join because we expect 1 or 3 arguments: {}",
+                  self);
+            return None;
+        }
+        let user = match self.prefix {
+            Prefix::User(ref u) => u,
+            _ => {
+                warn!("Not parsing message as Join because we expect prefix of user: {}",
+                      self);
+                return None;
+            }
+        };
+
+        Some(Join {
+            user: user,
+            channel: &self.arguments[0],
+            account: self.arguments.get(1).map(|a| a.as_str()).filter(|a| *a != "*"),
+            realname: self.arguments.get(2).map(|r| r.as_str()),
+        })
+    }
+
     pub fn join(channel: &str) -> Message {
         Message::from_strs(Prefix::None, commands::JOIN(), vec![channel])
     }
 }
+
+impl<'a> Join<'a> {
+    /// The channels joined, splitting up a comma-separated list.
+    pub fn channels(&self) -> Vec<&'a str> {
+        self.channel.split(',').filter(|channel| !channel.is_empty()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn plain() {
+        let message = message(":nick!user@host JOIN #channel\r\n");
+
+        assert_eq!(message.as_join(),
+                   Some(Join {
+                       user: &UserInfo::of_nickname_user_host("nick", "user", "host"),
+                       channel: "#channel",
+                       account: None,
+                       realname: None,
+                   }));
+    }
+
+    #[test]
+    fn extended() {
+        let message = message(":nick!user@host JOIN #channel account :Real Name\r\n");
+
+        assert_eq!(message.as_join(),
+                   Some(Join {
+                       user: &UserInfo::of_nickname_user_host("nick", "user", "host"),
+                       channel: "#channel",
+                       account: Some("account"),
+                       realname: Some("Real Name"),
+                   }));
+    }
+
+    #[test]
+    fn extended_not_logged_in() {
+        let message = message(":nick!user@host JOIN #channel * :Real Name\r\n");
+        let join = message.as_join().unwrap();
+
+        assert_eq!(join.account, None);
+        assert_eq!(join.realname, Some("Real Name"));
+    }
+
+    #[test]
+    fn several_channels() {
+        let several = message(":nick!user@host JOIN #one,#two\r\n");
+        assert_eq!(several.as_join().unwrap().channels(), vec!["#one", "#two"]);
+
+        let one = message(":nick!user@host JOIN #one\r\n");
+        assert_eq!(one.as_join().unwrap().channels(), vec!["#one"]);
+    }
+
+    #[test]
+    fn bad_no_prefix() {
+        let message = message("JOIN #channel\r\n");
+        assert_eq!(message.as_join(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
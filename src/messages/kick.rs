@@ -0,0 +1,116 @@
+use command::commands;
+use isupport::IsupportInfo;
+use message::Message;
+use message::Prefix;
+
+/// Simple accessor for a received KICK message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Kick<'a> {
+    /// Who did the kicking. This is usually a user, but can be a server or services.
+    pub by: &'a Prefix,
+    pub channel: &'a str,
+    /// The nickname of the user who was kicked.
+    pub nick: &'a str,
+    pub reason: Option<&'a str>,
+}
+
+impl Message {
+    pub fn as_kick(&self) -> Option<Kick<'_>> {
+        if self.command != commands::KICK() {
+            return None;
+        }
+        if self.arguments.len() != 2 && self.arguments.len() != 3 {
+            warn!("Not parsing message as Kick because we expect 2 or 3 arguments: {}",
+                  self);
+            return None;
+        }
+
+        Some(Kick {
+            by: &self.prefix,
+            channel: &self.arguments[0],
+            nick: &self.arguments[1],
+            reason: self.arguments.get(2).map(|r| r.as_str()).filter(|r| !r.is_empty()),
+        })
+    }
+
+    /// Creates a `KICK` message, removing `nick` from `channel`.
+    pub fn kick(channel: &str, nick: &str, reason: Option<&str>) -> Message {
+        let mut arguments = vec![channel, nick];
+        arguments.extend(reason);
+        Message::from_strs(Prefix::None, commands::KICK(), arguments)
+    }
+}
+
+impl<'a> Kick<'a> {
+    /// Whether we were the one kicked, given our nickname. Nicknames are compared using the
+    /// server's `CASEMAPPING`.
+    pub fn affects_me(&self, nick: &str, isupport: &IsupportInfo) -> bool {
+        isupport.casemapping().eq(self.nick, nick)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn with_reason() {
+        let message = message(":op!user@host KICK #channel Victim[1] :Behave\r\n");
+        let kick = message.as_kick().unwrap();
+
+        assert_eq!(kick,
+                   Kick {
+                       by: &Prefix::User(UserInfo::of_nickname_user_host("op", "user", "host")),
+                       channel: "#channel",
+                       nick: "Victim[1]",
+                       reason: Some("Behave"),
+                   });
+        let isupport = IsupportInfo::new();
+        assert!(kick.affects_me("victim{1}", &isupport));
+        assert!(!kick.affects_me("op", &isupport));
+    }
+
+    #[test]
+    fn affects_me_uses_casemapping() {
+        let mut isupport = IsupportInfo::new();
+        isupport.handle(&message(":server 005 me CASEMAPPING=ascii :are supported\r\n"));
+        let message = message(":op!user@host KICK #channel Victim[1]\r\n");
+        let kick = message.as_kick().unwrap();
+
+        assert!(kick.affects_me("victim[1]", &isupport));
+        assert!(!kick.affects_me("victim{1}", &isupport));
+    }
+
+    #[test]
+    fn by_server_without_reason() {
+        let message = message(":services.example.com KICK #channel victim\r\n");
+        let kick = message.as_kick().unwrap();
+
+        assert_eq!(kick.by, &Prefix::Server("services.example.com".into()));
+        assert_eq!(kick.reason, None);
+    }
+
+    #[test]
+    fn bad_missing_nick() {
+        let message = message(":op!user@host KICK #channel\r\n");
+        assert_eq!(message.as_kick(), None);
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(Message::kick("#channel", "victim", None),
+                   message("KICK #channel victim\r\n"));
+        assert_eq!(Message::kick("#channel", "victim", Some("Behave")),
+                   message("KICK #channel victim :Behave\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
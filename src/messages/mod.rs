@@ -1,8 +1,65 @@
+mod account;
+mod authenticate;
+mod away;
+mod batch;
+mod cap;
+mod chat;
+mod chathistory;
+mod error;
+mod invite;
 mod join;
+mod kick;
+mod mode;
+mod monitor;
+mod names;
 mod nick;
+mod notice;
+mod numeric;
+mod oper;
+mod part;
+mod pass;
 mod ping;
 mod privmsg;
+mod query;
+mod quit;
+mod reply;
+mod server_query;
+mod tagmsg;
+mod topic;
+mod typing;
 mod user;
+mod webirc;
+mod whox;
 
+pub use self::account::Account;
+pub use self::away::Away;
+pub use self::batch::BatchBoundary;
+pub use self::cap::Cap;
+pub use self::chat::ChatMessage;
+pub use self::chathistory::HistorySelector;
+pub use self::invite::Invite;
+pub use self::join::Join;
+pub use self::kick::Kick;
+pub use self::mode::ChannelMode;
+pub use self::mode::Mode;
+pub use self::mode::ModeChange;
+pub use self::mode::ModeTarget;
+pub use self::mode::UserMode;
+pub use self::mode::UserModes;
+pub use self::monitor::MonitorStatus;
+pub use self::names::NamesEntry;
+pub use self::names::NamesReply;
+pub use self::nick::NickChange;
+pub use self::notice::Notice;
+pub use self::numeric::Numeric;
+pub use self::oper::OperReply;
+pub use self::part::Part;
 pub use self::ping::Ping;
 pub use self::privmsg::Privmsg;
+pub use self::quit::Quit;
+pub use self::topic::Topic;
+pub use self::topic::TopicReply;
+pub use self::topic::TopicWhoTime;
+pub use self::typing::TypingState;
+pub use self::whox::WhoxQuery;
+pub use self::whox::WhoxReply;
@@ -0,0 +1,490 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+use command::commands;
+use command::responses;
+use isupport::ChannelModes;
+use isupport::IsupportInfo;
+use membership::MembershipPrefixes;
+use message::Message;
+use message::Prefix;
+
+/// A single mode being set or unset, like `+o nick` or `-m`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeChange {
+    /// `true` for `+`, `false` for `-`.
+    pub added: bool,
+    pub mode: char,
+    /// The mode's parameter, if it takes one. This is also `None` for list modes like `b` when no
+    /// parameter was given, which asks for the list rather than changing it.
+    pub argument: Option<String>,
+}
+
+/// What a MODE message is changing the modes of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeTarget<'a> {
+    Channel(&'a str),
+    /// A user's own modes, by nickname.
+    User(&'a str),
+}
+
+/// Simple accessor for a received MODE message, with the modes left undecoded. Use `changes` (or
+/// `as_channel_mode` and `as_user_mode`) to decode them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode<'a> {
+    /// Who changed the modes. This is a server or services as often as it's a user.
+    pub by: &'a Prefix,
+    pub target: ModeTarget<'a>,
+    /// The modes being changed, like `+ov-b`.
+    pub modestring: &'a str,
+    /// The modes' parameters, in order.
+    pub arguments: &'a [String],
+}
+
+/// Simple accessor for a received channel MODE message, or the `RPL_CHANNELMODEIS` (324) reply
+/// giving a channel's current modes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMode<'a> {
+    /// Who changed the modes, which is the server for `RPL_CHANNELMODEIS`.
+    pub by: &'a Prefix,
+    pub channel: &'a str,
+    pub changes: Vec<ModeChange>,
+}
+
+/// Simple accessor for a received user MODE message, or the `RPL_UMODEIS` (221) reply giving our
+/// current user modes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserMode<'a> {
+    pub nick: &'a str,
+    pub changes: Vec<ModeChange>,
+    /// Whether this is the full set of modes from `RPL_UMODEIS`, rather than a change to them.
+    pub complete: bool,
+}
+
+/// A set of user modes, like `+iwx`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserModes {
+    modes: BTreeSet<char>,
+}
+
+impl Message {
+    /// Splits up a MODE message without decoding the modes. Channels are told apart from users by
+    /// the default channel prefixes, `#` and `&`.
+    pub fn as_mode(&self) -> Option<Mode<'_>> {
+        if self.command != commands::MODE() {
+            return None;
+        }
+        if self.arguments.len() < 2 {
+            warn!("Not parsing message as Mode because we expect at least 2 arguments: {}",
+                  self);
+            return None;
+        }
+        let target = &self.arguments[0];
+
+        Some(Mode {
+            by: &self.prefix,
+            target: if IsupportInfo::new().is_channel(target) {
+                ModeTarget::Channel(target)
+            } else {
+                ModeTarget::User(target)
+            },
+            modestring: &self.arguments[1],
+            arguments: &self.arguments[2..],
+        })
+    }
+
+    /// Parses a channel mode change, using what the server told us in ISUPPORT to work out which
+    /// modes take parameters.
+    pub fn as_channel_mode(&self, isupport: &IsupportInfo) -> Option<ChannelMode<'_>> {
+        let arguments = if self.command == commands::MODE() {
+            &self.arguments[..]
+        } else if self.command == responses::RPL_CHANNELMODEIS() && !self.arguments.is_empty() {
+            &self.arguments[1..]
+        } else {
+            return None;
+        };
+        if arguments.len() < 2 || !isupport.is_channel(&arguments[0]) {
+            return None;
+        }
+
+        Some(ChannelMode {
+            by: &self.prefix,
+            channel: &arguments[0],
+            changes: ModeChange::parse_channel(&arguments[1],
+                                               &arguments[2..],
+                                               &isupport.chanmodes(),
+                                               &isupport.prefix()),
+        })
+    }
+
+    /// Parses a user mode change or `RPL_UMODEIS`.
+    pub fn as_user_mode(&self, isupport: &IsupportInfo) -> Option<UserMode<'_>> {
+        let (arguments, complete) = if self.command == commands::MODE() {
+            (&self.arguments[..], false)
+        } else if self.command == responses::RPL_UMODEIS() {
+            (&self.arguments[..], true)
+        } else {
+            return None;
+        };
+        if arguments.len() < 2 || isupport.is_channel(&arguments[0]) {
+            return None;
+        }
+
+        Some(UserMode {
+            nick: &arguments[0],
+            changes: ModeChange::parse_user(&arguments[1]),
+            complete: complete,
+        })
+    }
+
+    /// Builds a MODE message making `changes` to a channel. Changes are grouped by sign, so giving
+    /// op to `nick` and banning `*!*@host` gives `MODE #chan +ob nick *!*@host`.
+    ///
+    /// Servers limit how many parameterised changes go in one message (the `MODES` ISUPPORT
+    /// token), so long lists of changes may need splitting up. With no changes, this is the query
+    /// `MODE #chan`, which the server answers with `RPL_CHANNELMODEIS`.
+    pub fn channel_mode(channel: &str, changes: &[ModeChange]) -> Message {
+        Message::mode(channel, changes)
+    }
+
+    /// Builds a MODE message changing our user modes, or asking for them with no changes.
+    pub fn user_mode(nick: &str, changes: &[ModeChange]) -> Message {
+        Message::mode(nick, changes)
+    }
+
+    fn mode(target: &str, changes: &[ModeChange]) -> Message {
+        let mut modestring = String::new();
+        let mut added = None;
+        for change in changes {
+            if added != Some(change.added) {
+                modestring.push(if change.added { '+' } else { '-' });
+                added = Some(change.added);
+            }
+            modestring.push(change.mode);
+        }
+
+        let mut arguments = vec![target];
+        if !modestring.is_empty() {
+            arguments.push(&modestring);
+        }
+        arguments.extend(changes.iter().filter_map(|change| change.argument.as_deref()));
+        Message::from_strs(Prefix::None, commands::MODE(), arguments)
+    }
+}
+
+impl<'a> Mode<'a> {
+    /// Decodes the modes, using what the server told us in ISUPPORT to work out which take
+    /// parameters.
+    pub fn changes(&self, isupport: &IsupportInfo) -> Vec<ModeChange> {
+        match self.target {
+            ModeTarget::Channel(_) => {
+                ModeChange::parse_channel(self.modestring,
+                                          self.arguments,
+                                          &isupport.chanmodes(),
+                                          &isupport.prefix())
+            }
+            ModeTarget::User(_) => ModeChange::parse_user(self.modestring),
+        }
+    }
+}
+
+impl ModeChange {
+    /// Sets a mode which doesn't take a parameter, like `+m`.
+    pub fn set(mode: char) -> Self {
+        ModeChange {
+            added: true,
+            mode: mode,
+            argument: None,
+        }
+    }
+
+    /// Unsets a mode which doesn't take a parameter, like `-m`.
+    pub fn unset(mode: char) -> Self {
+        ModeChange {
+            added: false,
+            mode: mode,
+            argument: None,
+        }
+    }
+
+    /// Sets a mode with a parameter, like `+l 10`.
+    pub fn set_with(mode: char, argument: &str) -> Self {
+        ModeChange {
+            added: true,
+            mode: mode,
+            argument: Some(argument.into()),
+        }
+    }
+
+    /// Unsets a mode with a parameter, like `-b *!*@host`.
+    pub fn unset_with(mode: char, argument: &str) -> Self {
+        ModeChange {
+            added: false,
+            mode: mode,
+            argument: Some(argument.into()),
+        }
+    }
+
+    pub fn give_op(nick: &str) -> Self {
+        ModeChange::set_with('o', nick)
+    }
+
+    pub fn take_op(nick: &str) -> Self {
+        ModeChange::unset_with('o', nick)
+    }
+
+    pub fn give_voice(nick: &str) -> Self {
+        ModeChange::set_with('v', nick)
+    }
+
+    pub fn take_voice(nick: &str) -> Self {
+        ModeChange::unset_with('v', nick)
+    }
+
+    pub fn ban(mask: &str) -> Self {
+        ModeChange::set_with('b', mask)
+    }
+
+    pub fn unban(mask: &str) -> Self {
+        ModeChange::unset_with('b', mask)
+    }
+
+    /// Parses a channel mode string like `+ov-b` along with its parameters. Membership modes from
+    /// `prefixes` always take a parameter, as do `modes.list` and `modes.parameter`;
+    /// `modes.set_parameter` only take one when being set. Modes we don't know about are assumed
+    /// not to take one.
+    pub fn parse_channel<S: AsRef<str>>(modestring: &str,
+                                        arguments: &[S],
+                                        modes: &ChannelModes,
+                                        prefixes: &MembershipPrefixes)
+                                        -> Vec<ModeChange> {
+        let mut arguments = arguments.iter().map(|arg| arg.as_ref().to_string());
+        let mut added = true;
+        let mut changes = Vec::new();
+        for mode in modestring.chars() {
+            match mode {
+                '+' => added = true,
+                '-' => added = false,
+                _ => {
+                    let takes_argument = prefixes.prefix_for_mode(mode).is_some() ||
+                                         modes.list.contains(mode) ||
+                                         modes.parameter.contains(mode) ||
+                                         (added && modes.set_parameter.contains(mode));
+                    changes.push(ModeChange {
+                        added: added,
+                        mode: mode,
+                        argument: if takes_argument { arguments.next() } else { None },
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// Parses a user mode string like `+iw-x`. User modes never have parameters (or at least none
+    /// we know how to deal with).
+    pub fn parse_user(modestring: &str) -> Vec<ModeChange> {
+        ModeChange::parse_channel::<&str>(modestring,
+                                          &[],
+                                          &ChannelModes::parse(",,,").unwrap(),
+                                          &MembershipPrefixes::parse("").unwrap())
+    }
+}
+
+impl UserModes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, mode: char) -> bool {
+        self.modes.contains(&mode)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modes.is_empty()
+    }
+
+    /// Whether we're an IRC operator, global (`+o`) or local (`+O`).
+    pub fn is_oper(&self) -> bool {
+        self.contains('o') || self.contains('O')
+    }
+
+    pub fn is_invisible(&self) -> bool {
+        self.contains('i')
+    }
+
+    /// Applies changes from a MODE message. For `RPL_UMODEIS`, start from an empty set.
+    pub fn apply(&mut self, changes: &[ModeChange]) {
+        for change in changes {
+            if change.added {
+                self.modes.insert(change.mode);
+            } else {
+                self.modes.remove(&change.mode);
+            }
+        }
+    }
+}
+
+impl Display for UserModes {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "+")?;
+        for mode in &self.modes {
+            write!(fmt, "{}", mode)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn raw_mode() {
+        let channel_message = message(":op!user@host MODE #chan +o-b nick *!*@mask\r\n");
+        let mode = channel_message.as_mode().unwrap();
+
+        assert_eq!(mode.target, ModeTarget::Channel("#chan"));
+        assert_eq!(mode.modestring, "+o-b");
+        assert_eq!(mode.arguments, &["nick".to_string(), "*!*@mask".to_string()][..]);
+        assert_eq!(mode.changes(&IsupportInfo::new()),
+                   vec![change(true, 'o', Some("nick")), change(false, 'b', Some("*!*@mask"))]);
+
+        let user_message = message(":nick MODE nick :+i\r\n");
+        let mode = user_message.as_mode().unwrap();
+        assert_eq!(mode.target, ModeTarget::User("nick"));
+        assert_eq!(mode.changes(&IsupportInfo::new()), vec![change(true, 'i', None)]);
+    }
+
+    #[test]
+    fn mode_change() {
+        let message = message(":op!user@host MODE #chan +ovl-bk+m nick1 nick2 10 *!*@mask key\r\n");
+        let mode = message.as_channel_mode(&IsupportInfo::new()).unwrap();
+
+        assert_eq!(mode.channel, "#chan");
+        assert_eq!(mode.changes,
+                   vec![change(true, 'o', Some("nick1")),
+                        change(true, 'v', Some("nick2")),
+                        change(true, 'l', Some("10")),
+                        change(false, 'b', Some("*!*@mask")),
+                        change(false, 'k', Some("key")),
+                        change(true, 'm', None)]);
+    }
+
+    #[test]
+    fn unset_parameter_and_lists() {
+        let message = message(":op!user@host MODE #chan -l+b\r\n");
+        let mode = message.as_channel_mode(&IsupportInfo::new()).unwrap();
+
+        assert_eq!(mode.changes, vec![change(false, 'l', None), change(true, 'b', None)]);
+    }
+
+    #[test]
+    fn uses_isupport() {
+        let mut isupport = IsupportInfo::new();
+        isupport.handle(&message(":server 005 me CHANMODES=eIbq,k,flj,CFLMPQScgimnprstuz \
+                                  PREFIX=(qaohv)~&@%+ :are supported\r\n"));
+        let message = message(":op!user@host MODE #chan +qfP owner #other\r\n");
+        let mode = message.as_channel_mode(&isupport).unwrap();
+
+        assert_eq!(mode.changes,
+                   vec![change(true, 'q', Some("owner")),
+                        change(true, 'f', Some("#other")),
+                        change(true, 'P', None)]);
+    }
+
+    #[test]
+    fn channel_mode_is() {
+        let message = message(":irc.host 324 me #chan +nkl key 10\r\n");
+        let mode = message.as_channel_mode(&IsupportInfo::new()).unwrap();
+
+        assert_eq!(mode.by, &Prefix::Server("irc.host".into()));
+        assert_eq!(mode.channel, "#chan");
+        assert_eq!(mode.changes,
+                   vec![change(true, 'n', None),
+                        change(true, 'k', Some("key")),
+                        change(true, 'l', Some("10"))]);
+    }
+
+    #[test]
+    fn user_mode_is_not_channel_mode() {
+        let message = message(":nick MODE nick +i\r\n");
+        assert_eq!(message.as_channel_mode(&IsupportInfo::new()), None);
+    }
+
+    #[test]
+    fn user_mode() {
+        let message = message(":nick!user@host MODE nick :+iw-x\r\n");
+        let mode = message.as_user_mode(&IsupportInfo::new()).unwrap();
+
+        assert_eq!(mode.nick, "nick");
+        assert!(!mode.complete);
+        assert_eq!(mode.changes,
+                   vec![change(true, 'i', None),
+                        change(true, 'w', None),
+                        change(false, 'x', None)]);
+        assert_eq!(message.as_channel_mode(&IsupportInfo::new()), None);
+    }
+
+    #[test]
+    fn user_mode_is() {
+        let message = message(":irc.host 221 nick +Zi\r\n");
+        let mode = message.as_user_mode(&IsupportInfo::new()).unwrap();
+
+        assert!(mode.complete);
+        let mut modes = UserModes::new();
+        modes.apply(&mode.changes);
+        assert_eq!(modes.to_string(), "+Zi");
+        assert!(modes.is_invisible());
+        assert!(!modes.is_oper());
+
+        modes.apply(&ModeChange::parse_user("+o-i"));
+        assert!(modes.is_oper());
+        assert_eq!(modes.to_string(), "+Zo");
+    }
+
+    #[test]
+    fn builders() {
+        assert_eq!(Message::channel_mode("#chan",
+                                         &[ModeChange::give_op("nick"),
+                                           ModeChange::ban("*!*@host"),
+                                           ModeChange::unset('m'),
+                                           ModeChange::take_voice("other"),
+                                           ModeChange::set_with('l', "10")]),
+                   message("MODE #chan +ob-mv+l nick *!*@host other 10\r\n"));
+        assert_eq!(Message::user_mode("nick", &[ModeChange::set('i'), ModeChange::unset('w')]),
+                   message("MODE nick +i-w\r\n"));
+    }
+
+    #[test]
+    fn builders_query() {
+        assert_eq!(Message::channel_mode("#chan", &[]), message("MODE #chan\r\n"));
+        assert_eq!(Message::user_mode("nick", &[]), message("MODE nick\r\n"));
+    }
+
+    #[test]
+    fn builders_round_trip() {
+        let changes = vec![ModeChange::give_op("nick"), ModeChange::unban("*!*@host")];
+        let message = Message::channel_mode("#chan", &changes);
+        assert_eq!(message.as_channel_mode(&IsupportInfo::new()).unwrap().changes, changes);
+    }
+
+    fn change(added: bool, mode: char, argument: Option<&str>) -> ModeChange {
+        ModeChange {
+            added: added,
+            mode: mode,
+            argument: argument.map(|a| a.into()),
+        }
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use command::commands;
+use command::responses;
+use message::Message;
+use message::Prefix;
+
+/// Simple accessor for received RPL_MONONLINE and RPL_MONOFFLINE messages, which report that
+/// monitored nicknames have come online or gone offline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorStatus<'a> {
+    pub online: bool,
+    /// The targets as sent by the server. For RPL_MONONLINE these may include the user and host,
+    /// as in `nick!user@host`; use `nicks` to get just the nicknames.
+    pub targets: Vec<&'a str>,
+}
+
+impl Message {
+    pub fn as_monitor_status(&self) -> Option<MonitorStatus<'_>> {
+        let online = if self.command == responses::RPL_MONONLINE() {
+            true
+        } else if self.command == responses::RPL_MONOFFLINE() {
+            false
+        } else {
+            return None;
+        };
+        if self.arguments.len() != 2 {
+            warn!("Not parsing message as MonitorStatus because we expect 2 arguments: {}",
+                  self);
+            return None;
+        }
+
+        Some(MonitorStatus {
+            online: online,
+            targets: self.arguments[1].split(',').filter(|t| !t.is_empty()).collect(),
+        })
+    }
+
+    /// Creates a `MONITOR +` message adding nicknames to the monitor list.
+    pub fn monitor_add(nicks: &[&str]) -> Message {
+        Self::monitor("+", Some(nicks))
+    }
+
+    /// Creates a `MONITOR -` message removing nicknames from the monitor list.
+    pub fn monitor_remove(nicks: &[&str]) -> Message {
+        Self::monitor("-", Some(nicks))
+    }
+
+    /// Creates a `MONITOR C` message, clearing the monitor list.
+    pub fn monitor_clear() -> Message {
+        Self::monitor("C", None)
+    }
+
+    /// Creates a `MONITOR L` message, asking for the monitor list.
+    pub fn monitor_list() -> Message {
+        Self::monitor("L", None)
+    }
+
+    /// Creates a `MONITOR S` message, asking for the status of everyone on the monitor list.
+    pub fn monitor_status() -> Message {
+        Self::monitor("S", None)
+    }
+
+    fn monitor(subcommand: &str, nicks: Option<&[&str]>) -> Message {
+        let mut arguments = vec![subcommand.to_string()];
+        arguments.extend(nicks.map(|nicks| nicks.join(",")));
+        Message::new(Prefix::None, commands::MONITOR(), arguments)
+    }
+}
+
+impl<'a> MonitorStatus<'a> {
+    pub fn nicks(&self) -> Vec<&'a str> {
+        self.targets
+            .iter()
+            .map(|target| target.split('!').next().unwrap_or(target))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn online() {
+        let message = message(":irc.host 730 me :alice!a@host,bob!b@host\r\n");
+        let status = message.as_monitor_status().unwrap();
+
+        assert!(status.online);
+        assert_eq!(status.targets, vec!["alice!a@host", "bob!b@host"]);
+        assert_eq!(status.nicks(), vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn offline() {
+        let message = message(":irc.host 731 me :alice\r\n");
+        let status = message.as_monitor_status().unwrap();
+
+        assert!(!status.online);
+        assert_eq!(status.nicks(), vec!["alice"]);
+    }
+
+    #[test]
+    fn builders() {
+        assert_eq!(format!("{}", Message::monitor_add(&["alice", "bob"])),
+                   "MONITOR + alice,bob");
+        assert_eq!(format!("{}", Message::monitor_remove(&["alice"])),
+                   "MONITOR - alice");
+        assert_eq!(format!("{}", Message::monitor_clear()), "MONITOR C");
+        assert_eq!(format!("{}", Message::monitor_list()), "MONITOR L");
+        assert_eq!(format!("{}", Message::monitor_status()), "MONITOR S");
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use command::responses;
+use membership::Membership;
+use membership::MembershipPrefixes;
+use message::Message;
+
+/// Simple accessor for a received RPL_NAMREPLY message, one of the lines listing the members of a
+/// channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NamesReply<'a> {
+    /// `=` for public channels, `*` for private channels and `@` for secret channels.
+    pub visibility: &'a str,
+    pub channel: &'a str,
+    /// The raw space-separated list of (prefixed) nicknames.
+    pub names: &'a str,
+}
+
+/// A channel member as listed in a NAMES reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamesEntry<'a> {
+    /// The member's ranks in the channel, most powerful first.
+    pub ranks: Vec<Membership>,
+    pub nick: &'a str,
+}
+
+impl Message {
+    pub fn as_names_reply(&self) -> Option<NamesReply<'_>> {
+        if self.command != responses::RPL_NAMREPLY() {
+            return None;
+        }
+        if self.arguments.len() != 4 {
+            warn!("Not parsing message as NamesReply because we expect 4 arguments: {}",
+                  self);
+            return None;
+        }
+
+        Some(NamesReply {
+            visibility: &self.arguments[1],
+            channel: &self.arguments[2],
+            names: &self.arguments[3],
+        })
+    }
+}
+
+impl<'a> NamesReply<'a> {
+    /// Decodes the nickname list using the server's membership prefixes.
+    pub fn entries(&self, prefixes: &MembershipPrefixes) -> Vec<NamesEntry<'a>> {
+        self.names
+            .split(' ')
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let (ranks, nick) = prefixes.split(name);
+                NamesEntry {
+                    ranks: ranks,
+                    nick: nick,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use membership::Membership;
+    use membership::MembershipPrefixes;
+    use message::Message;
+
+    #[test]
+    fn names_reply() {
+        let message = message(":irc.host 353 me = #chan :@+alice bob %carol\r\n");
+        let reply = message.as_names_reply().unwrap();
+        let prefixes = MembershipPrefixes::parse("(ohv)@%+").unwrap();
+
+        assert_eq!(reply.visibility, "=");
+        assert_eq!(reply.channel, "#chan");
+        assert_eq!(reply.entries(&prefixes),
+                   vec![NamesEntry {
+                            ranks: vec![Membership::Op, Membership::Voice],
+                            nick: "alice",
+                        },
+                        NamesEntry {
+                            ranks: vec![],
+                            nick: "bob",
+                        },
+                        NamesEntry {
+                            ranks: vec![Membership::HalfOp],
+                            nick: "carol",
+                        }]);
+    }
+
+    #[test]
+    fn bad_missing_names() {
+        let message = message(":irc.host 353 me = #chan\r\n");
+        assert_eq!(message.as_names_reply(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -1,9 +1,81 @@
 use command::commands;
 use message::Message;
 use message::Prefix;
+use message::UserInfo;
+
+/// Simple accessor for a received NICK message, sent when a user changes their nickname.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NickChange<'a> {
+    /// The user, as they were known before the change.
+    pub user: &'a UserInfo,
+    pub new_nick: &'a str,
+}
 
 impl Message {
     pub fn nick(nick: &str) -> Message {
         Message::from_strs(Prefix::None, commands::NICK(), vec![nick])
     }
+
+    pub fn as_nick(&self) -> Option<NickChange<'_>> {
+        if self.command != commands::NICK() {
+            return None;
+        }
+        if self.arguments.len() != 1 {
+            warn!("Not parsing message as NickChange because we expect 1 argument: {}", self);
+            return None;
+        }
+        let user = match self.prefix {
+            Prefix::User(ref u) => u,
+            _ => {
+                warn!("Not parsing message as NickChange because we expect prefix of user: {}",
+                      self);
+                return None;
+            }
+        };
+
+        Some(NickChange {
+            user: user,
+            new_nick: &self.arguments[0],
+        })
+    }
+}
+
+impl<'a> NickChange<'a> {
+    /// The nickname the user had before the change.
+    pub fn old_nick(&self) -> &'a str {
+        self.user.nickname()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn nick_change() {
+        let message = message(":old!user@host NICK :new\r\n");
+        let change = message.as_nick().unwrap();
+
+        assert_eq!(change,
+                   NickChange {
+                       user: &UserInfo::of_nickname_user_host("old", "user", "host"),
+                       new_nick: "new",
+                   });
+        assert_eq!(change.old_nick(), "old");
+    }
+
+    #[test]
+    fn outgoing_nick() {
+        assert_eq!(Message::nick("new").as_nick(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
 }
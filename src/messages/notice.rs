@@ -0,0 +1,89 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+/// Simple accessor for a received NOTICE message.
+///
+/// Unlike PRIVMSGs, notices often come from servers (e.g. while connecting), so the sender is a
+/// `Prefix` rather than always a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Notice<'a> {
+    pub from: &'a Prefix,
+    pub to: &'a str,
+    pub text: &'a str,
+}
+
+impl Message {
+    pub fn as_notice(&self) -> Option<Notice<'_>> {
+        if self.command != commands::NOTICE() {
+            return None;
+        }
+        if self.arguments.len() != 2 {
+            warn!("Not parsing message as Notice because we expect 2 arguments: {}",
+                  self);
+            return None;
+        }
+
+        Some(Notice {
+            from: &self.prefix,
+            to: &self.arguments[0],
+            text: &self.arguments[1],
+        })
+    }
+
+    /// Builds a NOTICE. Bots should use these rather than PRIVMSGs for automatic replies, since
+    /// other bots know not to reply to them.
+    pub fn notice(to: &str, text: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::NOTICE(), vec![to, text])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn from_user() {
+        let message = message(":nick!user@host NOTICE #channel :Hello\r\n");
+
+        assert_eq!(message.as_notice(),
+                   Some(Notice {
+                       from: &Prefix::User(UserInfo::of_nickname_user_host("nick", "user", "host")),
+                       to: "#channel",
+                       text: "Hello",
+                   }));
+    }
+
+    #[test]
+    fn from_server() {
+        let message = message(":irc.example.com NOTICE * :*** Looking up your hostname\r\n");
+        let notice = message.as_notice().unwrap();
+
+        assert_eq!(notice.from, &Prefix::Server("irc.example.com".into()));
+        assert_eq!(notice.to, "*");
+    }
+
+    #[test]
+    fn bad_missing_text() {
+        let message = message(":nick!user@host NOTICE #channel\r\n");
+        assert_eq!(message.as_notice(), None);
+    }
+
+    #[test]
+    fn builder() {
+        let notice = Message::notice("nick", "Hello there");
+
+        assert_eq!(notice, message("NOTICE nick :Hello there\r\n"));
+        assert_eq!(notice.as_notice().unwrap().text, "Hello there");
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use command::Command;
+use message::Message;
+
+/// Simple accessor for a received numeric reply, for handling replies without comparing against
+/// each of the functions in `command::responses`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Numeric<'a> {
+    pub number: u16,
+    /// Who the reply is for. This is our nickname, or `*` before we've registered.
+    pub target: &'a str,
+    /// The rest of the arguments.
+    pub params: &'a [String],
+}
+
+impl Message {
+    pub fn as_numeric(&self) -> Option<Numeric<'_>> {
+        let number = match self.command {
+            Command::Number(number) => number,
+            Command::Word(_) => return None,
+        };
+        if self.arguments.is_empty() {
+            warn!("Not parsing message as Numeric because we expect at least 1 argument: {}",
+                  self);
+            return None;
+        }
+
+        Some(Numeric {
+            number: number,
+            target: &self.arguments[0],
+            params: &self.arguments[1..],
+        })
+    }
+}
+
+impl<'a> Numeric<'a> {
+    /// Whether this is an error reply, which are numbered 400 to 599.
+    pub fn is_error(&self) -> bool {
+        self.number >= 400 && self.number < 600
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn numeric() {
+        let message = message(":irc.host 433 * bot :Nickname is already in use\r\n");
+        let numeric = message.as_numeric().unwrap();
+
+        assert_eq!(numeric.number, 433);
+        assert_eq!(numeric.target, "*");
+        assert_eq!(numeric.params,
+                   &["bot".to_string(), "Nickname is already in use".to_string()][..]);
+        assert!(numeric.is_error());
+    }
+
+    #[test]
+    fn not_error() {
+        let message = message(":irc.host 001 bot :Welcome\r\n");
+        assert!(!message.as_numeric().unwrap().is_error());
+        assert_eq!(Message::privmsg("#chan", "433").as_numeric(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
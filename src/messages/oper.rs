@@ -0,0 +1,118 @@
+use command::commands;
+use command::responses;
+use message::Message;
+use message::Prefix;
+
+/// The server's reply to an `OPER` message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperReply {
+    /// `RPL_YOUREOPER` (381): we're now an operator.
+    Opered,
+    /// `ERR_PASSWDMISMATCH` (464): the name or password was wrong.
+    PasswordMismatch,
+    /// `ERR_NOOPERHOST` (491): there's no operator block for us, or not one we can use from our
+    /// host.
+    NoOperHost,
+}
+
+impl OperReply {
+    pub fn is_success(&self) -> bool {
+        *self == OperReply::Opered
+    }
+}
+
+impl Message {
+    /// Creates an `OPER` message, to become an IRC operator. The server replies with
+    /// `RPL_YOUREOPER` (381) if it worked; see `as_oper_reply`.
+    pub fn oper(name: &str, password: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::OPER(), vec![name, password])
+    }
+
+    /// Recognises the server's reply to an `OPER` message.
+    pub fn as_oper_reply(&self) -> Option<OperReply> {
+        if self.command == responses::RPL_YOUREOPER() {
+            Some(OperReply::Opered)
+        } else if self.command == responses::ERR_PASSWDMISMATCH() {
+            Some(OperReply::PasswordMismatch)
+        } else if self.command == responses::ERR_NOOPERHOST() {
+            Some(OperReply::NoOperHost)
+        } else {
+            None
+        }
+    }
+
+    /// Creates a `KILL` message, disconnecting `nick` from the network.
+    pub fn kill(nick: &str, reason: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::KILL(), vec![nick, reason])
+    }
+
+    /// Creates a `REHASH` message, making the server reload its configuration. It replies with
+    /// `RPL_REHASHING` (382).
+    pub fn rehash() -> Message {
+        Message::from_strs(Prefix::None, commands::REHASH(), vec![])
+    }
+
+    /// Creates a `CONNECT` message, asking `remote_server` (or the server we're on) to link to
+    /// `target_server` on `port`.
+    pub fn connect(target_server: &str, port: u16, remote_server: Option<&str>) -> Message {
+        let port = port.to_string();
+        let mut arguments = vec![target_server, &port];
+        arguments.extend(remote_server);
+        Message::from_strs(Prefix::None, commands::CONNECT(), arguments)
+    }
+
+    /// Creates an `SQUIT` message, breaking the link to `server`.
+    pub fn squit(server: &str, comment: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::SQUIT(), vec![server, comment])
+    }
+
+    /// Creates a `WALLOPS` message, sent to everyone with user mode `+w` (usually operators).
+    pub fn wallops(text: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::WALLOPS(), vec![text])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn oper() {
+        assert_eq!(Message::oper("admin", "hunter2"), message("OPER admin hunter2\r\n"));
+    }
+
+    #[test]
+    fn oper_replies() {
+        let opered = message(":server 381 bot :You are now an IRC operator\r\n");
+        assert_eq!(opered.as_oper_reply(), Some(OperReply::Opered));
+        assert!(opered.as_oper_reply().unwrap().is_success());
+        assert_eq!(message(":server 464 bot :Password incorrect\r\n").as_oper_reply(),
+                   Some(OperReply::PasswordMismatch));
+        assert_eq!(message(":server 491 bot :No O-lines for your host\r\n").as_oper_reply(),
+                   Some(OperReply::NoOperHost));
+        assert_eq!(message(":server 382 bot ircd.conf :Rehashing\r\n").as_oper_reply(), None);
+    }
+
+    #[test]
+    fn oper_commands() {
+        assert_eq!(Message::kill("spammer", "Spamming"), message("KILL spammer Spamming\r\n"));
+        assert_eq!(Message::rehash(), message("REHASH\r\n"));
+        assert_eq!(Message::connect("hub.example.com", 6667, None),
+                   message("CONNECT hub.example.com 6667\r\n"));
+        assert_eq!(Message::connect("hub.example.com", 6667, Some("leaf.example.com")),
+                   message("CONNECT hub.example.com 6667 leaf.example.com\r\n"));
+        assert_eq!(Message::squit("leaf.example.com", "Maintenance"),
+                   message("SQUIT leaf.example.com Maintenance\r\n"));
+        assert_eq!(Message::wallops("Restarting in 5 minutes"),
+                   message("WALLOPS :Restarting in 5 minutes\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+use message::UserInfo;
+
+/// Simple accessor for a received PART message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Part<'a> {
+    pub user: &'a UserInfo,
+    pub channel: &'a str,
+    pub reason: Option<&'a str>,
+}
+
+impl Message {
+    pub fn as_part(&self) -> Option<Part<'_>> {
+        if self.command != commands::PART() {
+            return None;
+        }
+        if self.arguments.is_empty() || self.arguments.len() > 2 {
+            warn!("Not parsing message as Part because we expect 1 or 2 arguments: {}",
+                  self);
+            return None;
+        }
+        let user = match self.prefix {
+            Prefix::User(ref u) => u,
+            _ => {
+                warn!("Not parsing message as Part because we expect prefix of user: {}",
+                      self);
+                return None;
+            }
+        };
+
+        Some(Part {
+            user: user,
+            channel: &self.arguments[0],
+            reason: self.arguments.get(1).map(|r| r.as_str()).filter(|r| !r.is_empty()),
+        })
+    }
+
+    /// Creates a `PART` message, leaving `channel`.
+    pub fn part(channel: &str, reason: Option<&str>) -> Message {
+        let mut arguments = vec![channel];
+        arguments.extend(reason);
+        Message::from_strs(Prefix::None, commands::PART(), arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn with_reason() {
+        let message = message(":nick!user@host PART #channel :Bye all\r\n");
+
+        assert_eq!(message.as_part(),
+                   Some(Part {
+                       user: &UserInfo::of_nickname_user_host("nick", "user", "host"),
+                       channel: "#channel",
+                       reason: Some("Bye all"),
+                   }));
+    }
+
+    #[test]
+    fn without_reason() {
+        let message = message(":nick!user@host PART #channel\r\n");
+        let part = message.as_part().unwrap();
+
+        assert_eq!(part.channel, "#channel");
+        assert_eq!(part.reason, None);
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(Message::part("#channel", None), message("PART #channel\r\n"));
+        assert_eq!(Message::part("#channel", Some("Bye all")),
+                   message("PART #channel :Bye all\r\n"));
+    }
+
+    #[test]
+    fn bad_no_prefix() {
+        let message = message("PART #channel\r\n");
+        assert_eq!(message.as_part(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
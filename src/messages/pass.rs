@@ -0,0 +1,28 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+impl Message {
+    /// Creates a `PASS` message, which has to be sent before `NICK` and `USER` to have any effect.
+    pub fn pass(password: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::PASS(), vec![password])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn pass() {
+        assert_eq!(Message::pass("oauth:token"), message("PASS oauth:token\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -1,15 +1,21 @@
 use command::commands;
+use message::Arguments;
 use message::Message;
 use message::Prefix;
 
 /// Represents a received PING message.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Ping<'a> {
-    pub arguments: &'a Vec<String>,
+    pub arguments: &'a Arguments,
 }
 
 impl Message {
-    pub fn as_ping(&self) -> Option<Ping> {
+    /// Creates a PING message. The server answers with a PONG whose last argument is `token`.
+    pub fn ping(token: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::PING(), vec![token])
+    }
+
+    pub fn as_ping(&self) -> Option<Ping<'_>> {
         if self.command != commands::PING() {
             return None;
         }
@@ -12,7 +12,7 @@ pub struct Privmsg<'a> {
 }
 
 impl Message {
-    pub fn as_privmsg(&self) -> Option<Privmsg> {
+    pub fn as_privmsg(&self) -> Option<Privmsg<'_>> {
         if self.command != commands::PRIVMSG() {
             return None;
         }
@@ -40,6 +40,50 @@ impl Message {
     pub fn privmsg(to: &str, text: &str) -> Message {
         Message::from_strs(Prefix::None, commands::PRIVMSG(), vec![to, text])
     }
+
+    /// Builds as many PRIVMSGs as it takes to send `text` without any line being longer than
+    /// `max_len` bytes (including the CRLF). Text is split between words where possible, and never
+    /// in the middle of a UTF-8 character. Line breaks in `text` (CR, LF or both) always start a
+    /// new message, blank lines are skipped, and NULs are dropped, so nothing in `text` can end a
+    /// line early.
+    ///
+    /// The server adds our prefix before passing messages on, so to be sure they arrive intact
+    /// `max_len` should be `MAX_LINE_LENGTH` minus the length of `:nick!user@host `.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `max_len` doesn't leave room for at least one character of text.
+    pub fn privmsg_split(to: &str, text: &str, max_len: usize) -> Vec<Message> {
+        // "PRIVMSG <to> :<text>\r\n"
+        let overhead = "PRIVMSG ".len() + to.len() + " :".len() + "\r\n".len();
+        assert!(max_len >= overhead + 4,
+                "max_len of {} is too short to send any text to {}",
+                max_len,
+                to);
+        let available = max_len - overhead;
+
+        let text = text.replace('\0', "");
+        let mut messages = Vec::new();
+        for line in text.split(['\r', '\n']).filter(|line| !line.is_empty()) {
+            let mut remaining = line;
+            while remaining.len() > available {
+                let mut end = available;
+                while !remaining.is_char_boundary(end) {
+                    end -= 1;
+                }
+                let (chunk, rest) = match remaining[..end].rfind(' ') {
+                    Some(space) if space > 0 => (&remaining[..space], &remaining[space + 1..]),
+                    _ => (&remaining[..end], &remaining[end..]),
+                };
+                messages.push(Message::privmsg(to, chunk));
+                remaining = rest;
+            }
+            if !remaining.is_empty() {
+                messages.push(Message::privmsg(to, remaining));
+            }
+        }
+        messages
+    }
 }
 
 #[cfg(test)]
@@ -92,6 +136,46 @@ mod tests {
         assert_eq!(message.as_privmsg(), None);
     }
 
+    #[test]
+    fn split_short_text() {
+        assert_eq!(Message::privmsg_split("#chan", "Hello", 512),
+                   vec![Message::privmsg("#chan", "Hello")]);
+    }
+
+    #[test]
+    fn split_on_words() {
+        // 17 bytes of overhead leaves 11 for text.
+        let messages = Message::privmsg_split("#chan", "The quick brown fox jumps", 28);
+
+        assert_eq!(messages,
+                   vec![Message::privmsg("#chan", "The quick"),
+                        Message::privmsg("#chan", "brown fox"),
+                        Message::privmsg("#chan", "jumps")]);
+        assert!(messages.iter().all(|m| m.line_len() <= 28));
+    }
+
+    #[test]
+    fn split_long_word_on_char_boundary() {
+        let messages = Message::privmsg_split("#chan", "ééééééé", 28);
+
+        assert_eq!(messages,
+                   vec![Message::privmsg("#chan", "ééééé"), Message::privmsg("#chan", "éé")]);
+    }
+
+    #[test]
+    fn split_on_line_breaks() {
+        assert_eq!(Message::privmsg_split("#chan", "one\r\n\ntwo", 512),
+                   vec![Message::privmsg("#chan", "one"), Message::privmsg("#chan", "two")]);
+        assert_eq!(Message::privmsg_split("#chan", "hi\rQUIT :bye\0", 512),
+                   vec![Message::privmsg("#chan", "hi"), Message::privmsg("#chan", "QUIT :bye")]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_max_len_too_short() {
+        Message::privmsg_split("#chan", "Hello", 20);
+    }
+
     fn message(message: &str) -> Message {
         let parsed = Message::parse(message.as_bytes());
         match parsed {
@@ -0,0 +1,77 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+impl Message {
+    /// Creates a `WHOIS` message, looking up `nick`. Collect the reply with `WhoisCollector`.
+    pub fn whois(nick: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::WHOIS(), vec![nick])
+    }
+
+    /// Creates a `WHOWAS` message, looking up who recently used `nick`, optionally limited to the
+    /// `count` most recent.
+    pub fn whowas(nick: &str, count: Option<u32>) -> Message {
+        let mut arguments = vec![nick.to_string()];
+        arguments.extend(count.map(|count| count.to_string()));
+        Message::new(Prefix::None, commands::WHOWAS(), arguments)
+    }
+
+    /// Creates a `WHO` message, listing users matching `mask` (often a channel). Collect the
+    /// reply with `WhoCollector`, or use `WhoxQuery` to choose the fields.
+    pub fn who(mask: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::WHO(), vec![mask])
+    }
+
+    /// Creates a `NAMES` message, listing the members of `channels`. Collect the reply with
+    /// `NamesCollector`.
+    pub fn names(channels: &[&str]) -> Message {
+        Message::from_strs(Prefix::None, commands::NAMES(), vec![&channels.join(",")])
+    }
+
+    /// Creates a `LIST` message, optionally with a filter such as a channel mask. `ListQuery`
+    /// builds the more complicated filters.
+    pub fn list(filter: Option<&str>) -> Message {
+        Message::from_strs(Prefix::None, commands::LIST(), filter.into_iter().collect())
+    }
+
+    /// Creates an `ISON` message, asking which of `nicks` are online.
+    pub fn ison(nicks: &[&str]) -> Message {
+        Message::from_strs(Prefix::None, commands::ISON(), nicks.to_vec())
+    }
+
+    /// Creates a `USERHOST` message, asking for the hosts of `nicks`. Servers only answer for the
+    /// first five.
+    pub fn userhost(nicks: &[&str]) -> Message {
+        Message::from_strs(Prefix::None, commands::USERHOST(), nicks.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn single_targets() {
+        assert_eq!(Message::whois("nick"), message("WHOIS nick\r\n"));
+        assert_eq!(Message::who("#chan"), message("WHO #chan\r\n"));
+        assert_eq!(Message::whowas("nick", None), message("WHOWAS nick\r\n"));
+        assert_eq!(Message::whowas("nick", Some(3)), message("WHOWAS nick 3\r\n"));
+        assert_eq!(Message::list(None), message("LIST\r\n"));
+        assert_eq!(Message::list(Some("#rust*")), message("LIST #rust*\r\n"));
+    }
+
+    #[test]
+    fn multiple_targets() {
+        assert_eq!(Message::names(&["#one", "#two"]), message("NAMES #one,#two\r\n"));
+        assert_eq!(Message::ison(&["alice", "bob"]), message("ISON alice bob\r\n"));
+        assert_eq!(Message::userhost(&["alice", "bob"]), message("USERHOST alice bob\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+use message::UserInfo;
+
+/// Simple accessor for a received QUIT message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quit<'a> {
+    pub user: &'a UserInfo,
+    pub reason: Option<&'a str>,
+}
+
+impl Message {
+    pub fn as_quit(&self) -> Option<Quit<'_>> {
+        if self.command != commands::QUIT() {
+            return None;
+        }
+        if self.arguments.len() > 1 {
+            warn!("Not parsing message as Quit because we expect at most 1 argument: {}",
+                  self);
+            return None;
+        }
+        let user = match self.prefix {
+            Prefix::User(ref u) => u,
+            _ => {
+                warn!("Not parsing message as Quit because we expect prefix of user: {}",
+                      self);
+                return None;
+            }
+        };
+
+        Some(Quit {
+            user: user,
+            reason: self.arguments.first().map(|r| r.as_str()).filter(|r| !r.is_empty()),
+        })
+    }
+
+    /// Creates a `QUIT` message. The server closes the connection after receiving it.
+    pub fn quit(reason: Option<&str>) -> Message {
+        Message::from_strs(Prefix::None, commands::QUIT(), reason.into_iter().collect())
+    }
+}
+
+impl<'a> Quit<'a> {
+    /// If the reason looks like the user was lost in a netsplit, the two servers which split
+    /// apart. Users can't normally choose a reason like this, but it's only a hint: some servers
+    /// hide their names (e.g. `*.net *.split`), and not every server stops users faking it.
+    pub fn netsplit(&self) -> Option<(&'a str, &'a str)> {
+        let (first, second) = self.reason?.split_once(' ')?;
+        if is_server_name(first) && is_server_name(second) {
+            Some((first, second))
+        } else {
+            None
+        }
+    }
+}
+
+fn is_server_name(name: &str) -> bool {
+    name.contains('.') && !name.starts_with('.') && !name.ends_with('.') &&
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn with_reason() {
+        let message = message(":nick!user@host QUIT :Quit: Leaving\r\n");
+
+        assert_eq!(message.as_quit(),
+                   Some(Quit {
+                       user: &UserInfo::of_nickname_user_host("nick", "user", "host"),
+                       reason: Some("Quit: Leaving"),
+                   }));
+        assert_eq!(message.as_quit().unwrap().netsplit(), None);
+    }
+
+    #[test]
+    fn without_reason() {
+        let message = message(":nick!user@host QUIT\r\n");
+        assert_eq!(message.as_quit().unwrap().reason, None);
+    }
+
+    #[test]
+    fn netsplit() {
+        let split = message(":nick!user@host QUIT :irc.one.net irc-2.two.org\r\n");
+        assert_eq!(split.as_quit().unwrap().netsplit(),
+                   Some(("irc.one.net", "irc-2.two.org")));
+
+        let hidden = message(":nick!user@host QUIT :*.net *.split\r\n");
+        assert_eq!(hidden.as_quit().unwrap().netsplit(), Some(("*.net", "*.split")));
+
+        for reason in &["see you.", "one.two three.four five.six", "a.b c.", "a.b :)"] {
+            let quit = Message::from_strs(Prefix::User(UserInfo::of_nickname("nick")),
+                                          commands::QUIT(),
+                                          vec![reason]);
+            assert_eq!(quit.as_quit().unwrap().netsplit(), None);
+        }
+    }
+
+    #[test]
+    fn bad_no_prefix() {
+        let message = message("QUIT :Bye\r\n");
+        assert_eq!(message.as_quit(), None);
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(Message::quit(None), message("QUIT\r\n"));
+        assert_eq!(Message::quit(Some("Gone fishing")), message("QUIT :Gone fishing\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
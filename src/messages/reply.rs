@@ -0,0 +1,70 @@
+use message::Message;
+use message::Tag;
+
+impl Message {
+    /// The server-assigned ID of this message, from the `msgid` tag.
+    pub fn msgid(&self) -> Option<&str> {
+        self.tag_value("msgid")
+    }
+
+    /// The ID of the message this one is replying to, from the `+draft/reply` client tag.
+    pub fn reply_to(&self) -> Option<&str> {
+        self.tag_value("+draft/reply")
+    }
+
+    /// The reaction this message carries, from the `+draft/react` client tag.
+    pub fn reaction(&self) -> Option<&str> {
+        self.tag_value("+draft/react")
+    }
+
+    /// Creates a PRIVMSG replying to the message with ID `in_reply_to`, so clients which support
+    /// it can show the reply threaded.
+    pub fn privmsg_reply(to: &str, text: &str, in_reply_to: &str) -> Message {
+        Message::privmsg(to, text).with_tags(vec![Tag::new("+draft/reply", Some(in_reply_to))])
+    }
+
+    /// Creates a TAGMSG reacting to the message with ID `in_reply_to`. The reaction is usually an
+    /// emoji.
+    pub fn react(to: &str, in_reply_to: &str, reaction: &str) -> Message {
+        Message::tagmsg(to).with_tags(vec![Tag::new("+draft/reply", Some(in_reply_to)),
+                                           Tag::new("+draft/react", Some(reaction))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn msgid() {
+        let message = message("@msgid=abc123 :nick!user@host PRIVMSG #chan :Hello\r\n");
+        assert_eq!(message.msgid(), Some("abc123"));
+    }
+
+    #[test]
+    fn reply() {
+        let reply = Message::privmsg_reply("#chan", "Hi yourself", "abc123");
+
+        assert_eq!(format!("{}", reply),
+                   "@+draft/reply=abc123 PRIVMSG #chan :Hi yourself");
+        assert_eq!(reply.reply_to(), Some("abc123"));
+    }
+
+    #[test]
+    fn react() {
+        let react = Message::react("#chan", "abc123", "👍");
+
+        assert_eq!(format!("{}", react),
+                   "@+draft/reply=abc123;+draft/react=👍 TAGMSG #chan");
+        assert_eq!(react.reaction(), Some("👍"));
+        assert_eq!(react.as_tagmsg(), Some("#chan"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
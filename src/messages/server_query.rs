@@ -0,0 +1,97 @@
+use command::Command;
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+// Informational queries about a server. Each asks the server we're connected to, unless given a
+// `server` elsewhere on the network to ask instead, which can be a mask like `*.example.com`.
+impl Message {
+    pub fn version(server: Option<&str>) -> Message {
+        Message::server_query(commands::VERSION(), server)
+    }
+
+    pub fn time(server: Option<&str>) -> Message {
+        Message::server_query(commands::TIME(), server)
+    }
+
+    pub fn admin(server: Option<&str>) -> Message {
+        Message::server_query(commands::ADMIN(), server)
+    }
+
+    pub fn info(server: Option<&str>) -> Message {
+        Message::server_query(commands::INFO(), server)
+    }
+
+    pub fn motd(server: Option<&str>) -> Message {
+        Message::server_query(commands::MOTD(), server)
+    }
+
+    /// Asks for the network's user and server counts.
+    pub fn lusers(server: Option<&str>) -> Message {
+        // The mask before the server is ignored by most servers, but is needed to get to it.
+        Message::from_strs(Prefix::None,
+                           commands::LUSERS(),
+                           server.map(|server| vec!["*", server]).unwrap_or_default())
+    }
+
+    /// Lists the servers matching `mask`, or all of them, as `server` sees the network.
+    pub fn links(server: Option<&str>, mask: Option<&str>) -> Message {
+        let arguments = match (server, mask) {
+            (Some(server), mask) => vec![server, mask.unwrap_or("*")],
+            (None, mask) => mask.into_iter().collect(),
+        };
+        Message::from_strs(Prefix::None, commands::LINKS(), arguments)
+    }
+
+    /// Asks for statistics, with `query` saying which (e.g. `u` for uptime). Which queries are
+    /// supported, and who is allowed to make them, varies between servers.
+    pub fn stats(query: char, server: Option<&str>) -> Message {
+        let query = query.to_string();
+        let mut arguments = vec![&query[..]];
+        arguments.extend(server);
+        Message::from_strs(Prefix::None, commands::STATS(), arguments)
+    }
+
+    fn server_query(command: Command, server: Option<&str>) -> Message {
+        Message::from_strs(Prefix::None, command, server.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn local() {
+        assert_eq!(Message::version(None), message("VERSION\r\n"));
+        assert_eq!(Message::time(None), message("TIME\r\n"));
+        assert_eq!(Message::admin(None), message("ADMIN\r\n"));
+        assert_eq!(Message::info(None), message("INFO\r\n"));
+        assert_eq!(Message::motd(None), message("MOTD\r\n"));
+        assert_eq!(Message::lusers(None), message("LUSERS\r\n"));
+        assert_eq!(Message::links(None, None), message("LINKS\r\n"));
+        assert_eq!(Message::links(None, Some("*.eu")), message("LINKS *.eu\r\n"));
+        assert_eq!(Message::stats('u', None), message("STATS u\r\n"));
+    }
+
+    #[test]
+    fn remote() {
+        assert_eq!(Message::version(Some("irc.example.com")),
+                   message("VERSION irc.example.com\r\n"));
+        assert_eq!(Message::motd(Some("*.example.com")), message("MOTD *.example.com\r\n"));
+        assert_eq!(Message::lusers(Some("irc.example.com")),
+                   message("LUSERS * irc.example.com\r\n"));
+        assert_eq!(Message::links(Some("irc.example.com"), None),
+                   message("LINKS irc.example.com *\r\n"));
+        assert_eq!(Message::stats('u', Some("irc.example.com")),
+                   message("STATS u irc.example.com\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
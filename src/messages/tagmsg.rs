@@ -0,0 +1,19 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+impl Message {
+    /// Creates a TAGMSG message, which carries nothing but tags. Add the tags with `with_tags`.
+    pub fn tagmsg(to: &str) -> Message {
+        Message::from_strs(Prefix::None, commands::TAGMSG(), vec![to])
+    }
+
+    /// If this is a TAGMSG message, returns its target.
+    pub fn as_tagmsg(&self) -> Option<&str> {
+        if self.command != commands::TAGMSG() || self.arguments.len() != 1 {
+            return None;
+        }
+
+        Some(&self.arguments[0])
+    }
+}
@@ -0,0 +1,182 @@
+use command::commands;
+use command::responses;
+use message::Message;
+use message::Prefix;
+
+/// Simple accessor for a received TOPIC message, sent when someone changes a channel's topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Topic<'a> {
+    /// Who changed the topic. This is usually a user, but can be a server or services.
+    pub by: &'a Prefix,
+    pub channel: &'a str,
+    /// The new topic, or `None` if it was cleared.
+    pub topic: Option<&'a str>,
+}
+
+/// Simple accessor for the `RPL_TOPIC` (332) or `RPL_NOTOPIC` (331) sent when we join a channel
+/// or ask for its topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopicReply<'a> {
+    pub channel: &'a str,
+    /// The topic, or `None` for `RPL_NOTOPIC`.
+    pub topic: Option<&'a str>,
+}
+
+/// Simple accessor for `RPL_TOPICWHOTIME` (333), which follows `RPL_TOPIC` to say who set the
+/// topic and when.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopicWhoTime<'a> {
+    pub channel: &'a str,
+    /// Who set the topic. Depending on the server this is a nickname or a full `nick!user@host`.
+    pub set_by: &'a str,
+    /// When the topic was set, in seconds since the Unix epoch.
+    pub set_at: u64,
+}
+
+impl Message {
+    pub fn as_topic(&self) -> Option<Topic<'_>> {
+        if self.command != commands::TOPIC() {
+            return None;
+        }
+        if self.arguments.len() != 2 {
+            warn!("Not parsing message as Topic because we expect 2 arguments: {}", self);
+            return None;
+        }
+
+        Some(Topic {
+            by: &self.prefix,
+            channel: &self.arguments[0],
+            topic: Some(&self.arguments[1][..]).filter(|t| !t.is_empty()),
+        })
+    }
+
+    /// Creates a `TOPIC` message. With no `topic` this asks for the channel's topic, which the
+    /// server replies to with `RPL_TOPIC` or `RPL_NOTOPIC`; an empty `topic` clears it.
+    pub fn topic(channel: &str, topic: Option<&str>) -> Message {
+        let mut arguments = vec![channel];
+        arguments.extend(topic);
+        Message::from_strs(Prefix::None, commands::TOPIC(), arguments)
+    }
+
+    pub fn as_topic_reply(&self) -> Option<TopicReply<'_>> {
+        let has_topic = if self.command == responses::RPL_TOPIC() {
+            true
+        } else if self.command == responses::RPL_NOTOPIC() {
+            false
+        } else {
+            return None;
+        };
+        // <client> <channel> :<topic>
+        if self.arguments.len() != 3 {
+            warn!("Not parsing message as TopicReply because we expect 3 arguments: {}",
+                  self);
+            return None;
+        }
+
+        Some(TopicReply {
+            channel: &self.arguments[1],
+            topic: if has_topic { Some(&self.arguments[2]) } else { None },
+        })
+    }
+
+    pub fn as_topic_who_time(&self) -> Option<TopicWhoTime<'_>> {
+        if self.command != responses::RPL_TOPICWHOTIME() {
+            return None;
+        }
+        // <client> <channel> <nick> <setat>
+        if self.arguments.len() != 4 {
+            warn!("Not parsing message as TopicWhoTime because we expect 4 arguments: {}",
+                  self);
+            return None;
+        }
+        let set_at = match self.arguments[3].parse() {
+            Ok(set_at) => set_at,
+            Err(_) => {
+                warn!("Not parsing message as TopicWhoTime because of bad time: {}", self);
+                return None;
+            }
+        };
+
+        Some(TopicWhoTime {
+            channel: &self.arguments[1],
+            set_by: &self.arguments[2],
+            set_at: set_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::UserInfo;
+
+    #[test]
+    fn topic_change() {
+        let message = message(":nick!user@host TOPIC #channel :New topic\r\n");
+
+        assert_eq!(message.as_topic(),
+                   Some(Topic {
+                       by: &Prefix::User(UserInfo::of_nickname_user_host("nick", "user", "host")),
+                       channel: "#channel",
+                       topic: Some("New topic"),
+                   }));
+    }
+
+    #[test]
+    fn topic_cleared() {
+        let message = message(":nick!user@host TOPIC #channel :\r\n");
+        assert_eq!(message.as_topic().unwrap().topic, None);
+    }
+
+    #[test]
+    fn topic_replies() {
+        let topic = message(":irc.host 332 me #channel :The topic\r\n");
+        assert_eq!(topic.as_topic_reply(),
+                   Some(TopicReply {
+                       channel: "#channel",
+                       topic: Some("The topic"),
+                   }));
+
+        let no_topic = message(":irc.host 331 me #channel :No topic is set\r\n");
+        assert_eq!(no_topic.as_topic_reply(),
+                   Some(TopicReply {
+                       channel: "#channel",
+                       topic: None,
+                   }));
+    }
+
+    #[test]
+    fn topic_who_time() {
+        let message = message(":irc.host 333 me #channel nick!user@host 1500000000\r\n");
+
+        assert_eq!(message.as_topic_who_time(),
+                   Some(TopicWhoTime {
+                       channel: "#channel",
+                       set_by: "nick!user@host",
+                       set_at: 1500000000,
+                   }));
+    }
+
+    #[test]
+    fn bad_time() {
+        let message = message(":irc.host 333 me #channel nick yesterday\r\n");
+        assert_eq!(message.as_topic_who_time(), None);
+    }
+
+    #[test]
+    fn builder() {
+        assert_eq!(Message::topic("#channel", None), message("TOPIC #channel\r\n"));
+        assert_eq!(Message::topic("#channel", Some("New topic")),
+                   message("TOPIC #channel :New topic\r\n"));
+        assert_eq!(Message::topic("#channel", Some("")).to_bytes(), b"TOPIC #channel :\r\n");
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use message::Message;
+use message::Tag;
+
+/// A typing notification, as sent in the `+typing` client tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypingState {
+    /// The user is typing.
+    Active,
+    /// The user has typed something but stopped for now.
+    Paused,
+    /// The user has stopped typing, e.g. they cleared the input or sent the message.
+    Done,
+}
+
+impl TypingState {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TypingState::Active => "active",
+            TypingState::Paused => "paused",
+            TypingState::Done => "done",
+        }
+    }
+
+    pub fn from_tag_value(value: &str) -> Option<Self> {
+        match value {
+            "active" => Some(TypingState::Active),
+            "paused" => Some(TypingState::Paused),
+            "done" => Some(TypingState::Done),
+            _ => None,
+        }
+    }
+}
+
+impl Message {
+    /// Creates a TAGMSG telling `to` about our typing state.
+    pub fn typing(to: &str, state: TypingState) -> Message {
+        Message::tagmsg(to).with_tags(vec![Tag::new("+typing", Some(state.as_str()))])
+    }
+
+    /// The typing state this message carries, if any.
+    pub fn typing_state(&self) -> Option<TypingState> {
+        self.tag_value("+typing").and_then(TypingState::from_tag_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn builder() {
+        assert_eq!(format!("{}", Message::typing("#chan", TypingState::Active)),
+                   "@+typing=active TAGMSG #chan");
+    }
+
+    #[test]
+    fn accessor() {
+        let message = message("@+typing=paused :nick!user@host TAGMSG #chan\r\n");
+        assert_eq!(message.typing_state(), Some(TypingState::Paused));
+    }
+
+    #[test]
+    fn accessor_unknown_state() {
+        let message = message("@+typing=thinking :nick!user@host TAGMSG #chan\r\n");
+        assert_eq!(message.typing_state(), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
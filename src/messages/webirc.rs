@@ -0,0 +1,38 @@
+use command::commands;
+use message::Message;
+use message::Prefix;
+
+impl Message {
+    /// Creates a `WEBIRC` message, with which a gateway (e.g. a web client or a bridge) tells the
+    /// server the real hostname and IP address of the user it's connecting for. It has to be sent
+    /// first, before `PASS`, `NICK` and `USER`, and the server has to be configured to accept it
+    /// from the gateway with `password`.
+    pub fn webirc(password: &str, gateway: &str, hostname: &str, ip: &str) -> Message {
+        // IPv6 addresses like `::1` would be taken as the start of a trailing argument.
+        let ip = if ip.starts_with(':') { format!("0{}", ip) } else { ip.to_string() };
+        Message::from_strs(Prefix::None,
+                           commands::WEBIRC(),
+                           vec![password, gateway, hostname, &ip])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use message::Message;
+
+    #[test]
+    fn webirc() {
+        assert_eq!(Message::webirc("secret", "gateway", "host.example.com", "192.0.2.1"),
+                   message("WEBIRC secret gateway host.example.com 192.0.2.1\r\n"));
+        assert_eq!(Message::webirc("secret", "gateway", "localhost", "::1"),
+                   message("WEBIRC secret gateway localhost 0::1\r\n"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use command::commands;
+use command::responses;
+use message::Message;
+use message::Prefix;
+
+// The order fields appear in replies, whatever order they were requested in.
+const FIELD_ORDER: &str = "tcuihsnfdlaor";
+
+/// A WHOX query: an extended WHO asking for specific fields, as in `WHO #chan %cnuhar,123`. Check
+/// for the `WHOX` ISUPPORT token before using this.
+///
+/// Build the query, send `message`, then use `parse` on each RPL_WHOSPCRPL received. Giving the
+/// query a token means replies to it can be told apart from replies to other queries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WhoxQuery {
+    fields: String,
+    token: Option<String>,
+}
+
+/// One RPL_WHOSPCRPL reply to a WHOX query. Fields which weren't requested are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WhoxReply<'a> {
+    pub token: Option<&'a str>,
+    pub channel: Option<&'a str>,
+    pub user: Option<&'a str>,
+    pub ip: Option<&'a str>,
+    pub host: Option<&'a str>,
+    pub server: Option<&'a str>,
+    pub nick: Option<&'a str>,
+    pub flags: Option<&'a str>,
+    pub hopcount: Option<&'a str>,
+    pub idle: Option<&'a str>,
+    /// The user's services account. Servers send `0` for users who aren't logged in, which we
+    /// turn into `None`.
+    pub account: Option<&'a str>,
+    pub oplevel: Option<&'a str>,
+    pub realname: Option<&'a str>,
+}
+
+impl WhoxQuery {
+    /// Creates a query for the fields given as WHOX field letters, e.g. `"cnuhar"` for channel,
+    /// nick, user, host, account and realname.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `fields` contains a character which isn't a WHOX field letter.
+    pub fn new(fields: &str) -> Self {
+        for c in fields.chars() {
+            assert!(FIELD_ORDER.contains(c), "Unknown WHOX field [{}]", c);
+        }
+        WhoxQuery {
+            fields: FIELD_ORDER.chars().filter(|&c| c != 't' && fields.contains(c)).collect(),
+            token: None,
+        }
+    }
+
+    /// Sets the token sent with the query and echoed in each reply.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `token` isn't a number of 1 to 3 digits, as servers require.
+    pub fn with_token(mut self, token: &str) -> Self {
+        assert!(!token.is_empty() && token.len() <= 3 && token.bytes().all(|b| b.is_ascii_digit()),
+                "WHOX tokens must be 1 to 3 digits but got [{}]",
+                token);
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Creates the WHO message querying everyone matching `mask`.
+    pub fn message(&self, mask: &str) -> Message {
+        let selector = match self.token {
+            Some(ref token) => format!("%t{},{}", self.fields, token),
+            None => format!("%{}", self.fields),
+        };
+        Message::new(Prefix::None,
+                     commands::WHO(),
+                     vec![mask.to_string(), selector])
+    }
+
+    /// Parses a reply to this query. Returns `None` if the message isn't an RPL_WHOSPCRPL, or is a
+    /// reply to a query with a different token or set of fields.
+    pub fn parse<'a>(&self, message: &'a Message) -> Option<WhoxReply<'a>> {
+        if message.command != responses::RPL_WHOSPCRPL() || message.arguments.is_empty() {
+            return None;
+        }
+
+        let mut fields = String::new();
+        if self.token.is_some() {
+            fields.push('t');
+        }
+        fields.push_str(&self.fields);
+
+        // The first argument is our own nickname.
+        let values = &message.arguments[1..];
+        if values.len() != fields.len() {
+            return None;
+        }
+
+        let mut reply = WhoxReply::default();
+        for (field, value) in fields.chars().zip(values) {
+            let value = Some(value.as_str());
+            match field {
+                't' => reply.token = value,
+                'c' => reply.channel = value,
+                'u' => reply.user = value,
+                'i' => reply.ip = value,
+                'h' => reply.host = value,
+                's' => reply.server = value,
+                'n' => reply.nick = value,
+                'f' => reply.flags = value,
+                'd' => reply.hopcount = value,
+                'l' => reply.idle = value,
+                'a' => reply.account = value.filter(|a| *a != "0"),
+                'o' => reply.oplevel = value,
+                'r' => reply.realname = value,
+                _ => unreachable!(),
+            }
+        }
+
+        if reply.token != self.token.as_deref() {
+            return None;
+        }
+        Some(reply)
+    }
+}
+
+impl<'a> WhoxReply<'a> {
+    /// Whether the user is away, from the `G` (gone) or `H` (here) in their flags. `None` if flags
+    /// weren't requested.
+    pub fn is_away(&self) -> Option<bool> {
+        self.flags.map(|flags| flags.contains('G'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn message_with_token() {
+        let query = WhoxQuery::new("ranhuc").with_token("123");
+        assert_eq!(format!("{}", query.message("#chan")), "WHO #chan %tcuhnar,123");
+    }
+
+    #[test]
+    fn message_without_token() {
+        let query = WhoxQuery::new("na");
+        assert_eq!(format!("{}", query.message("#chan")), "WHO #chan %na");
+    }
+
+    #[test]
+    #[should_panic]
+    fn bad_field() {
+        WhoxQuery::new("nz");
+    }
+
+    #[test]
+    #[should_panic]
+    fn bad_token() {
+        WhoxQuery::new("n").with_token("1234");
+    }
+
+    #[test]
+    fn parse() {
+        let query = WhoxQuery::new("cnufa").with_token("42");
+        let message = message(":irc.host 354 me 42 #chan ~a alice G@ alice_acct\r\n");
+
+        assert_eq!(query.parse(&message),
+                   Some(WhoxReply {
+                       token: Some("42"),
+                       channel: Some("#chan"),
+                       user: Some("~a"),
+                       nick: Some("alice"),
+                       flags: Some("G@"),
+                       account: Some("alice_acct"),
+                       ..WhoxReply::default()
+                   }));
+        assert_eq!(query.parse(&message).unwrap().is_away(), Some(true));
+    }
+
+    #[test]
+    fn parse_not_logged_in() {
+        let query = WhoxQuery::new("na");
+        let message = message(":irc.host 354 me bob 0\r\n");
+
+        assert_eq!(query.parse(&message).unwrap().account, None);
+    }
+
+    #[test]
+    fn parse_other_token() {
+        let query = WhoxQuery::new("na").with_token("1");
+        let message = message(":irc.host 354 me 2 bob 0\r\n");
+
+        assert_eq!(query.parse(&message), None);
+    }
+
+    #[test]
+    fn parse_wrong_field_count() {
+        let query = WhoxQuery::new("nar");
+        let message = message(":irc.host 354 me bob 0\r\n");
+
+        assert_eq!(query.parse(&message), None);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::sync::Arc;
+use std::time::Duration;
+
+use command::Command;
+
+/// Receives measurements of a connection as they happen, for exporting to Prometheus, StatsD or
+/// whatever else a long-running bot reports to. Every method does nothing by default, so only
+/// implement the ones you're interested in.
+///
+/// Give one to `IrcStream::with_metrics` (or `IrcCodec::with_metrics`) for message and byte
+/// counts and the send queue's depth, `LagMonitor::with_metrics` for lag, and
+/// `ReconnectingIrcStream::with_metrics` for reconnections. The same sink can be shared between
+/// all of them, and between connections; the methods take `&self`, so use atomics or a lock for
+/// anything they keep.
+pub trait MetricsSink: Send + Sync {
+    /// A message was sent. `IrcStream` reports this once the whole line has been written, and
+    /// `IrcCodec` once it's been encoded.
+    fn message_sent(&self, _command: &Command) {}
+
+    /// A message was received. Lines which couldn't be parsed aren't counted here, but their
+    /// bytes are.
+    fn message_received(&self, _command: &Command) {}
+
+    /// Bytes were sent, including line endings.
+    fn bytes_sent(&self, _bytes: usize) {}
+
+    /// Bytes were received, including line endings.
+    fn bytes_received(&self, _bytes: usize) {}
+
+    /// The number of messages waiting in the send queue changed.
+    fn queue_depth(&self, _depth: usize) {}
+
+    /// The lag was measured, or the PING measuring it has been waiting this long.
+    fn lag(&self, _lag: Duration) {}
+
+    /// A new connection was made after the last one was lost.
+    fn reconnected(&self) {}
+}
+
+// The sink something reports to, if it has one. Two are equal if they report to the same sink,
+// so the types holding one can still be compared.
+#[derive(Clone, Default)]
+pub(crate) struct Metrics(Option<Arc<dyn MetricsSink>>);
+
+impl Metrics {
+    pub(crate) fn new(sink: Arc<dyn MetricsSink>) -> Self {
+        Metrics(Some(sink))
+    }
+}
+
+impl Debug for Metrics {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(fmt, "Metrics(Some(..))"),
+            None => write!(fmt, "Metrics(None)"),
+        }
+    }
+}
+
+impl PartialEq for Metrics {
+    fn eq(&self, other: &Metrics) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Metrics {}
+
+impl MetricsSink for Metrics {
+    fn message_sent(&self, command: &Command) {
+        if let Some(ref sink) = self.0 {
+            sink.message_sent(command);
+        }
+    }
+
+    fn message_received(&self, command: &Command) {
+        if let Some(ref sink) = self.0 {
+            sink.message_received(command);
+        }
+    }
+
+    fn bytes_sent(&self, bytes: usize) {
+        if let Some(ref sink) = self.0 {
+            sink.bytes_sent(bytes);
+        }
+    }
+
+    fn bytes_received(&self, bytes: usize) {
+        if let Some(ref sink) = self.0 {
+            sink.bytes_received(bytes);
+        }
+    }
+
+    fn queue_depth(&self, depth: usize) {
+        if let Some(ref sink) = self.0 {
+            sink.queue_depth(depth);
+        }
+    }
+
+    fn lag(&self, lag: Duration) {
+        if let Some(ref sink) = self.0 {
+            sink.lag(lag);
+        }
+    }
+
+    fn reconnected(&self) {
+        if let Some(ref sink) = self.0 {
+            sink.reconnected();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::Mutex;
+    use std::time::Instant;
+    use irc_stream::IrcStream;
+    use irc_stream::ParsePolicy;
+    use irc_stream::ScriptedStream;
+    use irc_stream::StallingWrites;
+    use lag::LagMonitor;
+    use message::Message;
+    use reconnect::ReconnectingIrcStream;
+
+    // Writes down everything reported to it.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<String> {
+            self.0.lock().unwrap().drain(..).collect()
+        }
+
+        fn record(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    impl MetricsSink for Recorder {
+        fn message_sent(&self, command: &Command) {
+            self.record(format!("sent {}", command));
+        }
+
+        fn message_received(&self, command: &Command) {
+            self.record(format!("received {}", command));
+        }
+
+        fn bytes_sent(&self, bytes: usize) {
+            self.record(format!("{} bytes out", bytes));
+        }
+
+        fn bytes_received(&self, bytes: usize) {
+            self.record(format!("{} bytes in", bytes));
+        }
+
+        fn queue_depth(&self, depth: usize) {
+            self.record(format!("queue {}", depth));
+        }
+
+        fn lag(&self, lag: Duration) {
+            self.record(format!("lag {}ms", lag.as_millis()));
+        }
+
+        fn reconnected(&self) {
+            self.record("reconnected".into());
+        }
+    }
+
+    #[test]
+    fn stream_reports_messages_bytes_and_queue() {
+        let recorder = Arc::new(Recorder::default());
+        let input = b"PING :abc\r\n!!!\r\n:irc.example.net 001 me :Welcome\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input))
+            .with_auto_pong()
+            .with_parse_policy(ParsePolicy::SkipWithWarning)
+            .with_metrics(recorder.clone());
+
+        irc.queue(&message("PRIVMSG #chan :One\r\n")).unwrap();
+        irc.queue(&message("PRIVMSG #chan :Two\r\n")).unwrap();
+        assert_eq!(recorder.take(), vec!["queue 1", "queue 2"]);
+
+        irc.send_queued().unwrap();
+        assert_eq!(recorder.take(),
+                   vec!["queue 1", "sent PRIVMSG", "19 bytes out",
+                        "queue 0", "sent PRIVMSG", "19 bytes out"]);
+
+        irc.next_message().unwrap();
+        assert_eq!(recorder.take(),
+                   vec!["11 bytes in", "received PING", "sent PONG", "10 bytes out",
+                        "5 bytes in", "34 bytes in", "received 001"]);
+    }
+
+    #[test]
+    fn stream_reports_sent_once_written() {
+        let recorder = Arc::new(Recorder::default());
+        let mut irc = IrcStream::new(StallingWrites::new(vec![Some(20), None]))
+            .with_buffered_writes()
+            .with_metrics(recorder.clone());
+
+        irc.send(&message("PRIVMSG #chan One\r\n")).unwrap();
+        irc.send(&message("PRIVMSG #chan Two\r\n")).unwrap();
+        assert_eq!(recorder.take(), Vec::<String>::new());
+
+        // Only the first line was written before the write failed.
+        assert!(irc.flush().is_err());
+        assert_eq!(recorder.take(), vec!["sent PRIVMSG", "19 bytes out"]);
+        irc.flush().unwrap();
+        assert_eq!(recorder.take(), vec!["sent PRIVMSG", "19 bytes out"]);
+    }
+
+    #[test]
+    fn lag_monitor_reports_lag() {
+        let recorder = Arc::new(Recorder::default());
+        let mut lag = LagMonitor::new()
+            .with_threshold(Duration::from_secs(10))
+            .with_metrics(recorder.clone());
+        let start = Instant::now();
+
+        let ping = lag.poll_at(start).0.unwrap();
+        assert_eq!(recorder.take(), Vec::<String>::new());
+        lag.poll_at(start + Duration::from_secs(5));
+        lag.poll_at(start + Duration::from_secs(11));
+        lag.poll_at(start + Duration::from_secs(12));
+        let pong = message(&format!("PONG irc.example.net {}\r\n", ping.arguments[0]));
+        lag.handle_at(&pong, start + Duration::from_secs(13));
+
+        assert_eq!(recorder.take(),
+                   vec!["lag 5000ms", "lag 11000ms", "lag 12000ms", "lag 13000ms"]);
+    }
+
+    #[test]
+    fn reconnections_are_counted() {
+        let recorder = Arc::new(Recorder::default());
+        let mut irc = ReconnectingIrcStream::new(|| -> io::Result<_> {
+                Ok(IrcStream::new(ScriptedStream::new(b"")))
+            })
+            .with_metrics(recorder.clone());
+
+        irc.next_event();
+        assert!(recorder.take().is_empty());
+        irc.next_event();
+        irc.next_event();
+        irc.next_event();
+        assert_eq!(recorder.take(), vec!["reconnected"]);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use casemapping::IrcCase;
+use casemapping::IrcString;
+use command::responses;
+use message::Message;
+
+/// A change in the online status of a monitored nickname.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorEvent {
+    Online(String),
+    Offline(String),
+}
+
+/// Manages a MONITOR list, turning the server's replies into online/offline events for the
+/// nicknames being watched.
+///
+/// Use `add` and `remove` to build the messages to send, and pass every received message to
+/// `handle`.
+#[derive(Debug, Clone, Default)]
+pub struct MonitorList {
+    case: IrcCase,
+    // Whether each nickname is online, or `None` if we don't know yet.
+    watched: HashMap<IrcString, Option<bool>>,
+}
+
+impl MonitorList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `CASEMAPPING` the server advertised, which `MONITOR` replies are matched to
+    /// watched nicknames by. Nicknames already being watched are kept. Defaults to `rfc1459`.
+    pub fn set_casemapping(&mut self, case: IrcCase) {
+        self.case = case;
+        self.watched =
+            self.watched.drain().map(|(nick, online)| (case.key(nick.as_str()), online)).collect();
+    }
+
+    /// Starts watching nicknames, returning the message to send if any weren't already watched.
+    pub fn add(&mut self, nicks: &[&str]) -> Option<Message> {
+        let new: Vec<&str> = nicks.iter()
+            .cloned()
+            .filter(|nick| !self.watched.contains_key(&self.case.key(nick)))
+            .collect();
+        for nick in &new {
+            self.watched.insert(self.case.key(nick), None);
+        }
+
+        if new.is_empty() {
+            None
+        } else {
+            Some(Message::monitor_add(&new))
+        }
+    }
+
+    /// Stops watching nicknames, returning the message to send if any were being watched.
+    pub fn remove(&mut self, nicks: &[&str]) -> Option<Message> {
+        let removed: Vec<&str> = nicks.iter()
+            .cloned()
+            .filter(|nick| self.watched.remove(&self.case.key(nick)).is_some())
+            .collect();
+
+        if removed.is_empty() {
+            None
+        } else {
+            Some(Message::monitor_remove(&removed))
+        }
+    }
+
+    pub fn is_watched(&self, nick: &str) -> bool {
+        self.watched.contains_key(&self.case.key(nick))
+    }
+
+    /// Whether a watched nickname is online, or `None` if it isn't watched or the server hasn't
+    /// told us yet.
+    pub fn is_online(&self, nick: &str) -> Option<bool> {
+        self.watched.get(&self.case.key(nick)).and_then(|&online| online)
+    }
+
+    /// Updates from a received message, returning events for any watched nicknames whose status
+    /// has changed.
+    pub fn handle(&mut self, message: &Message) -> Vec<MonitorEvent> {
+        if message.command == responses::ERR_MONLISTFULL() && message.arguments.len() >= 3 {
+            warn!("Monitor list is full, not watching: {}", message.arguments[2]);
+            for nick in message.arguments[2].split(',') {
+                self.watched.remove(&self.case.key(nick));
+            }
+            return Vec::new();
+        }
+
+        let status = match message.as_monitor_status() {
+            Some(status) => status,
+            None => return Vec::new(),
+        };
+
+        let mut events = Vec::new();
+        for nick in status.nicks() {
+            if let Some(online) = self.watched.get_mut(&self.case.key(nick)) {
+                if *online != Some(status.online) {
+                    *online = Some(status.online);
+                    events.push(if status.online {
+                        MonitorEvent::Online(nick.into())
+                    } else {
+                        MonitorEvent::Offline(nick.into())
+                    });
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn add_only_new() {
+        let mut list = MonitorList::new();
+
+        assert_eq!(list.add(&["alice", "bob"]),
+                   Some(Message::monitor_add(&["alice", "bob"])));
+        assert_eq!(list.add(&["Alice", "carol"]),
+                   Some(Message::monitor_add(&["carol"])));
+        assert_eq!(list.add(&["bob"]), None);
+    }
+
+    #[test]
+    fn remove_only_watched() {
+        let mut list = MonitorList::new();
+        list.add(&["alice"]);
+
+        assert_eq!(list.remove(&["alice", "bob"]),
+                   Some(Message::monitor_remove(&["alice"])));
+        assert_eq!(list.remove(&["alice"]), None);
+        assert!(!list.is_watched("alice"));
+    }
+
+    #[test]
+    fn events_on_change() {
+        let mut list = MonitorList::new();
+        list.add(&["alice", "bob"]);
+
+        assert_eq!(list.handle(&message(":irc.host 730 me :alice!a@host,carol!c@host\r\n")),
+                   vec![MonitorEvent::Online("alice".into())]);
+        assert_eq!(list.is_online("alice"), Some(true));
+        assert_eq!(list.is_online("bob"), None);
+
+        assert_eq!(list.handle(&message(":irc.host 730 me :alice!a@host\r\n")),
+                   vec![]);
+        assert_eq!(list.handle(&message(":irc.host 731 me :alice,bob\r\n")),
+                   vec![MonitorEvent::Offline("alice".into()),
+                        MonitorEvent::Offline("bob".into())]);
+    }
+
+    #[test]
+    fn list_full() {
+        let mut list = MonitorList::new();
+        list.add(&["alice", "bob"]);
+
+        list.handle(&message(":irc.host 734 me 1 bob :Monitor list is full.\r\n"));
+
+        assert!(list.is_watched("alice"));
+        assert!(!list.is_watched("bob"));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use command::responses;
+use membership::Membership;
+use membership::MembershipPrefixes;
+use message::Message;
+
+/// Whether a channel is public, private or secret, as shown in NAMES replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelVisibility {
+    /// `=`
+    Public,
+    /// `*`
+    Private,
+    /// `@`
+    Secret,
+}
+
+/// A channel member, from a NAMES reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMember {
+    pub nick: String,
+    /// The member's ranks in the channel, most powerful first.
+    pub ranks: Vec<Membership>,
+}
+
+/// The full member list of a channel, put together from all the RPL_NAMREPLY (353) lines the
+/// server sent for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelNames {
+    pub channel: String,
+    /// `None` if there were no RPL_NAMREPLY lines, e.g. because the channel doesn't exist or we
+    /// can't see who's in it.
+    pub visibility: Option<ChannelVisibility>,
+    pub members: Vec<ChannelMember>,
+}
+
+/// Collects RPL_NAMREPLY lines until the RPL_ENDOFNAMES (366) that finishes them. Servers send
+/// these when we join a channel, as well as in reply to NAMES.
+///
+/// Pass every received message to `handle`; it returns each channel's member list once it's
+/// complete.
+#[derive(Debug, Clone, Default)]
+pub struct NamesCollector {
+    // Keyed by channel name exactly as the server gave it, which it keeps the same until 366.
+    pending: HashMap<String, ChannelNames>,
+}
+
+impl ChannelVisibility {
+    pub fn parse(symbol: &str) -> Option<Self> {
+        match symbol {
+            "=" => Some(ChannelVisibility::Public),
+            "*" => Some(ChannelVisibility::Private),
+            "@" => Some(ChannelVisibility::Secret),
+            _ => None,
+        }
+    }
+}
+
+impl ChannelNames {
+    fn new(channel: &str) -> Self {
+        ChannelNames {
+            channel: channel.into(),
+            visibility: None,
+            members: Vec::new(),
+        }
+    }
+
+    /// Looks up a member by nickname.
+    pub fn member(&self, nick: &str) -> Option<&ChannelMember> {
+        self.members.iter().find(|member| member.nick == nick)
+    }
+}
+
+impl NamesCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The channel `message` lists members of, if it's part of a NAMES reply.
+    pub fn channel(message: &Message) -> Option<&str> {
+        if let Some(reply) = message.as_names_reply() {
+            Some(reply.channel)
+        } else if message.command == responses::RPL_ENDOFNAMES() && message.arguments.len() >= 2 {
+            Some(&message.arguments[1])
+        } else {
+            None
+        }
+    }
+
+    /// Updates from a received message, decoding nicknames with the server's membership
+    /// `prefixes`. Returns a channel's member list when the end of it arrives.
+    pub fn handle(&mut self,
+                  message: &Message,
+                  prefixes: &MembershipPrefixes)
+                  -> Option<ChannelNames> {
+        if let Some(reply) = message.as_names_reply() {
+            let names = self.pending
+                .entry(reply.channel.into())
+                .or_insert_with(|| ChannelNames::new(reply.channel));
+            names.visibility = ChannelVisibility::parse(reply.visibility);
+            names.members.extend(reply.entries(prefixes).into_iter().map(|entry| {
+                ChannelMember {
+                    nick: entry.nick.into(),
+                    ranks: entry.ranks,
+                }
+            }));
+            return None;
+        }
+
+        // <client> <channel> :End of /NAMES list
+        let channel = NamesCollector::channel(message)?;
+        Some(self.pending.remove(channel).unwrap_or_else(|| ChannelNames::new(channel)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use membership::Membership;
+    use membership::MembershipPrefixes;
+    use message::Message;
+
+    #[test]
+    fn collects_until_end() {
+        let mut collector = NamesCollector::new();
+        let prefixes = MembershipPrefixes::default();
+
+        for line in &[":irc.host 353 me @ #chan :@alice bob\r\n",
+                      ":irc.host 353 me = #other :carol\r\n",
+                      ":irc.host 353 me @ #chan :+dave\r\n"] {
+            assert_eq!(collector.handle(&message(line), &prefixes), None);
+        }
+
+        let end = message(":irc.host 366 me #chan :End of /NAMES list\r\n");
+        let names = collector.handle(&end, &prefixes).unwrap();
+        assert_eq!(names.channel, "#chan");
+        assert_eq!(names.visibility, Some(ChannelVisibility::Secret));
+        assert_eq!(names.members.len(), 3);
+        assert_eq!(names.member("alice").unwrap().ranks, vec![Membership::Op]);
+        assert_eq!(names.member("dave").unwrap().ranks, vec![Membership::Voice]);
+        assert_eq!(names.member("carol"), None);
+    }
+
+    #[test]
+    fn empty() {
+        let mut collector = NamesCollector::new();
+        let end = message(":irc.host 366 me #nowhere :End of /NAMES list\r\n");
+        let names = collector.handle(&end, &MembershipPrefixes::default()).unwrap();
+        assert_eq!(names,
+                   ChannelNames {
+                       channel: "#nowhere".into(),
+                       visibility: None,
+                       members: vec![],
+                   });
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs::File;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::net::TcpStream;
+use std::path::Path;
+
+use native_tls;
+use native_tls::Certificate;
+use native_tls::Identity;
+use native_tls::Protocol;
+use native_tls::TlsStream;
+
+use tls::TlsConnector;
+use tls::TlsOptions;
+use tls::TlsVersion;
+
+/// A `TlsConnector` using native-tls, i.e. SChannel on Windows, Secure Transport on macOS and
+/// OpenSSL elsewhere.
+#[derive(Debug)]
+pub struct NativeTlsConnector {
+    connector: native_tls::TlsConnector,
+    options: TlsOptions,
+}
+
+impl NativeTlsConnector {
+    pub fn new(options: &TlsOptions) -> io::Result<Self> {
+        Ok(NativeTlsConnector {
+            connector: tls_connector(options)?,
+            options: options.clone(),
+        })
+    }
+
+    /// Wraps a `native_tls::TlsConnector` you've configured yourself.
+    pub fn from_tls_connector(connector: native_tls::TlsConnector) -> Self {
+        NativeTlsConnector {
+            connector: connector,
+            options: TlsOptions::default(),
+        }
+    }
+}
+
+impl TlsConnector for NativeTlsConnector {
+    type Stream = TlsStream<TcpStream>;
+
+    fn connect(&self, server: &str, stream: TcpStream) -> io::Result<TlsStream<TcpStream>> {
+        self.connector
+            .connect(self.options.server_name(server), stream)
+            .map_err(|err| io::Error::other(err.to_string()))
+    }
+}
+
+fn tls_connector(options: &TlsOptions) -> io::Result<native_tls::TlsConnector> {
+    let mut builder = native_tls::TlsConnector::builder();
+    if let Some(ca_file) = options.ca_file() {
+        debug!("Trusting CAs from {:?}", ca_file);
+        for cert in Certificate::stack_from_pem(&read_file(ca_file)?).map_err(invalid_data)? {
+            builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(client_cert) = options.client_cert() {
+        debug!("Using client certificate {:?}", client_cert.cert_path);
+        let identity = Identity::from_pkcs8(&read_file(&client_cert.cert_path)?,
+                                            &read_file(&client_cert.key_path)?)
+            .map_err(invalid_data)?;
+        builder.identity(identity);
+    }
+    if let Some(version) = options.min_version() {
+        builder.min_protocol_version(Some(match version {
+            TlsVersion::Tls10 => Protocol::Tlsv10,
+            TlsVersion::Tls11 => Protocol::Tlsv11,
+            TlsVersion::Tls12 => Protocol::Tlsv12,
+        }));
+    }
+    if options.accepts_invalid_certs() {
+        warn!("TLS certificate verification is turned off");
+        builder.danger_accept_invalid_certs(true);
+    }
+    builder.build().map_err(io::Error::other)
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    File::open(path)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+fn invalid_data(err: native_tls::Error) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tls_connector_options() {
+        let options = TlsOptions::new()
+            .with_min_version(TlsVersion::Tls12)
+            .danger_accept_invalid_certs();
+        assert!(tls_connector(&options).is_ok());
+
+        let missing_ca = TlsOptions::new().with_ca_file("/does/not/exist.pem");
+        assert!(tls_connector(&missing_ca).is_err());
+    }
+}
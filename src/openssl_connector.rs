@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::net::TcpStream;
+
+use openssl::hash::MessageDigest;
+use openssl::ssl::SSL_OP_NO_SSLV2;
+use openssl::ssl::SSL_OP_NO_SSLV3;
+use openssl::ssl::SSL_OP_NO_TLSV1;
+use openssl::ssl::SSL_OP_NO_TLSV1_1;
+use openssl::ssl::SSL_VERIFY_NONE;
+use openssl::ssl::SslConnector;
+use openssl::ssl::SslConnectorBuilder;
+use openssl::ssl::SslMethod;
+use openssl::ssl::SslStream;
+use openssl::x509::X509;
+use openssl::x509::X509Ref;
+use openssl::x509::X509_FILETYPE_PEM;
+
+use irc_stream::CertFpHash;
+use irc_stream::ClientCert;
+use irc_stream::IrcStream;
+use tls::TlsConnector;
+use tls::TlsOptions;
+use tls::TlsVersion;
+
+/// A `TlsConnector` using OpenSSL.
+pub struct OpensslConnector {
+    connector: SslConnector,
+    options: TlsOptions,
+}
+
+impl OpensslConnector {
+    pub fn new(options: &TlsOptions) -> io::Result<Self> {
+        Ok(OpensslConnector {
+            connector: ssl_connector(options)?,
+            options: options.clone(),
+        })
+    }
+
+    /// Wraps an `SslConnector` you've configured yourself.
+    pub fn from_ssl_connector(connector: SslConnector) -> Self {
+        OpensslConnector {
+            connector: connector,
+            options: TlsOptions::default(),
+        }
+    }
+}
+
+impl TlsConnector for OpensslConnector {
+    type Stream = SslStream<TcpStream>;
+
+    fn connect(&self, server: &str, stream: TcpStream) -> io::Result<SslStream<TcpStream>> {
+        self.connector
+            .connect(self.options.server_name(server), stream)
+            .map_err(|ssl_err| io::Error::new(ErrorKind::Other, ssl_err))
+    }
+}
+
+impl IrcStream<SslStream<TcpStream>> {
+    /// The fingerprint of the client certificate we presented to the server, if there was one.
+    pub fn client_cert_fingerprint(&self, hash: CertFpHash) -> Option<io::Result<String>> {
+        self.get_ref().ssl().certificate().map(|cert| fingerprint(cert, hash))
+    }
+}
+
+impl ClientCert {
+    /// Computes the fingerprint of this certificate, as a lowercase hex string. This is what you'd
+    /// register with services (e.g. `/msg NickServ CERT ADD <fingerprint>`).
+    ///
+    /// Only available with the `openssl` feature.
+    pub fn fingerprint(&self, hash: CertFpHash) -> io::Result<String> {
+        let mut pem = Vec::new();
+        File::open(&self.cert_path)?.read_to_end(&mut pem)?;
+        let cert = X509::from_pem(&pem).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        fingerprint(&cert, hash)
+    }
+}
+
+fn ssl_connector(options: &TlsOptions) -> io::Result<SslConnector> {
+    let mut builder = SslConnectorBuilder::new(SslMethod::tls())?;
+    if let Some(ca_file) = options.ca_file() {
+        debug!("Trusting CAs from {:?}", ca_file);
+        builder.set_ca_file(ca_file)?;
+    }
+    if let Some(client_cert) = options.client_cert() {
+        debug!("Using client certificate {:?}", client_cert.cert_path);
+        builder.set_certificate_file(&client_cert.cert_path, X509_FILETYPE_PEM)?;
+        builder.set_private_key_file(&client_cert.key_path, X509_FILETYPE_PEM)?;
+        builder.check_private_key()?;
+    }
+    if let Some(version) = options.min_version() {
+        let mut disabled = SSL_OP_NO_SSLV2 | SSL_OP_NO_SSLV3;
+        if version >= TlsVersion::Tls11 {
+            disabled |= SSL_OP_NO_TLSV1;
+        }
+        if version >= TlsVersion::Tls12 {
+            disabled |= SSL_OP_NO_TLSV1_1;
+        }
+        builder.set_options(disabled);
+    }
+    if options.accepts_invalid_certs() {
+        warn!("TLS certificate verification is turned off");
+        builder.set_verify(SSL_VERIFY_NONE);
+    }
+    Ok(builder.build())
+}
+
+fn fingerprint(cert: &X509Ref, hash: CertFpHash) -> io::Result<String> {
+    let digest = match hash {
+        CertFpHash::Sha256 => MessageDigest::sha256(),
+        CertFpHash::Sha512 => MessageDigest::sha512(),
+    };
+    let bytes = cert.fingerprint(digest)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ssl_connector_options() {
+        let options = TlsOptions::new()
+            .with_min_version(TlsVersion::Tls12)
+            .danger_accept_invalid_certs();
+        assert!(ssl_connector(&options).is_ok());
+
+        let missing_ca = TlsOptions::new().with_ca_file("/does/not/exist.pem");
+        assert!(ssl_connector(&missing_ca).is_err());
+    }
+
+    #[test]
+    fn fingerprints() {
+        let cert = X509::from_pem(TEST_CERT.as_bytes()).unwrap();
+
+        assert_eq!(fingerprint(&cert, CertFpHash::Sha256).unwrap(),
+                   "9dbbea145532946ecfab250be909f0a82b6ee06fc0cdc8c64a78fe87c8a9dc74");
+        assert_eq!(fingerprint(&cert, CertFpHash::Sha512).unwrap(),
+                   "311b4fade7f797bf9dea20af462ca94f14b50ed6e9c4c4abe3ca98dd6a7ce1e5\
+                    debf390bfeb7b7dbe339f6a157b4c84c9a60c0812d37f91754af9e0f539dae62");
+    }
+
+    const TEST_CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBgjCCASegAwIBAgIUV+0jGxwj5SZXkWVmSjAyKyfjlwUwCgYIKoZIzj0EAwIw
+FTETMBEGA1UEAwwKcnVzdGlyY2JvdDAgFw0yNjEwMTYxODA5MjVaGA8yMTI2MDky
+MjE4MDkyNVowFTETMBEGA1UEAwwKcnVzdGlyY2JvdDBZMBMGByqGSM49AgEGCCqG
+SM49AwEHA0IABPYZqXj+V36hkgVhcid9vG8aNGhP7M7o+YXUL+TY45EXsFEvNTah
+j2TtTOp+SNO6MZQcZHPOeYLx1eZX8WHHS/ujUzBRMB0GA1UdDgQWBBTG4NeHywBB
+E/Usf7b7GUnDUhl8XjAfBgNVHSMEGDAWgBTG4NeHywBBE/Usf7b7GUnDUhl8XjAP
+BgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0kAMEYCIQC/h/XXA8O8qTRye4Jf
+AMgAoxbwpDntLuz8VHMh74ml+QIhAP5FagY33p4hS/uCFd0N5E8DHeQD2K5jdqze
+UDGS3roz
+-----END CERTIFICATE-----
+";
+}
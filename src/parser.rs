@@ -1,33 +1,120 @@
 use std;
-use std::borrow::Cow;
 use std::error::Error;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::string::String;
 use std::str;
 use std::str::FromStr;
-use std::str::Utf8Error;
 use std::vec::Vec;
+use nom::Err as NomErr;
 use nom::IResult;
 use nom::is_digit;
 use nom::is_alphabetic;
 use command::Command;
+use command::CommandError;
+use fast_parser;
+use message::Arguments;
 use message::Message;
 use message::Prefix;
+use message::Tag;
 use message::UserInfo;
+use message::unescape_tag_value;
+use message_ref::MessageRef;
+
+#[cfg(test)]
+use nom::ErrorKind;
 
 #[cfg(test)]
 use nom::GetInput;
 
+#[cfg(test)]
+use message_ref::PrefixRef;
+
 #[cfg(test)]
 use command::commands;
 
 #[cfg(test)]
 use command::responses;
 
+/// Which line endings to accept. The RFC says lines end with CRLF, but some servers (and plenty of
+/// test fixtures) use a bare LF, and the last line before a connection closes may not have an
+/// ending at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEndings {
+    /// Only CRLF.
+    Strict,
+    /// CRLF or LF.
+    #[default]
+    Lenient,
+    /// CRLF, LF, or the end of the input.
+    AllowEof,
+}
+
+/// The part of a line that `ParseError` found a problem with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseElement {
+    /// The `@tags` at the start.
+    Tags,
+    /// The `:prefix` saying who the message is from.
+    Prefix,
+    Command,
+    Params,
+    /// The CRLF at the end, which is either missing or followed by more input.
+    LineEnding,
+}
+
+/// Why a line couldn't be parsed: which part of it was wrong, and where.
 #[derive(Debug)]
 pub struct ParseError {
     input: Vec<u8>,
+    trailing_input: bool,
+    element: ParseElement,
+    offset: usize,
+    context: String,
+}
+
+impl ParseError {
+    fn invalid(input: &[u8]) -> Self {
+        let (element, offset, context) = diagnose(input);
+        ParseError {
+            input: input.to_vec(),
+            trailing_input: false,
+            element: element,
+            offset: offset,
+            context: context,
+        }
+    }
+
+    // For a line that parses, but not as the message it was written from.
+    pub(crate) fn changed(input: &[u8], element: ParseElement) -> Self {
+        ParseError {
+            input: input.to_vec(),
+            trailing_input: false,
+            element: element,
+            offset: 0,
+            context: "parsed differently than it was written".into(),
+        }
+    }
+
+    /// The line which couldn't be parsed.
+    pub fn input(&self) -> &[u8] {
+        &self.input
+    }
+
+    pub fn element(&self) -> ParseElement {
+        self.element
+    }
+
+    /// How far into the line the problem is, in bytes.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// What went wrong, in the parser's terms, e.g. `TakeWhile1` if a word or number was expected
+    /// but there wasn't one.
+    pub fn context(&self) -> &str {
+        &self.context
+    }
 }
 
 impl Error for ParseError {
@@ -40,40 +127,236 @@ impl Display for ParseError {
     fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
         let as_text = str::from_utf8(&self.input);
 
-        if as_text.is_ok() {
-            write!(fmt, "Failed to parse line: [{}]", as_text.unwrap())
+        if self.trailing_input {
+            write!(fmt,
+                   "Expected a single line but found more input after the first: [{}]",
+                   String::from_utf8_lossy(&self.input))
+        } else if as_text.is_ok() {
+            write!(fmt,
+                   "Failed to parse {} at byte {} ({}) of line: [{}]",
+                   self.element,
+                   self.offset,
+                   self.context,
+                   as_text.unwrap())
         } else {
             write!(fmt,
-                   "Failed to parse line and could not interpret as UTF-8, raw bytes: [{:?}]",
+                   "Failed to parse {} at byte {} ({}) of line and could not interpret as UTF-8, \
+                    raw bytes: [{:?}]",
+                   self.element,
+                   self.offset,
+                   self.context,
                    self.input)
         }
     }
 }
 
+impl Display for ParseElement {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        let name = match *self {
+            ParseElement::Tags => "tags",
+            ParseElement::Prefix => "prefix",
+            ParseElement::Command => "command",
+            ParseElement::Params => "params",
+            ParseElement::LineEnding => "line ending",
+        };
+        write!(fmt, "{}", name)
+    }
+}
+
+// Works out where a line which failed to parse went wrong, by parsing its parts one at a time.
+fn diagnose(input: &[u8]) -> (ParseElement, usize, String) {
+    let offset = |rest: &[u8]| input.len() - rest.len();
+    let mut rest = input;
+    if rest.starts_with(b"@") {
+        match tags(rest) {
+            IResult::Done(remaining, _) => rest = remaining,
+            other => return failure(ParseElement::Tags, input, rest, other),
+        }
+    }
+    if rest.starts_with(b":") {
+        match prefix(rest) {
+            IResult::Done(remaining, _) => rest = remaining,
+            other => return failure(ParseElement::Prefix, input, rest, other),
+        }
+    }
+    match command(rest) {
+        IResult::Done(remaining, _) => rest = remaining,
+        other => return failure(ParseElement::Command, input, rest, other),
+    }
+    match params(rest) {
+        IResult::Done(remaining, _) => rest = remaining,
+        other => return failure(ParseElement::Params, input, rest, other),
+    }
+    match rest.first() {
+        None | Some(&b'\r') | Some(&b'\n') => {
+            (ParseElement::LineEnding, offset(rest), "expected CRLF".into())
+        }
+        Some(&c) => (ParseElement::Params, offset(rest), format!("unexpected {:?}", c as char)),
+    }
+}
+
+fn failure<O>(element: ParseElement,
+              input: &[u8],
+              rest: &[u8],
+              result: IResult<&[u8], O>)
+              -> (ParseElement, usize, String) {
+    let (position, context) = match result {
+        IResult::Error(ref error) => innermost_error(error),
+        IResult::Incomplete(_) => (Some(&input[input.len()..]), "incomplete".into()),
+        IResult::Done(..) => (None, String::new()),
+    };
+    let position = position.unwrap_or(rest);
+    (element, input.len() - position.len(), context)
+}
+
+// nom errors can be nested; the innermost is the most specific.
+fn innermost_error<'a>(error: &NomErr<&'a [u8]>) -> (Option<&'a [u8]>, String) {
+    match *error {
+        NomErr::Code(ref kind) => (None, format!("{:?}", kind)),
+        NomErr::Position(ref kind, position) => (Some(position), format!("{:?}", kind)),
+        NomErr::Node(_, ref next) => innermost_error(next),
+        NomErr::NodePosition(_, position, ref next) => {
+            let (inner, context) = innermost_error(next);
+            (inner.or(Some(position)), context)
+        }
+    }
+}
+
 pub fn parse_message(input: &[u8]) -> Result<(Message, &[u8]), ParseError> {
-    match message(input) {
-        IResult::Done(remaining, message) => Ok((message, remaining)),
-        _ => Err(ParseError { input: input.to_vec() }),
+    parse_message_with(input, LineEndings::default())
+}
+
+pub fn parse_message_with(input: &[u8],
+                          line_endings: LineEndings)
+                          -> Result<(Message, &[u8]), ParseError> {
+    match fast_parser::parse_message(input) {
+        Some((message, remaining)) => {
+            check_line_ending(input, remaining, line_endings)?;
+            Ok((message, remaining))
+        }
+        None => Err(ParseError::invalid(input)),
+    }
+}
+
+/// Parses exactly one message. The line ending is optional, but anything after it is an error.
+pub fn parse_line(input: &[u8]) -> Result<Message, ParseError> {
+    let (message, remaining) = parse_message_with(input, LineEndings::AllowEof)?;
+    if remaining.is_empty() {
+        Ok(message)
+    } else {
+        Err(ParseError {
+            input: input.to_vec(),
+            trailing_input: true,
+            element: ParseElement::LineEnding,
+            offset: input.len() - remaining.len(),
+            context: "more input after the line".into(),
+        })
+    }
+}
+
+pub fn parse_message_ref_with(input: &[u8],
+                              line_endings: LineEndings)
+                              -> Result<(MessageRef, &[u8]), ParseError> {
+    match fast_parser::parse_message_ref(input) {
+        Some((message, remaining)) => {
+            check_line_ending(input, remaining, line_endings)?;
+            Ok((message, remaining))
+        }
+        None => Err(ParseError::invalid(input)),
     }
 }
 
+// The grammar accepts any line ending, so this rejects the ones we weren't asked to accept.
+fn check_line_ending(input: &[u8],
+                     remaining: &[u8],
+                     line_endings: LineEndings)
+                     -> Result<(), ParseError> {
+    let line = &input[..input.len() - remaining.len()];
+    let (length, accepted) = if line.ends_with(b"\r\n") {
+        (2, true)
+    } else if line.ends_with(b"\n") {
+        (1, line_endings != LineEndings::Strict)
+    } else {
+        (0, line_endings == LineEndings::AllowEof)
+    };
+    if accepted {
+        return Ok(());
+    }
+    let expected = match line_endings {
+        LineEndings::Strict => "expected CRLF",
+        _ => "expected CRLF or LF",
+    };
+    Err(ParseError {
+        input: input.to_vec(),
+        trailing_input: false,
+        element: ParseElement::LineEnding,
+        offset: line.len() - length,
+        context: expected.into(),
+    })
+}
+
+/// Turns a command as it appears on the wire into a `Command`.
+pub fn command_of(text: &str) -> Command {
+    match command(text.as_bytes()) {
+        IResult::Done(_, command) => command,
+        _ => Command::of_word(text),
+    }
+}
+
+// The grammar for a whole message. Lines are actually parsed by the hand-rolled `fast_parser`, which
+// is tested against this; the parts of it are still used to diagnose lines that fail to parse.
+#[cfg(test)]
 named!(message<Message>, chain!(
+  tags: tags? ~
   prefix: prefix? ~
   command: command ~
   params: params ~
-  tag!("\r\n"), ||{
-    Message::new( prefix.unwrap_or( Prefix::None ), command, params )
+  line_ending, ||{
+    Message::with_arguments( prefix.unwrap_or( Prefix::None ), command, params ).with_tags( tags.unwrap_or_default() )
   }
 )) ;
 
-named!(params<Vec<String> >, many0!( preceded!( tag!(" "), alt!( final_param | param ) ) ) );
+// Any line ending is accepted here, and checked against the LineEndings afterwards.
+#[cfg(test)]
+named!(line_ending, alt!( end_of_input | tag!("\r\n") | tag!("\n") ) );
+
+#[cfg(test)]
+fn end_of_input(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    if input.is_empty() {
+        IResult::Done(input, input)
+    } else {
+        IResult::Error(NomErr::Position(ErrorKind::Eof, input))
+    }
+}
+
+// Like the prefix, this consumes the final space.
+named!(tags<Vec<Tag> >, delimited!(
+  tag!("@"),
+  separated_nonempty_list!( tag!(";"), message_tag ),
+  tag!(" ") ) );
+
+named!(message_tag<Tag>, chain!(
+  key: tag_key ~
+  value: preceded!( tag!("="), tag_value )?, ||{
+    Tag::new( key, value.as_deref() )
+  }
+));
+
+named!(tag_key<&str>, map_res!( take_while1!(is_tag_key_char), str::from_utf8 ) );
+named!(tag_value<String>, map!( take_while!(is_tag_value_char), unescape_to_string ) );
+
+// complete! stops the parameters returning Incomplete when they run up to the end of the input.
+named!(params<Arguments>, fold_many0!(
+  complete!( preceded!( tag!(" "), alt!( final_param | param ) ) ), Arguments::new(), |mut params: Arguments, param| {
+    params.push(param);
+    params
+  } ) );
 named!(param<String>, map!( take_while1!(not_space), copy_to_string ) );
 named!(final_param<String>, preceded!( tag!(":"), trailing ) );
 named!(trailing<String>, map!( take_while!(trailing_char), copy_to_string ) );
 
 named!(command<Command>, alt!( word_command | numeric_command ) );
 named!(word_command<Command>, map_res!( take_while1!(is_alphabetic), make_word) );
-// TODO: This does not limit values to 3 digits, and no validation in make_number.
 named!(numeric_command<Command>, map_res!( take_while1!(is_digit), make_number ) );
 
 // This consumes the final space too, a simple way of testing we eat everything
@@ -103,20 +386,75 @@ fn copy_to_string(input: &[u8]) -> String {
     String::from_utf8_lossy(input).into_owned()
 }
 
-fn to_cow_str(input: &[u8]) -> Result<Cow<str>, Utf8Error> {
-    str::from_utf8(input).map(|string| string.into())
+fn unescape_to_string(input: &[u8]) -> String {
+    unescape_tag_value(&String::from_utf8_lossy(input))
 }
 
+// The same grammar again, but picking out slices of the input rather than copying them, which
+// `fast_parser::parse_message_ref` is tested against.
+#[cfg(test)]
+named!(message_ref<MessageRef>, chain!(
+  tags: raw_tags? ~
+  prefix: prefix_ref? ~
+  command: raw_command ~
+  params: raw_params ~
+  line_ending, ||{
+    MessageRef::new( tags.unwrap_or( &[] ), prefix.unwrap_or( PrefixRef::None ), command, params )
+  }
+));
+
+#[cfg(test)]
+named!(raw_tags<&[u8]>, delimited!(
+  tag!("@"),
+  recognize!( chain!( raw_tag ~ fold_many0!( preceded!( tag!(";"), raw_tag ), (), |_, _| () ), ||{} ) ),
+  tag!(" ") ) );
+
+#[cfg(test)]
+named!(raw_tag<()>, chain!(
+  take_while1!(is_tag_key_char) ~
+  preceded!( tag!("="), take_while!(is_tag_value_char) )?, ||{} ) );
+
+#[cfg(test)]
+named!(raw_params<&[u8]>, recognize!( fold_many0!(
+  complete!( preceded!( tag!(" "), alt!( raw_final_param | raw_param ) ) ), (), |_, _| () ) ) );
+#[cfg(test)]
+named!(raw_param<&[u8]>, take_while1!(not_space) );
+#[cfg(test)]
+named!(raw_final_param<&[u8]>, recognize!( preceded!( tag!(":"), take_while!(trailing_char) ) ) );
+
+#[cfg(test)]
+named!(raw_command<&str>, map_res!( alt!( raw_word | raw_number ), str::from_utf8 ) );
+#[cfg(test)]
+named!(raw_word<&[u8]>, take_while1!(is_alphabetic) );
+#[cfg(test)]
+named!(raw_number<&[u8]>, map_res!( take_while1!(is_digit), check_number ) );
+
+#[cfg(test)]
+named!(prefix_ref<PrefixRef>, preceded!( tag!( ":" ), alt!(
+  complete!( terminated!( user_prefix_ref, tag!( " " ) ) )
+| complete!( terminated!( map!( host, PrefixRef::Server ), tag!( " " ) ) ) ) ) );
+
+#[cfg(test)]
+named!(user_prefix_ref<PrefixRef>, alt!(
+  complete!( chain!( n: nickname ~ tag!("!") ~ u: username ~ tag!("@") ~ h: host, ||{
+    PrefixRef::User { nickname: n, username: Some(u), host: Some(h) }
+  } ) )
+| complete!( chain!( n: nickname ~ tag!("@") ~ h: host, ||{
+    PrefixRef::User { nickname: n, username: None, host: Some(h) }
+  } ) )
+| map!( nickname, |n| PrefixRef::User { nickname: n, username: None, host: None } )
+));
+
 // This is a horrible hack; just over-match and allow anything
 // that can be in an IPv4 address, IPv6 address, or the RFC's
 // definition of "hostname".
 // TODO: What about internationalized hostnames?
-fn is_host_char(c: u8) -> bool {
+pub(crate) fn is_host_char(c: u8) -> bool {
     is_alphabetic(c) || is_digit(c) || c == b'.' || c == b':' || c == b'-'
 }
 
 // Everything except NUL, CR, LF, and " "
-fn not_space(c: u8) -> bool {
+pub(crate) fn not_space(c: u8) -> bool {
     (c != 0) && (c != b'\r') && (c != b'\n') && (c != b' ')
 }
 
@@ -126,24 +464,44 @@ fn is_special(c: u8) -> bool {
     (c == b'{') || (c == b'|') || (c == b'}')
 }
 
-fn trailing_char(c: u8) -> bool {
+// Covers the optional client prefix "+", the vendor hostname, and the "/" separating it from the
+// key name.
+pub(crate) fn is_tag_key_char(c: u8) -> bool {
+    is_alphabetic(c) || is_digit(c) || c == b'-' || c == b'.' || c == b'/' || c == b'+'
+}
+
+// Everything except NUL, CR, LF, ";" and " "
+pub(crate) fn is_tag_value_char(c: u8) -> bool {
+    not_space(c) && c != b';'
+}
+
+pub(crate) fn trailing_char(c: u8) -> bool {
     (c == b' ') || not_space(c)
 }
 
-fn make_word(input: &[u8]) -> Result<Command, str::Utf8Error> {
-    str::from_utf8(input).map(|w| Command::of_word(w))
+// The parser only passes ASCII to these, so the lossy conversions never actually lose anything.
+fn make_word(input: &[u8]) -> Result<Command, CommandError> {
+    Command::try_word(&String::from_utf8_lossy(input))
+}
+
+// Numbers are only validated for MessageRef, but they must be the same ones make_number accepts.
+#[cfg(test)]
+fn check_number(input: &[u8]) -> Result<&[u8], CommandError> {
+    make_number(input).map(|_| input)
 }
 
-fn make_number(input: &[u8]) -> Result<Command, str::Utf8Error> {
-    to_cow_str(input).map(|text| u16::from_str(&*text).unwrap_or(123)).map(Command::Number)
+pub(crate) fn make_number(input: &[u8]) -> Result<Command, CommandError> {
+    let text = String::from_utf8_lossy(input);
+    let number = u16::from_str(&text).unwrap_or(u16::MAX);
+    Command::try_number(number)
 }
 
-fn is_nickname_char(c: u8) -> bool {
+pub(crate) fn is_nickname_char(c: u8) -> bool {
     is_alphabetic(c) || is_special(c) || is_digit(c) || c == b'-'
 }
 
 // Not NUL, CR, LF, " " and "@"
-fn is_username_char(c: u8) -> bool {
+pub(crate) fn is_username_char(c: u8) -> bool {
     (c != 0) && (c != b'\r') && (c != b'\n') && (c != b' ') && (c != b'@')
 }
 
@@ -196,6 +554,49 @@ fn command_numeric() {
     }
 }
 
+#[test]
+fn command_numeric_too_large() {
+    assert!(command("1000 ".as_bytes()).is_err());
+    assert!(parse_message("99999 x\r\n".as_bytes()).is_err());
+}
+
+#[test]
+fn line_with_and_without_crlf() {
+    let expected = Message::from_strs(Prefix::None, commands::PING(), vec!["123"]);
+    assert_eq!(parse_line(b"PING 123\r\n").unwrap(), expected);
+    assert_eq!(parse_line(b"PING 123").unwrap(), expected);
+}
+
+#[test]
+fn line_with_trailing_input() {
+    let error = parse_line(b"PING 123\r\nPING 456\r\n").unwrap_err();
+    assert!(error.to_string().starts_with("Expected a single line"));
+    assert!(parse_line(b"PING 123\r\n\r\n").is_err());
+}
+
+#[test]
+fn error_diagnostics() {
+    let error = parse_message(b"@a=b;=c :nick PING x\r\n").unwrap_err();
+    assert_eq!((error.element(), error.offset()), (ParseElement::Tags, 4));
+
+    let error = parse_message(b":nick!user@ PING x\r\n").unwrap_err();
+    assert_eq!(error.element(), ParseElement::Prefix);
+
+    let error = parse_message(b":server 1000 x\r\n").unwrap_err();
+    assert_eq!((error.element(), error.offset()), (ParseElement::Command, 8));
+
+    let error = parse_message(b"PRIVMSG #chan  :two spaces\r\n").unwrap_err();
+    assert_eq!((error.element(), error.offset()), (ParseElement::Params, 13));
+    assert_eq!(error.input(), b"PRIVMSG #chan  :two spaces\r\n");
+
+    let error = parse_message_with(b"PING x\n", LineEndings::Strict).unwrap_err();
+    assert_eq!((error.element(), error.offset()), (ParseElement::LineEnding, 6));
+    assert_eq!(error.to_string(),
+               "Failed to parse line ending at byte 6 (expected CRLF) of line: [PING x\n]");
+    let error = parse_line(b"PING x\r\nPING y\r\n").unwrap_err();
+    assert_eq!((error.element(), error.offset()), (ParseElement::LineEnding, 8));
+}
+
 #[test]
 fn final_param_with_content() {
     match final_param(":content can contain spaces and ':'".as_bytes()) {
@@ -208,8 +609,8 @@ fn final_param_with_content() {
 fn params_multiple() {
     match params(" here are some :parameters including a long final one".as_bytes()) {
         IResult::Done(_, out) => {
-            assert_eq!(out,
-                       vec!["here", "are", "some", "parameters including a long final one"])
+            assert_eq!(out[..],
+                       ["here", "are", "some", "parameters including a long final one"])
         }
         other => panic!("{:?}", other),
     }
@@ -218,7 +619,7 @@ fn params_multiple() {
 #[test]
 fn params_no_trailing() {
     match params(" here are some parameters".as_bytes()) {
-        IResult::Done(_, out) => assert_eq!(out, vec!["here", "are", "some", "parameters"]),
+        IResult::Done(_, out) => assert_eq!(out[..], ["here", "are", "some", "parameters"]),
         other => panic!("{:?}", other),
     }
 }
@@ -306,6 +707,57 @@ fn prefix_user_prefix_nickname_only() {
     }
 }
 
+#[test]
+fn tags_multiple() {
+    match tags("@aaa=bbb;ccc;example.com/ddd=eee ".as_bytes()) {
+        IResult::Done(_, out) => {
+            assert_eq!(out,
+                       vec![Tag::new("aaa", Some("bbb")),
+                            Tag::new("ccc", None),
+                            Tag::new("example.com/ddd", Some("eee"))])
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn tags_escaped_and_empty_value() {
+    match tags("@+client-tag=semi\\:colon\\sand\\\\slash;empty= ".as_bytes()) {
+        IResult::Done(_, out) => {
+            assert_eq!(out,
+                       vec![Tag::new("+client-tag", Some("semi;colon and\\slash")),
+                            Tag::new("empty", None)])
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn message_tags_and_prefix() {
+    match message("@id=234AB :dan!d@localhost PRIVMSG #chan :Hey what's up!\r\n".as_bytes()) {
+        IResult::Done(_, out) => {
+            assert_eq!(out,
+                       Message::from_strs(Prefix::User(UserInfo::of_nickname_user_host("dan", "d", "localhost")),
+                                          commands::PRIVMSG(),
+                                          vec!["#chan", "Hey what's up!"])
+                           .with_tags(vec![Tag::new("id", Some("234AB"))]))
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn message_tags_no_prefix() {
+    match message("@time=2011-10-19T16:40:51.620Z PING 12345\r\n".as_bytes()) {
+        IResult::Done(_, out) => {
+            assert_eq!(out,
+                       Message::from_strs(Prefix::None, commands::PING(), vec!["12345"])
+                           .with_tags(vec![Tag::new("time", Some("2011-10-19T16:40:51.620Z"))]))
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
 #[test]
 fn real_message_complex() {
     match message(":leguin.freenode.net 005 zootmbot CHANTYPES=# EXCEPTS INVEX \
@@ -335,3 +787,47 @@ fn real_message_complex() {
         other => panic!("{:?}", other),
     }
 }
+
+#[test]
+fn fast_parser_matches_grammar() {
+    // Pieces of lines, valid and not, so random sequences of them hit every branch of the grammar.
+    let pieces: &[&[u8]] = &[b"@", b"@a", b"+x/y.z-w", b"=", b"=v\\:\\s\\\\", b";", b":", b" ",
+                             b"  ", b"!", b"@", b"nick", b"[n]\\", b"~user", b"us\xe9r",
+                             b"host.name", b"::1", b"PRIVMSG", b"ping", b"001", b"1000", b"0",
+                             b"#chan", b":trailing text", b"\xc3", b"\r\n", b"\n", b"\r", b"\0",
+                             b"a", b"-", b"1", b"."];
+    // Valid starts to lines, which would hardly ever come together by chance.
+    let tags: &[&[u8]] = &[b"", b"@a ", b"@a=b;+c/d.e=\\s\\:x;f= ", b"@k=\xc3 "];
+    let prefixes: &[&[u8]] = &[b"", b":nick ", b":n!~u@h.net ", b":irc.net ", b":n@::1 "];
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut line = Vec::new();
+    for _ in 0..200_000 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        line.clear();
+        line.extend_from_slice(tags[(seed % tags.len() as u64) as usize]);
+        line.extend_from_slice(prefixes[(seed / 7 % prefixes.len() as u64) as usize]);
+        let mut choices = seed / 35;
+        for _ in 0..(seed / 13 % 11) {
+            line.extend_from_slice(pieces[(choices % pieces.len() as u64) as usize]);
+            choices /= pieces.len() as u64;
+        }
+        let expected = match message(&line) {
+            IResult::Done(remaining, message) => Some((message, remaining)),
+            _ => None,
+        };
+        assert_eq!(fast_parser::parse_message(&line),
+                   expected,
+                   "Parsed {:?} differently",
+                   String::from_utf8_lossy(&line));
+        let expected = match message_ref(&line) {
+            IResult::Done(remaining, message) => Some((message, remaining)),
+            _ => None,
+        };
+        assert_eq!(fast_parser::parse_message_ref(&line),
+                   expected,
+                   "Parsed {:?} differently as a MessageRef",
+                   String::from_utf8_lossy(&line));
+    }
+}
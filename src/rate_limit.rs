@@ -0,0 +1,145 @@
+use std::time::Duration;
+use std::time::Instant;
+
+/// Limits how quickly lines are sent, so the server doesn't disconnect us for flooding.
+///
+/// This is the algorithm most servers use to decide who's flooding: each line sent costs
+/// `interval`, and up to `burst` lines' worth of cost can be outstanding at once. So a quiet
+/// connection can send `burst` lines immediately, after which it's held to one line per
+/// `interval`. The default of 5 lines then one every 2 seconds keeps well inside typical limits.
+///
+/// Use it with `IrcStream::with_rate_limit`, or call `delay` yourself before each send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimiter {
+    interval: Duration,
+    burst: u32,
+    // When all the cost of lines sent so far will have been paid off.
+    paid_off: Option<Instant>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(2), 5)
+    }
+}
+
+impl RateLimiter {
+    /// # Panics
+    ///
+    /// Will panic if `burst` is zero, since nothing could ever be sent.
+    pub fn new(interval: Duration, burst: u32) -> Self {
+        assert!(burst > 0, "A rate limit needs to allow a burst of at least one line");
+        RateLimiter {
+            interval: interval,
+            burst: burst,
+            paid_off: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Records that a line is about to be sent, returning how long to wait before sending it.
+    pub fn delay(&mut self) -> Duration {
+        self.delay_at(Instant::now())
+    }
+
+    /// As `delay`, but with the current time passed in.
+    pub fn delay_at(&mut self, now: Instant) -> Duration {
+        let paid_off = self.paid_off_after_next(now);
+        self.paid_off = Some(paid_off);
+        self.wait_until(paid_off, now)
+    }
+
+    /// How long until a line could be sent without waiting, without recording one being sent.
+    pub fn wait(&self) -> Duration {
+        self.wait_at(Instant::now())
+    }
+
+    /// As `wait`, but with the current time passed in.
+    pub fn wait_at(&self, now: Instant) -> Duration {
+        self.wait_until(self.paid_off_after_next(now), now)
+    }
+
+    // How long until no more than a burst's worth of cost is outstanding. A burst too large to
+    // represent never needs a wait.
+    fn wait_until(&self, paid_off: Instant, now: Instant) -> Duration {
+        let allowance = self.interval.checked_mul(self.burst).unwrap_or(Duration::MAX);
+        paid_off.saturating_duration_since(now).saturating_sub(allowance)
+    }
+
+    // When everything will have been paid off if another line is sent now.
+    fn paid_off_after_next(&self, now: Instant) -> Instant {
+        let paid_off = match self.paid_off {
+            Some(paid_off) if paid_off > now => paid_off,
+            _ => now,
+        };
+        paid_off + self.interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn burst_then_interval() {
+        let mut limiter = RateLimiter::new(Duration::from_secs(2), 3);
+        let start = Instant::now();
+
+        assert_eq!(limiter.delay_at(start), Duration::from_secs(0));
+        assert_eq!(limiter.delay_at(start), Duration::from_secs(0));
+        assert_eq!(limiter.delay_at(start), Duration::from_secs(0));
+        assert_eq!(limiter.delay_at(start), Duration::from_secs(2));
+        assert_eq!(limiter.delay_at(start), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn recovers_over_time() {
+        let mut limiter = RateLimiter::new(Duration::from_secs(2), 2);
+        let start = Instant::now();
+
+        limiter.delay_at(start);
+        limiter.delay_at(start);
+        assert_eq!(limiter.delay_at(start + Duration::from_secs(1)), Duration::from_secs(1));
+        // Quiet for long enough to pay everything off, so the full burst is available again.
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limiter.delay_at(later), Duration::from_secs(0));
+        assert_eq!(limiter.delay_at(later), Duration::from_secs(0));
+        assert_eq!(limiter.delay_at(later), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn wait_doesnt_record() {
+        let mut limiter = RateLimiter::new(Duration::from_secs(2), 1);
+        let start = Instant::now();
+
+        assert_eq!(limiter.wait_at(start), Duration::from_secs(0));
+        assert_eq!(limiter.wait_at(start), Duration::from_secs(0));
+        limiter.delay_at(start);
+        assert_eq!(limiter.wait_at(start), Duration::from_secs(2));
+        assert_eq!(limiter.wait_at(start + Duration::from_secs(1)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn huge_burst() {
+        let mut limiter = RateLimiter::new(Duration::from_secs(1 << 40), u32::MAX);
+        let start = Instant::now();
+
+        assert_eq!(limiter.delay_at(start), Duration::from_secs(0));
+        assert_eq!(limiter.wait_at(start), Duration::from_secs(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_burst() {
+        RateLimiter::new(Duration::from_secs(2), 0);
+    }
+}
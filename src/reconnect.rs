@@ -0,0 +1,313 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use irc_stream::IrcStream;
+use irc_stream::ParsePolicy;
+use message::Message;
+use metrics::Metrics;
+use metrics::MetricsSink;
+
+/// How long to wait between reconnection attempts. The delay doubles with each failed attempt up
+/// to `max`, and is randomly shortened by up to half so lots of clients dropped at once don't all
+/// come back at the same moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+/// Something that happened on a `ReconnectingIrcStream`.
+#[derive(Debug)]
+// Messages are bigger with their arguments stored inline, but they're the common case here.
+#[cfg_attr(feature = "smallvec", allow(clippy::large_enum_variant))]
+pub enum ConnectionEvent {
+    /// A (re)connection succeeded. Registration (NICK, USER, etc.) needs doing again.
+    Connected,
+    /// The connection was lost.
+    Disconnected(io::Error),
+    /// The next attempt to connect will be made after `delay`. `attempt` counts up from 1 for
+    /// each attempt since the connection was last up.
+    Reconnecting { attempt: u32, delay: Duration },
+    /// A message was received.
+    Message(Message),
+}
+
+/// Wraps an `IrcStream`, making a new connection whenever the current one fails.
+///
+/// Connections are given `ParsePolicy::SkipWithWarning`, so lines from the server which can't be
+/// parsed or are too long are skipped rather than counting as losing the connection. Any other
+/// error reading from it does.
+///
+/// ```no_run
+/// use irc::{ConnectionEvent, IrcStream, Message, ReconnectingIrcStream};
+///
+/// let mut irc = ReconnectingIrcStream::new(|| {
+///     IrcStream::connect("irc.example.com", 6667).map(|irc| irc.with_auto_pong())
+/// });
+/// loop {
+///     match irc.next_event() {
+///         ConnectionEvent::Connected => {
+///             irc.send(&Message::nick("mybot")).ok();
+///             irc.send(&Message::user("mybot", "My Bot")).ok();
+///         }
+///         ConnectionEvent::Message(message) => println!("{}", message),
+///         _ => (),
+///     }
+/// }
+/// ```
+pub struct ReconnectingIrcStream<S: Read + Write, F: FnMut() -> io::Result<IrcStream<S>>> {
+    connect: F,
+    backoff: Backoff,
+    stream: Option<IrcStream<S>>,
+    // Failed attempts since the connection was last up.
+    attempt: u32,
+    // Delay before the next attempt, once it has been announced with a Reconnecting event.
+    next_delay: Option<Duration>,
+    // Whether we've connected before, so the next connection is a reconnection.
+    connected_before: bool,
+    metrics: Metrics,
+}
+
+impl Default for Backoff {
+    /// Starts at 1 second, going up to 5 minutes.
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(300),
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay before attempt number `attempt` (counting from 1), before any jitter is applied.
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let doublings = attempt.saturating_sub(1).min(31);
+        self.initial
+            .checked_mul(1 << doublings)
+            .map(|delay| delay.min(self.max))
+            .unwrap_or(self.max)
+    }
+
+    /// The delay before attempt number `attempt`, with jitter.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.base_delay(attempt);
+        // RandomState is seeded randomly, which is all the randomness we need here.
+        let random = RandomState::new().build_hasher().finish();
+        let fraction = 0.5 + (random % 1000) as f64 / 2000.0;
+        base.mul_f64(fraction)
+    }
+}
+
+impl<S: Read + Write, F: FnMut() -> io::Result<IrcStream<S>>> ReconnectingIrcStream<S, F> {
+    /// Creates a stream which uses `connect` to make each connection. Nothing happens until the
+    /// first call to `next_event`.
+    pub fn new(connect: F) -> Self {
+        ReconnectingIrcStream {
+            connect: connect,
+            backoff: Backoff::default(),
+            stream: None,
+            attempt: 0,
+            next_delay: Some(Duration::from_secs(0)),
+            connected_before: false,
+            metrics: Metrics::default(),
+        }
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Reports each reconnection to `sink`. Give the streams `connect` makes their own
+    /// `with_metrics` for message counts.
+    pub fn with_metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Metrics::new(sink);
+        self
+    }
+
+    /// The current connection, if there is one.
+    pub fn stream(&mut self) -> Option<&mut IrcStream<S>> {
+        self.stream.as_mut()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Sends a message on the current connection. Fails with `NotConnected` if there isn't one;
+    /// messages aren't saved up to send after reconnecting.
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        match self.stream {
+            Some(ref mut stream) => stream.send(message),
+            None => Err(io::Error::new(ErrorKind::NotConnected, "Not connected")),
+        }
+    }
+
+    /// Waits for the next event. This blocks while reading messages and while waiting to
+    /// reconnect.
+    pub fn next_event(&mut self) -> ConnectionEvent {
+        if let Some(mut stream) = self.stream.take() {
+            return match stream.next_message() {
+                Ok(message) => {
+                    self.stream = Some(stream);
+                    ConnectionEvent::Message(message)
+                }
+                Err(e) => {
+                    warn!("Disconnected: {}", e);
+                    self.attempt = 0;
+                    self.next_delay = None;
+                    ConnectionEvent::Disconnected(e)
+                }
+            };
+        }
+
+        let delay = match self.next_delay.take() {
+            Some(delay) => delay,
+            None => return self.announce_attempt(),
+        };
+        thread::sleep(delay);
+
+        match (self.connect)() {
+            Ok(stream) => {
+                self.stream = Some(stream.with_parse_policy(ParsePolicy::SkipWithWarning));
+                self.attempt = 0;
+                if self.connected_before {
+                    self.metrics.reconnected();
+                }
+                self.connected_before = true;
+                ConnectionEvent::Connected
+            }
+            Err(e) => {
+                warn!("Failed to connect: {}", e);
+                self.announce_attempt()
+            }
+        }
+    }
+
+    fn announce_attempt(&mut self) -> ConnectionEvent {
+        self.attempt += 1;
+        let delay = self.backoff.delay(self.attempt);
+        self.next_delay = Some(delay);
+        ConnectionEvent::Reconnecting {
+            attempt: self.attempt,
+            delay: delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io;
+    use std::rc::Rc;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+    use irc_stream::IrcStream;
+    use irc_stream::ScriptedStream;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(5),
+        };
+
+        assert_eq!(backoff.base_delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.base_delay(2), Duration::from_secs(2));
+        assert_eq!(backoff.base_delay(3), Duration::from_secs(4));
+        assert_eq!(backoff.base_delay(4), Duration::from_secs(5));
+        assert_eq!(backoff.base_delay(100), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn jitter_shortens_by_up_to_half() {
+        let backoff = Backoff::default();
+        for _ in 0..20 {
+            let delay = backoff.delay(3);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+    }
+
+    #[test]
+    fn reconnects_after_failures() {
+        let mut results = vec![Ok(b"PING 1\r\n".to_vec()),
+                               Err(()),
+                               Ok(b"PING 2\r\n".to_vec())]
+            .into_iter();
+        let mut irc = ReconnectingIrcStream::new(move || match results.next().unwrap() {
+                Ok(input) => Ok(IrcStream::new(ScriptedStream::new(&input))),
+                Err(()) => Err(io::Error::new(ErrorKind::ConnectionRefused, "Refused")),
+            })
+            .with_backoff(Backoff {
+                initial: Duration::from_millis(1),
+                max: Duration::from_millis(1),
+            });
+
+        assert_matches(irc.next_event(), "Connected");
+        assert_matches(irc.next_event(), "Message");
+        assert_matches(irc.next_event(), "Disconnected");
+        assert!(!irc.is_connected());
+        assert!(irc.send(&Message::privmsg("#chan", "Hi")).is_err());
+        assert_matches(irc.next_event(), "Reconnecting { attempt: 1");
+        assert_matches(irc.next_event(), "Reconnecting { attempt: 2");
+        assert_matches(irc.next_event(), "Connected");
+        assert_matches(irc.next_event(), "Message");
+    }
+
+    #[test]
+    fn malformed_lines_dont_disconnect() {
+        let connections = Rc::new(Cell::new(0));
+        let counter = connections.clone();
+        let mut irc = ReconnectingIrcStream::new(move || {
+            counter.set(counter.get() + 1);
+            let input = b"!!!\r\nPRIVMSG #chan :Far too long\r\nPING 1\r\n";
+            Ok(IrcStream::new(ScriptedStream::new(input)).with_max_line_length(20))
+        });
+
+        assert_matches(irc.next_event(), "Connected");
+        assert_matches(irc.next_event(), "Message");
+        assert_matches(irc.next_event(), "Disconnected");
+        assert_eq!(connections.get(), 1);
+    }
+
+    #[test]
+    fn other_invalid_data_disconnects() {
+        let mut irc = ReconnectingIrcStream::new(|| Ok(IrcStream::new(BrokenStream)));
+
+        assert_matches(irc.next_event(), "Connected");
+        assert_matches(irc.next_event(), "Disconnected");
+    }
+
+    // Like a TLS stream which has hit a fatal error: every read fails.
+    struct BrokenStream;
+
+    impl Read for BrokenStream {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(ErrorKind::InvalidData, "Bad record MAC"))
+        }
+    }
+
+    impl Write for BrokenStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn assert_matches(event: ConnectionEvent, expected: &str) {
+        let debug = format!("{:?}", event);
+        assert!(debug.starts_with(expected), "Expected {} but got {}", expected, debug);
+    }
+}
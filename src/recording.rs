@@ -0,0 +1,258 @@
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Cursor;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::str;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Which way a recorded line went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+/// A line from a recording made by `RecordingStream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedLine {
+    pub time: SystemTime,
+    pub direction: Direction,
+    /// The line exactly as it was sent or received, without its line ending.
+    pub line: Vec<u8>,
+}
+
+/// Wraps a stream, writing every line that goes through it to a log along with its direction and
+/// when it happened. Give one to `IrcStream::new` to record a session, for reproducing problems
+/// with a particular server later on with `Replay`.
+///
+/// Each line of the log is the time in milliseconds since the Unix epoch, `<` for received or `>`
+/// for sent, and the line exactly as it went over the connection (which may not be valid UTF-8).
+/// Failing to write to the log is logged, but doesn't affect the connection.
+#[derive(Debug)]
+pub struct RecordingStream<S, W: Write> {
+    stream: S,
+    log: W,
+    // What's been received and sent of lines which haven't finished yet.
+    received: Vec<u8>,
+    sent: Vec<u8>,
+}
+
+impl<S, W: Write> RecordingStream<S, W> {
+    pub fn new(stream: S, log: W) -> Self {
+        RecordingStream {
+            stream: stream,
+            log: log,
+            received: Vec::new(),
+            sent: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Stops recording, returning the stream and the log. Any partial lines are left out.
+    pub fn into_inner(self) -> (S, W) {
+        (self.stream, self.log)
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        let (partial, marker) = match direction {
+            Direction::Received => (&mut self.received, b'<'),
+            Direction::Sent => (&mut self.sent, b'>'),
+        };
+        partial.extend_from_slice(bytes);
+        while let Some(newline) = partial.iter().position(|&c| c == b'\n') {
+            let line: Vec<u8> = partial.drain(..newline + 1).collect();
+            let line = trim_line_ending(&line);
+            let millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let mut entry = format!("{} {} ", millis, marker as char).into_bytes();
+            entry.extend_from_slice(line);
+            entry.push(b'\n');
+            if let Err(e) = self.log.write_all(&entry) {
+                warn!("Failed to record line: {}", e);
+            }
+        }
+    }
+}
+
+impl<S: Read, W: Write> Read for RecordingStream<S, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf)?;
+        self.record(Direction::Received, &buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<S: Write, W: Write> Write for RecordingStream<S, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buf)?;
+        self.record(Direction::Sent, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Err(e) = self.log.flush() {
+            warn!("Failed to flush recording: {}", e);
+        }
+        self.stream.flush()
+    }
+}
+
+/// Reads a recording made by `RecordingStream`.
+pub fn read_recording<R: BufRead>(mut reader: R) -> io::Result<Vec<RecordedLine>> {
+    let mut lines = Vec::new();
+    let mut entry = Vec::new();
+    while reader.read_until(b'\n', &mut entry)? > 0 {
+        lines.push(parse_entry(&entry)?);
+        entry.clear();
+    }
+    Ok(lines)
+}
+
+fn parse_entry(entry: &[u8]) -> io::Result<RecordedLine> {
+    let invalid = || {
+        io::Error::new(ErrorKind::InvalidData,
+                       format!("Invalid recording entry: {}", String::from_utf8_lossy(entry)))
+    };
+    let mut parts = entry.splitn(3, |&c| c == b' ');
+    let millis = parts.next()
+        .and_then(|millis| str::from_utf8(millis).ok())
+        .and_then(|millis| millis.parse().ok())
+        .ok_or_else(invalid)?;
+    let direction = match parts.next() {
+        Some(b"<") => Direction::Received,
+        Some(b">") => Direction::Sent,
+        _ => return Err(invalid()),
+    };
+    let line = parts.next().ok_or_else(invalid)?;
+    Ok(RecordedLine {
+        time: UNIX_EPOCH + Duration::from_millis(millis),
+        direction: direction,
+        line: trim_line_ending(line).to_vec(),
+    })
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Plays back the received side of a recording, for feeding through an `IrcStream` (and whatever
+/// is built on it) again. Lines are replayed as fast as they're read, and what's sent is kept
+/// rather than going anywhere, so it can be compared with what was sent originally.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    input: Cursor<Vec<u8>>,
+    recorded_sent: Vec<Vec<u8>>,
+    sent: Vec<u8>,
+}
+
+impl Replay {
+    pub fn new(recording: &[RecordedLine]) -> Self {
+        let mut input = Vec::new();
+        let mut recorded_sent = Vec::new();
+        for recorded in recording {
+            match recorded.direction {
+                Direction::Received => {
+                    input.extend_from_slice(&recorded.line);
+                    input.extend_from_slice(b"\r\n");
+                }
+                Direction::Sent => recorded_sent.push(recorded.line.clone()),
+            }
+        }
+        Replay {
+            input: Cursor::new(input),
+            recorded_sent: recorded_sent,
+            sent: Vec::new(),
+        }
+    }
+
+    /// Replays a recording saved to a file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let recording = read_recording(BufReader::new(File::open(path)?))?;
+        Ok(Self::new(&recording))
+    }
+
+    /// The lines sent in the original session, without their line endings.
+    pub fn recorded_sent(&self) -> &[Vec<u8>] {
+        &self.recorded_sent
+    }
+
+    /// Everything sent during the replay.
+    pub fn sent(&self) -> &[u8] {
+        &self.sent
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sent.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+    use irc_stream::IrcStream;
+    use irc_stream::ScriptedStream;
+    use message::Message;
+
+    #[test]
+    fn record_and_replay() {
+        let input = b":server PING :abc\r\n:nick!user@host PRIVMSG bot :caf\xe9\n";
+        let stream = RecordingStream::new(ScriptedStream::new(input), Vec::new());
+        let mut irc = IrcStream::new(stream).with_auto_pong();
+        irc.next_message().unwrap();
+        irc.send(&Message::privmsg("nick", "Hi")).unwrap();
+        let recording = read_recording(&irc.get_ref().log[..]).unwrap();
+        let lines: Vec<(Direction, &[u8])> = recording.iter()
+            .map(|recorded| (recorded.direction, &recorded.line[..]))
+            .collect();
+        // Both lines arrived in the same read, before the PONG went out.
+        assert_eq!(lines,
+                   vec![(Direction::Received, &b":server PING :abc"[..]),
+                        (Direction::Received, &b":nick!user@host PRIVMSG bot :caf\xe9"[..]),
+                        (Direction::Sent, &b"PONG abc"[..]),
+                        (Direction::Sent, &b"PRIVMSG nick Hi"[..])]);
+
+        let replay = Replay::new(&recording);
+        assert_eq!(replay.recorded_sent(), &[b"PONG abc".to_vec(), b"PRIVMSG nick Hi".to_vec()]);
+        let mut irc = IrcStream::new(replay).with_auto_pong();
+        assert_eq!(irc.next_message().unwrap().arguments[..], ["bot", "caf\u{fffd}"]);
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(irc.get_ref().sent(), b"PONG abc\r\n");
+    }
+
+    #[test]
+    fn invalid_recordings() {
+        assert!(read_recording(&b"123 < PING\nPING\n"[..]).is_err());
+        assert!(read_recording(&b"123 ? PING\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,358 @@
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use cap::CapNegotiator;
+use command::commands;
+use command::responses;
+use irc_stream::IrcStream;
+use message::Message;
+use sasl;
+
+/// Drives connection registration: `PASS`, `NICK` and `USER`, along with capability negotiation
+/// and SASL if they're wanted, and `WEBIRC` for gateways.
+///
+/// ```no_run
+/// use irc::{IrcStream, Registration};
+///
+/// # #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+/// # fn main() {
+/// let mut irc = IrcStream::connect_ssl("irc.libera.chat", 6697).unwrap().with_auto_pong();
+/// let registered = Registration::new("mybot", "mybot", "My Bot")
+///     .with_alternative_nickname("mybot_")
+///     .with_capabilities(&["multi-prefix", "server-time"])
+///     .with_sasl_plain("mybot", "hunter2")
+///     .register(&mut irc)
+///     .unwrap();
+/// println!("Registered as {}", registered.nickname);
+/// # }
+/// # #[cfg(not(any(feature = "openssl", feature = "rustls", feature = "native-tls")))]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registration {
+    webirc: Option<Message>,
+    password: Option<String>,
+    nicknames: Vec<String>,
+    username: String,
+    realname: String,
+    capabilities: Vec<String>,
+    sasl: Option<Sasl>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sasl {
+    Plain { account: String, password: String },
+    External,
+}
+
+/// What we found out while registering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registered {
+    /// The nickname we ended up with, which may be one of the alternatives.
+    pub nickname: String,
+    /// The outcome of capability negotiation. Nothing will have been negotiated if no
+    /// capabilities or SASL were asked for.
+    pub capabilities: CapNegotiator,
+    /// The `RPL_WELCOME` (001) message which completed registration.
+    pub welcome: Message,
+}
+
+impl Registration {
+    pub fn new(nickname: &str, username: &str, realname: &str) -> Self {
+        Registration {
+            webirc: None,
+            password: None,
+            nicknames: vec![nickname.into()],
+            username: username.into(),
+            realname: realname.into(),
+            capabilities: Vec::new(),
+            sasl: None,
+        }
+    }
+
+    /// Sends a server password with `PASS`, before anything else. For Twitch this is the
+    /// `oauth:` token.
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Logs into a bouncer such as ZNC, by sending `username/network:password` as the server
+    /// password. Leave out the network to use the bouncer's default one.
+    pub fn with_bouncer_login(self, username: &str, network: Option<&str>, password: &str) -> Self {
+        match network {
+            Some(network) => self.with_password(&format!("{}/{}:{}", username, network, password)),
+            None => self.with_password(&format!("{}:{}", username, password)),
+        }
+    }
+
+    /// Sends `WEBIRC` first, for gateways connecting on behalf of the user at `hostname` and
+    /// `ip`.
+    pub fn with_webirc(mut self, password: &str, gateway: &str, hostname: &str, ip: &str) -> Self {
+        self.webirc = Some(Message::webirc(password, gateway, hostname, ip));
+        self
+    }
+
+    /// A nickname to fall back to if the ones before it are in use or invalid. Alternatives are
+    /// tried in the order they're added.
+    pub fn with_alternative_nickname(mut self, nickname: &str) -> Self {
+        self.nicknames.push(nickname.into());
+        self
+    }
+
+    /// Capabilities to request, if the server supports them.
+    pub fn with_capabilities(mut self, capabilities: &[&str]) -> Self {
+        self.capabilities.extend(capabilities.iter().map(|cap| cap.to_string()));
+        self
+    }
+
+    /// Logs in with SASL PLAIN. Registration fails if the server doesn't support SASL or rejects
+    /// the login, rather than carrying on unauthenticated.
+    pub fn with_sasl_plain(mut self, account: &str, password: &str) -> Self {
+        self.sasl = Some(Sasl::Plain {
+            account: account.into(),
+            password: password.into(),
+        });
+        self
+    }
+
+    /// Logs in with SASL EXTERNAL, using the client certificate the connection was made with. As
+    /// with `with_sasl_plain`, failing to log in fails registration.
+    pub fn with_sasl_external(mut self) -> Self {
+        self.sasl = Some(Sasl::External);
+        self
+    }
+
+    /// Registers, blocking until the server welcomes us.
+    ///
+    /// Fails with `AlreadyExists` if none of the nicknames could be used, and with
+    /// `PermissionDenied` if the server rejects our password or SASL login, or otherwise refuses
+    /// the connection. PINGs are answered; any other messages received before the welcome are
+    /// logged and dropped.
+    pub fn register<S: Read + Write>(&self, irc: &mut IrcStream<S>) -> io::Result<Registered> {
+        let negotiating = !self.capabilities.is_empty() || self.sasl.is_some();
+        if let Some(ref webirc) = self.webirc {
+            irc.send(webirc)?;
+        }
+        if let Some(ref password) = self.password {
+            irc.send(&Message::pass(password))?;
+        }
+        if negotiating {
+            irc.send(&CapNegotiator::ls_message())?;
+        }
+        let mut nickname = 0;
+        irc.send(&Message::nick(&self.nicknames[nickname]))?;
+        irc.send(&Message::user(&self.username, &self.realname))?;
+
+        let mut caps = CapNegotiator::new();
+        let mut requested = false;
+        let mut ended = !negotiating;
+        loop {
+            let message = irc.next_message()?;
+            if caps.handle(&message) {
+                if caps.ls_complete() && !requested {
+                    requested = true;
+                    if let Some(request) = caps.request(&self.wanted_capabilities()) {
+                        irc.send(&request)?;
+                    }
+                }
+                // Later CAP messages (e.g. NEW and DEL) mustn't end negotiation again.
+                if requested && caps.requests_complete() && !ended {
+                    ended = true;
+                    self.authenticate(irc, &caps)?;
+                    caps.end(irc)?;
+                }
+            } else if message.command == responses::RPL_WELCOME() {
+                let registered_as = message.arguments
+                    .first()
+                    .cloned()
+                    .unwrap_or_else(|| self.nicknames[nickname].clone());
+                info!("Registered as {}", registered_as);
+                irc.nickname_changed(&registered_as);
+                return Ok(Registered {
+                    nickname: registered_as,
+                    capabilities: caps,
+                    welcome: message,
+                });
+            } else if is_nickname_error(&message) {
+                nickname += 1;
+                match self.nicknames.get(nickname) {
+                    Some(alternative) => {
+                        debug!("Nickname rejected, trying {}: {}", alternative, message);
+                        irc.send(&Message::nick(alternative))?;
+                    }
+                    None => {
+                        return Err(io::Error::new(ErrorKind::AlreadyExists,
+                                                  format!("No usable nickname: {}", message)))
+                    }
+                }
+            } else if is_fatal(&message) {
+                return Err(io::Error::new(ErrorKind::PermissionDenied,
+                                          format!("Registration failed: {}", message)));
+            } else if let Some(ping) = message.as_ping() {
+                irc.send(&ping.pong())?;
+            } else {
+                debug!("Dropping message received during registration: {}", message);
+            }
+        }
+    }
+
+    fn wanted_capabilities(&self) -> Vec<&str> {
+        let mut wanted: Vec<&str> = self.capabilities.iter().map(|cap| &cap[..]).collect();
+        if self.sasl.is_some() {
+            wanted.push("sasl");
+        }
+        wanted
+    }
+
+    fn authenticate<S: Read + Write>(&self,
+                                     irc: &mut IrcStream<S>,
+                                     caps: &CapNegotiator)
+                                     -> io::Result<()> {
+        let sasl = match self.sasl {
+            Some(ref sasl) => sasl,
+            None => return Ok(()),
+        };
+        if !caps.is_enabled("sasl") {
+            return Err(io::Error::new(ErrorKind::PermissionDenied,
+                                      "Server doesn't support SASL"));
+        }
+        match *sasl {
+            Sasl::Plain { ref account, ref password } => {
+                sasl::authenticate_plain(irc, account, password)
+            }
+            Sasl::External => sasl::authenticate_external(irc),
+        }
+    }
+}
+
+fn is_nickname_error(message: &Message) -> bool {
+    message.command == responses::ERR_NICKNAMEINUSE() ||
+    message.command == responses::ERR_ERRONEUSNICKNAME() ||
+    message.command == responses::ERR_NICKCOLLISION() ||
+    message.command == responses::ERR_UNAVAILRESOURCE()
+}
+
+fn is_fatal(message: &Message) -> bool {
+    message.command == responses::ERR_PASSWDMISMATCH() ||
+    message.command == responses::ERR_YOUREBANNEDCREEP() ||
+    message.command == commands::ERROR()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc_stream::ScriptedStream;
+
+    #[test]
+    fn simple() {
+        let input = b":server NOTICE * :*** Looking up your hostname\r\n\
+                      PING 123\r\n\
+                      :server 001 bot :Welcome to the network\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let registered = Registration::new("bot", "user", "A bot")
+            .with_password("secret")
+            .register(&mut irc)
+            .unwrap();
+        assert_eq!(registered.nickname, "bot");
+        assert_eq!(irc.sent(),
+                   "PASS secret\r\nNICK bot\r\nUSER user 0 * :A bot\r\nPONG 123\r\n");
+    }
+
+    #[test]
+    fn password_comes_first() {
+        let input = b":server CAP * LS :multi-prefix\r\n\
+                      :server CAP * ACK :multi-prefix\r\n\
+                      :server 001 bot :Welcome to the network\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        Registration::new("bot", "user", "A bot")
+            .with_bouncer_login("user", Some("libera"), "secret")
+            .with_capabilities(&["multi-prefix"])
+            .register(&mut irc)
+            .unwrap();
+        assert!(irc.sent().starts_with("PASS user/libera:secret\r\nCAP LS 302\r\nNICK bot\r\n"));
+    }
+
+    #[test]
+    fn webirc() {
+        let input = b":server 001 bot :Welcome to the network\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        Registration::new("bot", "user", "A bot")
+            .with_password("secret")
+            .with_webirc("gatewaypass", "gateway", "host.example.com", "192.0.2.1")
+            .register(&mut irc)
+            .unwrap();
+        assert!(irc.sent().starts_with("WEBIRC gatewaypass gateway host.example.com 192.0.2.1\r\n\
+                                        PASS secret\r\n"));
+    }
+
+    #[test]
+    fn nickname_fallback() {
+        let input = b":server 433 * bot :Nickname is already in use\r\n\
+                      :server 001 bot_ :Welcome to the network\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let registered = Registration::new("bot", "user", "A bot")
+            .with_alternative_nickname("bot_")
+            .register(&mut irc)
+            .unwrap();
+        assert_eq!(registered.nickname, "bot_");
+        assert!(irc.sent().ends_with("NICK bot_\r\n"));
+    }
+
+    #[test]
+    fn out_of_nicknames() {
+        let input = b":server 433 * bot :Nickname is already in use\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let error = Registration::new("bot", "user", "A bot").register(&mut irc).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn fatal_numeric() {
+        let input = b":server 464 * :Password incorrect\r\n\
+                      ERROR :Closing link\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let error = Registration::new("bot", "user", "A bot").register(&mut irc).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn capabilities_and_sasl() {
+        let input = b":server CAP * LS :multi-prefix sasl=PLAIN\r\n\
+                      :server CAP * ACK :multi-prefix sasl\r\n\
+                      AUTHENTICATE +\r\n\
+                      :server 903 * :SASL authentication successful\r\n\
+                      :server 001 bot :Welcome to the network\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let registered = Registration::new("bot", "user", "A bot")
+            .with_capabilities(&["multi-prefix", "away-notify"])
+            .with_sasl_plain("user", "pass")
+            .register(&mut irc)
+            .unwrap();
+        assert!(registered.capabilities.is_enabled("multi-prefix"));
+        assert_eq!(irc.sent(),
+                   "CAP LS 302\r\nNICK bot\r\nUSER user 0 * :A bot\r\nCAP REQ :multi-prefix sasl\r\n\
+                    AUTHENTICATE PLAIN\r\nAUTHENTICATE dXNlcgB1c2VyAHBhc3M=\r\nCAP END\r\n");
+    }
+
+    #[test]
+    fn sasl_unsupported() {
+        let input = b":server CAP * LS :multi-prefix\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let error = Registration::new("bot", "user", "A bot")
+            .with_sasl_external()
+            .register(&mut irc)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    }
+}
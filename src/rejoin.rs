@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use casemapping::IrcCase;
+use casemapping::IrcString;
+use command::commands;
+use command::responses;
+use message::Message;
+use message::Prefix;
+use reconnect::Backoff;
+
+/// Keeps us in a set of channels, rejoining after being kicked and retrying joins which fail for
+/// reasons that might go away: the channel being full (471), invite-only (473), banned (474), a bad
+/// key (475), or being forwarded elsewhere (470).
+///
+/// Pass every received message to `handle`, and call `poll` now and then to get the JOINs which
+/// are due. Rejoining after a kick waits `kick_delay`; failed joins are retried with `backoff`.
+#[derive(Debug, Clone)]
+pub struct AutoRejoin {
+    nick: String,
+    kick_delay: Duration,
+    backoff: Backoff,
+    case: IrcCase,
+    channels: HashMap<IrcString, RejoinState>,
+}
+
+#[derive(Debug, Clone)]
+struct RejoinState {
+    channel: String,
+    key: Option<String>,
+    // Failed joins since we were last in the channel.
+    attempt: u32,
+    // When to next try joining, if we're not in the channel.
+    due: Option<Instant>,
+}
+
+impl AutoRejoin {
+    /// `nick` is our nickname, which is followed across changes by `handle`.
+    pub fn new(nick: &str) -> Self {
+        AutoRejoin {
+            nick: nick.into(),
+            kick_delay: Duration::from_secs(5),
+            backoff: Backoff {
+                initial: Duration::from_secs(30),
+                max: Duration::from_secs(600),
+            },
+            case: IrcCase::default(),
+            channels: HashMap::new(),
+        }
+    }
+
+    /// How long to wait before rejoining after a kick. Defaults to 5 seconds.
+    pub fn with_kick_delay(mut self, delay: Duration) -> Self {
+        self.kick_delay = delay;
+        self
+    }
+
+    /// How long to wait before retrying failed joins. Defaults to starting at 30 seconds, going up
+    /// to 10 minutes.
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets our nickname, e.g. if the one we asked for at registration was taken.
+    pub fn set_nick(&mut self, nick: &str) {
+        self.nick = nick.into();
+    }
+
+    /// Sets how nicknames and channel names are compared, from the server's `CASEMAPPING`
+    /// ISUPPORT token. Defaults to `rfc1459`.
+    pub fn set_casemapping(&mut self, case: IrcCase) {
+        self.case = case;
+        self.channels = self.channels
+            .drain()
+            .map(|(channel, state)| (case.key(channel.as_str()), state))
+            .collect();
+    }
+
+    /// Starts keeping us in `channel`, joining with `key` if it has one. This doesn't join the
+    /// channel itself; that's left to the caller.
+    pub fn add(&mut self, channel: &str, key: Option<&str>) {
+        self.channels.insert(self.case.key(channel),
+                             RejoinState {
+                                 channel: channel.into(),
+                                 key: key.map(|key| key.into()),
+                                 attempt: 0,
+                                 due: None,
+                             });
+    }
+
+    /// Stops keeping us in `channel`, e.g. before parting it.
+    pub fn remove(&mut self, channel: &str) {
+        self.channels.remove(&self.case.key(channel));
+    }
+
+    pub fn handle(&mut self, message: &Message) {
+        self.handle_at(message, Instant::now())
+    }
+
+    /// As `handle`, with `now` as when the message arrived: a rejoin after a kick or failed JOIN
+    /// is scheduled from then.
+    pub fn handle_at(&mut self, message: &Message, now: Instant) {
+        if message.command == commands::NICK() && !message.arguments.is_empty() {
+            if self.is_from_us(message) {
+                self.nick = message.arguments[0].clone();
+            }
+        } else if message.command == commands::JOIN() && !message.arguments.is_empty() {
+            if !self.is_from_us(message) {
+                return;
+            }
+            if let Some(state) = self.state_mut(&message.arguments[0]) {
+                state.attempt = 0;
+                state.due = None;
+            }
+        } else if let Some(kick) = message.as_kick() {
+            if !self.case.eq(kick.nick, &self.nick) {
+                return;
+            }
+            let delay = self.kick_delay;
+            if let Some(state) = self.state_mut(kick.channel) {
+                info!("Kicked from {}, rejoining in {:?}", state.channel, delay);
+                state.due = Some(now + delay);
+            }
+        } else if is_recoverable_join_failure(message) && message.arguments.len() >= 2 {
+            let backoff = self.backoff;
+            if let Some(state) = self.state_mut(&message.arguments[1]) {
+                state.attempt += 1;
+                let delay = backoff.delay(state.attempt);
+                info!("Couldn't join {}, retrying in {:?}: {}", state.channel, delay, message);
+                state.due = Some(now + delay);
+            }
+        }
+    }
+
+    /// Returns JOINs for any channels which are due to be rejoined.
+    pub fn poll(&mut self) -> Vec<Message> {
+        self.poll_at(Instant::now())
+    }
+
+    /// As `poll`, but with the current time passed in.
+    pub fn poll_at(&mut self, now: Instant) -> Vec<Message> {
+        let mut joins = Vec::new();
+        for state in self.channels.values_mut() {
+            match state.due {
+                Some(due) if due <= now => (),
+                _ => continue,
+            }
+            state.due = None;
+            joins.push(match state.key {
+                Some(ref key) => {
+                    Message::from_strs(Prefix::None, commands::JOIN(), vec![&state.channel, key])
+                }
+                None => Message::join(&state.channel),
+            });
+        }
+        joins
+    }
+
+    /// When `poll` will next have something to return, if ever.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.channels.values().filter_map(|state| state.due).min()
+    }
+
+    fn state_mut(&mut self, channel: &str) -> Option<&mut RejoinState> {
+        self.channels.get_mut(&self.case.key(channel))
+    }
+
+    fn is_from_us(&self, message: &Message) -> bool {
+        match message.prefix {
+            Prefix::User(ref user) => self.case.eq(user.nickname(), &self.nick),
+            _ => false,
+        }
+    }
+}
+
+// These all have the channel we tried to join as the second argument.
+fn is_recoverable_join_failure(message: &Message) -> bool {
+    message.command == responses::ERR_LINKCHANNEL() ||
+    message.command == responses::ERR_CHANNELISFULL() ||
+    message.command == responses::ERR_INVITEONLYCHAN() ||
+    message.command == responses::ERR_BANNEDFROMCHAN() ||
+    message.command == responses::ERR_BADCHANNELKEY()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use std::time::Instant;
+    use message::Message;
+
+    #[test]
+    fn rejoin_after_kick() {
+        let mut rejoin = AutoRejoin::new("bot").with_kick_delay(Duration::from_secs(10));
+        rejoin.add("#chan", Some("secret"));
+        let now = Instant::now();
+
+        rejoin.handle_at(&message(":op!op@host KICK #other bot :bye\r\n"), now);
+        rejoin.handle_at(&message(":op!op@host KICK #chan someone :bye\r\n"), now);
+        assert_eq!(rejoin.next_due(), None);
+
+        rejoin.handle_at(&message(":op!op@host KICK #Chan bot :bye\r\n"), now);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(5)), vec![]);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(10)),
+                   vec![message("JOIN #chan secret\r\n")]);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(20)), vec![]);
+    }
+
+    #[test]
+    fn follows_nick_changes() {
+        let mut rejoin = AutoRejoin::new("bot").with_kick_delay(Duration::from_secs(0));
+        rejoin.add("#chan", None);
+        let now = Instant::now();
+
+        rejoin.handle_at(&message(":bot!bot@host NICK newbot\r\n"), now);
+        rejoin.handle_at(&message(":op!op@host KICK #chan newbot\r\n"), now);
+        assert_eq!(rejoin.poll_at(now), vec![Message::join("#chan")]);
+    }
+
+    #[test]
+    fn retry_failed_joins() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(10),
+            max: Duration::from_secs(60),
+        };
+        let mut rejoin = AutoRejoin::new("bot").with_backoff(backoff);
+        rejoin.add("#chan", None);
+        let now = Instant::now();
+
+        rejoin.handle_at(&message(":server 474 bot #chan :Cannot join channel (+b)\r\n"), now);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(4)), vec![]);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(10)), vec![Message::join("#chan")]);
+
+        // Backing off further each time.
+        rejoin.handle_at(&message(":server 471 bot #chan :Cannot join channel (+l)\r\n"), now);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(9)), vec![]);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(20)), vec![Message::join("#chan")]);
+
+        // Joining resets the backoff.
+        rejoin.handle_at(&message(":bot!bot@host JOIN #chan\r\n"), now);
+        rejoin.handle_at(&message(":server 473 bot #chan :Cannot join channel (+i)\r\n"), now);
+        assert_eq!(rejoin.poll_at(now + Duration::from_secs(10)), vec![Message::join("#chan")]);
+    }
+
+    #[test]
+    fn forwarded() {
+        let mut rejoin = AutoRejoin::new("bot");
+        rejoin.add("#chan", None);
+        let now = Instant::now();
+
+        rejoin.handle_at(&message(":server 470 bot #chan ##overflow :Forwarding to another \
+                                   channel\r\n"),
+                         now);
+        rejoin.handle_at(&message(":bot!bot@host JOIN ##overflow\r\n"), now);
+        assert!(rejoin.next_due().is_some());
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
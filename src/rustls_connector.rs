@@ -0,0 +1,176 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::io;
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use rustls::ClientConfig;
+use rustls::ClientConnection;
+use rustls::DigitallySignedStruct;
+use rustls::RootCertStore;
+use rustls::SignatureScheme;
+use rustls::StreamOwned;
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::client::danger::ServerCertVerified;
+use rustls::client::danger::ServerCertVerifier;
+use rustls::crypto::CryptoProvider;
+use rustls::crypto::ring;
+use rustls::crypto::verify_tls12_signature;
+use rustls::crypto::verify_tls13_signature;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::PrivateKeyDer;
+use rustls::pki_types::ServerName;
+use rustls::pki_types::UnixTime;
+use rustls::pki_types::pem::PemObject;
+
+use tls::TlsConnector;
+use tls::TlsOptions;
+
+/// A `TlsConnector` using rustls. The system's CA store isn't consulted; the webpki roots (the
+/// same ones Mozilla trusts) are used instead.
+///
+/// rustls only speaks TLS 1.2 and later, so older minimum versions in `TlsOptions` make no
+/// difference.
+#[derive(Debug)]
+pub struct RustlsConnector {
+    config: Arc<ClientConfig>,
+    options: TlsOptions,
+}
+
+impl RustlsConnector {
+    pub fn new(options: &TlsOptions) -> io::Result<Self> {
+        Ok(RustlsConnector {
+            config: Arc::new(client_config(options)?),
+            options: options.clone(),
+        })
+    }
+
+    /// Wraps a `ClientConfig` you've set up yourself.
+    pub fn from_client_config(config: Arc<ClientConfig>) -> Self {
+        RustlsConnector {
+            config: config,
+            options: TlsOptions::default(),
+        }
+    }
+}
+
+impl TlsConnector for RustlsConnector {
+    type Stream = StreamOwned<ClientConnection, TcpStream>;
+
+    fn connect(&self,
+               server: &str,
+               mut stream: TcpStream)
+               -> io::Result<StreamOwned<ClientConnection, TcpStream>> {
+        let name = ServerName::try_from(self.options.server_name(server).to_string())
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
+        let mut connection = ClientConnection::new(self.config.clone(), name).map_err(other)?;
+        // Handshake now, so failures are reported here rather than on the first read.
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+        Ok(StreamOwned::new(connection, stream))
+    }
+}
+
+fn client_config(options: &TlsOptions) -> io::Result<ClientConfig> {
+    let provider = Arc::new(ring::default_provider());
+
+    let mut roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+    if let Some(ca_file) = options.ca_file() {
+        debug!("Trusting CAs from {:?}", ca_file);
+        for cert in CertificateDer::pem_file_iter(ca_file).map_err(invalid_data)? {
+            roots.add(cert.map_err(invalid_data)?).map_err(invalid_data)?;
+        }
+    }
+
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(other)?
+        .with_root_certificates(roots);
+    let mut config = match options.client_cert() {
+        Some(client_cert) => {
+            debug!("Using client certificate {:?}", client_cert.cert_path);
+            let certs = CertificateDer::pem_file_iter(&client_cert.cert_path)
+                .map_err(invalid_data)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(invalid_data)?;
+            let key = PrivateKeyDer::from_pem_file(&client_cert.key_path).map_err(invalid_data)?;
+            builder.with_client_auth_cert(certs, key).map_err(invalid_data)?
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    if options.accepts_invalid_certs() {
+        warn!("TLS certificate verification is turned off");
+        config.dangerous().set_certificate_verifier(Arc::new(AcceptAnyCert(provider)));
+    }
+    Ok(config)
+}
+
+/// Accepts any certificate, for `TlsOptions::danger_accept_invalid_certs`. Signatures are still
+/// checked, so the handshake is at least with whoever holds the certificate's key.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(&self,
+                          _end_entity: &CertificateDer,
+                          _intermediates: &[CertificateDer],
+                          _server_name: &ServerName,
+                          _ocsp_response: &[u8],
+                          _now: UnixTime)
+                          -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self,
+                              message: &[u8],
+                              cert: &CertificateDer,
+                              dss: &DigitallySignedStruct)
+                              -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self,
+                              message: &[u8],
+                              cert: &CertificateDer,
+                              dss: &DigitallySignedStruct)
+                              -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn invalid_data<E: Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+fn other<E: Error + Send + Sync + 'static>(err: E) -> io::Error {
+    io::Error::other(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc_stream::ClientCert;
+    use tls::TlsVersion;
+
+    #[test]
+    fn client_config_options() {
+        let options = TlsOptions::new()
+            .with_min_version(TlsVersion::Tls12)
+            .danger_accept_invalid_certs();
+        assert!(client_config(&options).is_ok());
+
+        let missing_ca = TlsOptions::new().with_ca_file("/does/not/exist.pem");
+        assert!(client_config(&missing_ca).is_err());
+
+        let missing_cert = TlsOptions::new()
+            .with_client_cert(ClientCert::new("/does/not/exist.pem", "/does/not/exist.key"));
+        assert!(client_config(&missing_cert).is_err());
+    }
+}
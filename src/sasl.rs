@@ -0,0 +1,148 @@
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use base64;
+use command::responses;
+use irc_stream::IrcStream;
+use message::Message;
+
+/// Authenticates using SASL EXTERNAL, where the server identifies us by the client certificate we
+/// presented when connecting (see `IrcStream::connect_ssl_with_cert`).
+///
+/// This has to happen during capability negotiation, after the `sasl` capability has been ACKed
+/// and before `CAP END`. Something like:
+///
+/// ```no_run
+/// # #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+/// # fn main() {
+/// # use irc::{CapNegotiator, ClientCert, IrcStream};
+/// let cert = ClientCert::new("bot.pem", "bot.key");
+/// let mut irc = IrcStream::connect_ssl_with_cert("irc.libera.chat", 6697, &cert).unwrap();
+/// let mut caps = CapNegotiator::new();
+/// caps.negotiate(&mut irc, &["sasl"]).unwrap();
+/// if caps.is_enabled("sasl") {
+///     irc::sasl::authenticate_external(&mut irc).unwrap();
+/// }
+/// caps.end(&mut irc).unwrap();
+/// # }
+/// # #[cfg(not(any(feature = "openssl", feature = "rustls", feature = "native-tls")))]
+/// # fn main() {}
+/// ```
+///
+/// Returns an error of kind `PermissionDenied` if the server rejects us. PINGs received during the
+/// exchange are answered, other messages are logged and dropped.
+pub fn authenticate_external<S: Read + Write>(irc: &mut IrcStream<S>) -> io::Result<()> {
+    // We've nothing to add beyond the certificate, so always answer with an empty payload.
+    authenticate(irc, "EXTERNAL", b"")
+}
+
+/// Authenticates using SASL PLAIN, i.e. with an account name and password. As with
+/// `authenticate_external`, this has to happen during capability negotiation.
+///
+/// The password is only base64-encoded, so this should only be used over TLS.
+pub fn authenticate_plain<S: Read + Write>(irc: &mut IrcStream<S>,
+                                           account: &str,
+                                           password: &str)
+                                           -> io::Result<()> {
+    let payload = format!("{0}\0{0}\0{1}", account, password);
+    authenticate(irc, "PLAIN", payload.as_bytes())
+}
+
+fn authenticate<S: Read + Write>(irc: &mut IrcStream<S>,
+                                 mechanism: &str,
+                                 payload: &[u8])
+                                 -> io::Result<()> {
+    irc.send(&Message::authenticate(mechanism))?;
+
+    loop {
+        let message = irc.next_message()?;
+        if let Some(challenge) = message.as_authenticate() {
+            debug!("Server sent AUTHENTICATE {}", challenge);
+            for chunk in payload_chunks(payload) {
+                irc.send(&Message::authenticate(&chunk))?;
+            }
+        } else if message.command == responses::RPL_SASLSUCCESS() {
+            info!("SASL {} authentication succeeded", mechanism);
+            return Ok(());
+        } else if is_sasl_failure(&message) {
+            return Err(io::Error::new(ErrorKind::PermissionDenied,
+                                      format!("SASL {} authentication failed: {}",
+                                              mechanism,
+                                              message)));
+        } else if let Some(ping) = message.as_ping() {
+            irc.send(&ping.pong())?;
+        } else {
+            debug!("Dropping message received during SASL: {}", message);
+        }
+    }
+}
+
+/// Splits a payload into `AUTHENTICATE` arguments: base64 in 400-byte pieces, with a `+` to
+/// finish if the last piece is a full 400 bytes (or there's nothing to send at all).
+fn payload_chunks(payload: &[u8]) -> Vec<String> {
+    let encoded = base64::encode(payload);
+    let mut chunks: Vec<String> = encoded.as_bytes()
+        .chunks(400)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect();
+    if encoded.len().is_multiple_of(400) {
+        chunks.push("+".into());
+    }
+    chunks
+}
+
+fn is_sasl_failure(message: &Message) -> bool {
+    message.command == responses::ERR_NICKLOCKED() ||
+    message.command == responses::ERR_SASLFAIL() ||
+    message.command == responses::ERR_SASLTOOLONG() ||
+    message.command == responses::ERR_SASLABORTED() ||
+    message.command == responses::ERR_SASLALREADY()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc_stream::ScriptedStream;
+
+    #[test]
+    fn success() {
+        let input = b"AUTHENTICATE +\r\n\
+                      :server 900 nick nick!user@host account :You are now logged in\r\n\
+                      :server 903 nick :SASL authentication successful\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        authenticate_external(&mut irc).unwrap();
+        assert_eq!(irc.sent(), "AUTHENTICATE EXTERNAL\r\nAUTHENTICATE +\r\n");
+    }
+
+    #[test]
+    fn plain() {
+        let input = b"AUTHENTICATE +\r\n\
+                      :server 903 nick :SASL authentication successful\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        authenticate_plain(&mut irc, "user", "pass").unwrap();
+        assert_eq!(irc.sent(),
+                   "AUTHENTICATE PLAIN\r\nAUTHENTICATE dXNlcgB1c2VyAHBhc3M=\r\n");
+    }
+
+    #[test]
+    fn long_payloads_are_split() {
+        assert_eq!(payload_chunks(&[0; 300]).iter().map(|c| c.len()).collect::<Vec<_>>(),
+                   vec![400, 1]);
+        assert_eq!(payload_chunks(&[0; 301]).iter().map(|c| c.len()).collect::<Vec<_>>(),
+                   vec![400, 4]);
+    }
+
+    #[test]
+    fn failure() {
+        let input = b"AUTHENTICATE +\r\n\
+                      :server 904 nick :SASL authentication failed\r\n";
+        let mut irc = IrcStream::new(ScriptedStream::new(input));
+
+        let error = authenticate_external(&mut irc).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+    }
+}
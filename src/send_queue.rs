@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+
+use command::commands;
+use message::Message;
+
+/// How urgently a queued message needs to go out. Higher priorities are sent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Normal,
+    /// Moderation: `KICK`, `MODE` and `KILL`, which are no use if they're stuck behind the
+    /// spam they're dealing with.
+    High,
+    /// `PONG` and `PING`, so a busy connection isn't timed out while its queue drains.
+    Urgent,
+}
+
+impl Priority {
+    /// The priority `message` gets by default.
+    pub fn of(message: &Message) -> Self {
+        let command = &message.command;
+        if *command == commands::PONG() || *command == commands::PING() {
+            Priority::Urgent
+        } else if *command == commands::KICK() || *command == commands::MODE() ||
+                  *command == commands::KILL() {
+            Priority::High
+        } else {
+            Priority::Normal
+        }
+    }
+}
+
+/// Messages waiting to be sent, in a lane for each priority. Messages come out highest priority
+/// first, and in the order they were pushed within each priority.
+///
+/// `IrcStream::queue` uses one of these, but it can also be used to order messages for the async
+/// codec, or any other way of sending them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SendQueue {
+    urgent: VecDeque<Message>,
+    high: VecDeque<Message>,
+    normal: VecDeque<Message>,
+}
+
+impl SendQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a message with the priority `Priority::of` gives it.
+    pub fn push(&mut self, message: Message) {
+        let priority = Priority::of(&message);
+        self.push_with_priority(message, priority);
+    }
+
+    pub fn push_with_priority(&mut self, message: Message, priority: Priority) {
+        self.lane(priority).push_back(message);
+    }
+
+    /// The next message to send.
+    pub fn pop(&mut self) -> Option<Message> {
+        self.urgent
+            .pop_front()
+            .or_else(|| self.high.pop_front())
+            .or_else(|| self.normal.pop_front())
+    }
+
+    pub fn len(&self) -> usize {
+        self.urgent.len() + self.high.len() + self.normal.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lane(&mut self, priority: Priority) -> &mut VecDeque<Message> {
+        match priority {
+            Priority::Urgent => &mut self.urgent,
+            Priority::High => &mut self.high,
+            Priority::Normal => &mut self.normal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn priorities() {
+        assert_eq!(Priority::of(&Message::privmsg("#chan", "Hi")), Priority::Normal);
+        assert_eq!(Priority::of(&Message::kick("#chan", "spammer", None)), Priority::High);
+        assert_eq!(Priority::of(&message("PONG :server\r\n")), Priority::Urgent);
+    }
+
+    #[test]
+    fn higher_priorities_first() {
+        let mut queue = SendQueue::new();
+        queue.push(Message::privmsg("#chan", "one"));
+        queue.push(Message::privmsg("#chan", "two"));
+        queue.push(Message::kick("#chan", "spammer", None));
+        queue.push(message("PONG :server\r\n"));
+        queue.push_with_priority(Message::privmsg("#chan", "three"), Priority::High);
+        assert_eq!(queue.len(), 5);
+
+        assert_eq!(queue.pop(), Some(message("PONG :server\r\n")));
+        assert_eq!(queue.pop(), Some(Message::kick("#chan", "spammer", None)));
+        assert_eq!(queue.pop(), Some(Message::privmsg("#chan", "three")));
+        assert_eq!(queue.pop(), Some(Message::privmsg("#chan", "one")));
+        assert_eq!(queue.pop(), Some(Message::privmsg("#chan", "two")));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use message::Message;
+
+/// A UTC timestamp as sent by the server in the `time` tag when the `server-time` capability is
+/// enabled, e.g. `2011-10-19T16:40:51.620Z`.
+///
+/// This is deliberately minimal rather than pulling in a full date/time library; convert to a
+/// `SystemTime` with `to_system_time` if you need to do arithmetic with it. Fields are ordered so
+/// the derived ordering is chronological.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub millisecond: u16,
+}
+
+/// Returned when a `time` tag isn't in the format the spec requires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerTimeError {
+    input: String,
+}
+
+impl Message {
+    /// The time the server says this message was sent, if the `server-time` capability is enabled
+    /// and the tag is valid.
+    pub fn server_time(&self) -> Option<ServerTime> {
+        let value = self.tag_value("time")?;
+        match value.parse() {
+            Ok(time) => Some(time),
+            Err(err) => {
+                warn!("Ignoring server time on message: {}", err);
+                None
+            }
+        }
+    }
+}
+
+impl ServerTime {
+    /// Milliseconds since the Unix epoch. Negative for times before 1970.
+    pub fn unix_millis(&self) -> i64 {
+        let days = days_from_civil(i64::from(self.year), i64::from(self.month), i64::from(self.day));
+        let seconds = days * 86400 + i64::from(self.hour) * 3600 + i64::from(self.minute) * 60 +
+                      i64::from(self.second);
+        seconds * 1000 + i64::from(self.millisecond)
+    }
+
+    pub fn to_system_time(&self) -> SystemTime {
+        let millis = self.unix_millis();
+        if millis >= 0 {
+            UNIX_EPOCH + Duration::from_millis(millis as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_millis(millis.unsigned_abs())
+        }
+    }
+
+    /// Converts a `SystemTime`, truncating to millisecond precision. Returns `None` for times
+    /// before 1970, which never need to be sent.
+    pub fn from_system_time(time: SystemTime) -> Option<Self> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+        let seconds = since_epoch.as_secs() as i64;
+        let (year, month, day) = civil_from_days(seconds / 86400);
+        let seconds_of_day = seconds % 86400;
+
+        Some(ServerTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day % 3600 / 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+            millisecond: since_epoch.subsec_millis() as u16,
+        })
+    }
+}
+
+impl FromStr for ServerTime {
+    type Err = ServerTimeError;
+
+    /// Parses `YYYY-MM-DDThh:mm:ss[.sss]Z`. The fraction may have any number of digits, but only
+    /// milliseconds are kept.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let error = || ServerTimeError { input: input.into() };
+        let bytes = input.as_bytes();
+        // Everything below slices by byte offset, which only lines up with characters in ASCII.
+        if !input.is_ascii() || bytes.len() < 20 || bytes[4] != b'-' || bytes[7] != b'-' || bytes[10] != b'T' ||
+           bytes[13] != b':' || bytes[16] != b':' || bytes[bytes.len() - 1] != b'Z' {
+            return Err(error());
+        }
+
+        let number = |from: usize, to: usize| -> Result<u16, ServerTimeError> {
+            let digits = &input[from..to];
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(error());
+            }
+            digits.parse().map_err(|_| error())
+        };
+
+        let fraction = &input[19..input.len() - 1];
+        let millisecond = if fraction.is_empty() {
+            0
+        } else if fraction.len() >= 2 && fraction.starts_with('.') {
+            if !fraction[1..].bytes().all(|b| b.is_ascii_digit()) {
+                return Err(error());
+            }
+            let digits: String = fraction[1..].chars().chain("00".chars()).take(3).collect();
+            digits.parse().map_err(|_| error())?
+        } else {
+            return Err(error());
+        };
+
+        let time = ServerTime {
+            year: number(0, 4)?,
+            month: number(5, 7)? as u8,
+            day: number(8, 10)? as u8,
+            hour: number(11, 13)? as u8,
+            minute: number(14, 16)? as u8,
+            second: number(17, 19)? as u8,
+            millisecond: millisecond,
+        };
+
+        let valid = time.month >= 1 && time.month <= 12 && time.day >= 1 &&
+                    time.day <= days_in_month(time.year, time.month) && time.hour < 24 &&
+                    time.minute < 60 && time.second <= 60;
+        if valid { Ok(time) } else { Err(error()) }
+    }
+}
+
+impl Display for ServerTime {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt,
+               "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+               self.year,
+               self.month,
+               self.day,
+               self.hour,
+               self.minute,
+               self.second,
+               self.millisecond)
+    }
+}
+
+impl std::error::Error for ServerTimeError {
+    fn description(&self) -> &str {
+        "invalid server-time timestamp"
+    }
+}
+
+impl Display for ServerTimeError {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Invalid server-time timestamp: [{}]", self.input)
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// These two are Howard Hinnant's algorithms for converting between days since the epoch and
+// proleptic Gregorian dates: http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = if days >= 0 { days } else { days - 146096 } / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 -
+                       day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+    use message::Prefix;
+    use message::Tag;
+    use command::commands::PING;
+
+    #[test]
+    fn parse() {
+        assert_eq!("2011-10-19T16:40:51.620Z".parse(),
+                   Ok(ServerTime {
+                       year: 2011,
+                       month: 10,
+                       day: 19,
+                       hour: 16,
+                       minute: 40,
+                       second: 51,
+                       millisecond: 620,
+                   }));
+    }
+
+    #[test]
+    fn parse_other_precisions() {
+        let time: ServerTime = "2011-10-19T16:40:51Z".parse().unwrap();
+        assert_eq!(time.millisecond, 0);
+        let time: ServerTime = "2011-10-19T16:40:51.6Z".parse().unwrap();
+        assert_eq!(time.millisecond, 600);
+        let time: ServerTime = "2011-10-19T16:40:51.123456Z".parse().unwrap();
+        assert_eq!(time.millisecond, 123);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!("2011-10-19 16:40:51.620Z".parse::<ServerTime>().is_err());
+        assert!("2011-13-19T16:40:51.620Z".parse::<ServerTime>().is_err());
+        assert!("2011-10-19T16:40:51.620".parse::<ServerTime>().is_err());
+        assert!("2011-10-19T16:40:51.Z".parse::<ServerTime>().is_err());
+        assert!("yesterday".parse::<ServerTime>().is_err());
+    }
+
+    #[test]
+    fn parse_days_in_month() {
+        assert!("2011-02-28T16:40:51Z".parse::<ServerTime>().is_ok());
+        assert!("2011-02-29T16:40:51Z".parse::<ServerTime>().is_err());
+        assert!("2012-02-29T16:40:51Z".parse::<ServerTime>().is_ok());
+        assert!("2012-02-30T16:40:51Z".parse::<ServerTime>().is_err());
+        assert!("2012-02-31T16:40:51Z".parse::<ServerTime>().is_err());
+        assert!("1900-02-29T16:40:51Z".parse::<ServerTime>().is_err());
+        assert!("2000-02-29T16:40:51Z".parse::<ServerTime>().is_ok());
+        assert!("2011-04-30T16:40:51Z".parse::<ServerTime>().is_ok());
+        assert!("2011-04-31T16:40:51Z".parse::<ServerTime>().is_err());
+        assert!("2011-12-31T16:40:51Z".parse::<ServerTime>().is_ok());
+    }
+
+    #[test]
+    fn parse_non_ascii() {
+        // Multibyte characters where digits should be mustn't be sliced through.
+        assert!("2011-10-19T16:40:€Z".parse::<ServerTime>().is_err());
+        assert!("2011-10-19T16:40:51.6€Z".parse::<ServerTime>().is_err());
+        assert!("2011-10-19T16:4€:51Z".parse::<ServerTime>().is_err());
+    }
+
+    #[test]
+    fn display() {
+        let time: ServerTime = "2011-10-19T16:40:51.6Z".parse().unwrap();
+        assert_eq!(format!("{}", time), "2011-10-19T16:40:51.600Z");
+    }
+
+    #[test]
+    fn unix_millis() {
+        let time: ServerTime = "2011-10-19T16:40:51.620Z".parse().unwrap();
+        assert_eq!(time.unix_millis(), 1319042451620);
+    }
+
+    #[test]
+    fn system_time_round_trip() {
+        let time: ServerTime = "2024-02-29T23:59:59.999Z".parse().unwrap();
+        assert_eq!(ServerTime::from_system_time(time.to_system_time()), Some(time));
+    }
+
+    #[test]
+    fn from_message() {
+        let message = Message::new(Prefix::None, PING(), vec![])
+            .with_tags(vec![Tag::new("time", Some("2011-10-19T16:40:51.620Z"))]);
+
+        assert_eq!(message.server_time().map(|time| time.unix_millis()),
+                   Some(1319042451620));
+    }
+
+    #[test]
+    fn from_message_missing() {
+        assert_eq!(Message::new(Prefix::None, PING(), vec![]).server_time(), None);
+    }
+}
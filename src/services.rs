@@ -0,0 +1,184 @@
+use casemapping::IrcCase;
+use command::responses;
+use formatting::Span;
+use message::Message;
+use message::Prefix;
+
+/// What NickServ told us, from `NickServ::handle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NickServEvent {
+    /// Our nickname is registered and NickServ wants us to identify for it.
+    IdentifyRequested,
+    /// We're identified, either from NickServ's confirmation or `RPL_LOGGEDIN` (900).
+    Identified,
+    /// NickServ rejected our password.
+    IdentifyFailed,
+}
+
+/// Builds messages for NickServ and recognises its replies, for networks (or bots) where SASL
+/// isn't an option.
+///
+/// The replies are recognised by their wording, which is what Atheme and Anope (and most services
+/// derived from them) send in English. Where services aren't called `NickServ`, or have to be
+/// messaged at a full address such as `NickServ@services.example.com`, use `with_nick`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NickServ {
+    nick: String,
+}
+
+impl Default for NickServ {
+    fn default() -> Self {
+        NickServ { nick: "NickServ".into() }
+    }
+}
+
+impl NickServ {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Who to send messages to, and expect replies from. Defaults to `NickServ`; anything after
+    /// an `@` is only used when sending.
+    pub fn with_nick(mut self, nick: &str) -> Self {
+        self.nick = nick.into();
+        self
+    }
+
+    /// Identifies for our current nickname, or for `account` if given. Both Atheme and Anope
+    /// accept the account name first.
+    pub fn identify(&self, account: Option<&str>, password: &str) -> Message {
+        match account {
+            Some(account) => self.command(&format!("IDENTIFY {} {}", account, password)),
+            None => self.command(&format!("IDENTIFY {}", password)),
+        }
+    }
+
+    /// Disconnects whoever is using `nick`. The password can be left out if we're already
+    /// identified for it.
+    pub fn ghost(&self, nick: &str, password: Option<&str>) -> Message {
+        self.command_with_password("GHOST", nick, password)
+    }
+
+    /// Disconnects whoever is using `nick` and changes our nickname to it in one go. Atheme calls
+    /// this `REGAIN`; Anope uses `RECOVER` for something similar, which you can send with
+    /// `command`.
+    pub fn regain(&self, nick: &str, password: Option<&str>) -> Message {
+        self.command_with_password("REGAIN", nick, password)
+    }
+
+    /// Any other command, e.g. `RELEASE nick`.
+    pub fn command(&self, command: &str) -> Message {
+        Message::privmsg(&self.nick, command)
+    }
+
+    /// Recognises NickServ telling us about identification. Notices from anyone else are ignored,
+    /// as are NickServ's that we don't understand.
+    pub fn handle(&self, message: &Message) -> Option<NickServEvent> {
+        if message.command == responses::RPL_LOGGEDIN() {
+            return Some(NickServEvent::Identified);
+        }
+        let notice = message.as_notice()?;
+        let from_services = match *notice.from {
+            Prefix::User(ref user) => {
+                let nick = self.nick.split('@').next().unwrap_or(&self.nick);
+                IrcCase::default().eq(user.nickname(), nick)
+            }
+            _ => false,
+        };
+        if !from_services {
+            return None;
+        }
+
+        let text: String = Span::parse(notice.text).iter().map(|span| span.text).collect();
+        let text = text.to_lowercase();
+        let matches = |phrases: &[&str]| phrases.iter().any(|phrase| text.contains(phrase));
+        if matches(&["you are now identified", "password accepted", "you are now logged in"]) {
+            Some(NickServEvent::Identified)
+        } else if matches(&["invalid password", "password incorrect", "incorrect password"]) {
+            Some(NickServEvent::IdentifyFailed)
+        } else if matches(&["is registered", "please identify", "identify via"]) {
+            Some(NickServEvent::IdentifyRequested)
+        } else {
+            None
+        }
+    }
+
+    fn command_with_password(&self,
+                             command: &str,
+                             nick: &str,
+                             password: Option<&str>)
+                             -> Message {
+        match password {
+            Some(password) => self.command(&format!("{} {} {}", command, nick, password)),
+            None => self.command(&format!("{} {}", command, nick)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn commands() {
+        let nickserv = NickServ::new();
+        assert_eq!(nickserv.identify(None, "hunter2"),
+                   Message::privmsg("NickServ", "IDENTIFY hunter2"));
+        assert_eq!(nickserv.identify(Some("bot"), "hunter2"),
+                   Message::privmsg("NickServ", "IDENTIFY bot hunter2"));
+        assert_eq!(nickserv.ghost("bot", Some("hunter2")),
+                   Message::privmsg("NickServ", "GHOST bot hunter2"));
+        assert_eq!(nickserv.regain("bot", None), Message::privmsg("NickServ", "REGAIN bot"));
+    }
+
+    #[test]
+    fn atheme_notices() {
+        let nickserv = NickServ::new();
+        let requested = message(":NickServ!NickServ@services. NOTICE bot :This nickname is \
+                                 registered. Please choose a different nickname, or identify \
+                                 via \x02/msg NickServ identify <password>\x02\r\n");
+        assert_eq!(nickserv.handle(&requested), Some(NickServEvent::IdentifyRequested));
+        let identified = message(":NickServ!NickServ@services. NOTICE bot :You are now \
+                                  identified for \x02bot\x02.\r\n");
+        assert_eq!(nickserv.handle(&identified), Some(NickServEvent::Identified));
+        let failed = message(":NickServ!NickServ@services. NOTICE bot :Invalid password for \
+                              \x02bot\x02.\r\n");
+        assert_eq!(nickserv.handle(&failed), Some(NickServEvent::IdentifyFailed));
+    }
+
+    #[test]
+    fn anope_notices() {
+        let nickserv = NickServ::new();
+        let identified = message(":NickServ!services@services.host NOTICE bot :Password \
+                                  accepted - you are now recognized.\r\n");
+        assert_eq!(nickserv.handle(&identified), Some(NickServEvent::Identified));
+        let failed = message(":NickServ!services@services.host NOTICE bot :Password \
+                              incorrect.\r\n");
+        assert_eq!(nickserv.handle(&failed), Some(NickServEvent::IdentifyFailed));
+    }
+
+    #[test]
+    fn other_names_and_senders() {
+        let authserv = NickServ::new().with_nick("AuthServ@services.example.com");
+        assert_eq!(authserv.identify(None, "hunter2"),
+                   Message::privmsg("AuthServ@services.example.com", "IDENTIFY hunter2"));
+        let identified = message(":AuthServ!services@services.host NOTICE bot :Password \
+                                  accepted - you are now recognized.\r\n");
+        assert_eq!(authserv.handle(&identified), Some(NickServEvent::Identified));
+
+        let spoofed = message(":nick!user@host NOTICE bot :Password accepted\r\n");
+        assert_eq!(NickServ::new().handle(&spoofed), None);
+        let logged_in = message(":server 900 bot bot!user@host bot :You are now logged in as \
+                                 bot\r\n");
+        assert_eq!(NickServ::new().handle(&logged_in), Some(NickServEvent::Identified));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::TcpStream;
+
+use irc_stream::IrcStream;
+use tls::TlsConnector;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::DefaultTlsConnector;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsOptions;
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+use tls::TlsStream;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 1;
+const IPV4: u8 = 1;
+const DOMAIN_NAME: u8 = 3;
+const IPV6: u8 = 4;
+
+/// A SOCKS5 proxy to connect through, e.g. Tor (usually `127.0.0.1:9050`).
+///
+/// The server's hostname is passed to the proxy to resolve rather than looked up locally, so
+/// `.onion` addresses work and DNS lookups don't leak around the proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Socks5Proxy {
+    host: String,
+    port: u16,
+    auth: Option<(String, String)>,
+}
+
+impl Socks5Proxy {
+    pub fn new(host: &str, port: u16) -> Self {
+        Socks5Proxy {
+            host: host.into(),
+            port: port,
+            auth: None,
+        }
+    }
+
+    /// Authenticates with the proxy using a username and password. Tor also uses these to keep
+    /// connections with different credentials on separate circuits.
+    pub fn with_auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Connects to the proxy and asks it to connect on to `server`.
+    pub fn connect(&self, server: &str, port: u16) -> io::Result<TcpStream> {
+        debug!("Connecting to {}:{} via SOCKS5 proxy {}:{}",
+               server,
+               port,
+               self.host,
+               self.port);
+        let mut stream = TcpStream::connect((&self.host[..], self.port))?;
+        self.handshake(&mut stream, server, port)?;
+        Ok(stream)
+    }
+
+    fn handshake<S: Read + Write>(&self, stream: &mut S, server: &str, port: u16) -> io::Result<()> {
+        let method = if self.auth.is_some() { USERNAME_PASSWORD } else { NO_AUTH };
+        stream.write_all(&[VERSION, 1, method])?;
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != VERSION {
+            return Err(invalid_data("Proxy isn't speaking SOCKS5"));
+        }
+        match reply[1] {
+            NO_AUTH => (),
+            USERNAME_PASSWORD => self.authenticate(stream)?,
+            NO_ACCEPTABLE_METHODS => {
+                return Err(io::Error::new(ErrorKind::PermissionDenied,
+                                          "Proxy didn't accept our authentication method"))
+            }
+            other => return Err(invalid_data(&format!("Proxy chose unknown method {}", other))),
+        }
+
+        let mut request = vec![VERSION, CONNECT, 0];
+        match server.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                if server.len() > 255 {
+                    return Err(io::Error::new(ErrorKind::InvalidInput, "Hostname is too long"));
+                }
+                request.push(DOMAIN_NAME);
+                request.push(server.len() as u8);
+                request.extend_from_slice(server.as_bytes());
+            }
+        }
+        request.push((port >> 8) as u8);
+        request.push(port as u8);
+        stream.write_all(&request)?;
+
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            return Err(connect_error(reply[1]));
+        }
+        // The address the proxy bound, which we've no use for.
+        let address_len = match reply[3] {
+            IPV4 => 4,
+            IPV6 => 16,
+            DOMAIN_NAME => {
+                let mut len = [0; 1];
+                stream.read_exact(&mut len)?;
+                len[0] as usize
+            }
+            other => return Err(invalid_data(&format!("Proxy sent unknown address type {}", other))),
+        };
+        let mut address = vec![0; address_len + 2];
+        stream.read_exact(&mut address)
+    }
+
+    fn authenticate<S: Read + Write>(&self, stream: &mut S) -> io::Result<()> {
+        let (username, password) = match self.auth {
+            Some((ref username, ref password)) => (username, password),
+            None => {
+                return Err(io::Error::new(ErrorKind::PermissionDenied,
+                                          "Proxy wants a username and password"))
+            }
+        };
+        if username.len() > 255 || password.len() > 255 {
+            return Err(io::Error::new(ErrorKind::InvalidInput,
+                                      "Proxy username and password can be at most 255 bytes"));
+        }
+        let mut request = vec![1, username.len() as u8];
+        request.extend_from_slice(username.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request)?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            return Err(io::Error::new(ErrorKind::PermissionDenied,
+                                      "Proxy rejected our username and password"));
+        }
+        Ok(())
+    }
+}
+
+impl IrcStream<TcpStream> {
+    /// Connect to a server through a SOCKS5 proxy and wrap in an `IrcStream`.
+    pub fn connect_via_socks5(proxy: &Socks5Proxy, server: &str, port: u16) -> io::Result<Self> {
+        Ok(IrcStream::new(proxy.connect(server, port)?))
+    }
+}
+
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+impl IrcStream<TlsStream> {
+    /// Connect to a server over SSL configured with `options` through a SOCKS5 proxy, and wrap in
+    /// an `IrcStream`.
+    pub fn connect_ssl_via_socks5(proxy: &Socks5Proxy,
+                                  server: &str,
+                                  port: u16,
+                                  options: &TlsOptions)
+                                  -> io::Result<Self> {
+        Self::connect_tls_via_socks5(proxy, server, port, &DefaultTlsConnector::new(options)?)
+    }
+}
+
+impl<S: Read + Write> IrcStream<S> {
+    /// Connect to a server over TLS using `connector`, through a SOCKS5 proxy.
+    pub fn connect_tls_via_socks5<C: TlsConnector<Stream = S>>(proxy: &Socks5Proxy,
+                                                               server: &str,
+                                                               port: u16,
+                                                               connector: &C)
+                                                               -> io::Result<Self> {
+        let raw_connection = proxy.connect(server, port)?;
+        Ok(IrcStream::new(connector.connect(server, raw_connection)?))
+    }
+}
+
+fn connect_error(code: u8) -> io::Error {
+    let (kind, description) = match code {
+        2 => (ErrorKind::PermissionDenied, "connection not allowed by ruleset"),
+        3 => (ErrorKind::Other, "network unreachable"),
+        4 => (ErrorKind::Other, "host unreachable"),
+        5 => (ErrorKind::ConnectionRefused, "connection refused"),
+        6 => (ErrorKind::TimedOut, "TTL expired"),
+        7 => (ErrorKind::Other, "command not supported"),
+        8 => (ErrorKind::Other, "address type not supported"),
+        _ => (ErrorKind::Other, "general failure"),
+    };
+    io::Error::new(kind, format!("SOCKS5 proxy couldn't connect: {}", description))
+}
+
+fn invalid_data(description: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, description)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc_stream::ScriptedStream;
+
+    #[test]
+    fn connect_by_hostname() {
+        let proxy = Socks5Proxy::new("localhost", 9050);
+        let mut stream = ScriptedStream::new(&[5, 0, 5, 0, 0, 1, 127, 0, 0, 1, 0x1a, 0x0b]);
+
+        proxy.handshake(&mut stream, "irc.example.com", 6667).unwrap();
+        let mut expected = vec![5, 1, 0, 5, 1, 0, 3, 15];
+        expected.extend_from_slice(b"irc.example.com");
+        expected.extend_from_slice(&[0x1a, 0x0b]);
+        assert_eq!(stream.output, expected);
+    }
+
+    #[test]
+    fn connect_by_ip_with_auth() {
+        let proxy = Socks5Proxy::new("localhost", 9050).with_auth("user", "pw");
+        let mut stream = ScriptedStream::new(&[5, 2, 1, 0, 5, 0, 0, 3, 1, b'x', 0x1a, 0x0b]);
+
+        proxy.handshake(&mut stream, "192.0.2.1", 6697).unwrap();
+        assert_eq!(stream.output,
+                   vec![5, 1, 2, 1, 4, b'u', b's', b'e', b'r', 2, b'p', b'w', 5, 1, 0, 1, 192, 0,
+                        2, 1, 0x1a, 0x29]);
+    }
+
+    #[test]
+    fn errors() {
+        let proxy = Socks5Proxy::new("localhost", 9050).with_auth("user", "pw");
+        let rejected = proxy.handshake(&mut ScriptedStream::new(&[5, 2, 1, 1]), "irc", 6667);
+        assert_eq!(rejected.unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+        let proxy = Socks5Proxy::new("localhost", 9050);
+        let refused = proxy.handshake(&mut ScriptedStream::new(&[5, 0, 5, 5, 0, 1]), "irc", 6667);
+        assert_eq!(refused.unwrap_err().kind(), ErrorKind::ConnectionRefused);
+
+        let not_socks = proxy.handshake(&mut ScriptedStream::new(b"HTTP/1.1"), "irc", 6667);
+        assert_eq!(not_socks.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
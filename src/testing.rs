@@ -0,0 +1,443 @@
+//! Helpers for testing code built on this crate without a real server.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+
+use command::Command;
+use command::commands;
+use message::Message;
+use message_ref::MessageRef;
+
+/// Creates a connected pair of in-memory streams: whatever is written to one can be read from the
+/// other. Give one to an `IrcStream` and play the server with the other (which can be wrapped in
+/// an `IrcStream` too, to send and receive whole messages).
+///
+/// The ends can be moved to different threads. Dropping one closes the connection: the other
+/// reads whatever was left and then sees end-of-file, and writing to it fails with `BrokenPipe`.
+pub fn duplex() -> (MockStream, MockStream) {
+    let (a, b) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+    (MockStream::new(a.clone(), b.clone()), MockStream::new(b, a))
+}
+
+/// One end of an in-memory connection, from `duplex`.
+#[derive(Debug)]
+pub struct MockStream {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+    read_timeout: Option<Duration>,
+}
+
+// Bytes going one way, and whether either end has gone away.
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<(VecDeque<u8>, bool)>,
+    changed: Condvar,
+}
+
+impl Pipe {
+    fn close(&self) {
+        self.state.lock().unwrap().1 = true;
+        self.changed.notify_all();
+    }
+}
+
+impl MockStream {
+    fn new(incoming: Arc<Pipe>, outgoing: Arc<Pipe>) -> Self {
+        MockStream {
+            incoming: incoming,
+            outgoing: outgoing,
+            read_timeout: None,
+        }
+    }
+
+    /// How long reads wait for something to arrive before failing with `WouldBlock`, as with
+    /// `TcpStream::set_read_timeout`. By default they wait until the other end writes something
+    /// or is dropped, so a single-threaded test should only read what it knows has been written.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// How many bytes the other end has written that haven't been read yet.
+    pub fn available(&self) -> usize {
+        self.incoming.state.lock().unwrap().0.len()
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = self.read_timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.incoming.state.lock().unwrap();
+        loop {
+            let (ref mut bytes, closed) = *state;
+            if !bytes.is_empty() || buf.is_empty() {
+                let read = buf.len().min(bytes.len());
+                for (to, from) in buf.iter_mut().zip(bytes.drain(..read)) {
+                    *to = from;
+                }
+                return Ok(read);
+            }
+            if closed {
+                return Ok(0);
+            }
+            state = match deadline {
+                None => self.incoming.changed.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::Error::new(ErrorKind::WouldBlock, "Read timed out"));
+                    }
+                    self.incoming.changed.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.outgoing.state.lock().unwrap();
+        if state.1 {
+            return Err(io::Error::new(ErrorKind::BrokenPipe, "The other end has been dropped"));
+        }
+        state.0.extend(buf);
+        self.outgoing.changed.notify_all();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MockStream {
+    fn drop(&mut self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+/// A scripted server for testing a client end to end: it sends the lines it's given, and checks
+/// the client sends the messages it expects, in order.
+///
+/// ```
+/// use irc::{IrcStream, Message, Registration};
+/// use irc::testing::MockServer;
+///
+/// let (stream, server) = MockServer::new()
+///     .expect_registration("bot")
+///     .send(":nick!user@host PRIVMSG bot :!ping")
+///     .expect("PRIVMSG nick :pong")
+///     .start();
+///
+/// let mut irc = IrcStream::new(stream);
+/// Registration::new("bot", "bot", "Bot").register(&mut irc).unwrap();
+/// assert_eq!(irc.next_message().unwrap().arguments[1], "!ping");
+/// irc.send(&Message::privmsg("nick", "pong")).unwrap();
+/// server.finish();
+/// ```
+///
+/// The script runs on its own thread, and closes the connection when it's done.
+pub struct MockServer {
+    steps: Vec<Step>,
+    timeout: Duration,
+}
+
+enum Step {
+    Send(Vec<u8>),
+    Expect(String, Box<dyn Fn(&Message) -> bool + Send>),
+}
+
+impl Default for MockServer {
+    fn default() -> Self {
+        MockServer {
+            steps: Vec::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to wait for each expected message before giving up. Defaults to 5 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends a line to the client, adding a CRLF if it doesn't have one. The line isn't checked,
+    /// so it can be malformed on purpose.
+    pub fn send(mut self, line: &str) -> Self {
+        let mut line = line.as_bytes().to_vec();
+        if !line.ends_with(b"\n") {
+            line.extend_from_slice(b"\r\n");
+        }
+        self.steps.push(Step::Send(line));
+        self
+    }
+
+    /// Expects the client's next message to be `line`.
+    ///
+    /// Panics if `line` can't be parsed.
+    pub fn expect(self, line: &str) -> Self {
+        let expected: Message = match line.parse() {
+            Ok(message) => message,
+            Err(e) => panic!("Can't expect a line which doesn't parse: {}", e),
+        };
+        self.expect_matching(line, move |message| *message == expected)
+    }
+
+    /// Expects the client's next message to have `command`, whatever its arguments.
+    pub fn expect_command(self, command: Command) -> Self {
+        let description = format!("a {} message", command);
+        self.expect_matching(&description, move |message| message.command == command)
+    }
+
+    /// Expects the client's next message to pass a check. The description is used when it
+    /// doesn't.
+    pub fn expect_matching<F>(mut self, description: &str, matches: F) -> Self
+        where F: Fn(&Message) -> bool + Send + 'static
+    {
+        self.steps.push(Step::Expect(description.into(), Box::new(matches)));
+        self
+    }
+
+    /// Expects the client to register as `nickname` with `NICK` and `USER` (without capability
+    /// negotiation), and welcomes it with `RPL_WELCOME` (001).
+    pub fn expect_registration(self, nickname: &str) -> Self {
+        let nick = Message::nick(nickname);
+        self.expect_matching(&nick.to_string(), move |message| *message == nick)
+            .expect_command(commands::USER())
+            .send(&format!(":mock.server 001 {} :Welcome to the mock server", nickname))
+    }
+
+    /// Starts the script, returning the stream to connect the client to.
+    pub fn start(self) -> (MockStream, RunningServer) {
+        let (client, mut server) = duplex();
+        server.set_read_timeout(Some(self.timeout));
+        let thread = thread::spawn(move || run_script(self.steps, server));
+        (client, RunningServer { thread: thread })
+    }
+}
+
+/// A `MockServer` that's been started.
+pub struct RunningServer {
+    thread: JoinHandle<Result<Vec<Message>, String>>,
+}
+
+impl RunningServer {
+    /// Waits for the script to finish, and returns everything the client sent.
+    ///
+    /// Panics if the client didn't send what was expected, or disconnected before the script
+    /// was done.
+    pub fn finish(self) -> Vec<Message> {
+        match self.thread.join() {
+            Ok(Ok(received)) => received,
+            Ok(Err(failure)) => panic!("Mock server script failed: {}", failure),
+            Err(_) => panic!("Mock server thread panicked"),
+        }
+    }
+}
+
+fn run_script(steps: Vec<Step>, stream: MockStream) -> Result<Vec<Message>, String> {
+    let mut stream = BufReader::new(stream);
+    let mut received = Vec::new();
+    for step in steps {
+        match step {
+            Step::Send(line) => {
+                if let Err(e) = stream.get_mut().write_all(&line) {
+                    let line = String::from_utf8_lossy(&line);
+                    return Err(format!("Couldn't send {}: {}", line.trim_end(), e));
+                }
+            }
+            Step::Expect(description, matches) => {
+                let mut line = Vec::new();
+                let message = match stream.read_until(b'\n', &mut line) {
+                    Ok(0) => {
+                        return Err(format!("Expected {}, but the client disconnected", description))
+                    }
+                    Ok(_) => Message::parse(&line).map(|(message, _)| message),
+                    Err(e) => return Err(format!("Expected {}, but: {}", description, e)),
+                };
+                let message = message.map_err(|e| format!("Expected {}, but: {}", description, e))?;
+                if !matches(&message) {
+                    return Err(format!("Expected {}, got {}", description, message));
+                }
+                received.push(message);
+            }
+        }
+    }
+    Ok(received)
+}
+
+/// A fuzzing entry point: parses `data` as a message and, if it parses, checks that it survives
+/// being sent and parsed again with `fuzz_round_trip`. Panics on any problem.
+///
+/// With `cargo fuzz`, a target is just:
+///
+/// ```ignore
+/// fuzz_target!(|data: &[u8]| irc::testing::fuzz_parse(data));
+/// ```
+///
+/// The `fuzz` directory has this and a target for `fuzz_round_trip` with arbitrary messages, which
+/// needs the `arbitrary` feature.
+pub fn fuzz_parse(data: &[u8]) {
+    let owned = Message::parse(data);
+    let borrowed = MessageRef::parse(data);
+    match (&owned, &borrowed) {
+        (&Ok((ref message, _)), &Ok((ref message_ref, _))) => {
+            assert_eq!(*message, message_ref.to_owned(), "MessageRef parsed differently");
+            fuzz_round_trip(message);
+        }
+        (&Err(_), &Err(_)) => {}
+        _ => panic!("Only one of Message and MessageRef parsed: {:?} and {:?}", owned, borrowed),
+    }
+}
+
+/// Checks that sending `message` and parsing the result gives back the same message, that
+/// canonicalizing it changes nothing, and that its length is worked out correctly. Panics if not.
+pub fn fuzz_round_trip(message: &Message) {
+    match message.canonicalize() {
+        Ok(canonical) => assert_eq!(canonical, *message, "Not canonical"),
+        Err(e) => panic!("Couldn't canonicalize {}: {}", message, e),
+    }
+    let bytes = message.to_bytes();
+    if message.tags.is_empty() {
+        assert_eq!(message.line_len(), bytes.len(), "Wrong length for {}", message);
+    }
+    match Message::parse(&bytes) {
+        Ok((parsed, remaining)) => {
+            let line = String::from_utf8_lossy(&bytes);
+            assert_eq!(parsed, *message, "Didn't round trip as {:?}", line);
+            assert!(remaining.is_empty());
+        }
+        Err(e) => panic!("Couldn't parse {:?} again: {}", String::from_utf8_lossy(&bytes), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+    use std::thread;
+    use std::time::Duration;
+    use irc_stream::IrcStream;
+    use message::Message;
+    use registration::Registration;
+
+    #[test]
+    fn both_directions() {
+        let (client, server) = duplex();
+        let mut client = IrcStream::new(client).with_auto_pong();
+        let mut server = IrcStream::new(server);
+
+        server.send(&message("PING :server\r\n")).unwrap();
+        server.send(&Message::privmsg("bot", "Hi")).unwrap();
+        assert_eq!(client.next_message().unwrap(), Message::privmsg("bot", "Hi"));
+        assert_eq!(server.next_message().unwrap(), message("PONG :server\r\n"));
+
+        drop(client);
+        assert_eq!(server.next_message().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(server.send(&Message::privmsg("bot", "Hi")).unwrap_err().kind(),
+                   ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn timeouts() {
+        let (mut client, _server) = duplex();
+        client.set_read_timeout(Some(Duration::from_millis(10)));
+        let mut client = IrcStream::new(client);
+        assert_eq!(client.next_message().unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn threads() {
+        let (client, server) = duplex();
+        let server = thread::spawn(move || {
+            let mut server = IrcStream::new(server);
+            let ping = server.next_message().unwrap();
+            server.send(&ping.as_ping().unwrap().pong()).unwrap();
+        });
+
+        let mut client = IrcStream::new(client);
+        client.send(&Message::ping("token")).unwrap();
+        assert_eq!(client.next_message().unwrap(), message("PONG :token\r\n"));
+        server.join().unwrap();
+        assert_eq!(client.next_message().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn mock_server() {
+        let (stream, server) = MockServer::new()
+            .expect_registration("bot")
+            .send("!!!")
+            .send(":nick!user@host PRIVMSG bot :hi")
+            .expect_command(commands::JOIN())
+            .expect("PRIVMSG #chan :Hello")
+            .start();
+
+        let mut irc = IrcStream::new(stream);
+        let registered = Registration::new("bot", "bot", "Bot").register(&mut irc).unwrap();
+        assert_eq!(registered.nickname, "bot");
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(irc.next_message().unwrap(), message(":nick!user@host PRIVMSG bot :hi\r\n"));
+        irc.send(&Message::join("#chan")).unwrap();
+        irc.send(&Message::privmsg("#chan", "Hello")).unwrap();
+
+        let received = server.finish();
+        assert_eq!(received.len(), 4);
+        assert_eq!(irc.next_message().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected PRIVMSG #chan :Hello, got PRIVMSG #chan Goodbye")]
+    fn mock_server_unexpected_message() {
+        let (stream, server) = MockServer::new().expect("PRIVMSG #chan :Hello").start();
+        IrcStream::new(stream).send(&Message::privmsg("#chan", "Goodbye")).unwrap();
+        server.finish();
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected a JOIN message, but")]
+    fn mock_server_timeout() {
+        let (_stream, server) = MockServer::new()
+            .with_timeout(Duration::from_millis(10))
+            .expect_command(commands::JOIN())
+            .start();
+        server.finish();
+    }
+
+    #[test]
+    fn fuzzing() {
+        for line in &[&b"@a=b\\s;c :nick!user@host PRIVMSG #chan :Hi there\r\n"[..],
+                      b":server 001 bot :\r\n",
+                      b"PING ::colon\n",
+                      b"1000\r\n",
+                      b"\xff\r\n",
+                      b""] {
+            fuzz_parse(line);
+        }
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
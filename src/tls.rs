@@ -0,0 +1,160 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
+
+use irc_stream::ClientCert;
+
+/// Sets up TLS over a connected socket, for `IrcStream::connect_tls`.
+///
+/// There are implementations for OpenSSL, rustls and native-tls behind the features of the same
+/// names. Implement it yourself to bring a different TLS library, or to configure one in ways
+/// `TlsOptions` doesn't cover.
+pub trait TlsConnector {
+    type Stream: Read + Write;
+
+    /// Does the TLS handshake over `stream`, checking the certificate is valid for `server`.
+    fn connect(&self, server: &str, stream: TcpStream) -> io::Result<Self::Stream>;
+}
+
+/// The connector used by `IrcStream::connect_ssl`. If more than one TLS feature is enabled, the
+/// first of OpenSSL, rustls and native-tls wins. To use a particular one whatever else is enabled
+/// (e.g. rustls while OpenSSL is still on by default), pass its connector to
+/// `IrcStream::connect_tls`.
+#[cfg(feature = "openssl")]
+pub type DefaultTlsConnector = ::openssl_connector::OpensslConnector;
+#[cfg(all(feature = "rustls", not(feature = "openssl")))]
+pub type DefaultTlsConnector = ::rustls_connector::RustlsConnector;
+#[cfg(all(feature = "native-tls", not(any(feature = "openssl", feature = "rustls"))))]
+pub type DefaultTlsConnector = ::native_tls_connector::NativeTlsConnector;
+
+/// The stream type `IrcStream::connect_ssl` connects with.
+#[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+pub type TlsStream = <DefaultTlsConnector as TlsConnector>::Stream;
+
+/// The oldest TLS version to allow, for `TlsOptions::with_min_version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls10,
+    Tls11,
+    Tls12,
+}
+
+/// Settings for the TLS connectors, used by `IrcStream::connect_ssl_with_options`. The default is
+/// what `connect_ssl` uses: the system's trusted CAs, no client certificate, and the server's
+/// hostname used for SNI and verification.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    ca_file: Option<PathBuf>,
+    client_cert: Option<ClientCert>,
+    server_name: Option<String>,
+    min_version: Option<TlsVersion>,
+    accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts the CA certificates in this PEM file, in addition to the system's.
+    pub fn with_ca_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.ca_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Presents a client certificate to the server, e.g. for CertFP or SASL EXTERNAL.
+    pub fn with_client_cert(mut self, client_cert: ClientCert) -> Self {
+        self.client_cert = Some(client_cert);
+        self
+    }
+
+    /// Uses `server_name` for SNI and certificate verification rather than the hostname being
+    /// connected to. Useful when connecting by IP address, or through a tunnel.
+    pub fn with_server_name(mut self, server_name: &str) -> Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
+    pub fn with_min_version(mut self, version: TlsVersion) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    /// Turns off certificate verification entirely, so any certificate is accepted.
+    ///
+    /// **Don't use this for real connections.** Anyone between you and the server can read and
+    /// change everything sent, including passwords. It's only here for testing against servers
+    /// with self-signed certificates; for anything else use `with_ca_file` to trust the
+    /// certificate properly.
+    pub fn danger_accept_invalid_certs(mut self) -> Self {
+        self.accept_invalid_certs = true;
+        self
+    }
+
+    pub fn ca_file(&self) -> Option<&Path> {
+        self.ca_file.as_deref()
+    }
+
+    pub fn client_cert(&self) -> Option<&ClientCert> {
+        self.client_cert.as_ref()
+    }
+
+    /// The name to use for SNI and verification when connecting to `server`.
+    pub fn server_name<'a>(&'a self, server: &'a str) -> &'a str {
+        self.server_name.as_deref().unwrap_or(server)
+    }
+
+    pub fn min_version(&self) -> Option<TlsVersion> {
+        self.min_version
+    }
+
+    pub fn accepts_invalid_certs(&self) -> bool {
+        self.accept_invalid_certs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(any(feature = "openssl", feature = "rustls", feature = "native-tls"))]
+    use std::any::TypeId;
+
+    #[test]
+    fn server_name_defaults_to_server() {
+        assert_eq!(TlsOptions::new().server_name("irc.example.com"), "irc.example.com");
+        assert_eq!(TlsOptions::new()
+                       .with_server_name("irc.example.com")
+                       .server_name("192.0.2.1"),
+                   "irc.example.com");
+    }
+
+    #[test]
+    fn verification_on_by_default() {
+        assert!(!TlsOptions::new().accepts_invalid_certs());
+        assert!(TlsOptions::new().danger_accept_invalid_certs().accepts_invalid_certs());
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    fn openssl_is_the_default_whatever_else_is_enabled() {
+        assert_eq!(TypeId::of::<DefaultTlsConnector>(),
+                   TypeId::of::<::openssl_connector::OpensslConnector>());
+    }
+
+    #[cfg(all(feature = "rustls", not(feature = "openssl")))]
+    #[test]
+    fn rustls_is_the_default_without_openssl() {
+        assert_eq!(TypeId::of::<DefaultTlsConnector>(),
+                   TypeId::of::<::rustls_connector::RustlsConnector>());
+    }
+
+    #[cfg(all(feature = "native-tls", not(any(feature = "openssl", feature = "rustls"))))]
+    #[test]
+    fn native_tls_is_the_default_on_its_own() {
+        assert_eq!(TypeId::of::<DefaultTlsConnector>(),
+                   TypeId::of::<::native_tls_connector::NativeTlsConnector>());
+    }
+}
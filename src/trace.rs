@@ -0,0 +1,185 @@
+// Logging for connecting, sending and receiving. With the `tracing` feature these are `tracing`
+// spans and events, with the server, nickname and command as fields; without it they're the same
+// plain `log` lines as everything else.
+
+use message::Message;
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+// Stands in for `tracing::Span` without the feature, so connections can always carry one.
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Entered;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub(crate) fn current() -> Self {
+        Span
+    }
+
+    pub(crate) fn enter(&self) -> Entered {
+        Entered
+    }
+}
+
+/// Makes the span for a new connection, with the server and port, and notes we're connecting.
+#[cfg(feature = "tracing")]
+pub(crate) fn connecting(scheme: &str, server: &str, port: u16) -> Span {
+    let span = ::tracing::info_span!("irc",
+                                     server = server,
+                                     port = port,
+                                     nick = ::tracing::field::Empty);
+    ::tracing::debug!(parent: &span, scheme = scheme, "connecting");
+    span
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn connecting(scheme: &str, server: &str, port: u16) -> Span {
+    debug!("Connecting to {}://{}:{}", scheme, server, port);
+    Span
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn sent(message: &Message) {
+    ::tracing::debug!(command = %message.command, "SEND> {}", message);
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn sent(message: &Message) {
+    debug!("SEND> {}", message);
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn received(message: &Message) {
+    ::tracing::debug!(command = %message.command, "RECV> {}", message);
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn received(message: &Message) {
+    debug!("RECV> {}", message);
+}
+
+/// Records our nickname on a connection's span, once we're registered or it changes.
+#[cfg(feature = "tracing")]
+pub(crate) fn nickname(span: &Span, nickname: &str) {
+    span.record("nick", nickname);
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn nickname(_span: &Span, _nickname: &str) {}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use tracing::Event;
+    use tracing::Metadata;
+    use tracing::Subscriber;
+    use tracing::field::Field;
+    use tracing::field::Visit;
+    use tracing::span::Attributes;
+    use tracing::span::Id;
+    use tracing::span::Record;
+    use irc_stream::IrcStream;
+    use irc_stream::ScriptedStream;
+    use message::Message;
+
+    type Fields = Vec<(String, String)>;
+
+    // Keeps the fields of every event, followed by those of the span it happened in.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        spans: Arc<Mutex<HashMap<u64, Fields>>>,
+        entered: Arc<Mutex<Vec<u64>>>,
+        events: Arc<Mutex<Vec<Fields>>>,
+    }
+
+    struct Visitor<'a>(&'a mut Fields);
+
+    impl<'a> Visit for Visitor<'a> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push((field.name().into(), value.into()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push((field.name().into(), format!("{:?}", value)));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes) -> Id {
+            let mut spans = self.spans.lock().unwrap();
+            let mut fields = Vec::new();
+            span.record(&mut Visitor(&mut fields));
+            let id = spans.len() as u64 + 1;
+            spans.insert(id, fields);
+            Id::from_u64(id)
+        }
+
+        fn record(&self, span: &Id, values: &Record) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut Visitor(spans.get_mut(&span.into_u64()).unwrap()));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event) {
+            let mut fields = Vec::new();
+            event.record(&mut Visitor(&mut fields));
+            let span = event.parent()
+                .map(|span| span.into_u64())
+                .or_else(|| self.entered.lock().unwrap().last().cloned());
+            if let Some(span) = span {
+                fields.extend(self.spans.lock().unwrap()[&span].iter().cloned());
+            }
+            self.events.lock().unwrap().push(fields);
+        }
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+    }
+
+    #[test]
+    fn messages_are_traced_in_the_connection_span() {
+        let recorder = Recorder::default();
+        let events = recorder.events.clone();
+        ::tracing::subscriber::with_default(recorder, || {
+            let span = ::tracing::info_span!("irc",
+                                             server = "irc.example.net",
+                                             nick = ::tracing::field::Empty);
+            let input = b":irc.example.net 001 me :Welcome\r\n";
+            let mut irc = IrcStream::new(ScriptedStream::new(input)).with_span(span);
+            irc.send(&Message::privmsg("#chan", "Hi")).unwrap();
+            irc.next_message().unwrap();
+            irc.nickname_changed("me");
+            irc.send(&Message::privmsg("#chan", "Bye")).unwrap();
+        });
+
+        let field = |name: &str, value: &str| (name.to_string(), value.to_string());
+        let events = events.lock().unwrap();
+        assert_eq!(events[0],
+                   vec![field("message", "SEND> PRIVMSG #chan Hi"),
+                        field("command", "PRIVMSG"),
+                        field("server", "irc.example.net")]);
+        assert_eq!(events[1],
+                   vec![field("message", "RECV> :irc.example.net 001 me Welcome"),
+                        field("command", "001"),
+                        field("server", "irc.example.net")]);
+        assert_eq!(events[2][3], field("nick", "me"));
+    }
+}
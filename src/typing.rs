@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use message::Message;
+use messages::TypingState;
+
+/// Decides when typing notifications are worth sending, so a UI can report every keystroke
+/// without flooding the server.
+///
+/// Changes of state are always sent. Repeated `Active` notifications (which receivers expire after
+/// a few seconds) are only resent once `interval` has passed; the spec suggests 3 seconds.
+#[derive(Debug, Clone)]
+pub struct TypingThrottle {
+    interval: Duration,
+    // Last state sent to each target, and when.
+    sent: HashMap<String, (TypingState, Instant)>,
+}
+
+impl Default for TypingThrottle {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(3))
+    }
+}
+
+impl TypingThrottle {
+    pub fn new(interval: Duration) -> Self {
+        TypingThrottle {
+            interval: interval,
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Reports our typing state for `target`, returning the message to send if one is due.
+    pub fn update(&mut self, target: &str, state: TypingState) -> Option<Message> {
+        self.update_at(target, state, Instant::now())
+    }
+
+    /// As `update`, but with the current time passed in.
+    pub fn update_at(&mut self,
+                     target: &str,
+                     state: TypingState,
+                     now: Instant)
+                     -> Option<Message> {
+        let due = match self.sent.get(target) {
+            None => state != TypingState::Done,
+            Some(&(last, _)) if last != state => true,
+            Some(&(_, at)) => state == TypingState::Active && now.duration_since(at) >= self.interval,
+        };
+        if !due {
+            return None;
+        }
+
+        if state == TypingState::Done {
+            self.sent.remove(target);
+        } else {
+            self.sent.insert(target.into(), (state, now));
+        }
+        Some(Message::typing(target, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use std::time::Instant;
+    use message::Message;
+    use messages::TypingState;
+
+    #[test]
+    fn throttles_active() {
+        let mut throttle = TypingThrottle::default();
+        let start = Instant::now();
+
+        assert_eq!(throttle.update_at("#chan", TypingState::Active, start),
+                   Some(Message::typing("#chan", TypingState::Active)));
+        assert_eq!(throttle.update_at("#chan", TypingState::Active, start + Duration::from_secs(1)),
+                   None);
+        assert_eq!(throttle.update_at("#chan", TypingState::Active, start + Duration::from_secs(3)),
+                   Some(Message::typing("#chan", TypingState::Active)));
+    }
+
+    #[test]
+    fn changes_always_sent() {
+        let mut throttle = TypingThrottle::default();
+        let start = Instant::now();
+
+        throttle.update_at("#chan", TypingState::Active, start);
+        assert_eq!(throttle.update_at("#chan", TypingState::Paused, start),
+                   Some(Message::typing("#chan", TypingState::Paused)));
+        assert_eq!(throttle.update_at("#chan", TypingState::Paused, start), None);
+        assert_eq!(throttle.update_at("#chan", TypingState::Done, start),
+                   Some(Message::typing("#chan", TypingState::Done)));
+    }
+
+    #[test]
+    fn done_not_sent_when_not_typing() {
+        let mut throttle = TypingThrottle::default();
+        assert_eq!(throttle.update("#chan", TypingState::Done), None);
+    }
+
+    #[test]
+    fn targets_are_independent() {
+        let mut throttle = TypingThrottle::default();
+        let start = Instant::now();
+
+        throttle.update_at("#chan", TypingState::Active, start);
+        assert!(throttle.update_at("#other", TypingState::Active, start).is_some());
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+use casemapping::IrcCase;
+use casemapping::IrcString;
+use command::commands;
+use command::responses;
+use message::Message;
+use message::Prefix;
+use messages::WhoxQuery;
+use who::WhoEntry;
+
+// Marks replies to our own WHOX queries, so they aren't confused with anyone else's.
+const WHOX_TOKEN: &str = "152";
+
+/// What we know about a user we've seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserState {
+    pub nick: String,
+    /// The services account the user is logged into, if known.
+    pub account: Option<String>,
+    /// The user's away message, if they're away. This is empty if we know they're away but not
+    /// why, e.g. from a WHO reply.
+    pub away: Option<String>,
+    pub username: Option<String>,
+    pub host: Option<String>,
+    pub realname: Option<String>,
+    /// When a WHO reply last told us about this user.
+    pub synced: Option<Instant>,
+}
+
+/// Keeps track of information about other users, gleaned from the messages we receive.
+///
+/// Pass every received message to `handle`. Users are keyed by nickname, and are followed across
+/// nickname changes and forgotten when they quit.
+///
+/// Messages only tell us so much, so use `refresh` to fill in the gaps with WHO queries. Replies
+/// are picked up by `handle` like anything else, so nothing blocks waiting for them.
+#[derive(Debug, Clone, Default)]
+pub struct UserTracker {
+    case: IrcCase,
+    users: HashMap<IrcString, UserState>,
+    // When we last sent a WHO for each mask.
+    requested: HashMap<IrcString, Instant>,
+}
+
+impl UserState {
+    fn new(nick: &str) -> Self {
+        UserState {
+            nick: nick.into(),
+            account: None,
+            away: None,
+            username: None,
+            host: None,
+            realname: None,
+            synced: None,
+        }
+    }
+}
+
+impl UserTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `CASEMAPPING` the server advertised. Users (and outstanding WHO requests) are
+    /// looked up by nickname under it, so call this as soon as ISUPPORT arrives; anything already
+    /// tracked is kept. Defaults to `rfc1459`.
+    pub fn set_casemapping(&mut self, case: IrcCase) {
+        self.case = case;
+        self.users =
+            self.users.drain().map(|(nick, user)| (case.key(nick.as_str()), user)).collect();
+        self.requested =
+            self.requested.drain().map(|(mask, at)| (case.key(mask.as_str()), at)).collect();
+    }
+
+    pub fn handle(&mut self, message: &Message) {
+        // RPL_AWAY is sent by the server when we message (or WHOIS) someone who is away.
+        if message.command == responses::RPL_AWAY() && message.arguments.len() == 3 {
+            let nick = &message.arguments[1];
+            self.user_mut(nick).away = Some(message.arguments[2].clone());
+            return;
+        }
+        if let Some(entry) = WhoEntry::parse(message) {
+            self.handle_who_entry(&entry);
+            return;
+        }
+        if let Some(reply) = whox_query().parse(message) {
+            let nick = match reply.nick {
+                Some(nick) => nick,
+                None => return,
+            };
+            let user = self.user_mut(nick);
+            user.username = reply.user.map(|u| u.into());
+            user.host = reply.host.map(|h| h.into());
+            user.account = reply.account.map(|a| a.into());
+            user.realname = reply.realname.map(|r| r.into());
+            set_away_from_flags(user, reply.flags.unwrap_or(""));
+            user.synced = Some(Instant::now());
+            return;
+        }
+
+        let (nick, username, host) = match message.prefix {
+            Prefix::User(ref user) => (user.nickname(), user.username(), user.host()),
+            _ => return,
+        };
+
+        if message.command == commands::QUIT() {
+            self.users.remove(&self.case.key(nick));
+            return;
+        }
+
+        if message.command == commands::NICK() && !message.arguments.is_empty() {
+            let new_nick = &message.arguments[0];
+            let mut state =
+                self.users.remove(&self.case.key(nick)).unwrap_or_else(|| UserState::new(nick));
+            state.nick = new_nick.clone();
+            self.users.insert(self.case.key(new_nick), state);
+            return;
+        }
+
+        if username.is_some() || host.is_some() {
+            let user = self.user_mut(nick);
+            user.username = username.map(|u| u.into()).or(user.username.take());
+            user.host = host.map(|h| h.into()).or(user.host.take());
+        }
+
+        if let Some(away) = message.as_away() {
+            self.user_mut(nick).away = away.message.map(|m| m.into());
+        }
+
+        if let Some(account) = message.as_account() {
+            self.user_mut(nick).account = account.account.map(|a| a.into());
+        } else if let Some(join) = message.as_join().filter(|join| join.realname.is_some()) {
+            // With extended-join a missing account means the user definitely isn't logged in.
+            self.user_mut(nick).account = join.account.map(|a| a.into());
+        } else if let Some(account) = message.sender_account() {
+            self.user_mut(nick).account = Some(account.into());
+        }
+    }
+
+    pub fn user(&self, nick: &str) -> Option<&UserState> {
+        self.users.get(&self.case.key(nick))
+    }
+
+    /// The services account `nick` is logged into, if we know it.
+    pub fn account(&self, nick: &str) -> Option<&str> {
+        self.user(nick).and_then(|user| user.account.as_deref())
+    }
+
+    /// Whether `nick` is away, as far as we know. Relies on the `away-notify` capability to hear
+    /// about changes.
+    pub fn is_away(&self, nick: &str) -> bool {
+        self.away_message(nick).is_some()
+    }
+
+    pub fn away_message(&self, nick: &str) -> Option<&str> {
+        self.user(nick).and_then(|user| user.away.as_deref())
+    }
+
+    /// `nick!user@host` for `nick`, if we know their username and host.
+    pub fn hostmask(&self, nick: &str) -> Option<String> {
+        let user = self.user(nick)?;
+        match (&user.username, &user.host) {
+            (Some(username), Some(host)) => {
+                Some(format!("{}!{}@{}", user.nick, username, host))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns a WHO query to send if what we know about `mask` (a nickname or channel) is older
+    /// than `max_age`, or `None` if it's fresh enough or has already been asked about recently.
+    ///
+    /// Use WHOX if the server supports it (the `WHOX` ISUPPORT token), since only WHOX tells us
+    /// accounts.
+    pub fn refresh(&mut self, mask: &str, max_age: Duration, whox: bool) -> Option<Message> {
+        self.refresh_at(mask, max_age, whox, Instant::now())
+    }
+
+    /// As `refresh`, but with the current time passed in.
+    pub fn refresh_at(&mut self,
+                      mask: &str,
+                      max_age: Duration,
+                      whox: bool,
+                      now: Instant)
+                      -> Option<Message> {
+        let fresh = |at: Instant| now.saturating_duration_since(at) < max_age;
+        if self.user(mask).and_then(|user| user.synced).is_some_and(fresh) {
+            return None;
+        }
+        if self.requested.get(&self.case.key(mask)).cloned().is_some_and(fresh) {
+            return None;
+        }
+
+        self.requested.insert(self.case.key(mask), now);
+        Some(if whox {
+            whox_query().message(mask)
+        } else {
+            Message::who(mask)
+        })
+    }
+
+    /// Updates from a WHO reply. This happens in `handle` anyway, but is useful if the reply was
+    /// collected elsewhere, e.g. by `Client::who`.
+    pub fn handle_who_entry(&mut self, entry: &WhoEntry) {
+        let user = self.user_mut(&entry.nick);
+        user.username = Some(entry.user.clone());
+        user.host = Some(entry.host.clone());
+        set_away_from_flags(user, &entry.flags);
+        user.realname = Some(entry.realname.clone());
+        user.synced = Some(Instant::now());
+    }
+
+    fn user_mut(&mut self, nick: &str) -> &mut UserState {
+        self.users.entry(self.case.key(nick)).or_insert_with(|| UserState::new(nick))
+    }
+}
+
+fn whox_query() -> WhoxQuery {
+    WhoxQuery::new("nuhfar").with_token(WHOX_TOKEN)
+}
+
+/// WHO flags start with `H` (here) or `G` (gone).
+fn set_away_from_flags(user: &mut UserState, flags: &str) {
+    if flags.starts_with('G') {
+        user.away.get_or_insert_with(String::new);
+    } else if flags.starts_with('H') {
+        user.away = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn account_from_tag() {
+        let mut tracker = UserTracker::new();
+        tracker.handle(&message("@account=bob :Bobby!user@host PRIVMSG #chan :Hello\r\n"));
+
+        assert_eq!(tracker.account("bobby"), Some("bob"));
+    }
+
+    #[test]
+    fn account_notify() {
+        let mut tracker = UserTracker::new();
+
+        tracker.handle(&message(":bobby!user@host ACCOUNT bob\r\n"));
+        assert_eq!(tracker.account("bobby"), Some("bob"));
+        tracker.handle(&message(":bobby!user@host ACCOUNT *\r\n"));
+        assert_eq!(tracker.account("bobby"), None);
+    }
+
+    #[test]
+    fn account_from_extended_join() {
+        let mut tracker = UserTracker::new();
+
+        tracker.handle(&message(":bobby!user@host JOIN #chan bob :Bob\r\n"));
+        assert_eq!(tracker.account("bobby"), Some("bob"));
+        tracker.handle(&message(":bobby!user@host JOIN #chan2 * :Bob\r\n"));
+        assert_eq!(tracker.account("bobby"), None);
+    }
+
+    #[test]
+    fn away_notify() {
+        let mut tracker = UserTracker::new();
+
+        tracker.handle(&message(":bobby!user@host AWAY :Gone to lunch\r\n"));
+        assert!(tracker.is_away("bobby"));
+        assert_eq!(tracker.away_message("bobby"), Some("Gone to lunch"));
+        tracker.handle(&message(":bobby!user@host AWAY\r\n"));
+        assert!(!tracker.is_away("bobby"));
+    }
+
+    #[test]
+    fn away_reply() {
+        let mut tracker = UserTracker::new();
+        tracker.handle(&message(":irc.host 301 me bobby :Gone to lunch\r\n"));
+
+        assert_eq!(tracker.away_message("bobby"), Some("Gone to lunch"));
+    }
+
+    #[test]
+    fn follows_nick_changes() {
+        let mut tracker = UserTracker::new();
+
+        tracker.handle(&message(":bobby!user@host ACCOUNT bob\r\n"));
+        tracker.handle(&message(":bobby!user@host NICK :robert\r\n"));
+
+        assert_eq!(tracker.account("bobby"), None);
+        assert_eq!(tracker.account("robert"), Some("bob"));
+        assert_eq!(tracker.user("robert").unwrap().nick, "robert");
+    }
+
+    #[test]
+    fn forgets_on_quit() {
+        let mut tracker = UserTracker::new();
+
+        tracker.handle(&message(":bobby!user@host ACCOUNT bob\r\n"));
+        tracker.handle(&message(":bobby!user@host QUIT :Bye\r\n"));
+
+        assert_eq!(tracker.user("bobby"), None);
+    }
+
+    #[test]
+    fn casemapping() {
+        let mut tracker = UserTracker::new();
+        tracker.handle(&message(":nick[m]!user@host AWAY :Gone\r\n"));
+        assert!(tracker.is_away("NICK{M}"));
+
+        tracker.set_casemapping(IrcCase::Ascii);
+        assert!(tracker.is_away("NICK[M]"));
+        assert!(!tracker.is_away("nick{m}"));
+    }
+
+    #[test]
+    fn hostmask_from_prefix() {
+        let mut tracker = UserTracker::new();
+        tracker.handle(&message(":bobby!bob@example.com PRIVMSG #chan :Hello\r\n"));
+
+        assert_eq!(tracker.hostmask("bobby"), Some("bobby!bob@example.com".into()));
+        assert_eq!(tracker.hostmask("alice"), None);
+    }
+
+    #[test]
+    fn who_reply() {
+        let mut tracker = UserTracker::new();
+        tracker.handle(&message(":irc.host 352 me #chan bob example.com irc.host bobby G \
+                                 :0 Bob Smith\r\n"));
+
+        let user = tracker.user("bobby").unwrap();
+        assert_eq!(tracker.hostmask("bobby"), Some("bobby!bob@example.com".into()));
+        assert_eq!(user.realname, Some("Bob Smith".into()));
+        assert!(tracker.is_away("bobby"));
+        assert!(user.synced.is_some());
+    }
+
+    #[test]
+    fn whox_reply() {
+        let mut tracker = UserTracker::new();
+        tracker.handle(&message(":irc.host 354 me 152 bob example.com bobby H bob :Bob Smith\r\n"));
+        tracker.handle(&message(":irc.host 354 me 152 alice example.org alice G 0 :Alice\r\n"));
+
+        assert_eq!(tracker.account("bobby"), Some("bob"));
+        assert_eq!(tracker.hostmask("bobby"), Some("bobby!bob@example.com".into()));
+        assert!(!tracker.is_away("bobby"));
+        assert_eq!(tracker.account("alice"), None);
+        assert!(tracker.is_away("alice"));
+    }
+
+    #[test]
+    fn refresh_when_stale() {
+        let mut tracker = UserTracker::new();
+        let max_age = Duration::from_secs(60);
+        let now = Instant::now();
+
+        assert_eq!(tracker.refresh_at("#chan", max_age, true, now),
+                   Some(message("WHO #chan %tuhnfar,152\r\n")));
+        // Already asked.
+        assert_eq!(tracker.refresh_at("#chan", max_age, true, now), None);
+        assert!(tracker.refresh_at("#chan", max_age, false, now + max_age).is_some());
+
+        tracker.handle(&message(":irc.host 354 me 152 bob example.com bobby H bob :Bob\r\n"));
+        assert_eq!(tracker.refresh_at("bobby", max_age, false, Instant::now()), None);
+        assert_eq!(tracker.refresh_at("bobby", max_age, false, Instant::now() + max_age),
+                   Some(message("WHO bobby\r\n")));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
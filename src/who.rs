@@ -0,0 +1,131 @@
+use command::responses;
+use message::Message;
+
+/// One line of a WHO reply (`RPL_WHOREPLY`, 352), describing a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WhoEntry {
+    /// A channel the user is in, or `*` if the WHO wasn't for a channel.
+    pub channel: String,
+    pub user: String,
+    pub host: String,
+    pub server: String,
+    pub nick: String,
+    /// `H` (here) or `G` (gone), then `*` for IRC operators and any channel membership prefixes.
+    pub flags: String,
+    /// How many servers away the user is.
+    pub hopcount: u32,
+    pub realname: String,
+}
+
+/// Collects `RPL_WHOREPLY` lines until the `RPL_ENDOFWHO` (315) that finishes them.
+///
+/// The lines themselves don't say which WHO they're in reply to, so only have one WHO (or WHOX)
+/// in flight at a time when using this.
+#[derive(Debug, Clone, Default)]
+pub struct WhoCollector {
+    entries: Vec<WhoEntry>,
+}
+
+impl WhoEntry {
+    /// Parses an `RPL_WHOREPLY`, returning `None` for anything else.
+    pub fn parse(message: &Message) -> Option<Self> {
+        if message.command != responses::RPL_WHOREPLY() {
+            return None;
+        }
+        // <client> <channel> <user> <host> <server> <nick> <flags> :<hopcount> <realname>
+        let args = &message.arguments;
+        if args.len() != 8 {
+            warn!("Not parsing message as WhoEntry because we expect 8 arguments: {}", message);
+            return None;
+        }
+        let (hopcount, realname) = args[7].split_once(' ').unwrap_or((&args[7], ""));
+
+        Some(WhoEntry {
+            channel: args[1].clone(),
+            user: args[2].clone(),
+            host: args[3].clone(),
+            server: args[4].clone(),
+            nick: args[5].clone(),
+            flags: args[6].clone(),
+            hopcount: hopcount.parse().unwrap_or(0),
+            realname: realname.into(),
+        })
+    }
+
+    pub fn is_away(&self) -> bool {
+        self.flags.starts_with('G')
+    }
+
+    pub fn is_operator(&self) -> bool {
+        self.flags.contains('*')
+    }
+}
+
+impl WhoCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `message` is part of a WHO reply, so will be consumed by `handle`.
+    pub fn is_reply(message: &Message) -> bool {
+        message.command == responses::RPL_WHOREPLY() ||
+        message.command == responses::RPL_ENDOFWHO()
+    }
+
+    /// Updates from a received message, returning the reply's entries once the end arrives.
+    pub fn handle(&mut self, message: &Message) -> Option<Vec<WhoEntry>> {
+        if let Some(entry) = WhoEntry::parse(message) {
+            self.entries.push(entry);
+        } else if message.command == responses::RPL_ENDOFWHO() {
+            return Some(self.entries.drain(..).collect());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use message::Message;
+
+    #[test]
+    fn collects_until_end() {
+        let mut collector = WhoCollector::new();
+        let lines = [":irc.host 352 me #chan alice example.com irc.host Alice H*@ :0 Alice \
+                      Smith\r\n",
+                     ":irc.host 352 me #chan bob example.org other.host bob G :2 Bob\r\n"];
+        for line in &lines {
+            assert_eq!(collector.handle(&message(line)), None);
+        }
+
+        let entries = collector.handle(&message(":irc.host 315 me #chan :End of WHO list\r\n"))
+            .unwrap();
+        assert_eq!(entries[0],
+                   WhoEntry {
+                       channel: "#chan".into(),
+                       user: "alice".into(),
+                       host: "example.com".into(),
+                       server: "irc.host".into(),
+                       nick: "Alice".into(),
+                       flags: "H*@".into(),
+                       hopcount: 0,
+                       realname: "Alice Smith".into(),
+                   });
+        assert!(entries[0].is_operator());
+        assert!(!entries[0].is_away());
+        assert!(entries[1].is_away());
+        assert_eq!(entries[1].hopcount, 2);
+
+        // Ready for the next one.
+        let end = message(":irc.host 315 me nobody :End of WHO list\r\n");
+        assert_eq!(collector.handle(&end), Some(vec![]));
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::io;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use casemapping::IrcCase;
+use command::responses;
+use message::Message;
+
+/// Everything a WHOIS told us about a user. Servers vary in what they send, so most of this is
+/// optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WhoisInfo {
+    pub nick: String,
+    pub user: Option<String>,
+    pub host: Option<String>,
+    /// The user's real host or IP address, if we're allowed to see it (e.g. as an operator).
+    pub actual_host: Option<String>,
+    pub realname: Option<String>,
+    /// The server the user is connected to, and its description.
+    pub server: Option<(String, String)>,
+    /// Channels the user is in, as the server gave them, so with any membership prefixes (e.g.
+    /// `@#chan`).
+    pub channels: Vec<String>,
+    pub idle: Option<Duration>,
+    /// When the user connected, in seconds since the Unix epoch.
+    pub signon: Option<u64>,
+    /// The services account the user is logged into.
+    pub account: Option<String>,
+    pub away: Option<String>,
+    pub operator: bool,
+    /// Whether the user is connected using TLS.
+    pub secure: bool,
+}
+
+/// Assembles the numerics sent in reply to a WHOIS into a `WhoisInfo`.
+///
+/// Pass received messages to `handle` until it returns a result when `RPL_ENDOFWHOIS` (318)
+/// arrives: the info, or an error if the server said there's no such user. An `ERR_NOSUCHSERVER`
+/// isn't followed by 318, so it's an error straight away.
+#[derive(Debug, Clone)]
+pub struct WhoisCollector {
+    case: IrcCase,
+    info: WhoisInfo,
+    // The ERR_NOSUCHNICK, if we got one, to report once the reply ends.
+    no_such_nick: Option<Message>,
+}
+
+impl WhoisCollector {
+    /// Collects the reply to a WHOIS for `nick`.
+    pub fn new(nick: &str) -> Self {
+        WhoisCollector {
+            case: IrcCase::default(),
+            info: WhoisInfo {
+                nick: nick.into(),
+                ..WhoisInfo::default()
+            },
+            no_such_nick: None,
+        }
+    }
+
+    /// Sets the `CASEMAPPING` the server advertised, so `is_reply` recognises replies about the
+    /// nickname being looked up however the server capitalises it. Defaults to `rfc1459`.
+    pub fn set_casemapping(&mut self, case: IrcCase) {
+        self.case = case;
+    }
+
+    /// Whether `message` is part of the reply, so should be passed to `handle`.
+    pub fn is_reply(&self, message: &Message) -> bool {
+        is_whois_numeric(message) && message.arguments.len() >= 2 &&
+        self.case.eq(&message.arguments[1], &self.info.nick)
+    }
+
+    /// Updates from a received message, returning the result once the reply is complete.
+    /// Messages which aren't part of the reply are ignored.
+    pub fn handle(&mut self, message: &Message) -> Option<io::Result<WhoisInfo>> {
+        if !self.is_reply(message) {
+            return None;
+        }
+        let args = &message.arguments;
+        let info = &mut self.info;
+        if message.command == responses::RPL_WHOISUSER() && args.len() >= 6 {
+            // <client> <nick> <user> <host> * :<realname>
+            info.nick = args[1].clone();
+            info.user = Some(args[2].clone());
+            info.host = Some(args[3].clone());
+            info.realname = Some(args[5].clone());
+        } else if message.command == responses::RPL_WHOISSERVER() && args.len() >= 4 {
+            info.server = Some((args[2].clone(), args[3].clone()));
+        } else if message.command == responses::RPL_WHOISOPERATOR() {
+            info.operator = true;
+        } else if message.command == responses::RPL_WHOISIDLE() && args.len() >= 3 {
+            // <client> <nick> <secs> [<signon>] :seconds idle, signon time
+            info.idle = args[2].parse().ok().map(Duration::from_secs);
+            info.signon = args.get(3).and_then(|signon| signon.parse().ok());
+        } else if message.command == responses::RPL_WHOISCHANNELS() && args.len() >= 3 {
+            info.channels.extend(args[2].split(' ').filter(|c| !c.is_empty()).map(|c| c.into()));
+        } else if message.command == responses::RPL_WHOISACCOUNT() && args.len() >= 3 {
+            info.account = Some(args[2].clone());
+        } else if message.command == responses::RPL_WHOISACTUALLY() && args.len() >= 4 {
+            // Servers disagree about the format here; the last argument before the text is
+            // the most likely to be the host or IP.
+            info.actual_host = Some(args[args.len() - 2].clone());
+        } else if message.command == responses::RPL_AWAY() && args.len() >= 3 {
+            info.away = Some(args[2].clone());
+        } else if message.command == responses::RPL_WHOISSECURE() {
+            info.secure = true;
+        } else if message.command == responses::ERR_NOSUCHNICK() {
+            self.no_such_nick = Some(message.clone());
+        } else if message.command == responses::ERR_NOSUCHSERVER() {
+            return Some(Err(not_found(message)));
+        } else if message.command == responses::RPL_ENDOFWHOIS() {
+            return Some(match self.no_such_nick {
+                Some(ref error) => Err(not_found(error)),
+                None => Ok(info.clone()),
+            });
+        }
+        None
+    }
+}
+
+fn not_found(message: &Message) -> io::Error {
+    io::Error::new(ErrorKind::NotFound, format!("WHOIS failed: {}", message))
+}
+
+fn is_whois_numeric(message: &Message) -> bool {
+    [responses::RPL_WHOISUSER(),
+     responses::RPL_WHOISSERVER(),
+     responses::RPL_WHOISOPERATOR(),
+     responses::RPL_WHOISIDLE(),
+     responses::RPL_WHOISCHANNELS(),
+     responses::RPL_WHOISACCOUNT(),
+     responses::RPL_WHOISACTUALLY(),
+     responses::RPL_AWAY(),
+     responses::RPL_WHOISSECURE(),
+     responses::ERR_NOSUCHNICK(),
+     responses::ERR_NOSUCHSERVER(),
+     responses::RPL_ENDOFWHOIS()]
+        .contains(&message.command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use message::Message;
+
+    #[test]
+    fn collects_until_end() {
+        let mut collector = WhoisCollector::new("alice");
+        let lines = [":irc.host 311 me Alice al example.com * :Alice Smith\r\n",
+                     ":irc.host 319 me alice :@#chan +#other\r\n",
+                     ":irc.host 312 me alice irc.host :The server\r\n",
+                     ":irc.host 301 me alice :Gone\r\n",
+                     ":irc.host 311 me bob bob example.org * :Bob\r\n",
+                     ":irc.host 313 me alice :is an IRC operator\r\n",
+                     ":irc.host 671 me alice :is using a secure connection\r\n",
+                     ":irc.host 317 me alice 60 1500000000 :seconds idle, signon time\r\n",
+                     ":irc.host 330 me alice alice_acct :is logged in as\r\n",
+                     ":irc.host 338 me alice 192.0.2.1 :actually using host\r\n"];
+        for line in &lines {
+            assert!(collector.handle(&message(line)).is_none());
+        }
+
+        let end = message(":irc.host 318 me alice :End of /WHOIS list\r\n");
+        assert_eq!(collector.handle(&end).unwrap().unwrap(),
+                   WhoisInfo {
+                       nick: "Alice".into(),
+                       user: Some("al".into()),
+                       host: Some("example.com".into()),
+                       actual_host: Some("192.0.2.1".into()),
+                       realname: Some("Alice Smith".into()),
+                       server: Some(("irc.host".into(), "The server".into())),
+                       channels: vec!["@#chan".into(), "+#other".into()],
+                       idle: Some(Duration::from_secs(60)),
+                       signon: Some(1500000000),
+                       account: Some("alice_acct".into()),
+                       away: Some("Gone".into()),
+                       operator: true,
+                       secure: true,
+                   });
+    }
+
+    #[test]
+    fn no_such_nick() {
+        let mut collector = WhoisCollector::new("nobody");
+        assert!(collector.handle(&message(":irc.host 401 me nobody :No such nick\r\n")).is_none());
+        let error = collector.handle(&message(":irc.host 318 me nobody :End of /WHOIS list\r\n"))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().contains("No such nick"));
+
+        let mut collector = WhoisCollector::new("nobody");
+        let error = collector.handle(&message(":irc.host 402 me nobody :No such server\r\n"))
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+    }
+
+    fn message(message: &str) -> Message {
+        let parsed = Message::parse(message.as_bytes());
+        match parsed {
+            Ok((msg, _)) => msg,
+            other => panic!("Could not parse {}, got result {:?}", message, other),
+        }
+    }
+}